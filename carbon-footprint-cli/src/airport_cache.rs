@@ -0,0 +1,384 @@
+//! Keeping a downloaded airport list on disk, so it's only fetched again once it's stale.
+
+use crate::airports::check_airport_csv;
+use crate::error::CliError;
+use log::{debug, warn};
+use reqwest::header::{ETAG, IF_NONE_MATCH};
+use reqwest::{Client, StatusCode};
+use serde_derive::{Deserialize, Serialize};
+use std::fs;
+use std::future::Future;
+use std::io;
+use std::path::PathBuf;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long a downloaded airport list is used before it's fetched again.
+pub const DEFAULT_AIRPORTS_TTL_DAYS: u32 = 30;
+
+/// What a fetch of the airport list came back with.
+#[derive(Clone, Debug, PartialEq)]
+pub enum FetchedAirports {
+    /// A list in the bundled CSV format, with the ETag to revalidate it with later
+    Updated { csv: String, etag: Option<String> },
+    /// The cached copy with the ETag that was sent is still current
+    NotModified,
+}
+
+/// Somewhere to download the airport list from.
+pub trait AirportFetcher {
+    /// Fetches the list, or only checks it's unchanged when `etag` is given.
+    fn fetch(
+        &self,
+        etag: Option<&str>,
+    ) -> impl Future<Output = Result<FetchedAirports, CliError>> + Send;
+}
+
+/// A URL serving the list as CSV, revalidated with `If-None-Match`.
+pub struct HttpAirportFetcher {
+    client: Client,
+    url: String,
+}
+
+impl HttpAirportFetcher {
+    pub fn new(client: Client, url: &str) -> Self {
+        Self {
+            client,
+            url: url.into(),
+        }
+    }
+}
+
+impl AirportFetcher for HttpAirportFetcher {
+    async fn fetch(&self, etag: Option<&str>) -> Result<FetchedAirports, CliError> {
+        debug!("GET {}", self.url);
+        let mut request = self.client.get(&self.url);
+        if let Some(etag) = etag {
+            request = request.header(IF_NONE_MATCH, etag);
+        }
+        let response = request.send().await?;
+        if response.status() == StatusCode::NOT_MODIFIED {
+            return Ok(FetchedAirports::NotModified);
+        }
+
+        let response = response.error_for_status()?;
+        let etag = response
+            .headers()
+            .get(ETAG)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        Ok(FetchedAirports::Updated {
+            csv: response.text().await?,
+            etag,
+        })
+    }
+}
+
+/// When the cached list was fetched, and the ETag it came with.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct CacheMetadata {
+    /// Seconds since the Unix epoch
+    fetched_at: u64,
+    etag: Option<String>,
+}
+
+/// A downloaded airport list kept in `dir`, used for `ttl` before it's fetched again.
+pub struct AirportCache {
+    dir: PathBuf,
+    ttl: Duration,
+}
+
+impl AirportCache {
+    pub fn new(dir: PathBuf, ttl: Duration) -> Self {
+        Self { dir, ttl }
+    }
+
+    /// Where the list is cached unless overridden, e.g. `~/.cache/carbon-footprint` on
+    /// Linux.
+    pub fn default_dir() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("carbon-footprint"))
+    }
+
+    /// The airport list as CSV: the cached copy while it's fresh, otherwise whatever
+    /// `fetcher` returns, which is then cached. `refresh` fetches the whole list whatever
+    /// the cache holds. If the fetch fails, a cached copy is used however old it is, so
+    /// lookups keep working offline.
+    pub async fn load(
+        &self,
+        fetcher: &impl AirportFetcher,
+        refresh: bool,
+        now: SystemTime,
+    ) -> Result<String, CliError> {
+        let cached = self.read();
+        if let Some((csv, metadata)) = &cached {
+            if !refresh && self.is_fresh(metadata, now) {
+                debug!("Using the airport list cached in {}", self.dir.display());
+                return Ok(csv.clone());
+            }
+        }
+
+        let etag = match &cached {
+            Some((_, metadata)) if !refresh => metadata.etag.as_deref(),
+            _ => None,
+        };
+        let fetched = fetcher.fetch(etag).await.and_then(|fetched| match fetched {
+            FetchedAirports::Updated { csv, etag } => {
+                check_airport_csv(&csv)?;
+                Ok(Some((csv, etag)))
+            }
+            FetchedAirports::NotModified => Ok(None),
+        });
+
+        match (fetched, cached) {
+            (Ok(Some((csv, etag))), _) => {
+                self.write(&csv, etag, now);
+                Ok(csv)
+            }
+            (Ok(None), Some((csv, metadata))) => {
+                self.write(&csv, metadata.etag, now);
+                Ok(csv)
+            }
+            (Ok(None), None) => Err(CliError::ApiError(
+                "the airport list server said nothing had changed, but nothing is cached"
+                    .to_string(),
+            )),
+            (Err(err), Some((csv, _))) => {
+                warn!(
+                    "Couldn't refresh the airport list ({}); using the cached copy",
+                    err
+                );
+                Ok(csv)
+            }
+            (Err(err), None) => Err(err),
+        }
+    }
+
+    fn csv_path(&self) -> PathBuf {
+        self.dir.join("airports.csv")
+    }
+
+    fn metadata_path(&self) -> PathBuf {
+        self.dir.join("airports.json")
+    }
+
+    fn is_fresh(&self, metadata: &CacheMetadata, now: SystemTime) -> bool {
+        let fetched_at = UNIX_EPOCH + Duration::from_secs(metadata.fetched_at);
+        // A fetch time in the future means the clock has moved; refetch to be safe
+        now.duration_since(fetched_at)
+            .is_ok_and(|age| age < self.ttl)
+    }
+
+    /// The cached list and its metadata. A missing or unreadable cache is just a miss.
+    fn read(&self) -> Option<(String, CacheMetadata)> {
+        let csv = fs::read_to_string(self.csv_path()).ok()?;
+        let metadata = fs::read_to_string(self.metadata_path()).ok()?;
+        let metadata = serde_json::from_str(&metadata).ok()?;
+        Some((csv, metadata))
+    }
+
+    /// Caches `csv`. A cache that can't be written only costs a fetch next time, so
+    /// failures are logged rather than returned.
+    fn write(&self, csv: &str, etag: Option<String>, now: SystemTime) {
+        let metadata = CacheMetadata {
+            fetched_at: now
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since_epoch| since_epoch.as_secs()),
+            etag,
+        };
+        let write = || -> io::Result<()> {
+            fs::create_dir_all(&self.dir)?;
+            fs::write(self.csv_path(), csv)?;
+            fs::write(self.metadata_path(), serde_json::to_string(&metadata)?)
+        };
+        if let Err(err) = write() {
+            warn!(
+                "Couldn't cache the airport list in {}: {}",
+                self.dir.display(),
+                err
+            );
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use std::sync::Mutex;
+    use wiremock::{
+        matchers::{header, method},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    const CSV: &str = "iata,name,city,country,latitude,longitude\n\
+                       LHR,London Heathrow Airport,London,GB,51.4700,-0.4543\n";
+
+    const TTL: Duration = Duration::from_secs(30 * 24 * 60 * 60);
+
+    /// Answers with `response` and records the ETag each fetch was sent with.
+    struct FakeFetcher {
+        response: Result<FetchedAirports, String>,
+        fetches: Mutex<Vec<Option<String>>>,
+    }
+
+    impl FakeFetcher {
+        fn new(response: Result<FetchedAirports, String>) -> Self {
+            Self {
+                response,
+                fetches: Mutex::new(Vec::new()),
+            }
+        }
+
+        fn updated(csv: &str, etag: &str) -> Self {
+            Self::new(Ok(FetchedAirports::Updated {
+                csv: csv.to_string(),
+                etag: Some(etag.to_string()),
+            }))
+        }
+
+        fn fetches(&self) -> Vec<Option<String>> {
+            self.fetches.lock().unwrap().clone()
+        }
+    }
+
+    impl AirportFetcher for FakeFetcher {
+        async fn fetch(&self, etag: Option<&str>) -> Result<FetchedAirports, CliError> {
+            self.fetches.lock().unwrap().push(etag.map(String::from));
+            self.response.clone().map_err(CliError::ApiError)
+        }
+    }
+
+    fn days(days: u64) -> Duration {
+        Duration::from_secs(days * 24 * 60 * 60)
+    }
+
+    /// A cache in `dir` already holding `CSV`, fetched at `fetched_at`.
+    fn primed_cache(dir: &tempfile::TempDir, fetched_at: SystemTime) -> AirportCache {
+        let cache = AirportCache::new(dir.path().to_path_buf(), TTL);
+        cache.write(CSV, Some("\"v1\"".to_string()), fetched_at);
+        cache
+    }
+
+    #[tokio::test]
+    async fn test_cache_miss_fetches_and_caches() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = AirportCache::new(dir.path().join("cache"), TTL);
+        let fetcher = FakeFetcher::updated(CSV, "\"v1\"");
+        let now = SystemTime::now();
+
+        assert_eq!(cache.load(&fetcher, false, now).await.unwrap(), CSV);
+        assert_eq!(fetcher.fetches(), vec![None]);
+
+        // Now cached, so a second load doesn't fetch again
+        assert_eq!(cache.load(&fetcher, false, now).await.unwrap(), CSV);
+        assert_eq!(fetcher.fetches().len(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_fresh_cache_hit_does_not_fetch() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        let cache = primed_cache(&dir, now - days(29));
+        let fetcher = FakeFetcher::new(Err("offline".to_string()));
+
+        assert_eq!(cache.load(&fetcher, false, now).await.unwrap(), CSV);
+        assert!(fetcher.fetches().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_stale_cache_is_revalidated_with_its_etag() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        let cache = primed_cache(&dir, now - days(31));
+        let fetcher = FakeFetcher::new(Ok(FetchedAirports::NotModified));
+
+        assert_eq!(cache.load(&fetcher, false, now).await.unwrap(), CSV);
+        assert_eq!(fetcher.fetches(), vec![Some("\"v1\"".to_string())]);
+
+        // Revalidating resets the clock on the cached copy
+        let offline = FakeFetcher::new(Err("offline".to_string()));
+        cache.load(&offline, false, now).await.unwrap();
+        assert!(offline.fetches().is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_forced_refresh_fetches_the_whole_list() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        let cache = primed_cache(&dir, now);
+        let updated = format!(
+            "{}JFK,John F. Kennedy International Airport,New York,US,40.6413,-73.7781\n",
+            CSV
+        );
+        let fetcher = FakeFetcher::updated(&updated, "\"v2\"");
+
+        assert_eq!(cache.load(&fetcher, true, now).await.unwrap(), updated);
+        assert_eq!(fetcher.fetches(), vec![None]);
+        assert_eq!(cache.read().unwrap().1.etag.as_deref(), Some("\"v2\""));
+    }
+
+    #[tokio::test]
+    async fn test_stale_cache_is_used_when_the_fetch_fails() {
+        let dir = tempfile::tempdir().unwrap();
+        let now = SystemTime::now();
+        let cache = primed_cache(&dir, now - days(365));
+        let fetcher = FakeFetcher::new(Err("offline".to_string()));
+
+        assert_eq!(cache.load(&fetcher, true, now).await.unwrap(), CSV);
+    }
+
+    #[tokio::test]
+    async fn test_failed_fetch_without_a_cache_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = AirportCache::new(dir.path().to_path_buf(), TTL);
+        let fetcher = FakeFetcher::new(Err("offline".to_string()));
+
+        assert!(matches!(
+            cache.load(&fetcher, false, SystemTime::now()).await,
+            Err(CliError::ApiError(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_list_is_not_cached() {
+        let dir = tempfile::tempdir().unwrap();
+        let cache = AirportCache::new(dir.path().to_path_buf(), TTL);
+        let fetcher = FakeFetcher::updated("<html>Not Found</html>", "\"v1\"");
+
+        assert!(matches!(
+            cache.load(&fetcher, false, SystemTime::now()).await,
+            Err(CliError::InvalidInput(_))
+        ));
+        assert!(cache.read().is_none());
+    }
+
+    #[tokio::test]
+    async fn test_http_fetcher_revalidates_with_if_none_match() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(header("If-None-Match", "\"v1\""))
+            .respond_with(ResponseTemplate::new(304))
+            .mount(&server)
+            .await;
+        Mock::given(method("GET"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("ETag", "\"v1\"")
+                    .set_body_string(CSV),
+            )
+            .mount(&server)
+            .await;
+
+        let fetcher = HttpAirportFetcher::new(Client::new(), &server.uri());
+
+        assert_eq!(
+            fetcher.fetch(None).await.unwrap(),
+            FetchedAirports::Updated {
+                csv: CSV.to_string(),
+                etag: Some("\"v1\"".to_string()),
+            }
+        );
+        assert_eq!(
+            fetcher.fetch(Some("\"v1\"")).await.unwrap(),
+            FetchedAirports::NotModified
+        );
+    }
+}
@@ -0,0 +1,253 @@
+//! Airport code validation and lookup against the bundled airport list.
+
+use crate::error::CliError;
+use std::sync::OnceLock;
+
+pub fn is_valid_airport_code(code: &str) -> bool {
+    code.chars().all(|c| c.is_ascii_uppercase()) && code.len() == 3
+}
+
+/// Uppercases a typed airport code, so `lhr` and `Lhr` both become `LHR`. Anything that
+/// isn't three letters gives `None`.
+pub fn normalize_airport_code(code: &str) -> Option<String> {
+    let code = code.trim().to_ascii_uppercase();
+    is_valid_airport_code(&code).then_some(code)
+}
+
+/// Bundled `iata,name,city,country,latitude,longitude` rows for the world's major airports.
+pub const AIRPORTS_CSV: &str = include_str!("../data/airports.csv");
+
+/// A downloaded list used instead of `AIRPORTS_CSV`; see `use_airport_data`.
+static AIRPORT_DATA: OnceLock<String> = OnceLock::new();
+
+/// Looks airports up in `csv`, a list in the bundled format, instead of the bundled list
+/// for the rest of the run. Only the first call has any effect.
+pub fn use_airport_data(csv: String) {
+    let _ = AIRPORT_DATA.set(csv);
+}
+
+/// The airport list lookups use: a downloaded one if there is one, else the bundled one.
+fn airport_csv() -> &'static str {
+    AIRPORT_DATA.get().map_or(AIRPORTS_CSV, String::as_str)
+}
+
+/// Checks that a downloaded airport list has the bundled list's columns and at least one
+/// usable row, so a stray error page isn't mistaken for it.
+pub fn check_airport_csv(csv: &str) -> Result<(), CliError> {
+    let mut lines = csv.lines();
+    let header = AIRPORTS_CSV.lines().next();
+    if lines.next().map(str::trim) != header
+        || !lines.any(|line| Airport::from_csv_row(line).is_some())
+    {
+        return Err(CliError::InvalidInput(format!(
+            "the airport list isn't a CSV with {} columns and at least one airport",
+            header.unwrap_or_default()
+        )));
+    }
+    Ok(())
+}
+
+/// Returns whether `code` belongs to an airport in the airport list.
+pub fn is_known_iata(code: &str) -> bool {
+    airport_csv()
+        .lines()
+        .skip(1)
+        .any(|line| line.split(',').next() == Some(code))
+}
+
+/// One row of the bundled airport list.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Airport {
+    pub iata: String,
+    pub name: String,
+    pub city: String,
+    /// ISO 3166-1 alpha-2 country code
+    pub country: String,
+    pub latitude: f64,
+    pub longitude: f64,
+}
+
+impl Airport {
+    fn from_csv_row(line: &str) -> Option<Self> {
+        let fields: Vec<&str> = line.split(',').collect();
+        let [iata, name, city, country, latitude, longitude] = fields.as_slice() else {
+            return None;
+        };
+        Some(Self {
+            iata: iata.to_string(),
+            name: name.to_string(),
+            city: city.to_string(),
+            country: country.to_string(),
+            latitude: latitude.parse().ok()?,
+            longitude: longitude.parse().ok()?,
+        })
+    }
+}
+
+/// Every airport in the airport list, in file order.
+pub fn airports() -> impl Iterator<Item = Airport> {
+    airport_csv()
+        .lines()
+        .skip(1)
+        .filter_map(Airport::from_csv_row)
+}
+
+/// Looks up an airport in the bundled list by its IATA code.
+pub fn find_airport(code: &str) -> Option<Airport> {
+    airports().find(|airport| airport.iata == code)
+}
+
+/// Metropolitan area codes, which cover every airport in a city, and the main airport
+/// each one is estimated from.
+const METRO_CODES: &[(&str, &str)] = &[
+    ("BJS", "PEK"),
+    ("BUE", "EZE"),
+    ("CHI", "ORD"),
+    ("DTT", "DTW"),
+    ("JKT", "CGK"),
+    ("LON", "LHR"),
+    ("MIL", "MXP"),
+    ("MOW", "SVO"),
+    ("NYC", "JFK"),
+    ("OSA", "KIX"),
+    ("PAR", "CDG"),
+    ("RIO", "GIG"),
+    ("ROM", "FCO"),
+    ("SAO", "GRU"),
+    ("SEL", "ICN"),
+    ("STO", "ARN"),
+    ("TYO", "HND"),
+    ("WAS", "IAD"),
+    ("YTO", "YYZ"),
+];
+
+/// The airport to use for a metropolitan area code such as `LON`, or `None` for anything
+/// else, including ordinary airport codes.
+pub fn resolve_metro_code(code: &str) -> Option<&'static str> {
+    METRO_CODES
+        .iter()
+        .find(|(metro, _)| *metro == code)
+        .map(|(_, airport)| *airport)
+}
+
+/// Finds airports whose IATA code, city or name matches `query`, ignoring case.
+///
+/// An exact code or city match ranks first, then cities starting with the query, then
+/// any city or airport name that contains it.
+pub fn search_airports(query: &str) -> Vec<Airport> {
+    let query = query.trim().to_lowercase();
+    if query.is_empty() {
+        return Vec::new();
+    }
+
+    let mut matches: Vec<(u8, Airport)> = airports()
+        .filter_map(|airport| {
+            let city = airport.city.to_lowercase();
+            let rank = if airport.iata.to_lowercase() == query || city == query {
+                0
+            } else if city.starts_with(&query) {
+                1
+            } else if city.contains(&query) || airport.name.to_lowercase().contains(&query) {
+                2
+            } else {
+                return None;
+            };
+            Some((rank, airport))
+        })
+        .collect();
+    matches.sort_by_key(|(rank, _)| *rank);
+    matches.into_iter().map(|(_, airport)| airport).collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_normalize_airport_code_uppercases_mixed_case() {
+        assert_eq!(normalize_airport_code("lhr").as_deref(), Some("LHR"));
+        assert_eq!(normalize_airport_code("Lhr").as_deref(), Some("LHR"));
+        assert_eq!(normalize_airport_code(" JFK ").as_deref(), Some("JFK"));
+    }
+
+    #[test]
+    fn test_normalize_airport_code_rejects_non_letters() {
+        assert_eq!(normalize_airport_code("L1R"), None);
+        assert_eq!(normalize_airport_code("LH-"), None);
+        assert_eq!(normalize_airport_code("LHRX"), None);
+        assert_eq!(normalize_airport_code("ÉLH"), None);
+    }
+
+    #[test]
+    fn test_is_known_iata() {
+        assert!(is_known_iata("LHR"));
+        assert!(is_known_iata("JFK"));
+        assert!(!is_known_iata("ZZZ"));
+        assert!(!is_known_iata("iata"));
+    }
+
+    fn codes(airports: Vec<Airport>) -> Vec<String> {
+        airports.into_iter().map(|airport| airport.iata).collect()
+    }
+
+    #[test]
+    fn test_airports_parses_every_row() {
+        let heathrow = airports().find(|airport| airport.iata == "LHR").unwrap();
+
+        assert_eq!(airports().count(), AIRPORTS_CSV.lines().count() - 1);
+        assert_eq!(heathrow.city, "London");
+        assert_eq!(heathrow.country, "GB");
+        assert!((heathrow.latitude - 51.47).abs() < 1e-6);
+    }
+
+    #[test]
+    fn test_check_airport_csv() {
+        assert!(check_airport_csv(AIRPORTS_CSV).is_ok());
+        assert!(check_airport_csv("iata,name,city,country,latitude,longitude\n").is_err());
+        assert!(check_airport_csv("<html><body>Not Found</body></html>").is_err());
+    }
+
+    #[test]
+    fn test_resolve_metro_code() {
+        assert_eq!(resolve_metro_code("LON"), Some("LHR"));
+        assert_eq!(resolve_metro_code("NYC"), Some("JFK"));
+        assert_eq!(resolve_metro_code("TYO"), Some("HND"));
+        assert_eq!(resolve_metro_code("PAR"), Some("CDG"));
+        assert_eq!(resolve_metro_code("LHR"), None);
+    }
+
+    #[test]
+    fn test_metro_codes_resolve_to_bundled_airports() {
+        for (metro, airport) in METRO_CODES {
+            assert!(!is_known_iata(metro), "{} is an airport", metro);
+            assert!(is_known_iata(airport), "{} isn't bundled", airport);
+        }
+    }
+
+    #[test]
+    fn test_find_airport() {
+        assert_eq!(find_airport("CDG").unwrap().city, "Paris");
+        assert_eq!(find_airport("ZZZ"), None);
+    }
+
+    #[test]
+    fn test_search_airports_by_city() {
+        let london = codes(search_airports("london"));
+
+        assert_eq!(london, ["LHR", "LGW", "STN", "LTN", "LCY"]);
+        assert_eq!(codes(search_airports("Paris")), ["CDG", "ORY"]);
+    }
+
+    #[test]
+    fn test_search_airports_by_partial_name_or_code() {
+        assert_eq!(codes(search_airports("heathrow")), ["LHR"]);
+        assert_eq!(codes(search_airports("lhr")), ["LHR"]);
+        assert_eq!(codes(search_airports("laguardia")), ["LGA"]);
+    }
+
+    #[test]
+    fn test_search_airports_without_matches() {
+        assert!(search_airports("atlantis").is_empty());
+        assert!(search_airports("  ").is_empty());
+    }
+}
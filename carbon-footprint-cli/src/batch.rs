@@ -0,0 +1,1307 @@
+//! Estimating many requests read from a single JSON, JSON Lines or YAML file.
+
+use crate::client::{attributes_of, make_estimates_request, EstimateBackend};
+use crate::error::{CliError, RequestError};
+use crate::estimate_cache::{request_cache_key, EstimateCache};
+use crate::footprint::{combine_cabin_estimates, sum_attributes};
+use crate::model::{
+    is_missing, split_by_cabin, split_by_leg_passengers, split_legs, EstimateAttributes,
+    EstimateResult, FlightEstimateRequest, ItineraryGroup, Leg, MixedCabinLeg,
+};
+use crate::output::route_stops;
+use clap::ValueEnum;
+use futures::stream::{self, Stream, StreamExt};
+use schemars::{schema_for, JsonSchema};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashSet;
+use std::io::BufRead;
+use std::path::Path;
+use std::pin::pin;
+use std::time::Duration;
+use tokio::time::timeout;
+
+/// The formats a batch file can be written in.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum InputFormat {
+    /// A JSON array of requests
+    Json,
+    /// A YAML sequence of requests, which may have comments
+    Yaml,
+    /// One JSON request per line, read a line at a time
+    Jsonl,
+}
+
+/// The format a batch file's extension says it's in: YAML for `.yaml` and `.yml`, JSON
+/// Lines for `.jsonl` and `.ndjson`, JSON for anything else.
+pub fn input_format_from_path(path: &Path) -> InputFormat {
+    let extension = path
+        .extension()
+        .and_then(|extension| extension.to_str())
+        .map(str::to_ascii_lowercase);
+    match extension.as_deref() {
+        Some("yaml" | "yml") => InputFormat::Yaml,
+        Some("jsonl" | "ndjson") => InputFormat::Jsonl,
+        _ => InputFormat::Json,
+    }
+}
+
+/// A batch entry: a request, optionally labelled with the itinerary it belongs to.
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug)]
+pub struct BatchEntry {
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub itinerary: Option<String>,
+    #[serde(flatten)]
+    pub request: FlightEstimateRequest,
+}
+
+/// A JSON Schema for batch files, for validating them before they're submitted.
+pub fn batch_schema() -> serde_json::Value {
+    let mut schema = schema_for!(Vec<BatchEntry>);
+    schema.insert("title".to_string(), "Carbon footprint batch input".into());
+    schema.to_value()
+}
+
+/// A couple of realistic trips to start a batch file from: two ways to fly London to
+/// Tokyo, and a domestic round trip with a leg of its own passenger count.
+pub fn sample_batch() -> Vec<BatchEntry> {
+    let leg = |departure: &str, destination: &str, cabin_class: Option<&str>| Leg {
+        departure_airport: departure.to_string(),
+        destination_airport: destination.to_string(),
+        cabin_class: cabin_class.map(String::from),
+        passengers: None,
+        aircraft: None,
+    };
+    let entry =
+        |itinerary: &str, passengers: u32, legs: Vec<Leg>, distance_unit: &str| BatchEntry {
+            itinerary: Some(itinerary.to_string()),
+            request: FlightEstimateRequest {
+                estimate_type: "flight".to_string(),
+                passengers,
+                legs,
+                distance_unit: Some(distance_unit.to_string()),
+            },
+        };
+    vec![
+        entry(
+            "Tokyo via Helsinki",
+            2,
+            vec![
+                leg("LHR", "HEL", Some("economy")),
+                leg("HEL", "NRT", Some("economy")),
+            ],
+            "km",
+        ),
+        entry(
+            "Tokyo direct",
+            2,
+            vec![Leg {
+                aircraft: Some("B789".to_string()),
+                ..leg("LHR", "HND", Some("economy"))
+            }],
+            "km",
+        ),
+        entry(
+            "Coast to coast",
+            1,
+            vec![
+                leg("JFK", "LAX", Some("business")),
+                Leg {
+                    passengers: Some(2),
+                    ..leg("LAX", "JFK", None)
+                },
+            ],
+            "mi",
+        ),
+    ]
+}
+
+/// `sample_batch` written out as a batch file in `format`.
+pub fn render_sample_batch(format: InputFormat) -> String {
+    let sample = sample_batch();
+    match format {
+        InputFormat::Json => {
+            serde_json::to_string_pretty(&sample).expect("batch entries always serialize to JSON")
+        }
+        InputFormat::Yaml => {
+            serde_yaml::to_string(&sample).expect("batch entries always serialize to YAML")
+        }
+        InputFormat::Jsonl => sample
+            .iter()
+            .map(|entry| {
+                let line =
+                    serde_json::to_string(entry).expect("batch entries always serialize to JSON");
+                line + "\n"
+            })
+            .collect(),
+    }
+}
+
+/// Parses a JSON array of requests. Entries that don't describe a valid request are
+/// returned as errors in their position so the rest of the batch can still run.
+pub fn parse_batch(contents: &str) -> Result<Vec<Result<FlightEstimateRequest, String>>, CliError> {
+    Ok(parse_batch_entries(contents)?
+        .into_iter()
+        .map(|entry| entry.map(|entry| entry.request))
+        .collect())
+}
+
+/// Like `parse_batch`, keeping each entry's `itinerary` label.
+pub fn parse_batch_entries(contents: &str) -> Result<Vec<Result<BatchEntry, String>>, CliError> {
+    parse_batch_entries_as(contents, InputFormat::Json)
+}
+
+/// Like `parse_batch_entries`, for a batch written in `format`. Entries are read the same
+/// way whichever format they're in.
+pub fn parse_batch_entries_as(
+    contents: &str,
+    format: InputFormat,
+) -> Result<Vec<Result<BatchEntry, String>>, CliError> {
+    let entries: Vec<serde_json::Value> = match format {
+        InputFormat::Jsonl => {
+            return Ok(parse_json_lines(contents.as_bytes())
+                .map(|(_, entry)| entry)
+                .collect())
+        }
+        InputFormat::Json => serde_json::from_str(contents).map_err(|err| {
+            CliError::InvalidInput(format!("expected a JSON array of requests: {}", err))
+        })?,
+        InputFormat::Yaml => serde_yaml::from_str(contents).map_err(|err| {
+            CliError::InvalidInput(format!("expected a YAML sequence of requests: {}", err))
+        })?,
+    };
+
+    Ok(entries
+        .into_iter()
+        .map(|entry| checked_entry(serde_json::from_value(entry)))
+        .collect())
+}
+
+/// Reads a JSON Lines batch from `reader` a line at a time, giving each entry with its
+/// line number, from 1. Blank lines are skipped, and a line that can't be read ends the
+/// batch with an error for it.
+pub fn parse_json_lines(
+    reader: impl BufRead,
+) -> impl Iterator<Item = (usize, Result<BatchEntry, String>)> {
+    let mut unreadable = false;
+    reader
+        .lines()
+        .enumerate()
+        .map_while(move |(index, line)| {
+            if unreadable {
+                return None;
+            }
+            let entry = match line {
+                Ok(line) if line.trim().is_empty() => return Some(None),
+                Ok(line) => checked_entry(serde_json::from_str(&line)),
+                Err(err) => {
+                    unreadable = true;
+                    Err(format!("could not be read: {}", err))
+                }
+            };
+            Some(Some((index + 1, entry)))
+        })
+        .flatten()
+}
+
+/// An entry that parsed, if it has legs to estimate.
+fn checked_entry(entry: serde_json::Result<BatchEntry>) -> Result<BatchEntry, String> {
+    let entry = entry.map_err(|err| err.to_string())?;
+    entry
+        .request
+        .check_has_legs()
+        .map_err(|err| err.to_string())?;
+    Ok(entry)
+}
+
+/// Gathers labelled estimates into their itineraries, in the order each label first
+/// appears. An unlabelled estimate is an itinerary of its own, named after its route.
+pub fn group_by_itinerary(estimates: Vec<(Option<String>, EstimateResult)>) -> Vec<ItineraryGroup> {
+    let mut groups: Vec<ItineraryGroup> = Vec::new();
+    for (itinerary, estimate) in estimates {
+        let existing = itinerary
+            .as_ref()
+            .and_then(|name| groups.iter_mut().find(|group| &group.name == name));
+        match existing {
+            Some(group) => group.estimates.push(estimate),
+            None => groups.push(ItineraryGroup {
+                name: itinerary.unwrap_or_else(|| route_stops(&estimate.request.legs).join(">")),
+                estimates: vec![estimate],
+            }),
+        }
+    }
+    groups
+}
+
+/// Keeps the labelled estimates whose carbon is at least `min_kg` and at most `max_kg`,
+/// either bound being inclusive and optional, returning them with how many were dropped.
+/// Estimates without a `carbon_kg` can't be placed in the range, so they're kept, and
+/// counted last when there was a bound to check.
+pub fn filter_by_carbon(
+    estimates: Vec<(Option<String>, EstimateResult)>,
+    min_kg: Option<f32>,
+    max_kg: Option<f32>,
+) -> (Vec<(Option<String>, EstimateResult)>, usize, usize) {
+    let total = estimates.len();
+    let bounded = min_kg.is_some() || max_kg.is_some();
+    let mut unknown = 0;
+    let kept: Vec<_> = estimates
+        .into_iter()
+        .filter(|(_, estimate)| {
+            let carbon_kg = estimate.attributes.carbon_kg;
+            if is_missing(carbon_kg) {
+                unknown += usize::from(bounded);
+                return true;
+            }
+            min_kg.is_none_or(|min_kg| carbon_kg >= min_kg)
+                && max_kg.is_none_or(|max_kg| carbon_kg <= max_kg)
+        })
+        .collect();
+    let dropped = total - kept.len();
+    (kept, dropped, unknown)
+}
+
+/// How many batch requests may be in flight at once, unless overridden with `--concurrency`.
+pub const DEFAULT_CONCURRENCY: usize = 5;
+
+/// Submits up to `concurrency` requests at a time, returning one result per request in the
+/// order the requests were given. A failed request doesn't stop the others.
+pub async fn estimate_batch(
+    api_client: &impl EstimateBackend,
+    requests: Vec<FlightEstimateRequest>,
+    api_key: &str,
+    concurrency: usize,
+) -> Vec<Result<EstimateResult, CliError>> {
+    let cache = EstimateCache::in_memory();
+    estimate_batch_cached(api_client, requests, api_key, concurrency, &cache).await
+}
+
+/// Like `estimate_batch`, reusing the estimates in `cache` and adding new ones to it. A
+/// request repeated within the batch is only sent once: its repeats wait for it and are
+/// answered from the cache, or sent again if it failed.
+pub async fn estimate_batch_cached(
+    api_client: &impl EstimateBackend,
+    requests: Vec<FlightEstimateRequest>,
+    api_key: &str,
+    concurrency: usize,
+    cache: &EstimateCache,
+) -> Vec<Result<EstimateResult, CliError>> {
+    estimate_batch_within(api_client, requests, api_key, concurrency, cache, None).await
+}
+
+/// Like `estimate_batch_cached`, giving up on the whole batch once `deadline` has passed,
+/// retries included. Requests still in flight then are cancelled, and they and any not
+/// yet sent fail with `CliError::DeadlineExceeded`.
+pub async fn estimate_batch_within(
+    api_client: &impl EstimateBackend,
+    requests: Vec<FlightEstimateRequest>,
+    api_key: &str,
+    concurrency: usize,
+    cache: &EstimateCache,
+    deadline: Option<Duration>,
+) -> Vec<Result<EstimateResult, CliError>> {
+    estimate_batch_streaming(
+        api_client,
+        requests,
+        api_key,
+        concurrency,
+        cache,
+        deadline,
+        false,
+        |_, _| {},
+    )
+    .await
+}
+
+/// Like `estimate_batch_within`, handing each result to `on_result` with its position as
+/// soon as it's known, so a long batch can be shown as it goes. With `ordered` they're
+/// handed over in batch order, each once every earlier one is in; otherwise as they
+/// complete. All the results are still returned at the end, in batch order.
+#[allow(clippy::too_many_arguments)]
+pub async fn estimate_batch_streaming(
+    api_client: &impl EstimateBackend,
+    requests: Vec<FlightEstimateRequest>,
+    api_key: &str,
+    concurrency: usize,
+    cache: &EstimateCache,
+    deadline: Option<Duration>,
+    ordered: bool,
+    mut on_result: impl FnMut(usize, &Result<EstimateResult, CliError>),
+) -> Vec<Result<EstimateResult, CliError>> {
+    let total = requests.len();
+    let (firsts, repeats) = split_repeats(requests);
+    let mut completed: Vec<Option<Result<EstimateResult, CliError>>> =
+        (0..total).map(|_| None).collect();
+    // In ordered mode, the position of the next result to hand over
+    let mut next = 0;
+    let work = async {
+        for requests in [firsts, repeats] {
+            let mut pending = pin!(estimate_each(
+                api_client,
+                requests,
+                api_key,
+                concurrency,
+                cache
+            ));
+            // Responses arrive in whatever order the API answers them
+            while let Some((index, result)) = pending.next().await {
+                if !ordered {
+                    on_result(index, &result);
+                }
+                completed[index] = Some(result);
+                while let Some(Some(result)) = completed.get(next).filter(|_| ordered) {
+                    on_result(next, result);
+                    next += 1;
+                }
+            }
+        }
+    };
+    match deadline {
+        Some(deadline) => {
+            // Whatever finished in time is already in `completed`
+            let _ = timeout(deadline, work).await;
+        }
+        None => work.await,
+    }
+
+    // Hand over what the deadline cut short, and in ordered mode what was waiting on it
+    let mut results = Vec::with_capacity(total);
+    for (index, result) in completed.into_iter().enumerate() {
+        let result = match result {
+            Some(result) => {
+                if ordered && index >= next {
+                    on_result(index, &result);
+                }
+                result
+            }
+            None => {
+                let skipped = Err(CliError::DeadlineExceeded(deadline.unwrap_or_default()));
+                on_result(index, &skipped);
+                skipped
+            }
+        };
+        results.push(result);
+    }
+    results
+}
+
+/// Like `estimate_batch_cached`, but stops at the first failed request: nothing more is
+/// sent, and the error comes back with the failed request's position in `requests`.
+pub async fn estimate_batch_fail_fast(
+    api_client: &impl EstimateBackend,
+    requests: Vec<FlightEstimateRequest>,
+    api_key: &str,
+    concurrency: usize,
+    cache: &EstimateCache,
+) -> Result<Vec<EstimateResult>, RequestError> {
+    let routes: Vec<String> = requests.iter().map(route_of).collect();
+    let (firsts, repeats) = split_repeats(requests);
+    let mut completed = Vec::new();
+    for requests in [firsts, repeats] {
+        let mut pending = pin!(estimate_each(
+            api_client,
+            requests,
+            api_key,
+            concurrency,
+            cache
+        ));
+        while let Some((index, result)) = pending.next().await {
+            let estimate = result.map_err(|error| RequestError {
+                index,
+                route: routes[index].clone(),
+                error,
+            })?;
+            completed.push((index, estimate));
+        }
+    }
+    completed.sort_by_key(|(index, _)| *index);
+    Ok(completed
+        .into_iter()
+        .map(|(_, estimate)| estimate)
+        .collect())
+}
+
+/// Requests paired with their positions in the batch.
+type Positioned = Vec<(usize, FlightEstimateRequest)>;
+
+/// Splits `requests` into the first of each distinct request and the repeats of them.
+fn split_repeats(requests: Vec<FlightEstimateRequest>) -> (Positioned, Positioned) {
+    let mut seen = HashSet::new();
+    requests
+        .into_iter()
+        .enumerate()
+        .partition(|(_, request)| seen.insert(request_cache_key(request)))
+}
+
+/// Estimates each of `requests`, up to `concurrency` at a time, yielding them with their
+/// positions as they complete.
+fn estimate_each<'a>(
+    api_client: &'a impl EstimateBackend,
+    requests: Positioned,
+    api_key: &'a str,
+    concurrency: usize,
+    cache: &'a EstimateCache,
+) -> impl Stream<Item = (usize, Result<EstimateResult, CliError>)> + 'a {
+    stream::iter(requests)
+        .map(move |(index, request)| async move {
+            (
+                index,
+                estimate_one(api_client, request, api_key, cache).await,
+            )
+        })
+        .buffer_unordered(concurrency.max(1))
+}
+
+/// Estimates `request`, sending legs with their own passenger counts separately and
+/// adding the results up. Any failed part fails the estimate, since a partial total
+/// would understate the trip.
+async fn estimate_one(
+    api_client: &impl EstimateBackend,
+    request: FlightEstimateRequest,
+    api_key: &str,
+    cache: &EstimateCache,
+) -> Result<EstimateResult, CliError> {
+    let mut parts = Vec::new();
+    for part in split_by_leg_passengers(&request) {
+        parts.push(estimate_part(api_client, &part, api_key, cache).await?);
+    }
+    let attributes = match parts.as_slice() {
+        [attributes] => attributes.clone(),
+        _ => sum_attributes(&parts),
+    };
+    Ok(EstimateResult {
+        request,
+        attributes,
+    })
+}
+
+async fn estimate_part(
+    api_client: &impl EstimateBackend,
+    request: &FlightEstimateRequest,
+    api_key: &str,
+    cache: &EstimateCache,
+) -> Result<EstimateAttributes, CliError> {
+    if let Some(attributes) = cache.get(request) {
+        return Ok(attributes);
+    }
+    let response = make_estimates_request(api_client, request, api_key).await?;
+    let attributes = attributes_of(response)?;
+    cache.insert(request, &attributes);
+    Ok(attributes)
+}
+
+/// A batch request that failed, with its index in the batch file and its route.
+#[derive(Clone, Debug, PartialEq)]
+pub struct BatchFailure {
+    pub index: usize,
+    pub route: String,
+    pub error: String,
+}
+
+/// "N of M succeeded", followed by a line for each failure giving its index, route and
+/// error.
+pub fn batch_summary(total: usize, failures: &[BatchFailure]) -> String {
+    let mut summary = format!("{} of {} succeeded", total - failures.len(), total);
+    for failure in failures {
+        summary.push_str(&format!(
+            "\n  index {} ({}): {}",
+            failure.index, failure.route, failure.error
+        ));
+    }
+    summary
+}
+
+/// A request's stops as error messages name it, e.g. `LHR → JFK → LAX`.
+pub fn route_of(request: &FlightEstimateRequest) -> String {
+    route_stops(&request.legs).join(" → ")
+}
+
+/// Estimates legs with passengers in several cabins, sending each class as its own
+/// request and adding the results up with `combine_cabin_estimates`. Any failed request
+/// fails the whole estimate, since a partial total would understate the trip.
+pub async fn estimate_mixed_cabins(
+    api_client: &impl EstimateBackend,
+    legs: &[MixedCabinLeg],
+    distance_unit: Option<&str>,
+    api_key: &str,
+    concurrency: usize,
+) -> Result<EstimateAttributes, CliError> {
+    let requests = split_by_cabin(legs, distance_unit);
+    let results = estimate_batch(api_client, requests, api_key, concurrency).await;
+
+    let estimates = results
+        .into_iter()
+        .map(|result| result.map(|estimate| estimate.attributes))
+        .collect::<Result<Vec<_>, _>>()?;
+    Ok(combine_cabin_estimates(legs, estimates))
+}
+
+/// Estimates each leg of `request` on its own, returning one result per leg in order. The
+/// API only totals a whole trip, so this costs a request per leg; any failure fails the
+/// breakdown, naming the leg.
+pub async fn estimate_per_leg(
+    api_client: &impl EstimateBackend,
+    request: &FlightEstimateRequest,
+    api_key: &str,
+    concurrency: usize,
+) -> Result<Vec<EstimateResult>, CliError> {
+    let legs = split_legs(request);
+    let routes: Vec<String> = legs.iter().map(route_of).collect();
+    estimate_batch(api_client, legs, api_key, concurrency)
+        .await
+        .into_iter()
+        .zip(routes)
+        .enumerate()
+        .map(|(index, (result, route))| {
+            result.map_err(|error| {
+                CliError::InRequest(Box::new(RequestError {
+                    index,
+                    route,
+                    error,
+                }))
+            })
+        })
+        .collect()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::client::ApiClient;
+    use crate::footprint::sum_attributes;
+    use crate::model::{CabinPassengers, MISSING_FIGURE};
+    use crate::test_support::{create_mock_response, create_request};
+    use crate::FlightEstimateResponse;
+    use reqwest::Client;
+    use std::time::Duration;
+    use wiremock::{
+        matchers::{body_string_contains, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    #[tokio::test]
+    async fn test_leg_with_its_own_passengers_is_added_to_the_rest() {
+        let server = MockServer::start().await;
+        let shared = create_mock_response(1000000.0, 2204.6, 1000.0, 1.0, "km", 5555.0);
+        let joined = create_mock_response(500000.0, 1102.3, 500.0, 0.5, "km", 3983.0);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(body_string_contains(r#""passengers":2"#))
+            .and(body_string_contains("LHR"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&shared))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(body_string_contains(r#""passengers":3"#))
+            .and(body_string_contains("LAX"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&joined))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let mut request = create_request(2, &[("LHR", "JFK"), ("JFK", "LAX")]);
+        request.legs[1].passengers = Some(3);
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        let results = estimate_batch(&api_client, vec![request.clone()], "", 1).await;
+
+        let estimate = results[0].as_ref().unwrap();
+        assert_eq!(estimate.request, request);
+        assert_eq!(estimate.attributes.carbon_kg, 1500.0);
+        assert_eq!(estimate.attributes.distance_value, 9538.0);
+    }
+
+    #[tokio::test]
+    async fn test_mixed_cabin_leg_adds_up_each_class() {
+        let server = MockServer::start().await;
+        let economy = create_mock_response(300000.0, 661.4, 300.0, 0.3, "km", 5555.0);
+        let business = create_mock_response(870000.0, 1918.0, 870.0, 0.87, "km", 5555.0);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(body_string_contains(r#""cabin_class":"economy""#))
+            .and(body_string_contains(r#""passengers":3"#))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&economy))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(body_string_contains(r#""cabin_class":"business""#))
+            .and(body_string_contains(r#""passengers":1"#))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&business))
+            .expect(1)
+            .mount(&server)
+            .await;
+        let legs = vec![MixedCabinLeg {
+            departure_airport: "LHR".to_string(),
+            destination_airport: "JFK".to_string(),
+            cabins: vec![
+                CabinPassengers {
+                    cabin_class: "economy".to_string(),
+                    passengers: 3,
+                },
+                CabinPassengers {
+                    cabin_class: "business".to_string(),
+                    passengers: 1,
+                },
+            ],
+        }];
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        let total = estimate_mixed_cabins(&api_client, &legs, None, "", 2)
+            .await
+            .unwrap();
+
+        assert_eq!(total.carbon_kg, 1170.0);
+        assert_eq!(total.distance_value, 5555.0);
+        assert_eq!(total.distance_unit, "km");
+    }
+
+    #[tokio::test]
+    async fn test_mixed_cabin_estimate_fails_if_a_class_fails() {
+        let server = MockServer::start().await;
+        let economy = create_mock_response(300000.0, 661.4, 300.0, 0.3, "km", 5555.0);
+        Mock::given(method("POST"))
+            .and(body_string_contains("economy"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&economy))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(body_string_contains("first"))
+            .respond_with(
+                ResponseTemplate::new(422).set_body_json(FlightEstimateResponse {
+                    message: Some("Validation failed".to_string()),
+                    ..Default::default()
+                }),
+            )
+            .mount(&server)
+            .await;
+        let cabin = |cabin_class: &str| CabinPassengers {
+            cabin_class: cabin_class.to_string(),
+            passengers: 1,
+        };
+        let legs = vec![MixedCabinLeg {
+            departure_airport: "LHR".to_string(),
+            destination_airport: "JFK".to_string(),
+            cabins: vec![cabin("economy"), cabin("first")],
+        }];
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        let result = estimate_mixed_cabins(&api_client, &legs, None, "", 2).await;
+
+        assert!(matches!(result, Err(CliError::ValidationError(_))));
+    }
+
+    #[tokio::test]
+    async fn test_per_leg_breakdown_adds_up_to_the_trip() {
+        let server = MockServer::start().await;
+        let lhr_jfk = create_mock_response(400000.0, 881.8, 400.0, 0.4, "km", 5555.0);
+        let jfk_lax = create_mock_response(250000.0, 551.2, 250.0, 0.25, "km", 3983.0);
+        for (departure, response) in [("LHR", &lhr_jfk), ("JFK", &jfk_lax)] {
+            Mock::given(method("POST"))
+                .and(path("/api/v1/estimates"))
+                .and(body_string_contains(format!(
+                    r#""departure_airport":"{}""#,
+                    departure
+                )))
+                .respond_with(ResponseTemplate::new(200).set_body_json(response))
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        let request = create_request(2, &[("LHR", "JFK"), ("JFK", "LAX")]);
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        let legs = estimate_per_leg(&api_client, &request, "", DEFAULT_CONCURRENCY)
+            .await
+            .unwrap();
+
+        assert_eq!(legs.len(), 2);
+        assert_eq!(legs[0].request.legs[0].departure_airport, "LHR");
+        assert_eq!(legs[0].attributes.carbon_kg, 400.0);
+        assert_eq!(legs[1].request.legs[0].departure_airport, "JFK");
+        assert_eq!(legs[1].attributes.carbon_kg, 250.0);
+        let attributes: Vec<EstimateAttributes> =
+            legs.iter().map(|leg| leg.attributes.clone()).collect();
+        let total = sum_attributes(&attributes);
+        assert_eq!(total.carbon_kg, 650.0);
+        assert_eq!(total.distance_value, 9538.0);
+    }
+
+    #[tokio::test]
+    async fn test_per_leg_failure_names_the_leg() {
+        let server = MockServer::start().await;
+        let lhr_jfk = create_mock_response(400000.0, 881.8, 400.0, 0.4, "km", 5555.0);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(body_string_contains("LHR"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&lhr_jfk))
+            .mount(&server)
+            .await;
+        let error_response = FlightEstimateResponse {
+            message: Some("Validation failed: Legs require valid airport codes".to_string()),
+            ..Default::default()
+        };
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(body_string_contains("XYZ"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(&error_response))
+            .mount(&server)
+            .await;
+        let request = create_request(1, &[("LHR", "JFK"), ("JFK", "XYZ")]);
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        let err = estimate_per_leg(&api_client, &request, "", DEFAULT_CONCURRENCY)
+            .await
+            .unwrap_err();
+
+        assert!(err
+            .to_string()
+            .starts_with("index 1 (JFK → XYZ): API error: Validation failed"));
+        assert!(
+            matches!(&err, CliError::InRequest(failed) if matches!(failed.error, CliError::ApiError(_)))
+        );
+    }
+
+    #[tokio::test]
+    async fn test_estimate_batch_processes_every_entry() {
+        let server = MockServer::start().await;
+        let lhr_jfk = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        let cdg_nrt = create_mock_response(150000000.0, 330693.3, 150000.0, 150.0, "km", 9712.0);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(body_string_contains("LHR"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&lhr_jfk))
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(body_string_contains("CDG"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&cdg_nrt))
+            .mount(&server)
+            .await;
+
+        let batch = r#"[
+            {"passengers": 1, "legs": [{"departure_airport": "LHR", "destination_airport": "JFK"}]},
+            {"type": "flight", "passengers": 2, "legs": [{"departure_airport": "CDG", "destination_airport": "NRT"}]}
+        ]"#;
+        let requests: Vec<FlightEstimateRequest> = parse_batch(batch)
+            .unwrap()
+            .into_iter()
+            .map(Result::unwrap)
+            .collect();
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        let results = estimate_batch(&api_client, requests, "", DEFAULT_CONCURRENCY).await;
+
+        assert_eq!(results.len(), 2);
+        let first = results[0].as_ref().unwrap();
+        assert_eq!(first.request.legs[0].departure_airport, "LHR");
+        assert_eq!(first.attributes.carbon_kg, 99911.7);
+        let second = results[1].as_ref().unwrap();
+        assert_eq!(second.request.passengers, 2);
+        assert_eq!(second.attributes.carbon_kg, 150000.0);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_batch_concurrently_keeps_input_order() {
+        let server = MockServer::start().await;
+        // The earliest requests answer last, so completion order differs from input order
+        let routes = [
+            ("LHR", 400, 1000.0),
+            ("CDG", 200, 2000.0),
+            ("SYD", 0, 3000.0),
+        ];
+        for (departure, delay_ms, carbon_kg) in routes {
+            let response = create_mock_response(
+                carbon_kg * 1000.0,
+                carbon_kg * 2.2,
+                carbon_kg,
+                carbon_kg / 1000.0,
+                "km",
+                1000.0,
+            );
+            Mock::given(method("POST"))
+                .and(path("/api/v1/estimates"))
+                .and(body_string_contains(departure))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_json(&response)
+                        .set_delay(Duration::from_millis(delay_ms)),
+                )
+                .expect(1)
+                .mount(&server)
+                .await;
+        }
+        let error_response = FlightEstimateResponse {
+            message: Some("Validation failed: Legs require valid airport codes".to_string()),
+            ..Default::default()
+        };
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(body_string_contains("XYZ"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(&error_response))
+            .mount(&server)
+            .await;
+
+        let requests = vec![
+            create_request(1, &[("LHR", "JFK")]),
+            create_request(1, &[("XYZ", "JFK")]),
+            create_request(1, &[("CDG", "NRT")]),
+            create_request(1, &[("SYD", "LAX")]),
+        ];
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        let results = estimate_batch(&api_client, requests, "", 4).await;
+
+        assert_eq!(results.len(), 4);
+        let first = results[0].as_ref().unwrap();
+        assert_eq!(first.request.legs[0].departure_airport, "LHR");
+        assert_eq!(first.attributes.carbon_kg, 1000.0);
+        assert!(matches!(results[1], Err(CliError::ApiError(_))));
+        let third = results[2].as_ref().unwrap();
+        assert_eq!(third.request.legs[0].departure_airport, "CDG");
+        assert_eq!(third.attributes.carbon_kg, 2000.0);
+        let fourth = results[3].as_ref().unwrap();
+        assert_eq!(fourth.request.legs[0].departure_airport, "SYD");
+        assert_eq!(fourth.attributes.carbon_kg, 3000.0);
+    }
+
+    #[tokio::test]
+    async fn test_repeated_requests_are_sent_once() {
+        let server = MockServer::start().await;
+        let response = create_mock_response(1000000.0, 2204.6, 1000.0, 1.0, "km", 5555.0);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&response))
+            .expect(2)
+            .mount(&server)
+            .await;
+
+        let requests = vec![
+            create_request(1, &[("LHR", "JFK")]),
+            create_request(1, &[("CDG", "NRT")]),
+            create_request(1, &[("LHR", "JFK")]),
+        ];
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+        let cache = EstimateCache::in_memory();
+
+        let results = estimate_batch_cached(&api_client, requests, "", 3, &cache).await;
+
+        assert_eq!(results.len(), 3);
+        let repeat = results[2].as_ref().unwrap();
+        assert_eq!(repeat.request.legs[0].departure_airport, "LHR");
+        assert_eq!(repeat.attributes.carbon_kg, 1000.0);
+        assert_eq!(cache.hits(), 1);
+    }
+
+    #[tokio::test]
+    async fn test_deadline_skips_what_is_unfinished() {
+        let server = MockServer::start().await;
+        for (departure, delay_ms) in [("LHR", 0), ("CDG", 2000), ("SYD", 0)] {
+            let response = create_mock_response(1000000.0, 2204.6, 1000.0, 1.0, "km", 5555.0);
+            Mock::given(method("POST"))
+                .and(path("/api/v1/estimates"))
+                .and(body_string_contains(departure))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_json(&response)
+                        .set_delay(Duration::from_millis(delay_ms)),
+                )
+                .mount(&server)
+                .await;
+        }
+
+        let requests = vec![
+            create_request(1, &[("LHR", "JFK")]),
+            create_request(1, &[("CDG", "NRT")]),
+            create_request(1, &[("SYD", "LAX")]),
+        ];
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+        let cache = EstimateCache::in_memory();
+        let deadline = Duration::from_millis(300);
+
+        let results =
+            estimate_batch_within(&api_client, requests, "", 3, &cache, Some(deadline)).await;
+
+        assert_eq!(results.len(), 3);
+        assert!(results[0].is_ok());
+        assert!(matches!(
+            results[1],
+            Err(CliError::DeadlineExceeded(skipped_at)) if skipped_at == deadline
+        ));
+        assert!(results[2].is_ok());
+    }
+
+    async fn streamed_order(ordered: bool) -> (Vec<usize>, usize) {
+        let server = MockServer::start().await;
+        for (departure, delay_ms) in [("LHR", 300), ("CDG", 0), ("SYD", 100)] {
+            let response = create_mock_response(1000000.0, 2204.6, 1000.0, 1.0, "km", 5555.0);
+            Mock::given(method("POST"))
+                .and(path("/api/v1/estimates"))
+                .and(body_string_contains(departure))
+                .respond_with(
+                    ResponseTemplate::new(200)
+                        .set_body_json(&response)
+                        .set_delay(Duration::from_millis(delay_ms)),
+                )
+                .mount(&server)
+                .await;
+        }
+        let requests = vec![
+            create_request(1, &[("LHR", "JFK")]),
+            create_request(1, &[("CDG", "NRT")]),
+            create_request(1, &[("SYD", "LAX")]),
+        ];
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+        let cache = EstimateCache::in_memory();
+
+        let mut streamed = Vec::new();
+        let results = estimate_batch_streaming(
+            &api_client,
+            requests,
+            "",
+            3,
+            &cache,
+            None,
+            ordered,
+            |index, _| streamed.push(index),
+        )
+        .await;
+        (streamed, results.len())
+    }
+
+    #[tokio::test]
+    async fn test_streaming_hands_over_results_as_they_complete() {
+        assert_eq!(streamed_order(false).await, (vec![1, 2, 0], 3));
+    }
+
+    #[tokio::test]
+    async fn test_ordered_streaming_keeps_the_batch_order() {
+        assert_eq!(streamed_order(true).await, (vec![0, 1, 2], 3));
+    }
+
+    #[tokio::test]
+    async fn test_fail_fast_stops_at_the_first_failure() {
+        let server = MockServer::start().await;
+        let error_response = FlightEstimateResponse {
+            message: Some("Validation failed: Legs require valid airport codes".to_string()),
+            ..Default::default()
+        };
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(body_string_contains("XYZ"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(&error_response))
+            .expect(1)
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(body_string_contains("CDG"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+
+        let requests = vec![
+            create_request(1, &[("XYZ", "JFK")]),
+            create_request(1, &[("CDG", "NRT")]),
+        ];
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        let result =
+            estimate_batch_fail_fast(&api_client, requests, "", 1, &EstimateCache::in_memory())
+                .await;
+
+        assert!(matches!(
+            result,
+            Err(RequestError {
+                index: 0,
+                error: CliError::ApiError(_),
+                ..
+            })
+        ));
+    }
+
+    #[test]
+    fn test_batch_summary_lists_each_failure() {
+        assert_eq!(batch_summary(2, &[]), "2 of 2 succeeded");
+
+        let failures = vec![
+            BatchFailure {
+                index: 1,
+                route: "LHR → XYZ".to_string(),
+                error: "API error: unknown airport".to_string(),
+            },
+            BatchFailure {
+                index: 4,
+                route: "CDG → NRT".to_string(),
+                error: "Request timed out".to_string(),
+            },
+        ];
+        assert_eq!(
+            batch_summary(5, &failures),
+            "3 of 5 succeeded\n  index 1 (LHR → XYZ): API error: unknown airport\n  \
+             index 4 (CDG → NRT): Request timed out"
+        );
+    }
+
+    #[test]
+    fn test_sample_batch_parses_back() {
+        for format in [InputFormat::Json, InputFormat::Yaml, InputFormat::Jsonl] {
+            let entries = parse_batch_entries_as(&render_sample_batch(format), format).unwrap();
+
+            let requests: Vec<FlightEstimateRequest> = entries
+                .into_iter()
+                .map(|entry| entry.unwrap().request)
+                .collect();
+            let expected: Vec<FlightEstimateRequest> = sample_batch()
+                .into_iter()
+                .map(|entry| entry.request)
+                .collect();
+            assert_eq!(requests, expected);
+        }
+    }
+
+    #[test]
+    fn test_batch_schema_requires_legs() {
+        let schema: serde_json::Value =
+            serde_json::from_str(&serde_json::to_string_pretty(&batch_schema()).unwrap()).unwrap();
+
+        assert_eq!(schema["type"], "array");
+        let entry = &schema["items"];
+        let entry = match entry.get("$ref").and_then(|reference| reference.as_str()) {
+            Some(reference) => &schema["$defs"][reference.trim_start_matches("#/$defs/")],
+            None => entry,
+        };
+        assert!(entry["required"]
+            .as_array()
+            .unwrap()
+            .contains(&serde_json::json!("legs")));
+        assert_eq!(entry["properties"]["legs"]["type"], "array");
+    }
+
+    #[test]
+    fn test_parse_batch_reports_malformed_entries_in_place() {
+        let batch = r#"[
+            {"passengers": "many", "legs": []},
+            {"passengers": 1, "legs": [{"departure_airport": "LHR", "destination_airport": "JFK"}]}
+        ]"#;
+
+        let entries = parse_batch(batch).unwrap();
+
+        assert_eq!(entries.len(), 2);
+        assert!(entries[0].is_err());
+        let request = entries[1].as_ref().unwrap();
+        assert_eq!(request.estimate_type, "flight");
+        assert_eq!(request.passengers, 1);
+    }
+
+    #[test]
+    fn test_parse_batch_rejects_entries_without_legs() {
+        let batch = r#"[{"passengers": 1, "legs": []}]"#;
+
+        let entries = parse_batch(batch).unwrap();
+
+        assert_eq!(
+            entries[0].as_ref().unwrap_err(),
+            "Invalid input: at least one leg is required"
+        );
+    }
+
+    #[test]
+    fn test_parse_batch_entries_keeps_itinerary_labels() {
+        let batch = r#"[
+            {"itinerary": "Tokyo", "passengers": 1, "legs": [{"departure_airport": "LHR", "destination_airport": "HND"}]},
+            {"passengers": 1, "legs": [{"departure_airport": "HND", "destination_airport": "LHR"}]}
+        ]"#;
+
+        let entries = parse_batch_entries(batch).unwrap();
+
+        let first = entries[0].as_ref().unwrap();
+        assert_eq!(first.itinerary.as_deref(), Some("Tokyo"));
+        assert_eq!(first.request.legs[0].destination_airport, "HND");
+        assert_eq!(entries[1].as_ref().unwrap().itinerary, None);
+    }
+
+    #[test]
+    fn test_group_by_itinerary() {
+        let estimate = |route: &[(&str, &str)]| EstimateResult {
+            request: create_request(1, route),
+            attributes: create_mock_response(1000.0, 2.2, 1.0, 0.001, "km", 10.0)
+                .data
+                .unwrap()
+                .attributes,
+        };
+        let estimates = vec![
+            (Some("Tokyo".to_string()), estimate(&[("LHR", "HND")])),
+            (None, estimate(&[("LHR", "CDG")])),
+            (Some("Tokyo".to_string()), estimate(&[("HND", "LHR")])),
+        ];
+
+        let groups = group_by_itinerary(estimates);
+
+        let summary: Vec<(&str, usize)> = groups
+            .iter()
+            .map(|group| (group.name.as_str(), group.estimates.len()))
+            .collect();
+        assert_eq!(summary, [("Tokyo", 2), ("LHR>CDG", 1)]);
+    }
+
+    #[test]
+    fn test_filter_by_carbon_includes_the_bounds() {
+        let estimate = |carbon_kg: f32| {
+            let attributes =
+                create_mock_response(carbon_kg * 1000.0, 0.0, carbon_kg, 0.0, "km", 10.0)
+                    .data
+                    .unwrap()
+                    .attributes;
+            (
+                None,
+                EstimateResult {
+                    request: create_request(1, &[("LHR", "CDG")]),
+                    attributes,
+                },
+            )
+        };
+        let carbon = |estimates: &[(Option<String>, EstimateResult)]| -> Vec<f32> {
+            estimates
+                .iter()
+                .map(|(_, estimate)| estimate.attributes.carbon_kg)
+                .collect()
+        };
+        let estimates = vec![
+            estimate(99.0),
+            estimate(100.0),
+            estimate(250.0),
+            estimate(500.0),
+        ];
+
+        let (kept, dropped, _) = filter_by_carbon(estimates.clone(), Some(100.0), Some(250.0));
+        assert_eq!(carbon(&kept), [100.0, 250.0]);
+        assert_eq!(dropped, 2);
+
+        let (kept, dropped, _) = filter_by_carbon(estimates.clone(), Some(250.0), None);
+        assert_eq!(carbon(&kept), [250.0, 500.0]);
+        assert_eq!(dropped, 2);
+
+        let (kept, dropped, _) = filter_by_carbon(estimates.clone(), None, None);
+        assert_eq!(kept.len(), 4);
+        assert_eq!(dropped, 0);
+
+        let mut unknown = estimates;
+        unknown[3].1.attributes.carbon_kg = MISSING_FIGURE;
+        let (kept, dropped, missing) = filter_by_carbon(unknown.clone(), Some(100.0), None);
+        assert_eq!(kept.len(), 3);
+        assert_eq!((dropped, missing), (1, 1));
+        assert_eq!(filter_by_carbon(unknown, None, None).2, 0);
+    }
+
+    #[test]
+    fn test_yaml_and_json_batches_parse_the_same() {
+        let json = r#"[
+            {"itinerary": "Tokyo", "passengers": 2, "legs": [
+                {"departure_airport": "LHR", "destination_airport": "HND", "cabin_class": "business"}
+            ]},
+            {"passengers": 1, "distance_unit": "mi", "legs": [
+                {"departure_airport": "CDG", "destination_airport": "JFK"},
+                {"departure_airport": "JFK", "destination_airport": "CDG"}
+            ]}
+        ]"#;
+        let yaml = "
+# Outbound to Tokyo
+- itinerary: Tokyo
+  passengers: 2
+  legs:
+    - departure_airport: LHR
+      destination_airport: HND
+      cabin_class: business
+- passengers: 1
+  distance_unit: mi
+  legs:
+    - {departure_airport: CDG, destination_airport: JFK}
+    - {departure_airport: JFK, destination_airport: CDG}
+";
+
+        let from_json = parse_batch_entries_as(json, InputFormat::Json).unwrap();
+        let from_yaml = parse_batch_entries_as(yaml, InputFormat::Yaml).unwrap();
+
+        let requests = |entries: Vec<Result<BatchEntry, String>>| {
+            entries
+                .into_iter()
+                .map(|entry| {
+                    let entry = entry.unwrap();
+                    (entry.itinerary, entry.request)
+                })
+                .collect::<Vec<_>>()
+        };
+        let from_json = requests(from_json);
+        assert_eq!(from_json.len(), 2);
+        assert_eq!(from_json, requests(from_yaml));
+    }
+
+    #[test]
+    fn test_malformed_yaml_entries_are_reported_in_place() {
+        let yaml = "
+- passengers: many
+- passengers: 1
+  legs: [{departure_airport: LHR, destination_airport: JFK}]
+";
+
+        let entries = parse_batch_entries_as(yaml, InputFormat::Yaml).unwrap();
+
+        assert!(entries[0].is_err());
+        assert_eq!(entries[1].as_ref().unwrap().request.passengers, 1);
+    }
+
+    #[test]
+    fn test_input_format_from_path() {
+        assert_eq!(
+            input_format_from_path(Path::new("trips.yaml")),
+            InputFormat::Yaml
+        );
+        assert_eq!(
+            input_format_from_path(Path::new("trips.YML")),
+            InputFormat::Yaml
+        );
+        assert_eq!(
+            input_format_from_path(Path::new("trips.json")),
+            InputFormat::Json
+        );
+        assert_eq!(
+            input_format_from_path(Path::new("trips.ndjson")),
+            InputFormat::Jsonl
+        );
+        assert_eq!(input_format_from_path(Path::new("-")), InputFormat::Json);
+    }
+
+    #[test]
+    fn test_json_lines_skip_bad_lines_by_number() {
+        let lines = concat!(
+            r#"{"passengers": 1, "legs": [{"departure_airport": "LHR", "destination_airport": "JFK"}]}"#,
+            "\n\n",
+            r#"{"passengers": 2, "legs": [{"departure_airport": "#,
+            "\n",
+            r#"{"passengers": 3, "legs": [{"departure_airport": "CDG", "destination_airport": "NRT"}]}"#,
+            "\n",
+        );
+
+        let entries: Vec<(usize, Result<BatchEntry, String>)> =
+            parse_json_lines(lines.as_bytes()).collect();
+
+        let numbers: Vec<usize> = entries.iter().map(|(line, _)| *line).collect();
+        assert_eq!(numbers, vec![1, 3, 4]);
+        assert_eq!(entries[0].1.as_ref().unwrap().request.passengers, 1);
+        assert!(entries[1].1.is_err());
+        assert_eq!(entries[2].1.as_ref().unwrap().request.passengers, 3);
+    }
+
+    #[test]
+    fn test_parse_batch_requires_an_array() {
+        assert!(matches!(
+            parse_batch(r#"{"passengers": 1}"#),
+            Err(CliError::InvalidInput(_))
+        ));
+    }
+}
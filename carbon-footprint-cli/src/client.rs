@@ -0,0 +1,1978 @@
+//! HTTP client for the Carbon Interface estimates endpoint.
+
+use crate::error::CliError;
+use crate::model::{
+    ElectricityEstimateRequest, ElectricityEstimateResponse, EstimateAttributes, EstimateResponse,
+    FlightEstimateRequest, FlightEstimateResponse, ShippingEstimateRequest,
+    ShippingEstimateResponse, VehicleEstimateRequest, VehicleEstimateResponse,
+};
+use log::{debug, info, warn};
+use rand::rngs::StdRng;
+use rand::{RngExt, SeedableRng};
+use reqwest::header::{HeaderMap, HeaderValue, CONTENT_TYPE, RETRY_AFTER};
+use reqwest::{Client, Proxy, RequestBuilder, StatusCode};
+use serde::de::DeserializeOwned;
+use serde::Serialize;
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+use std::fmt;
+use std::future::Future;
+use std::sync::Mutex;
+use std::time::{Duration, Instant, SystemTime};
+
+pub const DEFAULT_BASE_URL: &str = "https://www.carboninterface.com";
+
+/// How long a request may take before it's abandoned, unless overridden with `--timeout`.
+pub const DEFAULT_TIMEOUT: Duration = Duration::from_secs(30);
+
+/// How many times a rate-limited or failed request is retried before giving up.
+pub const DEFAULT_MAX_RETRIES: u32 = 3;
+
+/// Wait before the first retry; each further retry waits twice as long as the one before.
+pub const DEFAULT_RETRY_DELAY: Duration = Duration::from_millis(500);
+
+/// What requests identify themselves as unless `--user-agent` says otherwise.
+pub const DEFAULT_USER_AGENT: &str = concat!("carbon-footprint-cli/", env!("CARGO_PKG_VERSION"));
+
+/// Builds the HTTP client, sending `user_agent` as every request's `User-Agent`.
+/// `HTTPS_PROXY`, `HTTP_PROXY` and `NO_PROXY` are honoured as usual; an explicit `proxy`
+/// URL takes precedence over them for every request. Responses may come back gzip,
+/// brotli or deflate compressed, which the client asks for with `Accept-Encoding` and
+/// undoes before the body is read.
+pub fn build_http_client(proxy: Option<&str>, user_agent: &str) -> Result<Client, CliError> {
+    let user_agent = HeaderValue::from_str(user_agent)
+        .map_err(|_| CliError::InvalidArguments(format!("invalid user agent {:?}", user_agent)))?;
+    let mut builder = Client::builder()
+        .user_agent(user_agent)
+        .gzip(true)
+        .brotli(true)
+        .deflate(true);
+    if let Some(proxy) = proxy {
+        let proxy = Proxy::all(proxy).map_err(|err| {
+            CliError::InvalidArguments(format!("invalid proxy URL {}: {}", proxy, err))
+        })?;
+        builder = builder.proxy(proxy);
+    }
+    builder.build().map_err(CliError::NetworkError)
+}
+
+/// Where a provider expects the API key on each request.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum AuthScheme {
+    /// `Authorization: Bearer <key>`, as Carbon Interface expects
+    #[default]
+    Bearer,
+    /// The bare key in a header of its own, e.g. `X-Api-Key`
+    Header(&'static str),
+    /// The key as a query parameter, e.g. `?api_key=<key>`
+    QueryParam(&'static str),
+}
+
+impl AuthScheme {
+    /// `builder` with `api_key` attached where this scheme puts it.
+    fn apply(self, builder: RequestBuilder, api_key: &str) -> RequestBuilder {
+        match self {
+            AuthScheme::Bearer => builder.bearer_auth(api_key),
+            AuthScheme::Header(name) => builder.header(name, api_key),
+            AuthScheme::QueryParam(name) => builder.query(&[(name, api_key)]),
+        }
+    }
+}
+
+/// Where the key goes, for logging that it was sent without logging the key.
+impl fmt::Display for AuthScheme {
+    fn fmt(&self, f: &mut fmt::Formatter) -> fmt::Result {
+        match self {
+            AuthScheme::Bearer => write!(f, "Authorization header"),
+            AuthScheme::Header(name) => write!(f, "{} header", name),
+            AuthScheme::QueryParam(name) => write!(f, "{} query parameter", name),
+        }
+    }
+}
+
+/// What the API sent back, before the body is interpreted.
+#[derive(Debug)]
+pub struct ApiResponse {
+    pub status: StatusCode,
+    pub content_type: Option<String>,
+    pub body: String,
+}
+
+impl ApiResponse {
+    fn is_json(&self) -> bool {
+        self.content_type
+            .as_deref()
+            .is_some_and(|content_type| content_type.contains("json"))
+    }
+}
+
+/// Somewhere estimate requests can be sent. `ApiClient` posts them to Carbon Interface;
+/// tests can stand in canned responses without starting a server.
+pub trait EstimateBackend {
+    /// Sends `request` and returns the response as received, leaving it to the caller to
+    /// interpret the status and body.
+    fn post_estimate(
+        &self,
+        request: &impl Serialize,
+        api_key: &str,
+    ) -> impl Future<Output = Result<ApiResponse, CliError>>;
+}
+
+/// Where request timings come from; tests stand in a clock they control.
+pub trait Clock {
+    fn now(&self) -> Instant;
+}
+
+/// The real monotonic clock.
+pub struct SystemClock;
+
+impl Clock for SystemClock {
+    fn now(&self) -> Instant {
+        Instant::now()
+    }
+}
+
+/// How long an estimate request took and how much went over the wire, for `--timing`.
+/// The duration covers every retry; the byte counts are of the request and response bodies.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct RequestDiagnostics {
+    pub duration: Duration,
+    pub bytes_sent: usize,
+    pub bytes_received: usize,
+}
+
+/// How much of an unexpected response body to quote in error messages.
+const BODY_SNIPPET_CHARS: usize = 200;
+
+/// The start of `body` on a single line, for quoting in error messages.
+fn body_snippet(body: &str) -> String {
+    let body = body.split_whitespace().collect::<Vec<_>>().join(" ");
+    let mut snippet: String = body.chars().take(BODY_SNIPPET_CHARS).collect();
+    if body.chars().count() > BODY_SNIPPET_CHARS {
+        snippet.push('…');
+    }
+    snippet
+}
+
+pub struct ApiClient {
+    client: Client,
+    base_url: String,
+    timeout: Duration,
+    max_retries: u32,
+    retry_delay: Duration,
+    auth_scheme: AuthScheme,
+    /// Picks where each backoff falls between zero and its cap
+    jitter: Mutex<StdRng>,
+}
+
+impl ApiClient {
+    pub fn new(client: Client, base_url: &str) -> Self {
+        Self {
+            client,
+            base_url: base_url.into(),
+            timeout: DEFAULT_TIMEOUT,
+            max_retries: DEFAULT_MAX_RETRIES,
+            retry_delay: DEFAULT_RETRY_DELAY,
+            auth_scheme: AuthScheme::default(),
+            jitter: Mutex::new(rand::make_rng()),
+        }
+    }
+
+    /// Sends the API key the way the provider behind `base_url` expects it.
+    pub fn with_auth_scheme(mut self, auth_scheme: AuthScheme) -> Self {
+        self.auth_scheme = auth_scheme;
+        self
+    }
+
+    pub fn with_timeout(mut self, timeout: Duration) -> Self {
+        self.timeout = timeout;
+        self
+    }
+
+    /// Retries 429 and 5xx responses up to `max_retries` times, waiting a random time up to
+    /// an exponentially growing cap that starts at `retry_delay`, unless the API says how
+    /// long to wait with `Retry-After`.
+    pub fn with_retries(mut self, max_retries: u32, retry_delay: Duration) -> Self {
+        self.max_retries = max_retries;
+        self.retry_delay = retry_delay;
+        self
+    }
+
+    /// Seeds the backoff jitter so the waits between retries are reproducible.
+    pub fn with_jitter_seed(mut self, seed: u64) -> Self {
+        self.jitter = Mutex::new(StdRng::seed_from_u64(seed));
+        self
+    }
+
+    /// Full-jitter backoff: a random wait up to `backoff_cap`, so clients that failed
+    /// together don't all retry at the same moment.
+    fn backoff_delay(&self, attempt: u32) -> Duration {
+        let cap = u64::try_from(self.backoff_cap(attempt).as_nanos()).unwrap_or(u64::MAX);
+        let nanos = self.jitter.lock().unwrap().random_range(0..=cap);
+        Duration::from_nanos(nanos)
+    }
+
+    /// Exponential backoff: `retry_delay` doubled once per earlier retry.
+    fn backoff_cap(&self, attempt: u32) -> Duration {
+        self.retry_delay
+            .saturating_mul(2u32.saturating_pow(attempt))
+    }
+
+    /// A query-param key is part of the request URL, which reqwest prints with its errors,
+    /// so the URL is dropped before the error can reach the terminal.
+    fn network_error(&self, err: reqwest::Error) -> CliError {
+        if err.is_timeout() {
+            return CliError::Timeout(Some(self.timeout));
+        }
+        match CliError::from(err) {
+            CliError::NetworkError(err)
+                if matches!(self.auth_scheme, AuthScheme::QueryParam(_)) =>
+            {
+                CliError::NetworkError(err.without_url())
+            }
+            err => err,
+        }
+    }
+}
+
+impl EstimateBackend for ApiClient {
+    async fn post_estimate(
+        &self,
+        request: &impl Serialize,
+        api_key: &str,
+    ) -> Result<ApiResponse, CliError> {
+        let json_body = serde_json::to_string(request)?;
+
+        let url = format!("{}/api/v1/estimates", self.base_url);
+        let mut attempt = 0;
+        let response = loop {
+            debug!("POST {}", url);
+            // Only ever log that the key was sent, never the key itself
+            debug!("{} set (value redacted)", self.auth_scheme);
+            debug!("Request body: {}", json_body);
+
+            let response = self
+                .auth_scheme
+                .apply(self.client.post(&url), api_key)
+                .header("Content-Type", "application/json")
+                .body(json_body.clone())
+                .timeout(self.timeout)
+                .send()
+                .await
+                .map_err(|err| self.network_error(err))?;
+            debug!("Response status: {}", response.status());
+
+            if attempt >= self.max_retries || !is_retryable(response.status()) {
+                break response;
+            }
+
+            let delay =
+                retry_after(response.headers()).unwrap_or_else(|| self.backoff_delay(attempt));
+            info!(
+                "Got {}, retrying in {:?} (retry {} of {})",
+                response.status(),
+                delay,
+                attempt + 1,
+                self.max_retries
+            );
+            tokio::time::sleep(delay).await;
+            attempt += 1;
+        };
+
+        let status = response.status();
+        let content_type = response
+            .headers()
+            .get(CONTENT_TYPE)
+            .and_then(|value| value.to_str().ok())
+            .map(String::from);
+        let body = response
+            .text()
+            .await
+            .map_err(|err| self.network_error(err))?;
+        debug!("Response body: {}", body);
+        Ok(ApiResponse {
+            status,
+            content_type,
+            body,
+        })
+    }
+}
+
+/// Rate limiting and server errors are usually temporary, so they're worth another attempt.
+fn is_retryable(status: StatusCode) -> bool {
+    status == StatusCode::TOO_MANY_REQUESTS || status.is_server_error()
+}
+
+/// Reads how long the API asked us to wait, given either as seconds or as an HTTP date.
+fn retry_after(headers: &HeaderMap) -> Option<Duration> {
+    parse_retry_after(headers.get(RETRY_AFTER)?.to_str().ok()?, SystemTime::now())
+}
+
+fn parse_retry_after(value: &str, now: SystemTime) -> Option<Duration> {
+    let value = value.trim();
+    if let Ok(seconds) = value.parse::<u64>() {
+        return Some(Duration::from_secs(seconds));
+    }
+
+    let retry_at = httpdate::parse_http_date(value).ok()?;
+    // A date in the past means we can retry straight away
+    Some(retry_at.duration_since(now).unwrap_or(Duration::ZERO))
+}
+
+pub async fn make_estimates_request(
+    api_client: &impl EstimateBackend,
+    request: &FlightEstimateRequest,
+    api_key: &str,
+) -> Result<FlightEstimateResponse, CliError> {
+    make_estimates_request_with_diagnostics(api_client, request, api_key, &SystemClock)
+        .await
+        .map(|(response, _)| response)
+}
+
+/// Estimates `request` and returns just its figures, without the response envelope. A
+/// response without any data is an error.
+///
+/// ```
+/// use carbon_footprint_cli::{
+///     estimate_flight, ApiResponse, CliError, EstimateBackend, FlightEstimateRequest, Leg,
+/// };
+/// use serde::Serialize;
+///
+/// /// Answers every request with the same figures, as a test double for `ApiClient`.
+/// struct CannedBackend;
+///
+/// impl EstimateBackend for CannedBackend {
+///     async fn post_estimate(
+///         &self,
+///         _request: &impl Serialize,
+///         _api_key: &str,
+///     ) -> Result<ApiResponse, CliError> {
+///         Ok(ApiResponse {
+///             status: reqwest::StatusCode::OK,
+///             content_type: Some("application/json".to_string()),
+///             body: r#"{"data": {"attributes": {"carbon_g": 499000.0, "carbon_lb": 1100.1,
+///                 "carbon_kg": 499.0, "carbon_mt": 0.499, "distance_unit": "km",
+///                 "distance_value": 5540.0}}}"#
+///                 .to_string(),
+///         })
+///     }
+/// }
+///
+/// # tokio::runtime::Runtime::new().unwrap().block_on(async {
+/// let request = FlightEstimateRequest::builder()
+///     .add_leg(Leg {
+///         departure_airport: "LHR".to_string(),
+///         destination_airport: "JFK".to_string(),
+///         cabin_class: None,
+///         passengers: None,
+///         aircraft: None,
+///     })
+///     .build()?;
+///
+/// let attributes = estimate_flight(&CannedBackend, &request, "YOUR_API_KEY").await?;
+/// assert_eq!(attributes.carbon_kg, 499.0);
+/// # Ok::<(), CliError>(())
+/// # }).unwrap();
+/// ```
+pub async fn estimate_flight(
+    api_client: &impl EstimateBackend,
+    request: &FlightEstimateRequest,
+    api_key: &str,
+) -> Result<EstimateAttributes, CliError> {
+    attributes_of(make_estimates_request(api_client, request, api_key).await?)
+}
+
+/// Like `make_estimates_request`, also measuring the call with `clock`.
+pub async fn make_estimates_request_with_diagnostics(
+    api_client: &impl EstimateBackend,
+    request: &FlightEstimateRequest,
+    api_key: &str,
+    clock: &impl Clock,
+) -> Result<(FlightEstimateResponse, RequestDiagnostics), CliError> {
+    request.check_has_legs()?;
+    let request = request.without_aircraft();
+    let (api_response, diagnostics) = timed_post(api_client, &request, api_key, clock).await?;
+    let response: FlightEstimateResponse = parse_estimate(api_response)?;
+    if let Some(data) = &response.data {
+        check_plausible(&data.attributes)?;
+    }
+    Ok((response, diagnostics))
+}
+
+/// Rejects negative or non-numeric figures, which only a corrupted response would have,
+/// and warns about a flight that covers a distance without emitting anything.
+fn check_plausible(attributes: &EstimateAttributes) -> Result<(), CliError> {
+    let figures = [
+        ("carbon_g", attributes.carbon_g),
+        ("carbon_lb", attributes.carbon_lb),
+        ("carbon_kg", attributes.carbon_kg),
+        ("carbon_mt", attributes.carbon_mt),
+        ("distance_value", attributes.distance_value),
+    ];
+    let (missing, reported): (Vec<_>, Vec<_>) =
+        figures.iter().partition(|(_, value)| value.is_nan());
+    if !missing.is_empty() {
+        let names: Vec<&str> = missing.iter().map(|(name, _)| *name).collect();
+        warn!(
+            "The API's response had no {}; shown as N/A",
+            names.join(", ")
+        );
+    }
+    if let Some((name, value)) = reported
+        .iter()
+        .find(|(_, value)| !value.is_finite() || *value < 0.0)
+    {
+        return Err(CliError::ImplausibleResponse(format!(
+            "{} is {}",
+            name, value
+        )));
+    }
+
+    if is_zero_carbon_flight(attributes) {
+        warn!(
+            "The API reported no emissions for {} {} of flying; the estimate may be wrong",
+            attributes.distance_value, attributes.distance_unit
+        );
+    }
+    Ok(())
+}
+
+fn is_zero_carbon_flight(attributes: &EstimateAttributes) -> bool {
+    attributes.distance_value > 0.0
+        && attributes.carbon_g == 0.0
+        && attributes.carbon_lb == 0.0
+        && attributes.carbon_kg == 0.0
+        && attributes.carbon_mt == 0.0
+}
+
+/// Like `make_estimates_request`, for a `vehicle` estimate.
+pub async fn make_vehicle_estimates_request(
+    api_client: &impl EstimateBackend,
+    request: &VehicleEstimateRequest,
+    api_key: &str,
+) -> Result<VehicleEstimateResponse, CliError> {
+    request_estimate(api_client, request, api_key).await
+}
+
+/// Like `make_estimates_request`, for a `shipping` estimate.
+pub async fn make_shipping_estimates_request(
+    api_client: &impl EstimateBackend,
+    request: &ShippingEstimateRequest,
+    api_key: &str,
+) -> Result<ShippingEstimateResponse, CliError> {
+    request_estimate(api_client, request, api_key).await
+}
+
+/// Like `make_estimates_request`, for an `electricity` estimate.
+pub async fn make_electricity_estimates_request(
+    api_client: &impl EstimateBackend,
+    request: &ElectricityEstimateRequest,
+    api_key: &str,
+) -> Result<ElectricityEstimateResponse, CliError> {
+    request_estimate(api_client, request, api_key).await
+}
+
+/// Sends any estimate request; only the request and attribute shapes differ between
+/// estimate types.
+async fn request_estimate<A: DeserializeOwned>(
+    api_client: &impl EstimateBackend,
+    request: &impl Serialize,
+    api_key: &str,
+) -> Result<EstimateResponse<A>, CliError> {
+    parse_estimate(api_client.post_estimate(request, api_key).await?)
+}
+
+/// Sends `request`, timing the call from just before it's sent until the whole response
+/// has been read.
+async fn timed_post(
+    api_client: &impl EstimateBackend,
+    request: &impl Serialize,
+    api_key: &str,
+    clock: &impl Clock,
+) -> Result<(ApiResponse, RequestDiagnostics), CliError> {
+    let bytes_sent = serde_json::to_vec(request)?.len();
+    let started = clock.now();
+    let api_response = api_client.post_estimate(request, api_key).await?;
+    let diagnostics = RequestDiagnostics {
+        duration: clock.now().saturating_duration_since(started),
+        bytes_sent,
+        bytes_received: api_response.body.len(),
+    };
+    Ok((api_response, diagnostics))
+}
+
+/// Interprets the status and body of an estimate response.
+fn parse_estimate<A: DeserializeOwned>(
+    api_response: ApiResponse,
+) -> Result<EstimateResponse<A>, CliError> {
+    // Checked before the body, which may be an HTML page from a gateway
+    if matches!(
+        api_response.status,
+        StatusCode::UNAUTHORIZED | StatusCode::FORBIDDEN
+    ) {
+        return Err(CliError::Unauthorized);
+    }
+
+    // Gateways and proxies answer failures with HTML pages that aren't worth parsing
+    if !api_response.status.is_success() && !api_response.is_json() {
+        return Err(CliError::ApiError(format!(
+            "HTTP {}: {}",
+            api_response.status,
+            body_snippet(&api_response.body)
+        )));
+    }
+
+    // Parsing nothing would only say "EOF while parsing"
+    if api_response.body.trim().is_empty() {
+        return Err(status_error(api_response.status, None).unwrap_or(CliError::EmptyResponse));
+    }
+
+    if api_response.status == StatusCode::UNPROCESSABLE_ENTITY {
+        if let Some(details) = validation_details(&api_response.body) {
+            return Err(CliError::ValidationError(details));
+        }
+    }
+
+    let response_json: Result<EstimateResponse<A>, _> = serde_json::from_str(&api_response.body);
+    match response_json {
+        Ok(mut response) => {
+            if let Some(error) = status_error(api_response.status, response.message.take()) {
+                return Err(error);
+            }
+
+            if let Some(data) = response.data.take() {
+                Ok(EstimateResponse {
+                    data: Some(data),
+                    ..Default::default()
+                })
+            } else {
+                Err(CliError::ApiError("Missing response data".to_string()))
+            }
+        }
+        Err(err) => Err(CliError::UnexpectedResponseFormat(err)),
+    }
+}
+
+/// The body of a 422 response that says which fields were wrong.
+#[derive(Deserialize)]
+struct ValidationBody {
+    #[serde(default)]
+    message: Option<String>,
+    errors: FieldErrors,
+}
+
+/// Field errors come either as a list, as in
+/// `[{"field": "legs[1].destination_airport", "message": "is invalid"}]`, or keyed by
+/// field, as in `{"legs[1].destination_airport": ["is invalid"]}`.
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FieldErrors {
+    List(Vec<FieldError>),
+    ByField(BTreeMap<String, FieldMessages>),
+}
+
+#[derive(Deserialize)]
+struct FieldError {
+    #[serde(default, alias = "attribute", alias = "param")]
+    field: Option<String>,
+    message: String,
+}
+
+#[derive(Deserialize)]
+#[serde(untagged)]
+enum FieldMessages {
+    One(String),
+    Many(Vec<String>),
+}
+
+/// The field errors in a 422 body as a bulleted list under its `message`, or `None` when
+/// it doesn't list any, leaving the plain message to explain the failure.
+fn validation_details(body: &str) -> Option<String> {
+    let body: ValidationBody = serde_json::from_str(body).ok()?;
+    let problems: Vec<(Option<String>, String)> = match body.errors {
+        FieldErrors::List(errors) => errors
+            .into_iter()
+            .map(|error| (error.field, error.message))
+            .collect(),
+        FieldErrors::ByField(errors) => errors
+            .into_iter()
+            .flat_map(|(field, messages)| {
+                let messages = match messages {
+                    FieldMessages::One(message) => vec![message],
+                    FieldMessages::Many(messages) => messages,
+                };
+                messages
+                    .into_iter()
+                    .map(move |message| (Some(field.clone()), message))
+            })
+            .collect(),
+    };
+    if problems.is_empty() {
+        return None;
+    }
+
+    let mut details = body
+        .message
+        .unwrap_or_else(|| "Validation failed".to_string());
+    for (field, message) in problems {
+        match field {
+            Some(field) => {
+                details.push_str(&format!("\n  - {}: {}", describe_field(&field), message))
+            }
+            None => details.push_str(&format!("\n  - {}", message)),
+        }
+    }
+    Some(details)
+}
+
+/// Names the leg a field such as `legs[1].destination_airport` or `legs.1.cabin_class`
+/// belongs to, counting from 1 as the prompts do. Other fields are given as they are.
+fn describe_field(field: &str) -> String {
+    let Some(rest) = field.strip_prefix("legs") else {
+        return field.to_string();
+    };
+    let (index, attribute) = match rest.strip_prefix('[') {
+        Some(rest) => match rest.split_once(']') {
+            Some((index, attribute)) => (index, attribute),
+            None => return field.to_string(),
+        },
+        None => match rest.strip_prefix('.').and_then(|rest| rest.split_once('.')) {
+            Some((index, attribute)) => (index, attribute),
+            None => return field.to_string(),
+        },
+    };
+    let Ok(index) = index.parse::<usize>() else {
+        return field.to_string();
+    };
+    let attribute = attribute.trim_start_matches('.');
+    if attribute.is_empty() {
+        format!("leg {}", index + 1)
+    } else {
+        format!("leg {} {}", index + 1, attribute)
+    }
+}
+
+/// Maps an unsuccessful status, or an error message in a successful one, to the error
+/// that best explains it.
+fn status_error(status: StatusCode, message: Option<String>) -> Option<CliError> {
+    if status == StatusCode::UNPROCESSABLE_ENTITY {
+        return Some(CliError::ValidationError(
+            message.unwrap_or_else(|| "no details given".to_string()),
+        ));
+    }
+    if status.is_server_error() {
+        return Some(CliError::ServerError(status.as_u16()));
+    }
+    message.map(CliError::ApiError)
+}
+
+pub fn attributes_of<A>(response: EstimateResponse<A>) -> Result<A, CliError> {
+    response
+        .data
+        .map(|data| data.attributes)
+        .ok_or_else(|| CliError::ApiError("Missing response data".to_string()))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::model::Leg;
+    use crate::test_support::{create_mock_response, create_request};
+    use flate2::write::GzEncoder;
+    use flate2::Compression;
+    use std::io::Write;
+    use std::sync::Mutex;
+    use wiremock::{
+        matchers::{header, header_exists, method, path, query_param},
+        Match, Mock, MockServer, ResponseTemplate,
+    };
+
+    /// Answers every request with the same canned response, without any HTTP.
+    struct FakeBackend {
+        status: u16,
+        content_type: &'static str,
+        body: String,
+    }
+
+    impl FakeBackend {
+        fn json(status: u16, body: &impl Serialize) -> Self {
+            Self::raw(
+                status,
+                "application/json",
+                &serde_json::to_string(body).unwrap(),
+            )
+        }
+
+        fn raw(status: u16, content_type: &'static str, body: &str) -> Self {
+            Self {
+                status,
+                content_type,
+                body: body.to_string(),
+            }
+        }
+    }
+
+    impl EstimateBackend for FakeBackend {
+        async fn post_estimate(
+            &self,
+            _request: &impl Serialize,
+            _api_key: &str,
+        ) -> Result<ApiResponse, CliError> {
+            Ok(ApiResponse {
+                status: StatusCode::from_u16(self.status).unwrap(),
+                content_type: Some(self.content_type.to_string()),
+                body: self.body.clone(),
+            })
+        }
+    }
+
+    /// Fails every request as if the API never answered.
+    struct UnreachableBackend;
+
+    impl EstimateBackend for UnreachableBackend {
+        async fn post_estimate(
+            &self,
+            _request: &impl Serialize,
+            _api_key: &str,
+        ) -> Result<ApiResponse, CliError> {
+            Err(CliError::Timeout(Some(DEFAULT_TIMEOUT)))
+        }
+    }
+
+    #[tokio::test]
+    async fn test_make_vehicle_estimates_request_success() {
+        let server = MockServer::start().await;
+        let body = serde_json::json!({
+            "data": {
+                "id": "b5d8b9a4",
+                "type": "estimate",
+                "attributes": {
+                    "distance_value": 100.0,
+                    "vehicle_make": "Toyota",
+                    "vehicle_model": "Corolla",
+                    "vehicle_year": 1993,
+                    "vehicle_model_id": "7268a9b7-17e8-4c8d-acca-57059252afe9",
+                    "distance_unit": "mi",
+                    "estimated_at": "2021-01-10T15:24:32.568Z",
+                    "carbon_g": 37029,
+                    "carbon_lb": 81.64,
+                    "carbon_kg": 37.03,
+                    "carbon_mt": 0.04
+                }
+            }
+        });
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(wiremock::matchers::body_json(serde_json::json!({
+                "type": "vehicle",
+                "distance_unit": "mi",
+                "distance_value": 100.0,
+                "vehicle_model_id": "7268a9b7-17e8-4c8d-acca-57059252afe9"
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let request = VehicleEstimateRequest {
+            estimate_type: "vehicle".to_string(),
+            distance_unit: "mi".to_string(),
+            distance_value: 100.0,
+            vehicle_model_id: "7268a9b7-17e8-4c8d-acca-57059252afe9".to_string(),
+        };
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        let response = make_vehicle_estimates_request(&api_client, &request, "").await;
+
+        let estimate = attributes_of(response.unwrap()).unwrap();
+        assert_eq!(estimate.carbon_kg, 37.03);
+        assert_eq!(estimate.vehicle_make, "Toyota");
+        assert_eq!(estimate.vehicle_year, 1993);
+    }
+
+    #[tokio::test]
+    async fn test_make_shipping_estimates_request_success() {
+        let server = MockServer::start().await;
+        let body = serde_json::json!({
+            "data": {
+                "id": "4e8a8fa0",
+                "type": "estimate",
+                "attributes": {
+                    "distance_value": 2000.0,
+                    "weight_unit": "g",
+                    "transport_method": "truck",
+                    "weight_value": 200.0,
+                    "distance_unit": "km",
+                    "estimated_at": "2020-07-24T02:25:50.837Z",
+                    "carbon_g": 37,
+                    "carbon_lb": 0.08,
+                    "carbon_kg": 0.04,
+                    "carbon_mt": 0.0
+                }
+            }
+        });
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let request = ShippingEstimateRequest {
+            estimate_type: "shipping".to_string(),
+            weight_value: 200.0,
+            weight_unit: "g".to_string(),
+            distance_value: 2000.0,
+            distance_unit: "km".to_string(),
+            transport_method: "truck".to_string(),
+        };
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        let response = make_shipping_estimates_request(&api_client, &request, "").await;
+
+        let estimate = attributes_of(response.unwrap()).unwrap();
+        assert_eq!(estimate.carbon_g, 37.0);
+        assert_eq!(estimate.transport_method, "truck");
+    }
+
+    #[tokio::test]
+    async fn test_make_electricity_estimates_request_success() {
+        let server = MockServer::start().await;
+        let body = serde_json::json!({
+            "data": {
+                "id": "e5d4b1b6",
+                "type": "estimate",
+                "attributes": {
+                    "country": "us",
+                    "state": "fl",
+                    "electricity_unit": "mwh",
+                    "electricity_value": 42.0,
+                    "estimated_at": "2020-07-24T02:23:12.252Z",
+                    "carbon_g": 18051428,
+                    "carbon_lb": 39796.57,
+                    "carbon_kg": 18051.43,
+                    "carbon_mt": 18.05
+                }
+            }
+        });
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(wiremock::matchers::body_json(serde_json::json!({
+                "type": "electricity",
+                "electricity_unit": "mwh",
+                "electricity_value": 42.0,
+                "country": "us",
+                "state": "fl"
+            })))
+            .respond_with(ResponseTemplate::new(200).set_body_json(body))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let request = ElectricityEstimateRequest {
+            estimate_type: "electricity".to_string(),
+            electricity_unit: "mwh".to_string(),
+            electricity_value: 42.0,
+            country: "us".to_string(),
+            state: Some("fl".to_string()),
+        };
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        let response = make_electricity_estimates_request(&api_client, &request, "").await;
+
+        let estimate = attributes_of(response.unwrap()).unwrap();
+        assert_eq!(estimate.carbon_kg, 18051.43);
+        assert_eq!(estimate.state.as_deref(), Some("fl"));
+    }
+
+    #[tokio::test]
+    async fn test_make_estimates_for_single_leg_request_success() {
+        // Start a WireMock server
+        let server = MockServer::start().await;
+
+        // Set up a mock response for a successful request
+        let mock_response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
+            .mount(&server)
+            .await;
+
+        // Create a test request
+        let request = FlightEstimateRequest {
+            estimate_type: "flight".to_string(),
+            passengers: 100,
+            legs: vec![Leg {
+                departure_airport: "LHR".to_string(),
+                destination_airport: "JFK".to_string(),
+                cabin_class: None,
+                passengers: None,
+                aircraft: None,
+            }],
+            distance_unit: None,
+        };
+
+        // Create the API client with the mock server's URI
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        // Make the request to the mock server
+        let response = make_estimates_request(&api_client, &request, "").await;
+
+        // Check the response
+        assert!(response.is_ok());
+        let response = response.unwrap();
+        assert!(response.data.is_some());
+        let estimate = response.data.unwrap().attributes;
+        assert_eq!(estimate.carbon_g, 99911700.0);
+        assert_eq!(estimate.carbon_lb, 267.6);
+        assert_eq!(estimate.carbon_kg, 99911.7);
+        assert_eq!(estimate.carbon_mt, 99.91);
+        assert_eq!(estimate.distance_unit, "km");
+        assert_eq!(estimate.distance_value, 5660.34);
+    }
+
+    #[tokio::test]
+    async fn test_make_estimates_for_single_leg_request_error() {
+        // Start a WireMock server
+        let server = MockServer::start().await;
+
+        // Set up a mock response for an error request
+        let error_response = FlightEstimateResponse {
+            message: Some("Validation failed: Legs require valid airport codes".to_string()),
+            ..Default::default()
+        };
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(ResponseTemplate::new(400).set_body_json(&error_response))
+            .mount(&server)
+            .await;
+
+        // Create a test request
+        let request = FlightEstimateRequest {
+            estimate_type: "flight".to_string(),
+            passengers: 100,
+            legs: vec![Leg {
+                departure_airport: "LHR".to_string(),
+                destination_airport: "XYZ".to_string(), // Invalid airport code
+                cabin_class: None,
+                passengers: None,
+                aircraft: None,
+            }],
+            distance_unit: None,
+        };
+
+        // Create the API client with the mock server's URI
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        // Make the request to the mock server
+        let response = make_estimates_request(&api_client, &request, "").await;
+
+        // Check the response
+        assert!(response.is_err());
+        let error = response.err().unwrap().to_string();
+        assert_eq!(
+            error,
+            "API error: Validation failed: Legs require valid airport codes"
+        );
+    }
+    #[tokio::test]
+    async fn test_make_estimates_request_multiple_legs_success() {
+        // Start a WireMock server
+        let server = MockServer::start().await;
+
+        // Set up a mock response for a successful request
+        let mock_response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
+            .mount(&server)
+            .await;
+
+        // Create a test request with multiple legs
+        let request = FlightEstimateRequest {
+            estimate_type: "flight".to_string(),
+            passengers: 100,
+            legs: vec![
+                Leg {
+                    departure_airport: "LHR".to_string(),
+                    destination_airport: "JFK".to_string(),
+                    cabin_class: None,
+                    passengers: None,
+                    aircraft: None,
+                },
+                Leg {
+                    departure_airport: "JFK".to_string(),
+                    destination_airport: "LHR".to_string(),
+                    cabin_class: None,
+                    passengers: None,
+                    aircraft: None,
+                },
+            ],
+            distance_unit: None,
+        };
+
+        // Create the API client with the mock server's URI
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        // Make the request to the mock server
+        let response = make_estimates_request(&api_client, &request, "").await;
+
+        // Check the response
+        assert!(response.is_ok());
+        let response = response.unwrap();
+        assert!(response.data.is_some());
+        let estimate = response.data.unwrap().attributes;
+        assert_eq!(estimate.carbon_g, 99911700.0);
+        assert_eq!(estimate.carbon_lb, 267.6);
+        assert_eq!(estimate.carbon_kg, 99911.7);
+        assert_eq!(estimate.carbon_mt, 99.91);
+        assert_eq!(estimate.distance_unit, "km");
+        assert_eq!(estimate.distance_value, 5660.34);
+    }
+    #[tokio::test]
+    async fn test_make_estimates_request_multiple_legs_error() {
+        let error_response = FlightEstimateResponse {
+            message: Some("Validation failed: Legs require valid airport codes".to_string()),
+            ..Default::default()
+        };
+        let backend = FakeBackend::json(400, &error_response);
+
+        // Create a test request with multiple legs
+        let request = FlightEstimateRequest {
+            estimate_type: "flight".to_string(),
+            passengers: 100,
+            legs: vec![
+                Leg {
+                    departure_airport: "LHR".to_string(),
+                    destination_airport: "XYZ".to_string(), // Invalid airport code
+                    cabin_class: None,
+                    passengers: None,
+                    aircraft: None,
+                },
+                Leg {
+                    departure_airport: "JFK".to_string(),
+                    destination_airport: "LHR".to_string(),
+                    cabin_class: None,
+                    passengers: None,
+                    aircraft: None,
+                },
+            ],
+            distance_unit: None,
+        };
+
+        let response = make_estimates_request(&backend, &request, "").await;
+
+        assert!(response.is_err());
+        let error = response.err().unwrap().to_string();
+        assert_eq!(
+            error,
+            "API error: Validation failed: Legs require valid airport codes"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_make_estimates_request_different_cabin_classes() {
+        // Start a WireMock server
+        let server = MockServer::start().await;
+
+        // Set up a mock response for a successful request
+        let mock_response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
+            .mount(&server)
+            .await;
+
+        // Create a test request with different cabin classes
+        let request = FlightEstimateRequest {
+            estimate_type: "flight".to_string(),
+            passengers: 100,
+            legs: vec![
+                Leg {
+                    departure_airport: "LHR".to_string(),
+                    destination_airport: "JFK".to_string(),
+                    cabin_class: Some("economy".to_string()),
+                    passengers: None,
+                    aircraft: None,
+                },
+                Leg {
+                    departure_airport: "JFK".to_string(),
+                    destination_airport: "LHR".to_string(),
+                    cabin_class: Some("business".to_string()),
+                    passengers: None,
+                    aircraft: None,
+                },
+            ],
+            distance_unit: None,
+        };
+
+        // Create the API client with the mock server's URI
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        // Make the request to the mock server
+        let response = make_estimates_request(&api_client, &request, "").await;
+
+        // Check the response
+        assert!(response.is_ok());
+        let response = response.unwrap();
+        assert!(response.data.is_some());
+        let estimate = response.data.unwrap().attributes;
+        assert_eq!(estimate.carbon_g, 99911700.0);
+        assert_eq!(estimate.carbon_lb, 267.6);
+        assert_eq!(estimate.carbon_kg, 99911.7);
+        assert_eq!(estimate.carbon_mt, 99.91);
+        assert_eq!(estimate.distance_unit, "km");
+        assert_eq!(estimate.distance_value, 5660.34);
+    }
+
+    #[tokio::test]
+    async fn test_make_estimates_request_different_distance_units() {
+        // Start a WireMock server
+        let server = MockServer::start().await;
+
+        // Set up a mock response for a successful request
+        let mock_response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "mi", 3512.0);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
+            .mount(&server)
+            .await;
+
+        // Create a test request with different distance units
+        let request = FlightEstimateRequest {
+            estimate_type: "flight".to_string(),
+            passengers: 100,
+            legs: vec![
+                Leg {
+                    departure_airport: "LHR".to_string(),
+                    destination_airport: "JFK".to_string(),
+                    cabin_class: None,
+                    passengers: None,
+                    aircraft: None,
+                },
+                Leg {
+                    departure_airport: "JFK".to_string(),
+                    destination_airport: "LHR".to_string(),
+                    cabin_class: None,
+                    passengers: None,
+                    aircraft: None,
+                },
+            ],
+            distance_unit: Some("mi".to_string()),
+        };
+
+        // Create the API client with the mock server's URI
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        // Make the request to the mock server
+        let response = make_estimates_request(&api_client, &request, "").await;
+
+        // Check the response
+        assert!(response.is_ok());
+        let response = response.unwrap();
+        assert!(response.data.is_some());
+        let estimate = response.data.unwrap().attributes;
+        assert_eq!(estimate.carbon_g, 99911700.0);
+        assert_eq!(estimate.carbon_lb, 267.6);
+        assert_eq!(estimate.carbon_kg, 99911.7);
+        assert_eq!(estimate.carbon_mt, 99.91);
+        assert_eq!(estimate.distance_unit, "mi");
+        assert_eq!(estimate.distance_value, 3512.0);
+    }
+    #[tokio::test]
+    async fn test_make_estimates_request_different_passenger_counts() {
+        // Start a WireMock server
+        let server = MockServer::start().await;
+
+        // Set up a mock response for a successful request
+        let mock_response =
+            create_mock_response(199823400.0, 535.2, 199823.4, 199.82, "km", 5660.34);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
+            .mount(&server)
+            .await;
+
+        let request = FlightEstimateRequest {
+            estimate_type: "flight".to_string(),
+            passengers: 200,
+            legs: vec![
+                Leg {
+                    departure_airport: "LHR".to_string(),
+                    destination_airport: "JFK".to_string(),
+                    cabin_class: None,
+                    passengers: None,
+                    aircraft: None,
+                },
+                Leg {
+                    departure_airport: "JFK".to_string(),
+                    destination_airport: "LHR".to_string(),
+                    cabin_class: None,
+                    passengers: None,
+                    aircraft: None,
+                },
+            ],
+            distance_unit: None,
+        };
+
+        // Create the API client with the mock server's URI
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        // Make the request to the mock server
+        let response = make_estimates_request(&api_client, &request, "").await;
+
+        // Check the response
+        assert!(response.is_ok());
+        let response = response.unwrap();
+        assert!(response.data.is_some());
+        let estimate = response.data.unwrap().attributes;
+        assert_eq!(estimate.carbon_g, 199823400.0);
+        assert_eq!(estimate.carbon_lb, 535.2);
+        assert_eq!(estimate.carbon_kg, 199823.4);
+        assert_eq!(estimate.carbon_mt, 199.82);
+        assert_eq!(estimate.distance_unit, "km");
+        assert_eq!(estimate.distance_value, 5660.34);
+    }
+
+    #[tokio::test]
+    async fn test_make_estimates_request_times_out() {
+        // Start a WireMock server that answers slower than the client is willing to wait
+        let server = MockServer::start().await;
+        let mock_response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(&mock_response)
+                    .set_delay(Duration::from_secs(2)),
+            )
+            .mount(&server)
+            .await;
+
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let api_client =
+            ApiClient::new(Client::new(), &server.uri()).with_timeout(Duration::from_millis(200));
+
+        let response = make_estimates_request(&api_client, &request, "").await;
+
+        let error = response.err().unwrap();
+        assert!(matches!(error, CliError::Timeout(_)));
+        assert_eq!(error.to_string(), "Request timed out after 0.2 seconds");
+    }
+
+    /// Collects every log line so tests can check what would have been printed.
+    struct CapturingLogger {
+        lines: Mutex<Vec<String>>,
+    }
+
+    impl log::Log for CapturingLogger {
+        fn enabled(&self, _: &log::Metadata) -> bool {
+            true
+        }
+
+        fn log(&self, record: &log::Record) {
+            self.lines.lock().unwrap().push(record.args().to_string());
+        }
+
+        fn flush(&self) {}
+    }
+
+    static LOGGER: CapturingLogger = CapturingLogger {
+        lines: Mutex::new(Vec::new()),
+    };
+
+    #[tokio::test]
+    async fn test_post_estimate_never_logs_the_api_key() {
+        let _ = log::set_logger(&LOGGER);
+        log::set_max_level(log::LevelFilter::Trace);
+        let server = MockServer::start().await;
+        let mock_response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
+            .mount(&server)
+            .await;
+
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+        make_estimates_request(&api_client, &request, "super-secret-key")
+            .await
+            .unwrap();
+
+        let lines = LOGGER.lines.lock().unwrap();
+        assert!(lines
+            .iter()
+            .any(|line| line.contains("Authorization header set")));
+        assert!(lines
+            .iter()
+            .any(|line| line.contains(r#""departure_airport":"LHR""#)));
+        assert!(lines.iter().all(|line| !line.contains("super-secret-key")));
+    }
+
+    #[tokio::test]
+    async fn test_post_estimate_retries_after_rate_limit() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(ResponseTemplate::new(429).insert_header("Retry-After", "1"))
+            .up_to_n_times(1)
+            .expect(1)
+            .mount(&server)
+            .await;
+        let mock_response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let request = create_request(1, &[("LHR", "JFK")]);
+        // A backoff this short shows the wait came from the header
+        let api_client = ApiClient::new(Client::new(), &server.uri())
+            .with_retries(DEFAULT_MAX_RETRIES, Duration::from_millis(1));
+
+        let started = std::time::Instant::now();
+        let response = make_estimates_request(&api_client, &request, "").await;
+
+        assert!(started.elapsed() >= Duration::from_secs(1));
+        let estimate = response.unwrap().data.unwrap().attributes;
+        assert_eq!(estimate.carbon_kg, 99911.7);
+    }
+
+    #[test]
+    fn test_parse_retry_after_seconds() {
+        let now = SystemTime::now();
+
+        assert_eq!(
+            parse_retry_after("120", now),
+            Some(Duration::from_secs(120))
+        );
+        assert_eq!(parse_retry_after(" 0 ", now), Some(Duration::ZERO));
+    }
+
+    #[test]
+    fn test_parse_retry_after_http_date() {
+        let now = httpdate::parse_http_date("Wed, 21 Oct 2015 07:28:00 GMT").unwrap();
+
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:28:30 GMT", now),
+            Some(Duration::from_secs(30))
+        );
+        assert_eq!(
+            parse_retry_after("Wed, 21 Oct 2015 07:27:00 GMT", now),
+            Some(Duration::ZERO)
+        );
+    }
+
+    #[test]
+    fn test_parse_retry_after_rejects_garbage() {
+        assert_eq!(parse_retry_after("soon", SystemTime::now()), None);
+        assert_eq!(parse_retry_after("-5", SystemTime::now()), None);
+    }
+
+    #[test]
+    fn test_backoff_cap_doubles_each_attempt() {
+        let api_client = ApiClient::new(Client::new(), DEFAULT_BASE_URL)
+            .with_retries(3, Duration::from_millis(100));
+
+        assert_eq!(api_client.backoff_cap(0), Duration::from_millis(100));
+        assert_eq!(api_client.backoff_cap(1), Duration::from_millis(200));
+        assert_eq!(api_client.backoff_cap(2), Duration::from_millis(400));
+    }
+
+    #[test]
+    fn test_seeded_backoff_delays_are_jittered_within_the_cap() {
+        let seeded = || {
+            ApiClient::new(Client::new(), DEFAULT_BASE_URL)
+                .with_retries(6, Duration::from_millis(100))
+                .with_jitter_seed(42)
+        };
+        let delays = |api_client: ApiClient| -> Vec<Duration> {
+            (0..6)
+                .map(|attempt| api_client.backoff_delay(attempt))
+                .collect()
+        };
+
+        let first = delays(seeded());
+        let second = delays(seeded());
+
+        assert_eq!(first, second);
+        for (attempt, delay) in first.iter().enumerate() {
+            assert!(
+                *delay <= Duration::from_millis(100 << attempt),
+                "{:?}",
+                first
+            );
+        }
+        // Jittered delays shouldn't just reproduce the caps
+        assert!(first
+            .iter()
+            .enumerate()
+            .any(|(attempt, delay)| *delay != Duration::from_millis(100 << attempt)));
+        assert_ne!(delays(seeded().with_jitter_seed(7)), first);
+    }
+
+    #[test]
+    fn test_zero_retry_delay_never_waits() {
+        let api_client = ApiClient::new(Client::new(), DEFAULT_BASE_URL)
+            .with_retries(3, Duration::ZERO)
+            .with_jitter_seed(1);
+
+        assert_eq!(api_client.backoff_delay(2), Duration::ZERO);
+    }
+
+    #[tokio::test]
+    async fn test_build_http_client_sends_requests_through_proxy() {
+        // The mock server stands in for the proxy; the API host itself doesn't resolve
+        let proxy = MockServer::start().await;
+        let mock_response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
+            .expect(1)
+            .mount(&proxy)
+            .await;
+
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let client = build_http_client(Some(&proxy.uri()), DEFAULT_USER_AGENT).unwrap();
+        let api_client = ApiClient::new(client, "http://carbon-api.invalid");
+
+        let response = make_estimates_request(&api_client, &request, "").await;
+
+        assert_eq!(
+            response.unwrap().data.unwrap().attributes.carbon_kg,
+            99911.7
+        );
+    }
+
+    #[tokio::test]
+    async fn test_compressed_responses_are_decoded() {
+        let server = MockServer::start().await;
+        let mock_response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        let mut encoder = GzEncoder::new(Vec::new(), Compression::default());
+        encoder
+            .write_all(serde_json::to_string(&mock_response).unwrap().as_bytes())
+            .unwrap();
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(header_exists("accept-encoding"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .insert_header("content-encoding", "gzip")
+                    .set_body_raw(encoder.finish().unwrap(), "application/json"),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let client = build_http_client(None, DEFAULT_USER_AGENT).unwrap();
+        let api_client = ApiClient::new(client, &server.uri());
+        let request = create_request(1, &[("LHR", "JFK")]);
+
+        let response = make_estimates_request(&api_client, &request, "").await;
+
+        assert_eq!(
+            response.unwrap().data.unwrap().attributes.carbon_kg,
+            99911.7
+        );
+    }
+
+    #[test]
+    fn test_build_http_client_rejects_invalid_proxy() {
+        assert!(matches!(
+            build_http_client(Some("http://[::1"), DEFAULT_USER_AGENT),
+            Err(CliError::InvalidArguments(_))
+        ));
+        assert!(matches!(
+            build_http_client(None, "bad\nagent"),
+            Err(CliError::InvalidArguments(_))
+        ));
+    }
+
+    async fn user_agent_sent(user_agent: &str) -> bool {
+        let server = MockServer::start().await;
+        let mock_response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(header("user-agent", user_agent))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
+            .mount(&server)
+            .await;
+        let client = build_http_client(None, user_agent).unwrap();
+        let api_client = ApiClient::new(client, &server.uri());
+
+        make_estimates_request(&api_client, &create_request(1, &[("LHR", "JFK")]), "")
+            .await
+            .is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_requests_identify_the_tool_by_default() {
+        assert!(DEFAULT_USER_AGENT.starts_with("carbon-footprint-cli/0."));
+        assert!(user_agent_sent(DEFAULT_USER_AGENT).await);
+    }
+
+    #[tokio::test]
+    async fn test_user_agent_can_be_overridden() {
+        assert!(user_agent_sent("fleet-reports/2.1 (ops@example.com)").await);
+    }
+
+    /// The key as `auth_scheme` sent it: the request only gets an answer if `matcher` finds
+    /// the key where it should be.
+    async fn key_sent(auth_scheme: AuthScheme, matcher: impl Match + 'static) -> bool {
+        let server = MockServer::start().await;
+        let mock_response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(matcher)
+            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
+            .mount(&server)
+            .await;
+        let api_client = ApiClient::new(Client::new(), &server.uri()).with_auth_scheme(auth_scheme);
+
+        make_estimates_request(&api_client, &create_request(1, &[("LHR", "JFK")]), "k3y")
+            .await
+            .is_ok()
+    }
+
+    #[tokio::test]
+    async fn test_each_auth_scheme_puts_the_key_in_its_place() {
+        assert!(key_sent(AuthScheme::Bearer, header("authorization", "Bearer k3y")).await);
+        assert!(key_sent(AuthScheme::Header("X-Api-Key"), header("x-api-key", "k3y")).await);
+        assert!(
+            key_sent(
+                AuthScheme::QueryParam("api_key"),
+                query_param("api_key", "k3y")
+            )
+            .await
+        );
+
+        assert!(
+            !key_sent(
+                AuthScheme::Header("X-Api-Key"),
+                header_exists("authorization")
+            )
+            .await
+        );
+        assert!(
+            !key_sent(
+                AuthScheme::QueryParam("api_key"),
+                header_exists("authorization")
+            )
+            .await
+        );
+    }
+
+    #[tokio::test]
+    async fn test_query_param_key_stays_out_of_network_errors() {
+        let listener = std::net::TcpListener::bind("127.0.0.1:0").unwrap();
+        let uri = format!("http://{}", listener.local_addr().unwrap());
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                drop(stream);
+            }
+        });
+        let api_client =
+            ApiClient::new(Client::new(), &uri).with_auth_scheme(AuthScheme::QueryParam("api_key"));
+
+        let error =
+            make_estimates_request(&api_client, &create_request(1, &[("LHR", "JFK")]), "k3y")
+                .await
+                .unwrap_err();
+
+        assert!(matches!(error, CliError::NetworkError(_)), "{:?}", error);
+        assert!(!error.to_string().contains("k3y"), "{}", error);
+    }
+
+    #[test]
+    fn test_auth_scheme_names_where_the_key_went() {
+        assert_eq!(AuthScheme::Bearer.to_string(), "Authorization header");
+        assert_eq!(
+            AuthScheme::Header("X-Api-Key").to_string(),
+            "X-Api-Key header"
+        );
+        assert_eq!(
+            AuthScheme::QueryParam("api_key").to_string(),
+            "api_key query parameter"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_make_estimates_request_reports_html_error_pages() {
+        let page = format!(
+            "<html>\n  <body><h1>502 Bad Gateway</h1>{}</body>\n</html>",
+            "x".repeat(500)
+        );
+        let backend = FakeBackend::raw(502, "text/html", &page);
+
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let error = make_estimates_request(&backend, &request, "")
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, CliError::ApiError(_)));
+        let message = error.to_string();
+        assert!(message
+            .starts_with("API error: HTTP 502 Bad Gateway: <html> <body><h1>502 Bad Gateway</h1>"));
+        assert!(message.ends_with('…'));
+    }
+
+    #[test]
+    fn test_body_snippet_keeps_short_bodies_whole() {
+        assert_eq!(
+            body_snippet("Service\n  Unavailable\n"),
+            "Service Unavailable"
+        );
+    }
+
+    async fn error_for_status(status: u16) -> CliError {
+        let error_response = FlightEstimateResponse {
+            message: Some("Validation failed: Legs require valid airport codes".to_string()),
+            ..Default::default()
+        };
+        let backend = FakeBackend::json(status, &error_response);
+
+        let request = create_request(1, &[("LHR", "JFK")]);
+        make_estimates_request(&backend, &request, "")
+            .await
+            .unwrap_err()
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized_status() {
+        let error = error_for_status(401).await;
+
+        assert!(matches!(error, CliError::Unauthorized));
+        assert_eq!(error.to_string(), "Authentication failed: check that your API key is right (whether from --api-key, CARBON_INTERFACE_API_KEY or `login`) and that your Carbon Interface account has quota left.");
+    }
+
+    #[tokio::test]
+    async fn test_forbidden_status_is_unauthorized() {
+        let error = error_for_status(403).await;
+
+        assert!(matches!(error, CliError::Unauthorized));
+    }
+
+    #[tokio::test]
+    async fn test_unauthorized_html_page_is_still_unauthorized() {
+        let backend = FakeBackend::raw(401, "text/html", "<h1>Unauthorized</h1>");
+
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let error = make_estimates_request(&backend, &request, "badkey")
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, CliError::Unauthorized));
+        assert!(error.to_string().contains("CARBON_INTERFACE_API_KEY"));
+    }
+
+    #[tokio::test]
+    async fn test_unprocessable_status_is_a_validation_error() {
+        let error = error_for_status(422).await;
+
+        assert!(matches!(
+            &error,
+            CliError::ValidationError(message) if message.contains("valid airport codes")
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_unprocessable_status_lists_each_field() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(ResponseTemplate::new(422).set_body_json(serde_json::json!({
+                "message": "Validation failed",
+                "errors": [
+                    {"field": "legs[1].destination_airport", "message": "is not a valid airport code"},
+                    {"field": "passengers", "message": "must be greater than 0"}
+                ]
+            })))
+            .mount(&server)
+            .await;
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        let request = create_request(1, &[("LHR", "JFK"), ("JFK", "XYZ")]);
+        let error = make_estimates_request(&api_client, &request, "")
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, CliError::ValidationError(_)));
+        assert_eq!(
+            error.to_string(),
+            "The API rejected the trip details: Validation failed\n  \
+             - leg 2 destination_airport: is not a valid airport code\n  \
+             - passengers: must be greater than 0"
+        );
+    }
+
+    #[test]
+    fn test_validation_details_keyed_by_field() {
+        let body = r#"{"errors": {"legs.0.cabin_class": ["is not included in the list"]}}"#;
+
+        assert_eq!(
+            validation_details(body).as_deref(),
+            Some("Validation failed\n  - leg 1 cabin_class: is not included in the list")
+        );
+        assert_eq!(
+            validation_details(r#"{"message": "Bad", "errors": []}"#),
+            None
+        );
+        assert_eq!(validation_details(r#"{"message": "Bad"}"#), None);
+        assert_eq!(describe_field("legs[x].cabin_class"), "legs[x].cabin_class");
+    }
+
+    #[tokio::test]
+    async fn test_server_error_status() {
+        let error = error_for_status(500).await;
+
+        assert!(matches!(error, CliError::ServerError(500)));
+        assert!(error.to_string().contains("HTTP 500"));
+    }
+
+    #[tokio::test]
+    async fn test_bad_request_status_keeps_the_api_message() {
+        let error = error_for_status(400).await;
+
+        assert_eq!(
+            error.to_string(),
+            "API error: Validation failed: Legs require valid airport codes"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_success_without_data_is_an_error() {
+        let backend = FakeBackend::raw(200, "application/json", "{}");
+
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let error = make_estimates_request(&backend, &request, "")
+            .await
+            .unwrap_err();
+
+        assert_eq!(error.to_string(), "API error: Missing response data");
+    }
+
+    #[tokio::test]
+    async fn test_empty_body_is_reported_as_an_empty_response() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(ResponseTemplate::new(200).set_body_string(""))
+            .mount(&server)
+            .await;
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let error = make_estimates_request(&api_client, &request, "")
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, CliError::EmptyResponse));
+        assert_eq!(
+            error.to_string(),
+            "Empty response from server — the request may not have completed"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_blank_body_keeps_the_status_error() {
+        let whitespace = FakeBackend::raw(200, "application/json", " \n");
+        let server_error = FakeBackend::raw(503, "application/json", "");
+        let request = create_request(1, &[("LHR", "JFK")]);
+
+        assert!(matches!(
+            make_estimates_request(&whitespace, &request, "").await,
+            Err(CliError::EmptyResponse)
+        ));
+        assert!(matches!(
+            make_estimates_request(&server_error, &request, "").await,
+            Err(CliError::ServerError(503))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_malformed_json_is_an_unexpected_format() {
+        let backend = FakeBackend::raw(200, "application/json", "{\"data\": [");
+
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let error = make_estimates_request(&backend, &request, "")
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, CliError::UnexpectedResponseFormat(_)));
+    }
+
+    #[tokio::test]
+    async fn test_request_without_legs_is_rejected_before_sending() {
+        let mut request = create_request(1, &[("LHR", "JFK")]);
+        request.legs.clear();
+
+        // The backend would answer with a timeout if anything were sent
+        let error = make_estimates_request(&UnreachableBackend, &request, "")
+            .await
+            .unwrap_err();
+
+        assert_eq!(
+            error.to_string(),
+            "Invalid input: at least one leg is required"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_backend_errors_are_passed_through() {
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let error = make_estimates_request(&UnreachableBackend, &request, "")
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, CliError::Timeout(Some(DEFAULT_TIMEOUT))));
+    }
+
+    #[tokio::test]
+    async fn test_negative_carbon_is_implausible() {
+        let backend = FakeBackend::json(
+            200,
+            &create_mock_response(-1000.0, -2.2, -1.0, -0.001, "km", 5540.0),
+        );
+
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let error = make_estimates_request(&backend, &request, "")
+            .await
+            .unwrap_err();
+
+        assert!(matches!(
+            &error,
+            CliError::ImplausibleResponse(message) if message == "carbon_g is -1000"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_missing_figures_keep_the_others() {
+        let backend = FakeBackend::json(
+            200,
+            &serde_json::json!({"data": {"attributes": {
+                "carbon_g": 1000.0,
+                "carbon_kg": 1.0,
+                "carbon_mt": 0.001,
+                "distance_unit": "km",
+                "distance_value": 5540.0
+            }}}),
+        );
+
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let response = make_estimates_request(&backend, &request, "")
+            .await
+            .unwrap();
+
+        let attributes = response.data.unwrap().attributes;
+        assert!(attributes.carbon_lb.is_nan());
+        assert_eq!(attributes.carbon_kg, 1.0);
+        assert_eq!(attributes.carbon_mt, 0.001);
+        assert_eq!(attributes.distance_value, 5540.0);
+    }
+
+    #[tokio::test]
+    async fn test_estimate_flight_returns_the_figures() {
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let backend = FakeBackend::json(
+            200,
+            &create_mock_response(1000.0, 2.2, 1.0, 0.001, "km", 5540.0),
+        );
+
+        let attributes = estimate_flight(&backend, &request, "").await.unwrap();
+        assert_eq!(attributes.carbon_kg, 1.0);
+        assert_eq!(attributes.distance_value, 5540.0);
+
+        let empty = FakeBackend::json(200, &serde_json::json!({}));
+        assert!(matches!(
+            estimate_flight(&empty, &request, "").await,
+            Err(CliError::ApiError(message)) if message == "Missing response data"
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_negative_distance_is_implausible() {
+        let backend = FakeBackend::json(
+            200,
+            &create_mock_response(1000.0, 2.2, 1.0, 0.001, "km", -5540.0),
+        );
+
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let error = make_estimates_request(&backend, &request, "")
+            .await
+            .unwrap_err();
+
+        assert!(matches!(error, CliError::ImplausibleResponse(_)));
+    }
+
+    #[tokio::test]
+    async fn test_zero_carbon_over_a_distance_is_only_a_warning() {
+        let response = create_mock_response(0.0, 0.0, 0.0, 0.0, "km", 5540.0);
+        assert!(is_zero_carbon_flight(
+            &response.data.as_ref().unwrap().attributes
+        ));
+        let backend = FakeBackend::json(200, &response);
+
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let estimate = attributes_of(
+            make_estimates_request(&backend, &request, "")
+                .await
+                .unwrap(),
+        )
+        .unwrap();
+
+        assert_eq!(estimate.carbon_kg, 0.0);
+    }
+
+    #[test]
+    fn test_zero_carbon_without_distance_is_not_flagged() {
+        let response = create_mock_response(0.0, 0.0, 0.0, 0.0, "km", 0.0);
+
+        assert!(!is_zero_carbon_flight(&response.data.unwrap().attributes));
+    }
+
+    /// Moves on by `step` each time it's read, so a request appears to take exactly `step`.
+    struct SteppingClock {
+        start: Instant,
+        step: Duration,
+        reads: Mutex<u32>,
+    }
+
+    impl Clock for SteppingClock {
+        fn now(&self) -> Instant {
+            let mut reads = self.reads.lock().unwrap();
+            let now = self.start + self.step * *reads;
+            *reads += 1;
+            now
+        }
+    }
+
+    #[tokio::test]
+    async fn test_diagnostics_measure_the_call() {
+        let response = create_mock_response(1000.0, 2.2, 1.0, 0.001, "km", 5540.0);
+        let body = serde_json::to_string(&response).unwrap();
+        let backend = FakeBackend::json(200, &response);
+        let clock = SteppingClock {
+            start: Instant::now(),
+            step: Duration::from_millis(250),
+            reads: Mutex::new(0),
+        };
+
+        let request = create_request(2, &[("LHR", "JFK")]);
+        let (response, diagnostics) =
+            make_estimates_request_with_diagnostics(&backend, &request, "", &clock)
+                .await
+                .unwrap();
+
+        assert_eq!(attributes_of(response).unwrap().carbon_kg, 1.0);
+        assert_eq!(
+            diagnostics,
+            RequestDiagnostics {
+                duration: Duration::from_millis(250),
+                bytes_sent: serde_json::to_string(&request).unwrap().len(),
+                bytes_received: body.len(),
+            }
+        );
+    }
+}
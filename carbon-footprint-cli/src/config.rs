@@ -0,0 +1,385 @@
+//! Defaults read from a TOML config file.
+
+use crate::error::CliError;
+use crate::model::DISTANCE_UNITS;
+use crate::offline::CabinFactors;
+use crate::output::OutputFormat;
+use serde_derive::Deserialize;
+use std::collections::BTreeMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+
+/// Defaults for options that would otherwise have to be passed on every run. Anything
+/// given on the command line takes precedence.
+///
+/// ```toml
+/// distance_unit = "mi"
+/// passengers = 2
+/// offset_price = 12.5
+/// base_url = "https://www.carboninterface.com"
+/// output_format = "json"
+/// emission_factor = 0.115
+/// rf_multiplier = 1.9
+/// currency = "GBP"
+/// airports_url = "https://example.com/airports.csv"
+/// airports_ttl_days = 30
+/// user_agent = "carbon-footprint-cli (reports@example.com)"
+/// default_profile = "personal"
+///
+/// [cabin_factors]
+/// business = 3.0
+///
+/// [thresholds]
+/// warn_carbon_kg = 500.0
+/// error_carbon_kg = 1000.0
+/// short_flight_km = 400.0
+///
+/// [profiles.work]
+/// offset_price = 30.0
+/// currency = "EUR"
+/// ```
+///
+/// A profile holds the same settings as the top level, and overrides them when it's
+/// selected with `--profile` or `default_profile`.
+#[derive(Deserialize, Default, Clone, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Config {
+    pub distance_unit: Option<String>,
+    pub passengers: Option<u32>,
+    pub offset_price: Option<f32>,
+    pub base_url: Option<String>,
+    pub output_format: Option<OutputFormat>,
+    /// kg of CO2 per passenger-km used by `--offline`
+    pub emission_factor: Option<f32>,
+    /// Radiative forcing multiplier for the CO2-equivalent figure
+    pub rf_multiplier: Option<f32>,
+    /// Per-cabin multipliers for `--offline`
+    pub cabin_factors: Option<CabinFactors>,
+    /// Levels the estimates are checked against on every run
+    pub thresholds: Option<Thresholds>,
+    /// ISO code of the currency offset costs are shown in
+    pub currency: Option<String>,
+    /// Where to download the airport list from instead of using the bundled one
+    pub airports_url: Option<String>,
+    /// How many days a downloaded airport list is used before it's fetched again
+    pub airports_ttl_days: Option<u32>,
+    /// What requests send as their `User-Agent`
+    pub user_agent: Option<String>,
+    /// The profile used when `--profile` isn't given
+    pub default_profile: Option<String>,
+    #[serde(default)]
+    pub profiles: BTreeMap<String, Config>,
+}
+
+/// Warning and error levels for a run's emissions and a trip's distance, applied as
+/// `--warn-carbon-kg`, `--budget-kg`, `--warn-distance` and `--max-distance` respectively
+/// unless those flags are given. Distances are in the run's distance unit, except
+/// `short_flight_km` (`--short-flight-km`), which is always in km.
+#[derive(Deserialize, Default, Clone, Copy, Debug, PartialEq)]
+#[serde(deny_unknown_fields)]
+pub struct Thresholds {
+    pub warn_carbon_kg: Option<f32>,
+    pub error_carbon_kg: Option<f32>,
+    pub warn_distance: Option<f32>,
+    pub error_distance: Option<f32>,
+    pub short_flight_km: Option<f32>,
+}
+
+impl Thresholds {
+    fn validate(&self) -> Result<(), String> {
+        let levels = [
+            ("carbon_kg", self.warn_carbon_kg, self.error_carbon_kg),
+            ("distance", self.warn_distance, self.error_distance),
+        ];
+        for (name, warn, error) in levels {
+            if warn.into_iter().chain(error).any(|level| level <= 0.0) {
+                return Err(format!("thresholds for {} must be positive", name));
+            }
+            if let (Some(warn), Some(error)) = (warn, error) {
+                if warn > error {
+                    return Err(format!(
+                        "thresholds.warn_{} can't be over thresholds.error_{}",
+                        name, name
+                    ));
+                }
+            }
+        }
+        if self.short_flight_km.is_some_and(|km| km <= 0.0) {
+            return Err("thresholds.short_flight_km must be positive".to_string());
+        }
+        Ok(())
+    }
+}
+
+impl Config {
+    /// Where the config file lives when `--config` isn't given, e.g.
+    /// `~/.config/carbon-footprint/config.toml` on Linux.
+    pub fn default_path() -> Option<PathBuf> {
+        dirs::config_dir().map(|dir| dir.join("carbon-footprint").join("config.toml"))
+    }
+
+    /// Reads the config file at `path`, which must exist.
+    pub fn load(path: &Path) -> Result<Config, CliError> {
+        let contents = fs::read_to_string(path)
+            .map_err(|err| CliError::InvalidConfig(path.to_path_buf(), err.to_string()))?;
+        Config::parse(&contents)
+            .map_err(|message| CliError::InvalidConfig(path.to_path_buf(), message))
+    }
+
+    /// Like `load`, but a missing file just means there are no configured defaults.
+    pub fn load_or_default(path: &Path) -> Result<Config, CliError> {
+        match fs::metadata(path) {
+            Err(err) if err.kind() == io::ErrorKind::NotFound => Ok(Config::default()),
+            _ => Config::load(path),
+        }
+    }
+
+    /// The settings for a run: the top level overlaid with `profile`, or else with
+    /// `default_profile` if there is one.
+    pub fn select_profile(mut self, profile: Option<&str>) -> Result<Config, CliError> {
+        let Some(name) = profile.map(String::from).or(self.default_profile.take()) else {
+            return Ok(self);
+        };
+        let Some(selected) = self.profiles.remove(&name) else {
+            let known: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            return Err(CliError::InvalidArguments(format!(
+                "there's no profile named {} in the config (profiles: {})",
+                name,
+                if known.is_empty() {
+                    "none".to_string()
+                } else {
+                    known.join(", ")
+                }
+            )));
+        };
+
+        Ok(Config {
+            distance_unit: selected.distance_unit.or(self.distance_unit),
+            passengers: selected.passengers.or(self.passengers),
+            offset_price: selected.offset_price.or(self.offset_price),
+            base_url: selected.base_url.or(self.base_url),
+            output_format: selected.output_format.or(self.output_format),
+            emission_factor: selected.emission_factor.or(self.emission_factor),
+            rf_multiplier: selected.rf_multiplier.or(self.rf_multiplier),
+            cabin_factors: selected.cabin_factors.or(self.cabin_factors),
+            thresholds: selected.thresholds.or(self.thresholds),
+            currency: selected.currency.or(self.currency),
+            airports_url: selected.airports_url.or(self.airports_url),
+            airports_ttl_days: selected.airports_ttl_days.or(self.airports_ttl_days),
+            user_agent: selected.user_agent.or(self.user_agent),
+            default_profile: None,
+            profiles: BTreeMap::new(),
+        })
+    }
+
+    fn parse(contents: &str) -> Result<Config, String> {
+        let mut config: Config = toml::from_str(contents).map_err(|err| err.to_string())?;
+        config.validate()?;
+
+        for (name, profile) in &mut config.profiles {
+            if profile.default_profile.is_some() || !profile.profiles.is_empty() {
+                return Err(format!("profile {} can't contain other profiles", name));
+            }
+            profile
+                .validate()
+                .map_err(|message| format!("profile {}: {}", name, message))?;
+        }
+        if let Some(name) = &config.default_profile {
+            if !config.profiles.contains_key(name) {
+                return Err(format!("default_profile {} isn't defined", name));
+            }
+        }
+
+        Ok(config)
+    }
+
+    /// Checks the settings themselves, normalising the currency code.
+    fn validate(&mut self) -> Result<(), String> {
+        if let Some(distance_unit) = &self.distance_unit {
+            if !DISTANCE_UNITS.contains(&distance_unit.as_str()) {
+                return Err(format!(
+                    "distance_unit should be one of: {}",
+                    DISTANCE_UNITS.join(", ")
+                ));
+            }
+        }
+        if self.passengers == Some(0) {
+            return Err("passengers must be at least 1".to_string());
+        }
+        if self.offset_price.is_some_and(|price| price < 0.0) {
+            return Err("offset_price can't be negative".to_string());
+        }
+        if self.emission_factor.is_some_and(|factor| factor <= 0.0) {
+            return Err("emission_factor must be positive".to_string());
+        }
+        if self.rf_multiplier.is_some_and(|factor| factor <= 0.0) {
+            return Err("rf_multiplier must be positive".to_string());
+        }
+        if let Some(thresholds) = &self.thresholds {
+            thresholds.validate()?;
+        }
+        if let Some(factors) = &self.cabin_factors {
+            let values = [
+                factors.economy,
+                factors.premium,
+                factors.business,
+                factors.first,
+            ];
+            if values.iter().any(|factor| *factor <= 0.0) {
+                return Err("cabin_factors must all be positive".to_string());
+            }
+        }
+        if let Some(currency) = &mut self.currency {
+            if currency.len() != 3 || !currency.chars().all(|c| c.is_ascii_alphabetic()) {
+                return Err("currency should be a three-letter ISO code, e.g. EUR".to_string());
+            }
+            currency.make_ascii_uppercase();
+        }
+
+        Ok(())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_parse_config() {
+        let config = Config::parse(
+            r#"
+            distance_unit = "mi"
+            passengers = 2
+            offset_price = 12.5
+            output_format = "csv"
+            emission_factor = 0.1
+
+            [cabin_factors]
+            first = 5.0
+            "#,
+        )
+        .unwrap();
+
+        assert_eq!(
+            config,
+            Config {
+                distance_unit: Some("mi".to_string()),
+                passengers: Some(2),
+                offset_price: Some(12.5),
+                base_url: None,
+                output_format: Some(OutputFormat::Csv),
+                emission_factor: Some(0.1),
+                cabin_factors: Some(CabinFactors {
+                    first: 5.0,
+                    ..CabinFactors::default()
+                }),
+                ..Config::default()
+            }
+        );
+    }
+
+    const PROFILES: &str = r#"
+        offset_price = 15.0
+        passengers = 2
+        default_profile = "personal"
+
+        [profiles.personal]
+        currency = "gbp"
+
+        [profiles.work]
+        offset_price = 30.0
+        currency = "EUR"
+    "#;
+
+    #[test]
+    fn test_select_named_profile() {
+        let config = Config::parse(PROFILES)
+            .unwrap()
+            .select_profile(Some("work"))
+            .unwrap();
+
+        assert_eq!(config.offset_price, Some(30.0));
+        assert_eq!(config.currency.as_deref(), Some("EUR"));
+        // Settings the profile leaves out come from the top level
+        assert_eq!(config.passengers, Some(2));
+    }
+
+    #[test]
+    fn test_default_profile_is_used_without_a_name() {
+        let config = Config::parse(PROFILES)
+            .unwrap()
+            .select_profile(None)
+            .unwrap();
+
+        assert_eq!(config.currency.as_deref(), Some("GBP"));
+        assert_eq!(config.offset_price, Some(15.0));
+
+        let plain = Config::parse("offset_price = 15.0")
+            .unwrap()
+            .select_profile(None)
+            .unwrap();
+        assert_eq!(plain.offset_price, Some(15.0));
+    }
+
+    #[test]
+    fn test_unknown_profiles_are_rejected() {
+        assert!(matches!(
+            Config::parse(PROFILES)
+                .unwrap()
+                .select_profile(Some("holiday")),
+            Err(CliError::InvalidArguments(_))
+        ));
+        assert!(Config::parse(r#"default_profile = "work""#).is_err());
+        assert!(Config::parse(
+            "[profiles.work]
+offset_price = -1.0"
+        )
+        .is_err());
+        assert!(Config::parse(
+            "[profiles.work.profiles.nested]
+passengers = 1"
+        )
+        .is_err());
+    }
+
+    #[test]
+    fn test_parse_config_rejects_invalid_values() {
+        assert!(Config::parse(r#"distance_unit = "furlongs""#).is_err());
+        assert!(Config::parse("passengers = 0").is_err());
+        assert!(Config::parse("offset_price = -1.0").is_err());
+        assert!(Config::parse("emission_factor = 0.0").is_err());
+        assert!(Config::parse("rf_multiplier = -1.9").is_err());
+        assert!(Config::parse("[thresholds]\nwarn_carbon_kg = 0.0").is_err());
+        assert!(Config::parse("[thresholds]\nshort_flight_km = -5.0").is_err());
+        assert!(
+            Config::parse("[thresholds]\nwarn_distance = 9000.0\nerror_distance = 8000.0").is_err()
+        );
+        assert!(Config::parse("[cabin_factors]\nbusiness = -2.9").is_err());
+        assert!(Config::parse("[cabin_factors]\nlie_flat = 3.0").is_err());
+        assert!(Config::parse(r#"output_format = "xml""#).is_err());
+        assert!(Config::parse(r#"distance_units = "km""#).is_err());
+    }
+
+    #[test]
+    fn test_missing_config_file_is_ignored() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+
+        assert_eq!(Config::load_or_default(&path).unwrap(), Config::default());
+        assert!(matches!(
+            Config::load(&path),
+            Err(CliError::InvalidConfig(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_load_config_reports_the_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(&path, "passengers = \"two\"").unwrap();
+
+        let error = Config::load_or_default(&path).unwrap_err().to_string();
+        assert!(error.contains("config.toml"));
+    }
+}
@@ -0,0 +1,158 @@
+//! Keeping the API key in the platform secret store between runs.
+
+use crate::error::CliError;
+use log::debug;
+use std::sync::Mutex;
+
+/// Somewhere the API key can be kept once `login` has asked for it.
+pub trait SecretStore {
+    /// The stored key, or `None` if nothing has been stored yet.
+    fn load(&self) -> Result<Option<String>, CliError>;
+    fn save(&self, api_key: &str) -> Result<(), CliError>;
+    /// Forgets the stored key, returning whether there was one.
+    fn delete(&self) -> Result<bool, CliError>;
+}
+
+/// Service and account names the key is filed under in the secret store.
+#[cfg(feature = "keyring")]
+const KEYRING_SERVICE: &str = "carbon-footprint-cli";
+#[cfg(feature = "keyring")]
+const KEYRING_USER: &str = "api-key";
+
+/// The platform secret store: the Keychain on macOS, the Credential Manager on Windows and
+/// the kernel keyring on Linux.
+#[cfg(feature = "keyring")]
+pub struct KeyringStore {
+    entry: keyring::Entry,
+}
+
+#[cfg(feature = "keyring")]
+impl KeyringStore {
+    pub fn new() -> Result<Self, CliError> {
+        let entry = keyring::Entry::new(KEYRING_SERVICE, KEYRING_USER).map_err(keyring_error)?;
+        Ok(Self { entry })
+    }
+}
+
+#[cfg(feature = "keyring")]
+impl SecretStore for KeyringStore {
+    fn load(&self) -> Result<Option<String>, CliError> {
+        match self.entry.get_password() {
+            Ok(api_key) => Ok(Some(api_key)),
+            Err(keyring::Error::NoEntry) => Ok(None),
+            Err(err) => Err(keyring_error(err)),
+        }
+    }
+
+    fn save(&self, api_key: &str) -> Result<(), CliError> {
+        self.entry.set_password(api_key).map_err(keyring_error)
+    }
+
+    fn delete(&self) -> Result<bool, CliError> {
+        match self.entry.delete_credential() {
+            Ok(()) => Ok(true),
+            Err(keyring::Error::NoEntry) => Ok(false),
+            Err(err) => Err(keyring_error(err)),
+        }
+    }
+}
+
+#[cfg(feature = "keyring")]
+fn keyring_error(err: keyring::Error) -> CliError {
+    CliError::KeyringError(err.to_string())
+}
+
+/// Keeps the key in memory for the life of the process; a stand-in for the platform store
+/// in tests.
+#[derive(Default)]
+pub struct MemoryStore {
+    api_key: Mutex<Option<String>>,
+}
+
+impl SecretStore for MemoryStore {
+    fn load(&self) -> Result<Option<String>, CliError> {
+        Ok(self.api_key.lock().unwrap().clone())
+    }
+
+    fn save(&self, api_key: &str) -> Result<(), CliError> {
+        *self.api_key.lock().unwrap() = Some(api_key.to_string());
+        Ok(())
+    }
+
+    fn delete(&self) -> Result<bool, CliError> {
+        Ok(self.api_key.lock().unwrap().take().is_some())
+    }
+}
+
+/// The platform secret store, or an error when this build was made without it.
+pub fn default_store() -> Result<Box<dyn SecretStore>, CliError> {
+    #[cfg(feature = "keyring")]
+    return Ok(Box::new(KeyringStore::new()?));
+
+    #[cfg(not(feature = "keyring"))]
+    Err(CliError::KeyringError(
+        "this build doesn't include keyring support".to_string(),
+    ))
+}
+
+/// The key saved by `login`, if the store has one. A store that can't be reached is
+/// treated as empty, so the caller falls back to asking for the key.
+pub fn stored_api_key(store: &dyn SecretStore) -> Option<String> {
+    match store.load() {
+        Ok(api_key) => api_key.filter(|api_key| !api_key.is_empty()),
+        Err(err) => {
+            debug!("Not using the stored API key: {}", err);
+            None
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    struct UnavailableStore;
+
+    impl SecretStore for UnavailableStore {
+        fn load(&self) -> Result<Option<String>, CliError> {
+            Err(CliError::KeyringError("no secret service".to_string()))
+        }
+
+        fn save(&self, _api_key: &str) -> Result<(), CliError> {
+            Err(CliError::KeyringError("no secret service".to_string()))
+        }
+
+        fn delete(&self) -> Result<bool, CliError> {
+            Err(CliError::KeyringError("no secret service".to_string()))
+        }
+    }
+
+    #[test]
+    fn test_memory_store_round_trip() {
+        let store = MemoryStore::default();
+        assert_eq!(stored_api_key(&store), None);
+
+        store.save("Xk3mD9pQ2rT7vW1yZ5bN8c").unwrap();
+        assert_eq!(
+            stored_api_key(&store).as_deref(),
+            Some("Xk3mD9pQ2rT7vW1yZ5bN8c")
+        );
+
+        assert!(store.delete().unwrap());
+        assert!(!store.delete().unwrap());
+        assert_eq!(stored_api_key(&store), None);
+    }
+
+    #[test]
+    fn test_unavailable_store_has_no_key() {
+        assert_eq!(stored_api_key(&UnavailableStore), None);
+    }
+
+    #[test]
+    fn test_empty_stored_key_is_ignored() {
+        let store = MemoryStore::default();
+        store.save("").unwrap();
+
+        assert_eq!(stored_api_key(&store), None);
+    }
+}
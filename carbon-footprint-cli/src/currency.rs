@@ -0,0 +1,195 @@
+//! Showing offset costs in currencies other than USD.
+
+use crate::error::CliError;
+use reqwest::Client;
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::future::Future;
+
+/// Approximate units of each currency per US dollar, used when no FX endpoint is configured.
+pub const STATIC_USD_RATES: [(&str, f32); 10] = [
+    ("EUR", 0.92),
+    ("GBP", 0.79),
+    ("JPY", 150.0),
+    ("CAD", 1.36),
+    ("AUD", 1.52),
+    ("NZD", 1.65),
+    ("CHF", 0.88),
+    ("SEK", 10.5),
+    ("CNY", 7.2),
+    ("INR", 83.0),
+];
+
+/// Converts `amount_usd` at `rate` units per dollar, rounded to two decimals.
+pub fn convert_currency(amount_usd: f32, rate: f32) -> f32 {
+    (amount_usd * rate * 100.0).round() / 100.0
+}
+
+/// The currency amounts are shown in, and how many units of it make a dollar.
+#[derive(Clone, Debug, PartialEq)]
+pub struct Currency {
+    pub code: String,
+    pub usd_rate: f32,
+}
+
+impl Currency {
+    pub fn usd() -> Self {
+        Self {
+            code: "USD".to_string(),
+            usd_rate: 1.0,
+        }
+    }
+
+    /// Formats a USD amount in this currency, e.g. `€13.80` or `SEK 157.50`.
+    pub fn format(&self, amount_usd: f32) -> String {
+        let amount = convert_currency(amount_usd, self.usd_rate);
+        match currency_symbol(&self.code) {
+            Some(symbol) => format!("{}{:.2}", symbol, amount),
+            None => format!("{} {:.2}", self.code, amount),
+        }
+    }
+}
+
+fn currency_symbol(code: &str) -> Option<&'static str> {
+    match code {
+        "USD" => Some("$"),
+        "EUR" => Some("€"),
+        "GBP" => Some("£"),
+        "JPY" | "CNY" => Some("¥"),
+        "INR" => Some("₹"),
+        "CAD" => Some("CA$"),
+        "AUD" => Some("A$"),
+        "NZD" => Some("NZ$"),
+        _ => None,
+    }
+}
+
+/// Somewhere to look up exchange rates.
+pub trait RateSource {
+    /// Units of `currency` per US dollar.
+    fn usd_rate(&self, currency: &str) -> impl Future<Output = Result<f32, CliError>> + Send;
+}
+
+/// The bundled `STATIC_USD_RATES`, for offline use.
+pub struct StaticRates;
+
+impl RateSource for StaticRates {
+    async fn usd_rate(&self, currency: &str) -> Result<f32, CliError> {
+        if currency == "USD" {
+            return Ok(1.0);
+        }
+        STATIC_USD_RATES
+            .iter()
+            .find(|(code, _)| *code == currency)
+            .map(|(_, rate)| *rate)
+            .ok_or_else(|| unknown_currency(currency))
+    }
+}
+
+/// An FX endpoint answering `GET {url}?from=USD&to=EUR` with `{"rates": {"EUR": 0.92}}`,
+/// as Frankfurter does.
+pub struct HttpRates {
+    client: Client,
+    url: String,
+}
+
+impl HttpRates {
+    pub fn new(client: Client, url: &str) -> Self {
+        Self {
+            client,
+            url: url.into(),
+        }
+    }
+}
+
+#[derive(Deserialize)]
+struct RatesResponse {
+    rates: HashMap<String, f32>,
+}
+
+impl RateSource for HttpRates {
+    async fn usd_rate(&self, currency: &str) -> Result<f32, CliError> {
+        if currency == "USD" {
+            return Ok(1.0);
+        }
+        let body = self
+            .client
+            .get(&self.url)
+            .query(&[("from", "USD"), ("to", currency)])
+            .send()
+            .await?
+            .error_for_status()?
+            .text()
+            .await?;
+        let response: RatesResponse = serde_json::from_str(&body)?;
+        response
+            .rates
+            .get(currency)
+            .copied()
+            .ok_or_else(|| unknown_currency(currency))
+    }
+}
+
+fn unknown_currency(currency: &str) -> CliError {
+    CliError::InvalidArguments(format!("no exchange rate available for {}", currency))
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use wiremock::{
+        matchers::{method, query_param},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    #[test]
+    fn test_convert_currency() {
+        assert_eq!(convert_currency(15.0, 1.0), 15.0);
+        assert_eq!(convert_currency(15.0, 0.92), 13.8);
+        assert_eq!(convert_currency(1498.65, 0.79), 1183.93);
+    }
+
+    #[test]
+    fn test_currency_format_uses_symbol_or_code() {
+        assert_eq!(Currency::usd().format(27.0), "$27.00");
+        let euros = Currency {
+            code: "EUR".to_string(),
+            usd_rate: 0.92,
+        };
+        assert_eq!(euros.format(15.0), "€13.80");
+        let krona = Currency {
+            code: "SEK".to_string(),
+            usd_rate: 10.5,
+        };
+        assert_eq!(krona.format(15.0), "SEK 157.50");
+    }
+
+    #[tokio::test]
+    async fn test_static_rates() {
+        assert_eq!(StaticRates.usd_rate("GBP").await.unwrap(), 0.79);
+        assert_eq!(StaticRates.usd_rate("USD").await.unwrap(), 1.0);
+        assert!(matches!(
+            StaticRates.usd_rate("XYZ").await,
+            Err(CliError::InvalidArguments(_))
+        ));
+    }
+
+    #[tokio::test]
+    async fn test_http_rates() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .and(query_param("from", "USD"))
+            .and(query_param("to", "EUR"))
+            .respond_with(
+                ResponseTemplate::new(200)
+                    .set_body_json(serde_json::json!({"rates": {"EUR": 0.9}})),
+            )
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let rates = HttpRates::new(Client::new(), &format!("{}/latest", server.uri()));
+
+        assert_eq!(rates.usd_rate("EUR").await.unwrap(), 0.9);
+    }
+}
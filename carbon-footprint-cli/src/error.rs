@@ -0,0 +1,266 @@
+//! The error type shared by the library and the command line interface.
+
+use std::error::Error;
+use std::fmt;
+use std::io;
+use std::path::PathBuf;
+use std::time::Duration;
+
+use crate::i18n::{t, tf, tfv};
+
+/// Everything that can go wrong while collecting input or requesting an estimate.
+#[derive(Debug)]
+pub enum CliError {
+    NetworkError(reqwest::Error),
+    /// The API's host name couldn't be looked up
+    DnsError(reqwest::Error),
+    /// Nothing was listening at the API's address
+    ConnectionRefused(reqwest::Error),
+    UnexpectedResponseFormat(serde_json::Error),
+    /// The API answered with nothing in the body, as when the connection drops before
+    /// the body arrives
+    EmptyResponse,
+    ApiError(String),
+    /// The API refused the key (401) or what it's allowed to do (403)
+    Unauthorized,
+    ValidationError(String),
+    ServerError(u16),
+    /// The API answered with figures no real flight could have, such as negative emissions
+    ImplausibleResponse(String),
+    TooManyInvalidInputs,
+    /// Standard input ended while a prompt was still waiting for an answer
+    InputClosed,
+    InvalidArguments(String),
+    InvalidInput(String),
+    /// The request took too long; the limit is known when the client set it
+    Timeout(Option<Duration>),
+    OutputFileExists(PathBuf),
+    OutputFileError(PathBuf, io::Error),
+    InvalidConfig(PathBuf, String),
+    HistoryError(PathBuf, io::Error),
+    /// The platform secret store couldn't be used
+    KeyringError(String),
+    /// The estimate succeeded but went over `--budget-kg`
+    BudgetExceeded {
+        total_kg: f32,
+        budget_kg: f32,
+    },
+    /// The estimate succeeded but went over `--warn-carbon-kg`, which isn't a failure
+    /// but is worth a script's attention
+    OverWarningLevel {
+        total_kg: f32,
+        warn_kg: f32,
+    },
+    /// A limit such as `--budget-kg` couldn't be checked, as the API left out the figure
+    /// it's about
+    UncheckedLimit {
+        limit: &'static str,
+        figure: &'static str,
+    },
+    /// Some requests in an `--input` batch failed; the rest were still estimated
+    BatchFailed {
+        failed: usize,
+        total: usize,
+    },
+    /// `--deadline` ran out before the request finished, so it was abandoned or never sent
+    DeadlineExceeded(Duration),
+    /// One of several requests failed, with which one it was
+    InRequest(Box<RequestError>),
+}
+
+/// An error from one of the requests of a batch or per-leg breakdown, with the request's
+/// position and route so the failing trip can be found.
+#[derive(Debug)]
+pub struct RequestError {
+    /// From 0, in the batch file or among the trip's legs
+    pub index: usize,
+    /// The request's stops, e.g. `LHR → JFK`
+    pub route: String,
+    pub error: CliError,
+}
+
+impl fmt::Display for RequestError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(&tfv(
+            "cli_error.in_request",
+            &[&self.index, &self.route, &self.error],
+        ))
+    }
+}
+
+impl fmt::Display for CliError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let message = match self {
+            CliError::NetworkError(err) => tf("cli_error.network", err),
+            CliError::DnsError(err) => match err.url().and_then(|url| url.host_str()) {
+                Some(host) => tf("cli_error.dns_host", host),
+                None => t("cli_error.dns").to_string(),
+            },
+            CliError::ConnectionRefused(err) => match err.url() {
+                Some(url) => tf("cli_error.refused_by", url.origin().ascii_serialization()),
+                None => t("cli_error.refused").to_string(),
+            },
+            CliError::UnexpectedResponseFormat(err) => tf("cli_error.response_format", err),
+            CliError::EmptyResponse => t("cli_error.empty_response").to_string(),
+            CliError::ApiError(err) => tf("cli_error.api", err),
+            CliError::Unauthorized => t("cli_error.unauthorized").to_string(),
+            CliError::ValidationError(err) => tf("cli_error.validation", err),
+            CliError::ServerError(status) => tf("cli_error.server", status),
+            CliError::ImplausibleResponse(err) => tf("cli_error.implausible", err),
+            CliError::TooManyInvalidInputs => t("cli_error.too_many_invalid_inputs").to_string(),
+            CliError::InputClosed => t("cli_error.input_closed").to_string(),
+            CliError::InvalidArguments(err) => tf("cli_error.invalid_arguments", err),
+            CliError::InvalidInput(err) => tf("cli_error.invalid_input", err),
+            CliError::OutputFileExists(path) => tf("cli_error.output_file_exists", path.display()),
+            CliError::OutputFileError(path, err) => {
+                tfv("cli_error.output_file", &[&path.display(), err])
+            }
+            CliError::InvalidConfig(path, err) => {
+                tfv("cli_error.invalid_config", &[&path.display(), err])
+            }
+            CliError::HistoryError(path, err) => tfv("cli_error.history", &[&path.display(), err]),
+            CliError::Timeout(Some(timeout)) => {
+                tf("cli_error.timeout_after", timeout.as_secs_f64())
+            }
+            CliError::Timeout(None) => t("cli_error.timeout").to_string(),
+            CliError::KeyringError(err) => tf("cli_error.keyring", err),
+            CliError::UncheckedLimit { limit, figure } => {
+                tfv("cli_error.unchecked_limit", &[limit, figure])
+            }
+            CliError::BudgetExceeded {
+                total_kg,
+                budget_kg,
+            } => tfv(
+                "cli_error.budget_exceeded",
+                &[&format!("{:.2}", total_kg), &format!("{:.2}", budget_kg)],
+            ),
+            CliError::OverWarningLevel { total_kg, warn_kg } => tfv(
+                "cli_error.over_warning_level",
+                &[&format!("{:.2}", total_kg), &format!("{:.2}", warn_kg)],
+            ),
+            CliError::BatchFailed { failed, total } => {
+                tfv("cli_error.batch_failed", &[failed, total])
+            }
+            CliError::DeadlineExceeded(deadline) => {
+                tf("cli_error.deadline", deadline.as_secs_f64())
+            }
+            CliError::InRequest(err) => err.to_string(),
+        };
+        f.write_str(&message)
+    }
+}
+
+impl Error for CliError {}
+
+/// Timeouts and failures to connect get their own variants, so users can tell a typo in
+/// the host from a server that's down; anything else stays a generic network error.
+impl From<reqwest::Error> for CliError {
+    fn from(err: reqwest::Error) -> Self {
+        if err.is_timeout() {
+            CliError::Timeout(None)
+        } else if err.is_connect() && caused_by(&err, is_connection_refused) {
+            CliError::ConnectionRefused(err)
+        } else if err.is_connect() && caused_by(&err, is_dns_failure) {
+            CliError::DnsError(err)
+        } else {
+            CliError::NetworkError(err)
+        }
+    }
+}
+
+/// Whether `err` or anything in its chain of sources matches `test`.
+fn caused_by(err: &(dyn Error + 'static), test: fn(&(dyn Error + 'static)) -> bool) -> bool {
+    let mut source = Some(err);
+    while let Some(err) = source {
+        if test(err) {
+            return true;
+        }
+        source = err.source();
+    }
+    false
+}
+
+fn is_connection_refused(err: &(dyn Error + 'static)) -> bool {
+    err.downcast_ref::<io::Error>()
+        .is_some_and(|err| err.kind() == io::ErrorKind::ConnectionRefused)
+}
+
+/// The HTTP stack reports failed lookups as a "dns error" wrapping the resolver's own error.
+fn is_dns_failure(err: &(dyn Error + 'static)) -> bool {
+    err.to_string().starts_with("dns error")
+}
+
+impl From<serde_json::Error> for CliError {
+    fn from(err: serde_json::Error) -> Self {
+        CliError::UnexpectedResponseFormat(err)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use reqwest::Client;
+    use std::net::TcpListener;
+    use wiremock::matchers::method;
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    async fn request_error(client: Client, url: &str) -> CliError {
+        CliError::from(client.get(url).send().await.unwrap_err())
+    }
+
+    fn client() -> Client {
+        // Keep proxies from the environment out of the way of the failures under test
+        Client::builder().no_proxy().build().unwrap()
+    }
+
+    #[tokio::test]
+    async fn test_unresolvable_host_is_a_dns_error() {
+        // .invalid is reserved and never resolves
+        let error = request_error(client(), "http://carbon-interface.invalid/").await;
+
+        assert!(matches!(error, CliError::DnsError(_)), "{:?}", error);
+        assert_eq!(
+            error.to_string(),
+            "Could not resolve host carbon-interface.invalid"
+        );
+    }
+
+    #[tokio::test]
+    async fn test_closed_port_is_connection_refused() {
+        let port = {
+            let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+            listener.local_addr().unwrap().port()
+        };
+
+        let error = request_error(client(), &format!("http://127.0.0.1:{}/", port)).await;
+
+        assert!(
+            matches!(error, CliError::ConnectionRefused(_)),
+            "{:?}",
+            error
+        );
+        assert_eq!(
+            error.to_string(),
+            format!("Connection refused by http://127.0.0.1:{}", port)
+        );
+    }
+
+    #[tokio::test]
+    async fn test_slow_response_is_a_timeout() {
+        let server = MockServer::start().await;
+        Mock::given(method("GET"))
+            .respond_with(ResponseTemplate::new(200).set_delay(Duration::from_secs(2)))
+            .mount(&server)
+            .await;
+        let client = Client::builder()
+            .no_proxy()
+            .timeout(Duration::from_millis(100))
+            .build()
+            .unwrap();
+
+        let error = request_error(client, &server.uri()).await;
+
+        assert!(matches!(error, CliError::Timeout(None)));
+        assert_eq!(error.to_string(), "Request timed out");
+    }
+}
@@ -0,0 +1,235 @@
+//! Reusing the estimates of requests already made, so a repeated trip doesn't use API
+//! quota.
+
+use crate::model::{EstimateAttributes, FlightEstimateRequest};
+use log::{debug, warn};
+use serde_derive::{Deserialize, Serialize};
+use std::collections::HashMap;
+use std::fs;
+use std::io;
+use std::path::{Path, PathBuf};
+use std::sync::atomic::{AtomicUsize, Ordering};
+use std::sync::Mutex;
+use std::time::{Duration, SystemTime, UNIX_EPOCH};
+
+/// How long an estimate kept by `--cache` is reused before the API is asked again.
+pub const DEFAULT_CACHE_TTL_HOURS: u32 = 24;
+
+/// Identifies a request by what's sent for it, so identical requests share a key. A
+/// request without a distance unit gets the same key as one asking for the API's
+/// default of km.
+pub fn request_cache_key(request: &FlightEstimateRequest) -> String {
+    let mut request = request.clone();
+    request
+        .distance_unit
+        .get_or_insert_with(|| "km".to_string());
+    let json = serde_json::to_string(&request).expect("flight requests always serialize to JSON");
+    format!("{:016x}", fnv1a(json.as_bytes()))
+}
+
+/// 64-bit FNV-1a, which unlike `DefaultHasher` is the same in every build, so keys saved
+/// to disk stay valid.
+fn fnv1a(bytes: &[u8]) -> u64 {
+    bytes.iter().fold(0xcbf2_9ce4_8422_2325, |hash, byte| {
+        (hash ^ u64::from(*byte)).wrapping_mul(0x0100_0000_01b3)
+    })
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+struct CachedEstimate {
+    /// Seconds since the Unix epoch
+    fetched_at: u64,
+    attributes: EstimateAttributes,
+}
+
+/// Estimates already made in this run, by `request_cache_key`, optionally kept in a file
+/// for later runs.
+pub struct EstimateCache {
+    entries: Mutex<HashMap<String, CachedEstimate>>,
+    file: Option<PathBuf>,
+    /// The API a saved cache's estimates came from, so another API's aren't reused
+    base_url: Option<String>,
+    now: SystemTime,
+    hits: AtomicUsize,
+}
+
+impl EstimateCache {
+    /// A cache that lasts as long as the run.
+    pub fn in_memory() -> Self {
+        Self {
+            entries: Mutex::new(HashMap::new()),
+            file: None,
+            base_url: None,
+            now: UNIX_EPOCH,
+            hits: AtomicUsize::new(0),
+        }
+    }
+
+    /// A cache kept in `file` for estimates from the API at `base_url`, starting with the
+    /// ones in it that are younger than `ttl` at `now`. A missing or unreadable file starts
+    /// an empty cache.
+    pub fn persistent(file: PathBuf, base_url: &str, ttl: Duration, now: SystemTime) -> Self {
+        let saved: HashMap<String, CachedEstimate> = fs::read_to_string(&file)
+            .ok()
+            .and_then(|contents| serde_json::from_str(&contents).ok())
+            .unwrap_or_default();
+        let entries = saved
+            .into_iter()
+            .filter(|(_, cached)| {
+                let fetched_at = UNIX_EPOCH + Duration::from_secs(cached.fetched_at);
+                // A fetch time in the future means the clock has moved; ask again to be safe
+                now.duration_since(fetched_at).is_ok_and(|age| age < ttl)
+            })
+            .collect();
+        Self {
+            entries: Mutex::new(entries),
+            file: Some(file),
+            base_url: Some(base_url.to_string()),
+            now,
+            hits: AtomicUsize::new(0),
+        }
+    }
+
+    /// Where `--cache` keeps estimates, e.g. `~/.cache/carbon-footprint/estimates.json`
+    /// on Linux.
+    pub fn default_file() -> Option<PathBuf> {
+        dirs::cache_dir().map(|dir| dir.join("carbon-footprint").join("estimates.json"))
+    }
+
+    /// The estimate already made for `request`, if there is one.
+    pub fn get(&self, request: &FlightEstimateRequest) -> Option<EstimateAttributes> {
+        let key = self.key(request);
+        let cached = self.entries.lock().unwrap().get(&key).cloned()?;
+        debug!("Reusing the cached estimate {}", key);
+        self.hits.fetch_add(1, Ordering::Relaxed);
+        Some(cached.attributes)
+    }
+
+    /// Keeps `attributes` as the estimate for `request`, saving the cache to its file if
+    /// it has one.
+    pub fn insert(&self, request: &FlightEstimateRequest, attributes: &EstimateAttributes) {
+        let cached = CachedEstimate {
+            fetched_at: self
+                .now
+                .duration_since(UNIX_EPOCH)
+                .map_or(0, |since_epoch| since_epoch.as_secs()),
+            attributes: attributes.clone(),
+        };
+        let mut entries = self.entries.lock().unwrap();
+        entries.insert(self.key(request), cached);
+        if let Some(file) = &self.file {
+            self.write(file, &entries);
+        }
+    }
+
+    /// `request_cache_key`, told apart by API for a saved cache since one file serves them all.
+    fn key(&self, request: &FlightEstimateRequest) -> String {
+        let key = request_cache_key(request);
+        match &self.base_url {
+            Some(base_url) => format!("{:016x}", fnv1a(format!("{} {}", base_url, key).as_bytes())),
+            None => key,
+        }
+    }
+
+    /// How many estimates have been answered from the cache.
+    pub fn hits(&self) -> usize {
+        self.hits.load(Ordering::Relaxed)
+    }
+
+    /// A cache that can't be written only costs a request next time, so failures are
+    /// logged rather than returned.
+    fn write(&self, file: &Path, entries: &HashMap<String, CachedEstimate>) {
+        let write = || -> io::Result<()> {
+            if let Some(parent) = file.parent() {
+                fs::create_dir_all(parent)?;
+            }
+            fs::write(file, serde_json::to_string(entries)?)
+        };
+        if let Err(err) = write() {
+            warn!("Couldn't cache estimates in {}: {}", file.display(), err);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::create_request;
+
+    const TTL: Duration = Duration::from_secs(24 * 60 * 60);
+    const BASE_URL: &str = "https://www.carboninterface.com";
+
+    fn attributes(carbon_kg: f32) -> EstimateAttributes {
+        EstimateAttributes {
+            carbon_g: carbon_kg * 1000.0,
+            carbon_lb: carbon_kg * 2.20462,
+            carbon_kg,
+            carbon_mt: carbon_kg / 1000.0,
+            distance_unit: "km".to_string(),
+            distance_value: 5555.0,
+        }
+    }
+
+    #[test]
+    fn test_identical_requests_share_a_key() {
+        let request = create_request(2, &[("LHR", "JFK")]);
+        let mut in_km = request.clone();
+        in_km.distance_unit = Some("km".to_string());
+        let mut in_miles = request.clone();
+        in_miles.distance_unit = Some("mi".to_string());
+
+        assert_eq!(
+            request_cache_key(&request),
+            request_cache_key(&create_request(2, &[("LHR", "JFK")]))
+        );
+        assert_eq!(request_cache_key(&request), request_cache_key(&in_km));
+        assert_ne!(request_cache_key(&request), request_cache_key(&in_miles));
+        assert_ne!(
+            request_cache_key(&request),
+            request_cache_key(&create_request(2, &[("LHR", "CDG")]))
+        );
+    }
+
+    #[test]
+    fn test_cache_counts_hits() {
+        let cache = EstimateCache::in_memory();
+        let request = create_request(1, &[("LHR", "JFK")]);
+
+        assert_eq!(cache.get(&request), None);
+        cache.insert(&request, &attributes(300.0));
+
+        assert_eq!(cache.get(&request), Some(attributes(300.0)));
+        assert_eq!(cache.hits(), 1);
+    }
+
+    #[test]
+    fn test_persistent_cache_expires_after_the_ttl() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("cache").join("estimates.json");
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let fetched_at = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        EstimateCache::persistent(file.clone(), BASE_URL, TTL, fetched_at)
+            .insert(&request, &attributes(300.0));
+
+        let fresh = EstimateCache::persistent(file.clone(), BASE_URL, TTL, fetched_at + TTL / 2);
+        assert_eq!(fresh.get(&request), Some(attributes(300.0)));
+
+        let stale = EstimateCache::persistent(file, BASE_URL, TTL, fetched_at + TTL);
+        assert_eq!(stale.get(&request), None);
+    }
+
+    #[test]
+    fn test_persistent_cache_keeps_apis_apart() {
+        let dir = tempfile::tempdir().unwrap();
+        let file = dir.path().join("estimates.json");
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let now = UNIX_EPOCH + Duration::from_secs(1_700_000_000);
+        EstimateCache::persistent(file.clone(), BASE_URL, TTL, now)
+            .insert(&request, &attributes(300.0));
+
+        let other_api = EstimateCache::persistent(file.clone(), "http://localhost:8080", TTL, now);
+        assert_eq!(other_api.get(&request), None);
+        let same_api = EstimateCache::persistent(file, BASE_URL, TTL, now);
+        assert_eq!(same_api.get(&request), Some(attributes(300.0)));
+    }
+}
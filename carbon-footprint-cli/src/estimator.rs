@@ -0,0 +1,126 @@
+//! Where flight estimates come from, so a source other than Carbon Interface can stand in
+//! without changing the rest of the tool.
+
+use crate::client::{estimate_flight, ApiClient, AuthScheme};
+use crate::error::CliError;
+use crate::model::{EstimateAttributes, FlightEstimateRequest};
+use crate::offline::{offline_estimate, CabinFactors, DEFAULT_EMISSION_FACTOR};
+use clap::ValueEnum;
+use std::future::{ready, Future};
+
+/// Something that can estimate a flight's emissions.
+pub trait CarbonEstimator {
+    /// The figures for `request`. Sources that need no key ignore `api_key`.
+    fn estimate(
+        &self,
+        request: &FlightEstimateRequest,
+        api_key: &str,
+    ) -> impl Future<Output = Result<EstimateAttributes, CliError>>;
+}
+
+/// The Carbon Interface API.
+impl CarbonEstimator for ApiClient {
+    fn estimate(
+        &self,
+        request: &FlightEstimateRequest,
+        api_key: &str,
+    ) -> impl Future<Output = Result<EstimateAttributes, CliError>> {
+        estimate_flight(self, request, api_key)
+    }
+}
+
+/// The great-circle approximation of `offline_estimate`, worked out without any request.
+#[derive(Clone, Copy, Debug, PartialEq)]
+pub struct OfflineEstimator {
+    pub kg_per_passenger_km: f32,
+    pub cabin_factors: CabinFactors,
+}
+
+impl Default for OfflineEstimator {
+    fn default() -> Self {
+        Self {
+            kg_per_passenger_km: DEFAULT_EMISSION_FACTOR,
+            cabin_factors: CabinFactors::default(),
+        }
+    }
+}
+
+impl CarbonEstimator for OfflineEstimator {
+    fn estimate(
+        &self,
+        request: &FlightEstimateRequest,
+        _api_key: &str,
+    ) -> impl Future<Output = Result<EstimateAttributes, CliError>> {
+        ready(offline_estimate(
+            request,
+            self.kg_per_passenger_km,
+            &self.cabin_factors,
+        ))
+    }
+}
+
+/// The estimate sources `--provider` chooses between.
+#[derive(ValueEnum, Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub enum Provider {
+    /// The Carbon Interface API
+    #[default]
+    CarbonInterface,
+    /// The local great-circle approximation
+    Offline,
+}
+
+impl Provider {
+    /// How this provider's API takes the key, or `None` for one that sends no request.
+    pub fn auth_scheme(self) -> Option<AuthScheme> {
+        match self {
+            Provider::CarbonInterface => Some(AuthScheme::Bearer),
+            Provider::Offline => None,
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::create_request;
+    use reqwest::Client;
+    use wiremock::matchers::{method, path};
+    use wiremock::{Mock, MockServer, ResponseTemplate};
+
+    #[tokio::test]
+    async fn test_estimators_answer_through_the_trait() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(ResponseTemplate::new(201).set_body_json(serde_json::json!({
+                "data": {"attributes": {
+                    "carbon_g": 300000.0, "carbon_lb": 661.4, "carbon_kg": 300.0,
+                    "carbon_mt": 0.3, "distance_unit": "km", "distance_value": 5555.0
+                }}
+            })))
+            .mount(&server)
+            .await;
+        let request = create_request(1, &[("LHR", "JFK")]);
+
+        let api = ApiClient::new(Client::new(), &server.uri())
+            .estimate(&request, "key")
+            .await
+            .unwrap();
+        let offline = OfflineEstimator::default()
+            .estimate(&request, "")
+            .await
+            .unwrap();
+
+        assert_eq!(api.carbon_kg, 300.0);
+        assert!(offline.distance_value > 5500.0 && offline.distance_value < 5600.0);
+    }
+
+    #[test]
+    fn test_each_provider_knows_its_auth_scheme() {
+        assert_eq!(
+            Provider::CarbonInterface.auth_scheme(),
+            Some(AuthScheme::Bearer)
+        );
+        assert_eq!(Provider::Offline.auth_scheme(), None);
+    }
+}
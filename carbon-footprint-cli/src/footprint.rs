@@ -0,0 +1,708 @@
+//! Translating emissions into offset prices and everyday equivalents.
+
+use crate::model::{is_missing, EstimateAttributes, MixedCabinLeg, MISSING_FIGURE};
+use serde_derive::Serialize;
+
+/// Typical voluntary-market price in USD for offsetting a tonne of CO2.
+pub const DEFAULT_OFFSET_PRICE: f32 = 15.0;
+
+/// Price in USD of offsetting `carbon_mt` tonnes of CO2, rounded to cents.
+pub fn offset_cost(carbon_mt: f32, price_per_tonne: f32) -> f32 {
+    (carbon_mt * price_per_tonne * 100.0).round() / 100.0
+}
+
+/// The radiative forcing multiplier that leaves estimates as the API reports them.
+pub const DEFAULT_RF_MULTIPLIER: f32 = 1.0;
+
+/// The estimate as CO2-equivalent: its emissions scaled by a radiative forcing `factor`
+/// for the extra warming of emissions at altitude (commonly around 1.9). The distance is
+/// kept as it is.
+pub fn apply_rf(attributes: &EstimateAttributes, factor: f32) -> EstimateAttributes {
+    EstimateAttributes {
+        carbon_g: attributes.carbon_g * factor,
+        carbon_lb: attributes.carbon_lb * factor,
+        carbon_kg: attributes.carbon_kg * factor,
+        carbon_mt: attributes.carbon_mt * factor,
+        ..attributes.clone()
+    }
+}
+
+/// Splits an estimate's `(carbon_kg, carbon_mt)` evenly between its passengers. A passenger
+/// count of zero is treated as one rather than dividing by zero.
+pub fn per_passenger(attributes: &EstimateAttributes, passengers: u32) -> (f32, f32) {
+    let passengers = passengers.max(1) as f32;
+    (
+        attributes.carbon_kg / passengers,
+        attributes.carbon_mt / passengers,
+    )
+}
+
+/// kg of CO2 per unit of distance travelled, in the estimate's `distance_unit`. Returns
+/// `None` for a zero (or missing) distance rather than dividing by it.
+pub fn intensity(attributes: &EstimateAttributes) -> Option<f32> {
+    (attributes.distance_value > 0.0).then(|| attributes.carbon_kg / attributes.distance_value)
+}
+
+/// kg of CO2 emitted per km by an average petrol car.
+pub const PETROL_CAR_KG_PER_KM: f32 = 0.17;
+/// kg of CO2 a mature tree absorbs in a year.
+pub const TREE_KG_PER_YEAR: f32 = 21.0;
+/// kg of CO2 emitted by fully charging a smartphone once.
+pub const SMARTPHONE_CHARGE_KG: f32 = 0.00822;
+
+/// Everyday activities with roughly the same carbon footprint as an estimate.
+#[derive(Debug, PartialEq)]
+pub struct Equivalencies {
+    pub car_km: f32,
+    pub tree_years: f32,
+    pub smartphone_charges: f32,
+}
+
+pub fn equivalencies(carbon_kg: f32) -> Equivalencies {
+    Equivalencies {
+        car_km: carbon_kg / PETROL_CAR_KG_PER_KM,
+        tree_years: carbon_kg / TREE_KG_PER_YEAR,
+        smartphone_charges: carbon_kg / SMARTPHONE_CHARGE_KG,
+    }
+}
+
+pub const KM_PER_MILE: f32 = 1.609_344;
+
+/// kg of CO2 emitted per passenger per km by an average intercity train.
+pub const RAIL_KG_PER_PASSENGER_KM: f32 = 0.035;
+
+/// What one traveller would emit covering a flight's distance without flying. The car
+/// figure assumes they drive alone in an average petrol car.
+#[derive(Debug, PartialEq)]
+pub struct Alternatives {
+    pub train_kg: f32,
+    pub car_kg: f32,
+}
+
+pub fn alternative_emissions(distance_km: f32) -> Alternatives {
+    Alternatives {
+        train_kg: distance_km * RAIL_KG_PER_PASSENGER_KM,
+        car_kg: distance_km * PETROL_CAR_KG_PER_KM,
+    }
+}
+
+/// An estimate's distance in km, whichever unit the API reported it in.
+pub fn distance_km(attributes: &EstimateAttributes) -> f32 {
+    convert_distance(attributes.distance_value, &attributes.distance_unit, "km")
+}
+
+/// Converts `value` between `km` and `mi`. Anything else is returned unchanged, since
+/// there's nothing to convert it with.
+pub fn convert_distance(value: f32, from: &str, to: &str) -> f32 {
+    match (from, to) {
+        ("km", "mi") => value / KM_PER_MILE,
+        ("mi", "km") => value * KM_PER_MILE,
+        _ => value,
+    }
+}
+
+/// Re-expresses an estimate's distance in `unit`, whichever unit the API answered in.
+pub fn with_distance_unit(attributes: &mut EstimateAttributes, unit: &str) {
+    attributes.distance_value =
+        convert_distance(attributes.distance_value, &attributes.distance_unit, unit);
+    attributes.distance_unit = unit.to_string();
+}
+
+/// Whether an estimate's distance goes over `max_distance`, given in `unit`, whichever
+/// unit the API answered in.
+pub fn exceeds_max_distance(
+    attributes: &EstimateAttributes,
+    max_distance: f32,
+    unit: &str,
+) -> bool {
+    convert_distance(attributes.distance_value, &attributes.distance_unit, unit) > max_distance
+}
+
+/// Whether an estimate covers no distance at all, which usually means the API couldn't
+/// place an airport or was sent a leg that goes nowhere.
+pub fn is_zero_distance(attributes: &EstimateAttributes) -> bool {
+    attributes.distance_value == 0.0
+}
+
+/// Legs shorter than this, in km, are usually greener by train or car.
+pub const DEFAULT_SHORT_FLIGHT_KM: f32 = 500.0;
+
+/// Whether a leg of `distance_km` is short enough to suggest going by land instead. A
+/// leg exactly at `threshold_km` isn't.
+pub fn is_short_flight(distance_km: f32, threshold_km: f32) -> bool {
+    distance_km < threshold_km
+}
+
+/// Whether `total_kg` goes over `budget`; landing exactly on it is still within budget.
+pub fn exceeds_budget(total_kg: f32, budget: f32) -> bool {
+    total_kg > budget
+}
+
+/// How much less `alternative_kg` emits than `flight_kg`, as a percentage of the flight;
+/// negative when the alternative emits more. `None` for a flight with no emissions.
+pub fn reduction_vs_flying(flight_kg: f32, alternative_kg: f32) -> Option<f32> {
+    (flight_kg > 0.0).then(|| (flight_kg - alternative_kg) / flight_kg * 100.0)
+}
+
+/// A unit the API reports emissions in.
+#[derive(clap::ValueEnum, Clone, Copy, Debug, PartialEq, Eq)]
+pub enum CarbonUnit {
+    /// Grams
+    G,
+    /// Kilograms
+    Kg,
+    /// Pounds
+    Lb,
+    /// Metric tonnes
+    Mt,
+}
+
+impl CarbonUnit {
+    /// The unit's suffix in the API's attribute names, as in `carbon_kg`.
+    pub fn suffix(self) -> &'static str {
+        match self {
+            CarbonUnit::G => "g",
+            CarbonUnit::Kg => "kg",
+            CarbonUnit::Lb => "lb",
+            CarbonUnit::Mt => "mt",
+        }
+    }
+}
+
+/// Picks out the emissions figures for `units`, in the order asked for and without repeats.
+pub fn select_units(
+    attributes: &EstimateAttributes,
+    units: &[CarbonUnit],
+) -> Vec<(CarbonUnit, f32)> {
+    let mut selected: Vec<(CarbonUnit, f32)> = Vec::new();
+    for &unit in units {
+        if selected.iter().any(|(seen, _)| *seen == unit) {
+            continue;
+        }
+        let value = match unit {
+            CarbonUnit::G => attributes.carbon_g,
+            CarbonUnit::Kg => attributes.carbon_kg,
+            CarbonUnit::Lb => attributes.carbon_lb,
+            CarbonUnit::Mt => attributes.carbon_mt,
+        };
+        selected.push((unit, value));
+    }
+    selected
+}
+
+/// The unit a total's distance is kept in once its estimates use different units.
+const CANONICAL_DISTANCE_UNIT: &str = "km";
+
+/// Adds up the known `figures`, leaving out missing ones. The sum is only `MISSING_FIGURE`
+/// when there were figures and none of them was known.
+pub fn sum_known(figures: impl IntoIterator<Item = f32>) -> f32 {
+    let mut total = None;
+    let mut any = false;
+    for figure in figures {
+        any = true;
+        if !is_missing(figure) {
+            *total.get_or_insert(0.0) += figure;
+        }
+    }
+    match total {
+        Some(total) => total,
+        None if any => MISSING_FIGURE,
+        None => 0.0,
+    }
+}
+
+/// Adds up several estimates. The distance stays in the estimates' unit when they all
+/// share one, and is otherwise totalled in km, each estimate converted from its own unit.
+/// Each figure is summed with `sum_known`, so one an estimate lacks is left out.
+pub fn sum_attributes(items: &[EstimateAttributes]) -> EstimateAttributes {
+    let distance_unit = match items.split_first() {
+        Some((first, rest))
+            if rest
+                .iter()
+                .all(|item| item.distance_unit == first.distance_unit) =>
+        {
+            first.distance_unit.clone()
+        }
+        _ => CANONICAL_DISTANCE_UNIT.to_string(),
+    };
+    let distance_value =
+        sum_known(items.iter().map(|item| {
+            convert_distance(item.distance_value, &item.distance_unit, &distance_unit)
+        }));
+
+    EstimateAttributes {
+        carbon_g: sum_known(items.iter().map(|item| item.carbon_g)),
+        carbon_lb: sum_known(items.iter().map(|item| item.carbon_lb)),
+        carbon_kg: sum_known(items.iter().map(|item| item.carbon_kg)),
+        carbon_mt: sum_known(items.iter().map(|item| item.carbon_mt)),
+        distance_unit,
+        distance_value,
+    }
+}
+
+/// Running totals over an interactive session's estimates, for a closing summary. Like
+/// `sum_attributes`, the distance moves to km once estimates in another unit are added.
+#[derive(Clone, Debug, Default, PartialEq)]
+pub struct SessionTotals {
+    pub flights: usize,
+    pub carbon_kg: f32,
+    pub carbon_mt: f32,
+    pub distance_value: f32,
+    /// The first estimate's unit, or km once one in another unit has been added. `None`
+    /// until anything has been.
+    pub distance_unit: Option<String>,
+}
+
+impl SessionTotals {
+    /// Counts one more successful estimate, leaving out any figure it lacks.
+    pub fn add(&mut self, attributes: &EstimateAttributes) {
+        let known = |figure: f32| if is_missing(figure) { 0.0 } else { figure };
+        self.flights += 1;
+        self.carbon_kg += known(attributes.carbon_kg);
+        self.carbon_mt += known(attributes.carbon_mt);
+        match &self.distance_unit {
+            None => {
+                self.distance_value = known(attributes.distance_value);
+                self.distance_unit = Some(attributes.distance_unit.clone());
+            }
+            Some(unit) if *unit == attributes.distance_unit => {
+                self.distance_value += known(attributes.distance_value);
+            }
+            Some(unit) => {
+                self.distance_value =
+                    convert_distance(self.distance_value, unit, CANONICAL_DISTANCE_UNIT)
+                        + known(distance_km(attributes));
+                self.distance_unit = Some(CANONICAL_DISTANCE_UNIT.to_string());
+            }
+        }
+    }
+}
+
+/// Adds up the per-class estimates for `legs`, given in `split_by_cabin` order. Every class
+/// on a leg flies the same distance, so each leg's distance is only counted once.
+pub fn combine_cabin_estimates(
+    legs: &[MixedCabinLeg],
+    estimates: Vec<EstimateAttributes>,
+) -> EstimateAttributes {
+    let mut estimates = estimates.into_iter();
+    let leg_totals: Vec<EstimateAttributes> = legs
+        .iter()
+        .map(|leg| {
+            let classes: Vec<EstimateAttributes> =
+                estimates.by_ref().take(leg.cabins.len()).collect();
+            let mut leg_total = sum_attributes(&classes);
+            if let Some(first) = classes.first() {
+                leg_total.distance_value = first.distance_value;
+            }
+            leg_total
+        })
+        .collect();
+    sum_attributes(&leg_totals)
+}
+
+/// Which of two compared estimates has the smaller footprint.
+#[derive(Serialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum Greener {
+    First,
+    Second,
+    Equal,
+}
+
+/// How two estimates differ, from the first's point of view.
+#[derive(Serialize, Debug, PartialEq)]
+pub struct Comparison {
+    /// `second.carbon_kg - first.carbon_kg`
+    pub difference_kg: f32,
+    /// The difference as a percentage of the first estimate, `None` when that's zero
+    pub percent_difference: Option<f32>,
+    pub greener: Greener,
+}
+
+pub fn compare_estimates(a: &EstimateAttributes, b: &EstimateAttributes) -> Comparison {
+    let difference_kg = b.carbon_kg - a.carbon_kg;
+    let greener = if difference_kg > 0.0 {
+        Greener::First
+    } else if difference_kg < 0.0 {
+        Greener::Second
+    } else {
+        Greener::Equal
+    };
+
+    Comparison {
+        difference_kg,
+        percent_difference: (a.carbon_kg != 0.0).then(|| difference_kg / a.carbon_kg * 100.0),
+        greener,
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::create_mock_response;
+
+    #[test]
+    fn test_offset_cost() {
+        assert_eq!(offset_cost(99.91, 15.0), 1498.65);
+        assert_eq!(offset_cost(1.0, 12.5), 12.5);
+        assert_eq!(offset_cost(0.123456, 10.0), 1.23);
+    }
+
+    #[test]
+    fn test_apply_rf() {
+        let attributes = create_mock_response(100000.0, 220.462, 100.0, 0.1, "km", 500.0)
+            .data
+            .unwrap()
+            .attributes;
+
+        assert_eq!(apply_rf(&attributes, DEFAULT_RF_MULTIPLIER), attributes);
+
+        let co2e = apply_rf(&attributes, 1.9);
+        assert!((co2e.carbon_kg - 190.0).abs() < 0.001);
+        assert!((co2e.carbon_mt - 0.19).abs() < 0.0001);
+        assert!((co2e.carbon_g - 190000.0).abs() < 0.1);
+        assert_eq!(co2e.distance_value, 500.0);
+    }
+
+    #[test]
+    fn test_offset_cost_for_zero_emissions() {
+        assert_eq!(offset_cost(0.0, 15.0), 0.0);
+    }
+
+    #[test]
+    fn test_equivalencies() {
+        let equivalent = equivalencies(170.0);
+
+        assert_eq!(equivalent.car_km, 1000.0);
+        assert!((equivalent.tree_years - 8.095).abs() < 0.001);
+        assert!((equivalent.smartphone_charges - 20681.3).abs() < 0.1);
+    }
+
+    #[test]
+    fn test_alternative_emissions() {
+        let alternatives = alternative_emissions(1000.0);
+
+        assert!((alternatives.train_kg - 35.0).abs() < 0.001);
+        assert!((alternatives.car_kg - 170.0).abs() < 0.001);
+        assert_eq!(
+            alternative_emissions(0.0),
+            Alternatives {
+                train_kg: 0.0,
+                car_kg: 0.0
+            }
+        );
+    }
+
+    #[test]
+    fn test_distance_km_converts_miles() {
+        let mut attributes = create_mock_response(0.0, 0.0, 0.0, 0.0, "mi", 100.0)
+            .data
+            .unwrap()
+            .attributes;
+        assert!((distance_km(&attributes) - 160.934).abs() < 0.001);
+
+        attributes.distance_unit = "km".to_string();
+        assert_eq!(distance_km(&attributes), 100.0);
+    }
+
+    #[test]
+    fn test_convert_distance() {
+        assert!((convert_distance(100.0, "km", "mi") - 62.137).abs() < 0.001);
+        assert!((convert_distance(100.0, "mi", "km") - 160.934).abs() < 0.001);
+        assert_eq!(convert_distance(100.0, "km", "km"), 100.0);
+        assert_eq!(convert_distance(100.0, "mi", "mi"), 100.0);
+    }
+
+    #[test]
+    fn test_with_distance_unit() {
+        let mut attributes = create_mock_response(0.0, 0.0, 0.0, 0.0, "km", 5660.34)
+            .data
+            .unwrap()
+            .attributes;
+
+        with_distance_unit(&mut attributes, "mi");
+
+        assert_eq!(attributes.distance_unit, "mi");
+        assert!((attributes.distance_value - 3517.17).abs() < 0.01);
+    }
+
+    #[test]
+    fn test_exceeds_budget() {
+        assert!(!exceeds_budget(999.99, 1000.0));
+        assert!(!exceeds_budget(1000.0, 1000.0));
+        assert!(exceeds_budget(1000.01, 1000.0));
+        assert!(!exceeds_budget(0.0, 0.5));
+    }
+
+    #[test]
+    fn test_exceeds_max_distance() {
+        let long_haul = create_mock_response(1.0, 1.0, 1.0, 1.0, "km", 17015.0)
+            .data
+            .unwrap()
+            .attributes;
+        let implausible = create_mock_response(1.0, 1.0, 1.0, 1.0, "mi", 15000.0)
+            .data
+            .unwrap()
+            .attributes;
+
+        assert!(!exceeds_max_distance(&long_haul, 20000.0, "km"));
+        assert!(exceeds_max_distance(&implausible, 20000.0, "km"));
+        // 17,015 km is about 10,573 mi
+        assert!(exceeds_max_distance(&long_haul, 10000.0, "mi"));
+        assert!(!exceeds_max_distance(&long_haul, 11000.0, "mi"));
+    }
+
+    #[test]
+    fn test_reduction_vs_flying() {
+        assert_eq!(reduction_vs_flying(200.0, 50.0), Some(75.0));
+        assert_eq!(reduction_vs_flying(100.0, 150.0), Some(-50.0));
+        assert_eq!(reduction_vs_flying(0.0, 10.0), None);
+    }
+
+    #[test]
+    fn test_equivalencies_for_zero_emissions() {
+        assert_eq!(
+            equivalencies(0.0),
+            Equivalencies {
+                car_km: 0.0,
+                tree_years: 0.0,
+                smartphone_charges: 0.0,
+            }
+        );
+    }
+
+    #[test]
+    fn test_per_passenger_splits_totals() {
+        let response = create_mock_response(199823400.0, 535.2, 199823.4, 199.82, "km", 5660.34);
+        let attributes = response.data.unwrap().attributes;
+
+        assert_eq!(per_passenger(&attributes, 200), (999.117, 0.9991));
+        assert_eq!(per_passenger(&attributes, 1), (199823.4, 199.82));
+    }
+
+    #[test]
+    fn test_per_passenger_with_zero_passengers_does_not_divide_by_zero() {
+        let response = create_mock_response(1000000.0, 2204.6, 1000.0, 1.0, "km", 11000.0);
+        let attributes = response.data.unwrap().attributes;
+
+        assert_eq!(per_passenger(&attributes, 0), (1000.0, 1.0));
+    }
+
+    fn attributes_with_kg(carbon_kg: f32) -> EstimateAttributes {
+        create_mock_response(
+            carbon_kg * 1000.0,
+            carbon_kg * 2.2,
+            carbon_kg,
+            carbon_kg / 1000.0,
+            "km",
+            100.0,
+        )
+        .data
+        .unwrap()
+        .attributes
+    }
+
+    #[test]
+    fn test_compare_estimates_first_greener() {
+        let comparison = compare_estimates(&attributes_with_kg(200.0), &attributes_with_kg(300.0));
+
+        assert_eq!(
+            comparison,
+            Comparison {
+                difference_kg: 100.0,
+                percent_difference: Some(50.0),
+                greener: Greener::First,
+            }
+        );
+    }
+
+    #[test]
+    fn test_compare_estimates_second_greener() {
+        let comparison = compare_estimates(&attributes_with_kg(400.0), &attributes_with_kg(300.0));
+
+        assert_eq!(comparison.difference_kg, -100.0);
+        assert_eq!(comparison.percent_difference, Some(-25.0));
+        assert_eq!(comparison.greener, Greener::Second);
+    }
+
+    #[test]
+    fn test_compare_equal_estimates() {
+        let comparison = compare_estimates(&attributes_with_kg(250.0), &attributes_with_kg(250.0));
+
+        assert_eq!(comparison.difference_kg, 0.0);
+        assert_eq!(comparison.percent_difference, Some(0.0));
+        assert_eq!(comparison.greener, Greener::Equal);
+    }
+
+    #[test]
+    fn test_compare_against_zero_emissions() {
+        let comparison = compare_estimates(&attributes_with_kg(0.0), &attributes_with_kg(0.0));
+
+        assert_eq!(comparison.percent_difference, None);
+        assert_eq!(comparison.greener, Greener::Equal);
+    }
+
+    #[test]
+    fn test_sum_attributes() {
+        let total = sum_attributes(&[attributes_with_kg(200.0), attributes_with_kg(300.0)]);
+
+        assert_eq!(total.carbon_g, 500000.0);
+        assert_eq!(total.carbon_kg, 500.0);
+        assert_eq!(total.carbon_mt, 0.5);
+        assert_eq!(total.distance_unit, "km");
+        assert_eq!(total.distance_value, 200.0);
+    }
+
+    #[test]
+    fn test_sum_attributes_totals_mixed_distance_units_in_km() {
+        let mut miles = attributes_with_kg(300.0);
+        miles.distance_unit = "mi".to_string();
+
+        let total = sum_attributes(&[attributes_with_kg(200.0), miles.clone()]);
+
+        assert_eq!(total.carbon_kg, 500.0);
+        assert_eq!(total.distance_unit, "km");
+        assert_eq!(total.distance_value, 100.0 + 100.0 * KM_PER_MILE);
+        assert_eq!(miles.distance_unit, "mi");
+        assert_eq!(miles.distance_value, 100.0);
+    }
+
+    #[test]
+    fn test_sum_attributes_keeps_a_shared_unit() {
+        let mut miles = attributes_with_kg(300.0);
+        miles.distance_unit = "mi".to_string();
+
+        let total = sum_attributes(&[miles.clone(), miles]);
+
+        assert_eq!(total.distance_unit, "mi");
+        assert_eq!(total.distance_value, 200.0);
+    }
+
+    #[test]
+    fn test_sums_leave_out_missing_figures() {
+        let mut partial = attributes_with_kg(300.0);
+        partial.carbon_kg = MISSING_FIGURE;
+        let mut unknown = partial.clone();
+        unknown.carbon_g = MISSING_FIGURE;
+
+        let total = sum_attributes(&[attributes_with_kg(200.0), partial.clone()]);
+        assert_eq!(total.carbon_kg, 200.0);
+        assert_eq!(total.carbon_g, 500000.0);
+        assert!(is_missing(
+            sum_attributes(&[partial.clone(), unknown]).carbon_kg
+        ));
+        assert_eq!(sum_known([]), 0.0);
+
+        let mut totals = SessionTotals::default();
+        totals.add(&partial);
+        totals.add(&attributes_with_kg(50.0));
+        assert_eq!(totals.carbon_kg, 50.0);
+    }
+
+    #[test]
+    fn test_intensity() {
+        let attributes = attributes_with_kg(200.0);
+
+        assert_eq!(intensity(&attributes), Some(2.0));
+    }
+
+    #[test]
+    fn test_intensity_without_distance() {
+        let mut attributes = attributes_with_kg(200.0);
+        attributes.distance_value = 0.0;
+
+        assert_eq!(intensity(&attributes), None);
+    }
+
+    #[test]
+    fn test_select_units() {
+        let attributes = attributes_with_kg(200.0);
+
+        assert_eq!(
+            select_units(&attributes, &[CarbonUnit::Kg]),
+            [(CarbonUnit::Kg, 200.0)]
+        );
+        assert_eq!(
+            select_units(
+                &attributes,
+                &[CarbonUnit::Mt, CarbonUnit::G, CarbonUnit::Mt]
+            ),
+            [(CarbonUnit::Mt, 0.2), (CarbonUnit::G, 200000.0)]
+        );
+        assert!(select_units(&attributes, &[]).is_empty());
+    }
+
+    #[test]
+    fn test_combined_cabins_count_each_leg_distance_once() {
+        let cabin = |cabin_class: &str, passengers| crate::model::CabinPassengers {
+            cabin_class: cabin_class.to_string(),
+            passengers,
+        };
+        let leg = |cabins| MixedCabinLeg {
+            departure_airport: "LHR".to_string(),
+            destination_airport: "JFK".to_string(),
+            cabins,
+        };
+        let legs = vec![
+            leg(vec![cabin("economy", 2), cabin("business", 1)]),
+            leg(vec![cabin("economy", 3)]),
+        ];
+        let estimates = vec![
+            attributes_with_kg(200.0),
+            attributes_with_kg(300.0),
+            attributes_with_kg(100.0),
+        ];
+
+        let total = combine_cabin_estimates(&legs, estimates);
+
+        let distance = attributes_with_kg(0.0).distance_value;
+        assert_eq!(total.carbon_kg, 600.0);
+        assert_eq!(total.distance_value, distance * 2.0);
+    }
+
+    #[test]
+    fn test_short_flight_boundary() {
+        assert!(is_short_flight(499.9, DEFAULT_SHORT_FLIGHT_KM));
+        assert!(!is_short_flight(500.0, DEFAULT_SHORT_FLIGHT_KM));
+        assert!(!is_short_flight(5540.0, DEFAULT_SHORT_FLIGHT_KM));
+        assert!(is_short_flight(700.0, 800.0));
+        assert!(!is_short_flight(f32::NAN, DEFAULT_SHORT_FLIGHT_KM));
+    }
+
+    #[test]
+    fn test_session_totals_accumulate() {
+        let mut totals = SessionTotals::default();
+        totals.add(&attributes_with_kg(200.0));
+        totals.add(&attributes_with_kg(300.0));
+        totals.add(&attributes_with_kg(50.0));
+
+        assert_eq!(totals.flights, 3);
+        assert_eq!(totals.carbon_kg, 550.0);
+        assert!((totals.carbon_mt - 0.55).abs() < 1e-6);
+        assert_eq!(totals.distance_value, 300.0);
+        assert_eq!(totals.distance_unit.as_deref(), Some("km"));
+    }
+
+    #[test]
+    fn test_session_totals_move_mixed_distance_units_to_km() {
+        let mut miles = attributes_with_kg(100.0);
+        miles.distance_unit = "mi".to_string();
+        let mut totals = SessionTotals::default();
+        totals.add(&miles);
+        totals.add(&attributes_with_kg(200.0));
+        totals.add(&attributes_with_kg(50.0));
+
+        assert_eq!(totals.flights, 3);
+        assert_eq!(totals.carbon_kg, 350.0);
+        assert_eq!(totals.distance_unit.as_deref(), Some("km"));
+        assert!((totals.distance_value - (100.0 * KM_PER_MILE + 200.0)).abs() < 1e-3);
+    }
+
+    #[test]
+    fn test_sum_of_nothing_is_zero() {
+        let total = sum_attributes(&[]);
+
+        assert_eq!(total.carbon_kg, 0.0);
+        assert_eq!(total.distance_value, 0.0);
+    }
+}
@@ -0,0 +1,145 @@
+//! Maps a flown route as GeoJSON, using the bundled airport coordinates.
+
+use crate::airports::{find_airport, Airport};
+use crate::error::CliError;
+use crate::model::{EstimateAttributes, FlightEstimateRequest};
+use serde_json::{json, Value};
+
+/// Builds a FeatureCollection with a Point for each airport visited and a LineString
+/// following the legs in order. The LineString's properties carry the estimate.
+pub fn route_geojson(
+    request: &FlightEstimateRequest,
+    attributes: &EstimateAttributes,
+) -> Result<Value, CliError> {
+    let mut path: Vec<Airport> = Vec::new();
+    for leg in &request.legs {
+        for code in [&leg.departure_airport, &leg.destination_airport] {
+            let airport = locate(code)?;
+            // A connection's arrival and next departure are the same point
+            if path.last().map(|last| &last.iata) != Some(&airport.iata) {
+                path.push(airport);
+            }
+        }
+    }
+
+    let mut features: Vec<Value> = Vec::new();
+    for airport in &path {
+        if features
+            .iter()
+            .any(|feature| feature["properties"]["iata"] == airport.iata.as_str())
+        {
+            continue;
+        }
+        features.push(json!({
+            "type": "Feature",
+            "geometry": { "type": "Point", "coordinates": coordinates(airport) },
+            "properties": {
+                "iata": airport.iata,
+                "name": airport.name,
+                "city": airport.city,
+            },
+        }));
+    }
+    features.push(json!({
+        "type": "Feature",
+        "geometry": {
+            "type": "LineString",
+            "coordinates": path.iter().map(coordinates).collect::<Vec<_>>(),
+        },
+        "properties": {
+            "passengers": request.passengers,
+            "carbon_kg": attributes.carbon_kg,
+            "distance_value": attributes.distance_value,
+            "distance_unit": attributes.distance_unit,
+        },
+    }));
+
+    Ok(json!({ "type": "FeatureCollection", "features": features }))
+}
+
+/// GeoJSON orders positions longitude first.
+fn coordinates(airport: &Airport) -> [f64; 2] {
+    [airport.longitude, airport.latitude]
+}
+
+fn locate(code: &str) -> Result<Airport, CliError> {
+    find_airport(code).ok_or_else(|| {
+        CliError::InvalidInput(format!(
+            "{} isn't in the bundled airport list, so it can't be mapped",
+            code
+        ))
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{create_mock_response, create_request};
+
+    fn attributes(carbon_kg: f32) -> EstimateAttributes {
+        create_mock_response(
+            carbon_kg * 1000.0,
+            carbon_kg * 2.2,
+            carbon_kg,
+            carbon_kg / 1000.0,
+            "km",
+            8750.0,
+        )
+        .data
+        .unwrap()
+        .attributes
+    }
+
+    #[test]
+    fn test_two_leg_route_geojson() {
+        let request = create_request(1, &[("LHR", "JFK"), ("JFK", "LAX")]);
+
+        let geojson = route_geojson(&request, &attributes(1234.5)).unwrap();
+        let parsed: Value = serde_json::from_str(&geojson.to_string()).unwrap();
+
+        assert_eq!(parsed["type"], "FeatureCollection");
+        let features = parsed["features"].as_array().unwrap();
+        let points: Vec<&Value> = features
+            .iter()
+            .filter(|feature| feature["geometry"]["type"] == "Point")
+            .collect();
+        assert_eq!(points.len(), 3);
+        let line = features
+            .iter()
+            .find(|feature| feature["geometry"]["type"] == "LineString")
+            .unwrap();
+        let coordinates = line["geometry"]["coordinates"].as_array().unwrap();
+        assert_eq!(coordinates.len(), 3);
+        let lhr = find_airport("LHR").unwrap();
+        assert_eq!(coordinates[0][0], lhr.longitude);
+        assert_eq!(coordinates[0][1], lhr.latitude);
+        assert_eq!(line["properties"]["carbon_kg"], 1234.5);
+    }
+
+    #[test]
+    fn test_round_trip_repeats_coordinates_but_not_points() {
+        let request = create_request(1, &[("LHR", "JFK"), ("JFK", "LHR")]);
+
+        let geojson = route_geojson(&request, &attributes(10.0)).unwrap();
+
+        let features = geojson["features"].as_array().unwrap();
+        assert_eq!(features.len(), 3);
+        assert_eq!(
+            features[2]["geometry"]["coordinates"]
+                .as_array()
+                .unwrap()
+                .len(),
+            3
+        );
+    }
+
+    #[test]
+    fn test_unknown_airport_is_rejected() {
+        let request = create_request(1, &[("LHR", "ZZZ")]);
+
+        assert!(matches!(
+            route_geojson(&request, &attributes(10.0)),
+            Err(CliError::InvalidInput(_))
+        ));
+    }
+}
@@ -0,0 +1,244 @@
+//! A JSON Lines log of past estimates.
+
+use crate::error::CliError;
+use crate::model::{EstimateAttributes, FlightEstimateRequest};
+use crate::output::route_stops;
+use chrono::{DateTime, NaiveDate, Utc};
+use log::warn;
+use serde_derive::{Deserialize, Serialize};
+use std::cmp::Reverse;
+use std::fs::{self, OpenOptions};
+use std::io::{self, Write};
+use std::path::{Path, PathBuf};
+
+/// One estimated trip, as stored on a single line of the history file.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct HistoryRecord {
+    pub timestamp: DateTime<Utc>,
+    /// Every airport visited, in order
+    pub route: Vec<String>,
+    pub passengers: u32,
+    /// The cabin class of each leg, `None` where the API default was used
+    pub cabin_classes: Vec<Option<String>>,
+    pub carbon_kg: f32,
+}
+
+impl HistoryRecord {
+    pub fn new(
+        request: &FlightEstimateRequest,
+        attributes: &EstimateAttributes,
+        timestamp: DateTime<Utc>,
+    ) -> Self {
+        Self {
+            timestamp,
+            route: route_stops(&request.legs)
+                .into_iter()
+                .map(String::from)
+                .collect(),
+            passengers: request.passengers,
+            cabin_classes: request
+                .legs
+                .iter()
+                .map(|leg| leg.cabin_class.clone())
+                .collect(),
+            carbon_kg: attributes.carbon_kg,
+        }
+    }
+}
+
+/// Where history is kept unless overridden, e.g.
+/// `~/.local/share/carbon-footprint/history.jsonl` on Linux.
+pub fn default_history_path() -> Option<PathBuf> {
+    dirs::data_dir().map(|dir| dir.join("carbon-footprint").join("history.jsonl"))
+}
+
+/// Adds `record` to the end of the history file, creating it if needed.
+pub fn append_record(path: &Path, record: &HistoryRecord) -> Result<(), CliError> {
+    let line = serde_json::to_string(record)?;
+    let append = || -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        let mut file = OpenOptions::new().create(true).append(true).open(path)?;
+        writeln!(file, "{}", line)
+    };
+    append().map_err(|err| CliError::HistoryError(path.to_path_buf(), err))
+}
+
+/// Reads every record, newest first. A missing file is an empty history, and lines that
+/// can't be parsed are skipped with a warning.
+pub fn read_history(path: &Path) -> Result<Vec<HistoryRecord>, CliError> {
+    let contents = match fs::read_to_string(path) {
+        Ok(contents) => contents,
+        Err(err) if err.kind() == io::ErrorKind::NotFound => return Ok(Vec::new()),
+        Err(err) => return Err(CliError::HistoryError(path.to_path_buf(), err)),
+    };
+
+    let mut records: Vec<HistoryRecord> = contents
+        .lines()
+        .enumerate()
+        .filter(|(_, line)| !line.trim().is_empty())
+        .filter_map(|(index, line)| match serde_json::from_str(line) {
+            Ok(record) => Some(record),
+            Err(err) => {
+                warn!("Skipping history line {}: {}", index + 1, err);
+                None
+            }
+        })
+        .collect();
+    records.sort_by_key(|record| Reverse(record.timestamp));
+    Ok(records)
+}
+
+/// A span of days to total emissions over, in UTC. Either end may be left open.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Period {
+    /// The first day included
+    pub start: Option<NaiveDate>,
+    /// The first day no longer included
+    pub end: Option<NaiveDate>,
+}
+
+impl Period {
+    /// Everything from `since` onwards, limited to the calendar `year` if one is given.
+    pub fn new(since: Option<NaiveDate>, year: Option<i32>) -> Self {
+        let year_start = year.and_then(|year| NaiveDate::from_ymd_opt(year, 1, 1));
+        let year_end = year.and_then(|year| NaiveDate::from_ymd_opt(year + 1, 1, 1));
+        Self {
+            start: since.max(year_start),
+            end: year_end,
+        }
+    }
+
+    pub fn contains(&self, timestamp: DateTime<Utc>) -> bool {
+        let day = timestamp.date_naive();
+        self.start.is_none_or(|start| day >= start) && self.end.is_none_or(|end| day < end)
+    }
+}
+
+/// Emissions added up over the records in a period.
+#[derive(Debug, Default, PartialEq)]
+pub struct HistoryTotal {
+    pub flights: usize,
+    pub carbon_kg: f32,
+}
+
+pub fn total_emissions(records: &[HistoryRecord], period: &Period) -> HistoryTotal {
+    records
+        .iter()
+        .filter(|record| period.contains(record.timestamp))
+        .fold(HistoryTotal::default(), |total, record| HistoryTotal {
+            flights: total.flights + 1,
+            carbon_kg: total.carbon_kg + record.carbon_kg,
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::{create_mock_response, create_request};
+    use chrono::TimeZone;
+
+    fn record_at(hour: u32, route: &[(&str, &str)]) -> HistoryRecord {
+        let request = create_request(2, route);
+        let attributes = create_mock_response(1000000.0, 2204.6, 1000.0, 1.0, "km", 5000.0)
+            .data
+            .unwrap()
+            .attributes;
+        let timestamp = Utc.with_ymd_and_hms(2026, 10, 14, hour, 0, 0).unwrap();
+        HistoryRecord::new(&request, &attributes, timestamp)
+    }
+
+    #[test]
+    fn test_history_record_from_estimate() {
+        let record = record_at(9, &[("LHR", "JFK"), ("JFK", "LAX")]);
+
+        assert_eq!(record.route, vec!["LHR", "JFK", "LAX"]);
+        assert_eq!(record.passengers, 2);
+        assert_eq!(record.cabin_classes, vec![None, None]);
+        assert_eq!(record.carbon_kg, 1000.0);
+    }
+
+    #[test]
+    fn test_history_is_read_back_newest_first() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("data").join("history.jsonl");
+        let older = record_at(9, &[("LHR", "JFK")]);
+        let newer = record_at(17, &[("CDG", "NRT")]);
+
+        append_record(&path, &older).unwrap();
+        append_record(&path, &newer).unwrap();
+
+        assert_eq!(fs::read_to_string(&path).unwrap().lines().count(), 2);
+        assert_eq!(read_history(&path).unwrap(), vec![newer, older]);
+    }
+
+    #[test]
+    fn test_read_history_skips_malformed_lines() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        append_record(&path, &record_at(9, &[("LHR", "JFK")])).unwrap();
+        fs::write(
+            &path,
+            format!("not json\n{}", fs::read_to_string(&path).unwrap()),
+        )
+        .unwrap();
+
+        assert_eq!(read_history(&path).unwrap().len(), 1);
+    }
+
+    fn record_on(timestamp: &str, carbon_kg: f32) -> String {
+        let mut record = record_at(9, &[("LHR", "JFK")]);
+        record.timestamp = timestamp.parse().unwrap();
+        record.carbon_kg = carbon_kg;
+        serde_json::to_string(&record).unwrap()
+    }
+
+    fn date(input: &str) -> NaiveDate {
+        input.parse().unwrap()
+    }
+
+    #[test]
+    fn test_total_emissions_over_a_period() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let lines = [
+            record_on("2023-12-31T23:59:59Z", 1.0),
+            record_on("2024-01-01T00:00:00Z", 10.0),
+            "{\"timestamp\": \"not a date\"}".to_string(),
+            record_on("2024-06-30T12:00:00Z", 100.0),
+            record_on("2024-12-31T23:59:59Z", 1000.0),
+            record_on("2025-01-01T00:00:00Z", 10000.0),
+        ];
+        fs::write(&path, lines.join("\n")).unwrap();
+        let records = read_history(&path).unwrap();
+
+        let year = total_emissions(&records, &Period::new(None, Some(2024)));
+        assert_eq!(
+            year,
+            HistoryTotal {
+                flights: 3,
+                carbon_kg: 1110.0
+            }
+        );
+
+        // A record exactly on --since counts
+        let since = total_emissions(&records, &Period::new(Some(date("2024-01-01")), None));
+        assert_eq!(since.flights, 4);
+        assert_eq!(since.carbon_kg, 11110.0);
+
+        let both = total_emissions(&records, &Period::new(Some(date("2024-06-30")), Some(2024)));
+        assert_eq!(both.carbon_kg, 1100.0);
+
+        assert_eq!(total_emissions(&records, &Period::default()).flights, 5);
+    }
+
+    #[test]
+    fn test_missing_history_is_empty() {
+        let dir = tempfile::tempdir().unwrap();
+
+        assert!(read_history(&dir.path().join("history.jsonl"))
+            .unwrap()
+            .is_empty());
+    }
+}
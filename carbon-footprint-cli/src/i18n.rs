@@ -0,0 +1,300 @@
+//! Translations of the interactive prompts, result labels, warnings and error messages.
+
+use std::fmt::Display;
+use std::sync::OnceLock;
+
+/// A language the prompts and labels are available in.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Locale {
+    English,
+    Spanish,
+}
+
+impl Locale {
+    /// The locale for a language code such as `es`, or a `LANG` value such as
+    /// `es_ES.UTF-8`. Languages without a catalog get `None`.
+    pub fn from_code(code: &str) -> Option<Self> {
+        let language = code
+            .split(['_', '-', '.'])
+            .next()
+            .unwrap_or_default()
+            .to_ascii_lowercase();
+        match language.as_str() {
+            "en" => Some(Locale::English),
+            "es" => Some(Locale::Spanish),
+            _ => None,
+        }
+    }
+
+    fn catalog(self) -> &'static [(&'static str, &'static str)] {
+        match self {
+            Locale::English => ENGLISH,
+            Locale::Spanish => SPANISH,
+        }
+    }
+}
+
+/// Every message, keyed by what it's for. `{}` marks where `tf` or `tfv` puts a value.
+const ENGLISH: &[(&str, &str)] = &[
+    ("prompt.departure", "🛫 Enter the departure airport (IATA code or city): "),
+    ("prompt.destination", "🛬 Enter the destination airport (IATA code or city): "),
+    ("prompt.cabin", "💺 Enter the cabin class (economy, premium, business or first): "),
+    ("error.cabin", "❌ Invalid input. Cabin class can be 'economy', 'premium', 'business' or 'first'."),
+    ("prompt.legs_menu", "✈️ Legs: [a]dd, [r]emove N, [e]dit N, [v]iew, [d]one: "),
+    ("error.legs_menu", "❌ Invalid input. Enter a, v or d, or r/e followed by a listed leg number."),
+    ("prompt.same_airport", "⚠️ {} is both the departure and the destination. Use it anyway? [y/N] "),
+    ("prompt.leg_details", "🔢 Enter details for leg {}:"),
+    ("prompt.leg_new_details", "🔢 Enter new details for leg {}:"),
+    ("error.no_legs", "❌ At least one leg is required."),
+    ("prompt.passengers", "👥 Enter the number of passengers: "),
+    ("error.passengers", "❌ Invalid input. Number of passengers must be at least 1."),
+    ("prompt.distance_unit", "📏 Enter the distance unit (km or mi): "),
+    ("error.distance_unit", "❌ Invalid input. Distance unit can be 'km' or 'mi'."),
+    ("error.airport", "❌ Invalid input. Enter a 3-letter IATA code, or a city or airport name from the bundled list."),
+    ("message.unknown_airport", "{} isn't in the bundled airport list; pass --allow-unknown-iata to use it anyway"),
+    ("message.unknown_airport_used", "⚠️ {} isn't in the bundled airport list, using it anyway."),
+    ("prompt.pick_airport", "🔢 Pick an airport by number: "),
+    ("error.pick_airport", "❌ Invalid input. Enter one of the numbers above."),
+    ("prompt.api_key", "🔑 Please enter your API key: "),
+    ("prompt.submit", "❓ Submit this estimate? [y/N] "),
+    ("message.not_sent", "Cancelled; nothing was sent."),
+    ("result.heading", "🌍 Estimated carbon emissions for your trip are: 🌍"),
+    ("result.metric", "Metric"),
+    ("result.value", "Value"),
+    ("result.unit", "Unit"),
+    ("result.carbon", "Carbon emissions ({})"),
+    ("result.distance", "Distance"),
+    ("result.per_passenger", "Per passenger:"),
+    ("result.across_passengers", "across {} passenger(s)"),
+    ("result.co2e", "CO2-equivalent with radiative forcing (x{}):"),
+    ("result.intensity", "Emission intensity:"),
+    ("result.equivalent", "That's roughly the same as:"),
+    ("result.offset_heading", "🌳 Please consider offsetting your carbon footprint. 🌳"),
+    ("result.offset_cost", "Estimated offset cost:"),
+    ("result.per_tonne", "(at {} per tonne)"),
+    ("result.car_km", "  🚗 Driving {} km in an average petrol car"),
+    ("result.tree_years", "  🌲 What {} trees absorb in a year"),
+    ("result.smartphone_charges", "  📱 Charging a smartphone {} times"),
+    ("result.learn_more", "Learn more at: {}"),
+    ("message.filtered_out", "🔎 {} result(s) outside the --min-carbon-kg/--max-carbon-kg range were left out"),
+    ("message.unknown_carbon_kept", "🔎 {} result(s) had no carbon_kg to check against the --min-carbon-kg/--max-carbon-kg range and were kept"),
+    ("warning.unknown_in_total", "WARN: {} estimate(s) had no carbon_kg and are left out of the total checked against the budget"),
+    ("warning.unchecked_limit", "WARN: {} couldn't be checked, as the API's estimate had no {}"),
+    ("cli_error.network", "Network error: {}"),
+    ("cli_error.dns_host", "Could not resolve host {}"),
+    ("cli_error.dns", "Could not resolve host"),
+    ("cli_error.refused_by", "Connection refused by {}"),
+    ("cli_error.refused", "Connection refused"),
+    ("cli_error.response_format", "Unexpected response format: {}"),
+    ("cli_error.empty_response", "Empty response from server — the request may not have completed"),
+    ("cli_error.api", "API error: {}"),
+    ("cli_error.unauthorized", "Authentication failed: check that your API key is right (whether from --api-key, CARBON_INTERFACE_API_KEY or `login`) and that your Carbon Interface account has quota left."),
+    ("cli_error.validation", "The API rejected the trip details: {}"),
+    ("cli_error.server", "The Carbon Interface API failed with HTTP {}; try again later."),
+    ("cli_error.implausible", "The API returned an implausible estimate: {}"),
+    ("cli_error.too_many_invalid_inputs", "Too many invalid inputs, giving up."),
+    ("cli_error.input_closed", "Input ended before every question was answered."),
+    ("cli_error.invalid_arguments", "Invalid arguments: {}"),
+    ("cli_error.invalid_input", "Invalid input: {}"),
+    ("cli_error.output_file_exists", "{} already exists; pass --force to overwrite it."),
+    ("cli_error.output_file", "Could not write {}: {}"),
+    ("cli_error.invalid_config", "Invalid config file {}: {}"),
+    ("cli_error.history", "Could not use history file {}: {}"),
+    ("cli_error.timeout_after", "Request timed out after {} seconds"),
+    ("cli_error.timeout", "Request timed out"),
+    ("cli_error.keyring", "Could not use the system keyring: {}"),
+    ("cli_error.unchecked_limit", "Can't check {}: the API's estimate had no {}"),
+    ("cli_error.budget_exceeded", "Over budget: {} kg CO2 is more than your {} kg budget"),
+    ("cli_error.over_warning_level", "{} kg CO2 is over the {} kg warning level"),
+    ("cli_error.batch_failed", "{} of {} batch requests failed"),
+    ("cli_error.deadline", "Skipped: the {} second deadline ran out first"),
+    ("cli_error.in_request", "index {} ({}): {}"),
+];
+
+const SPANISH: &[(&str, &str)] = &[
+    ("prompt.departure", "🛫 Introduce el aeropuerto de salida (código IATA o ciudad): "),
+    ("prompt.destination", "🛬 Introduce el aeropuerto de destino (código IATA o ciudad): "),
+    ("prompt.cabin", "💺 Introduce la clase (economy, premium, business o first): "),
+    ("error.cabin", "❌ Entrada no válida. La clase puede ser 'economy', 'premium', 'business' o 'first'."),
+    ("prompt.legs_menu", "✈️ Tramos: [a]ñadir, [r] N quitar, [e] N editar, [v]er, [d] terminar: "),
+    ("error.legs_menu", "❌ Entrada no válida. Escribe a, v o d, o r/e seguido del número de un tramo."),
+    ("prompt.same_airport", "⚠️ {} es a la vez la salida y el destino. ¿Usarlo de todos modos? [y/N] "),
+    ("prompt.leg_details", "🔢 Introduce los datos del tramo {}:"),
+    ("prompt.leg_new_details", "🔢 Introduce los nuevos datos del tramo {}:"),
+    ("error.no_legs", "❌ Se necesita al menos un tramo."),
+    ("prompt.passengers", "👥 Introduce el número de pasajeros: "),
+    ("error.passengers", "❌ Entrada no válida. Debe haber al menos 1 pasajero."),
+    ("prompt.distance_unit", "📏 Introduce la unidad de distancia (km o mi): "),
+    ("error.distance_unit", "❌ Entrada no válida. La unidad de distancia puede ser 'km' o 'mi'."),
+    ("error.airport", "❌ Entrada no válida. Introduce un código IATA de 3 letras o el nombre de una ciudad o aeropuerto de la lista incluida."),
+    ("message.unknown_airport", "{} no está en la lista de aeropuertos incluida; usa --allow-unknown-iata para usarlo de todos modos"),
+    ("message.unknown_airport_used", "⚠️ {} no está en la lista de aeropuertos incluida; se usa de todos modos."),
+    ("prompt.pick_airport", "🔢 Elige un aeropuerto por su número: "),
+    ("error.pick_airport", "❌ Entrada no válida. Introduce uno de los números de arriba."),
+    ("prompt.api_key", "🔑 Introduce tu clave de API: "),
+    ("prompt.submit", "❓ ¿Enviar esta estimación? [y/N] "),
+    ("message.not_sent", "Cancelado; no se ha enviado nada."),
+    ("result.heading", "🌍 Las emisiones de carbono estimadas de tu viaje son: 🌍"),
+    ("result.metric", "Métrica"),
+    ("result.value", "Valor"),
+    ("result.unit", "Unidad"),
+    ("result.carbon", "Emisiones de carbono ({})"),
+    ("result.distance", "Distancia"),
+    ("result.per_passenger", "Por pasajero:"),
+    ("result.across_passengers", "entre {} pasajero(s)"),
+    ("result.co2e", "CO2 equivalente con forzamiento radiativo (x{}):"),
+    ("result.intensity", "Intensidad de emisiones:"),
+    ("result.equivalent", "Equivale aproximadamente a:"),
+    ("result.offset_heading", "🌳 Considera compensar tu huella de carbono. 🌳"),
+    ("result.offset_cost", "Coste estimado de compensación:"),
+    ("result.per_tonne", "(a {} por tonelada)"),
+    ("result.car_km", "  🚗 Conducir {} km en un coche de gasolina medio"),
+    ("result.tree_years", "  🌲 Lo que absorben {} árboles en un año"),
+    ("result.smartphone_charges", "  📱 Cargar un smartphone {} veces"),
+    ("result.learn_more", "Más información en: {}"),
+    ("message.filtered_out", "🔎 Se omitieron {} resultado(s) fuera del rango de --min-carbon-kg/--max-carbon-kg"),
+    ("message.unknown_carbon_kept", "🔎 Se conservaron {} resultado(s) sin carbon_kg con el que comprobar el rango de --min-carbon-kg/--max-carbon-kg"),
+    ("warning.unknown_in_total", "AVISO: {} estimación(es) no tenían carbon_kg y quedan fuera del total comparado con el presupuesto"),
+    ("warning.unchecked_limit", "AVISO: no se pudo comprobar {}, ya que la estimación de la API no incluía {}"),
+    ("cli_error.network", "Error de red: {}"),
+    ("cli_error.dns_host", "No se pudo resolver el host {}"),
+    ("cli_error.dns", "No se pudo resolver el host"),
+    ("cli_error.refused_by", "Conexión rechazada por {}"),
+    ("cli_error.refused", "Conexión rechazada"),
+    ("cli_error.response_format", "Formato de respuesta inesperado: {}"),
+    ("cli_error.empty_response", "Respuesta vacía del servidor: puede que la solicitud no se completara"),
+    ("cli_error.api", "Error de la API: {}"),
+    ("cli_error.unauthorized", "La autenticación ha fallado: comprueba que tu clave de API es correcta (venga de --api-key, CARBON_INTERFACE_API_KEY o `login`) y que a tu cuenta de Carbon Interface le queda cuota."),
+    ("cli_error.validation", "La API ha rechazado los datos del viaje: {}"),
+    ("cli_error.server", "La API de Carbon Interface ha fallado con HTTP {}; inténtalo más tarde."),
+    ("cli_error.implausible", "La API ha devuelto una estimación inverosímil: {}"),
+    ("cli_error.too_many_invalid_inputs", "Demasiadas entradas no válidas; se abandona."),
+    ("cli_error.input_closed", "La entrada terminó antes de responder a todas las preguntas."),
+    ("cli_error.invalid_arguments", "Argumentos no válidos: {}"),
+    ("cli_error.invalid_input", "Entrada no válida: {}"),
+    ("cli_error.output_file_exists", "{} ya existe; usa --force para sobrescribirlo."),
+    ("cli_error.output_file", "No se pudo escribir {}: {}"),
+    ("cli_error.invalid_config", "Archivo de configuración no válido {}: {}"),
+    ("cli_error.history", "No se pudo usar el archivo de historial {}: {}"),
+    ("cli_error.timeout_after", "La solicitud superó el tiempo de espera tras {} segundos"),
+    ("cli_error.timeout", "La solicitud superó el tiempo de espera"),
+    ("cli_error.keyring", "No se pudo usar el llavero del sistema: {}"),
+    ("cli_error.unchecked_limit", "No se puede comprobar {}: la estimación de la API no incluía {}"),
+    ("cli_error.budget_exceeded", "Presupuesto superado: {} kg de CO2 es más que tu presupuesto de {} kg"),
+    ("cli_error.over_warning_level", "{} kg de CO2 supera el nivel de aviso de {} kg"),
+    ("cli_error.batch_failed", "Fallaron {} de {} solicitudes del lote"),
+    ("cli_error.deadline", "Omitida: el plazo de {} segundos se agotó antes"),
+    ("cli_error.in_request", "índice {} ({}): {}"),
+];
+
+static LOCALE: OnceLock<Locale> = OnceLock::new();
+
+/// Shows messages in `locale` for the rest of the run. Only the first call has any effect.
+pub fn set_locale(locale: Locale) {
+    let _ = LOCALE.set(locale);
+}
+
+fn current_locale() -> Locale {
+    LOCALE.get().copied().unwrap_or(Locale::English)
+}
+
+/// The message for `key` in the current locale.
+pub fn t(key: &str) -> &str {
+    translate(current_locale(), key)
+}
+
+/// Like `t`, with `value` in place of the message's `{}`.
+pub fn tf(key: &str, value: impl Display) -> String {
+    tfv(key, &[&value])
+}
+
+/// Like `t`, with `values` in place of the message's `{}`s, in order. A value that
+/// itself contains `{}` is left as it is.
+pub fn tfv(key: &str, values: &[&dyn Display]) -> String {
+    let mut parts = t(key).split("{}");
+    let mut message = parts.next().unwrap_or_default().to_string();
+    let mut values = values.iter();
+    for part in parts {
+        match values.next() {
+            Some(value) => message.push_str(&value.to_string()),
+            None => message.push_str("{}"),
+        }
+        message.push_str(part);
+    }
+    message
+}
+
+/// The message for `key` in `locale`, falling back to English when it hasn't been
+/// translated, and to the key itself when there's no such message.
+pub fn translate(locale: Locale, key: &str) -> &str {
+    lookup(locale.catalog(), key)
+        .or_else(|| lookup(ENGLISH, key))
+        .unwrap_or(key)
+}
+
+fn lookup(catalog: &'static [(&'static str, &'static str)], key: &str) -> Option<&'static str> {
+    catalog
+        .iter()
+        .find(|(message_key, _)| *message_key == key)
+        .map(|(_, message)| *message)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn test_translate_present_key() {
+        assert_eq!(translate(Locale::English, "result.distance"), "Distance");
+        assert_eq!(translate(Locale::Spanish, "result.distance"), "Distancia");
+    }
+
+    #[test]
+    fn test_translate_missing_key_falls_back_to_the_key() {
+        assert_eq!(translate(Locale::Spanish, "no.such.key"), "no.such.key");
+    }
+
+    #[test]
+    fn test_every_message_is_translated() {
+        for (key, _) in ENGLISH {
+            assert!(lookup(SPANISH, key).is_some(), "{} has no translation", key);
+        }
+        for (key, _) in SPANISH {
+            assert!(
+                lookup(ENGLISH, key).is_some(),
+                "{} isn't an English key",
+                key
+            );
+        }
+    }
+
+    #[test]
+    fn test_locale_from_code() {
+        assert_eq!(Locale::from_code("es"), Some(Locale::Spanish));
+        assert_eq!(Locale::from_code("es_ES.UTF-8"), Some(Locale::Spanish));
+        assert_eq!(Locale::from_code("EN-gb"), Some(Locale::English));
+        assert_eq!(Locale::from_code("C.UTF-8"), None);
+        assert_eq!(Locale::from_code(""), None);
+    }
+
+    #[test]
+    fn test_tf_fills_in_the_value() {
+        assert_eq!(
+            translate(Locale::Spanish, "prompt.leg_details").replacen("{}", "2", 1),
+            "🔢 Introduce los datos del tramo 2:"
+        );
+        assert_eq!(tf("prompt.leg_details", 2), "🔢 Enter details for leg 2:");
+    }
+
+    #[test]
+    fn test_tfv_fills_in_each_value_in_order() {
+        assert_eq!(
+            tfv("cli_error.output_file", &[&"out.csv", &"{}"]),
+            "Could not write out.csv: {}"
+        );
+        assert_eq!(
+            tfv("cli_error.batch_failed", &[&2]),
+            "2 of {} batch requests failed"
+        );
+    }
+}
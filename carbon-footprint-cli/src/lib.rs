@@ -0,0 +1,67 @@
+//! Estimate the carbon footprint of flights with the
+//! [Carbon Interface](https://www.carboninterface.com) API.
+//!
+//! The `carbon-footprint-cli` binary is a thin wrapper around this crate; the same client
+//! and types can be used to request estimates from other programs.
+//!
+//! ```no_run
+//! use carbon_footprint_cli::{estimate_flight, ApiClient, FlightEstimateRequest, Leg};
+//!
+//! # async fn estimate() -> Result<(), carbon_footprint_cli::CliError> {
+//! let api_client = ApiClient::new(reqwest::Client::new(), carbon_footprint_cli::DEFAULT_BASE_URL);
+//! let request = FlightEstimateRequest::builder()
+//!     .passengers(2)
+//!     .add_leg(Leg {
+//!         departure_airport: "LHR".to_string(),
+//!         destination_airport: "JFK".to_string(),
+//!         cabin_class: Some("economy".to_string()),
+//!         passengers: None,
+//!         aircraft: None,
+//!     })
+//!     .build()?;
+//!
+//! let attributes = estimate_flight(&api_client, &request, "YOUR_API_KEY").await?;
+//! println!("{} kg CO2", attributes.carbon_kg);
+//! # Ok(())
+//! # }
+//! ```
+
+pub mod airport_cache;
+pub mod airports;
+pub mod batch;
+mod client;
+pub mod config;
+pub mod credentials;
+pub mod currency;
+mod error;
+pub mod estimate_cache;
+pub mod estimator;
+pub mod footprint;
+pub mod geojson;
+pub mod history;
+pub mod i18n;
+mod model;
+pub mod offline;
+pub mod output;
+
+#[cfg(test)]
+mod test_support;
+
+pub use client::{
+    attributes_of, build_http_client, estimate_flight, make_electricity_estimates_request,
+    make_estimates_request, make_estimates_request_with_diagnostics,
+    make_shipping_estimates_request, make_vehicle_estimates_request, ApiClient, ApiResponse,
+    AuthScheme, Clock, EstimateBackend, RequestDiagnostics, SystemClock, DEFAULT_BASE_URL,
+    DEFAULT_MAX_RETRIES, DEFAULT_RETRY_DELAY, DEFAULT_TIMEOUT, DEFAULT_USER_AGENT,
+};
+pub use error::{CliError, RequestError};
+pub use model::{
+    is_missing, is_same_airport_leg, mirror_legs, normalize_cabin_class, split_by_cabin,
+    split_by_leg_passengers, split_legs, validate_request, CabinPassengers,
+    ElectricityEstimateAttributes, ElectricityEstimateRequest, ElectricityEstimateResponse,
+    EstimateAttributes, EstimateData, EstimateResponse, EstimateResult, FlightEstimateRequest,
+    FlightEstimateRequestBuilder, FlightEstimateResponse, ItineraryGroup, Leg, MixedCabinLeg,
+    ShippingEstimateAttributes, ShippingEstimateRequest, ShippingEstimateResponse, ValidationIssue,
+    VehicleEstimateAttributes, VehicleEstimateRequest, VehicleEstimateResponse, CABIN_CLASSES,
+    DISTANCE_UNITS, ELECTRICITY_UNITS, MISSING_FIGURE, TRANSPORT_METHODS, WEIGHT_UNITS,
+};
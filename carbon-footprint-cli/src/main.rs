@@ -1,688 +1,5826 @@
+use carbon_footprint_cli::airport_cache::{
+    AirportCache, HttpAirportFetcher, DEFAULT_AIRPORTS_TTL_DAYS,
+};
+use carbon_footprint_cli::airports::{
+    find_airport, is_known_iata, is_valid_airport_code, normalize_airport_code, resolve_metro_code,
+    search_airports, use_airport_data, Airport,
+};
+use carbon_footprint_cli::batch::{
+    batch_schema, batch_summary, estimate_batch_cached, estimate_batch_fail_fast,
+    estimate_batch_streaming, estimate_mixed_cabins, estimate_per_leg, filter_by_carbon,
+    group_by_itinerary, input_format_from_path, parse_batch_entries_as, parse_json_lines,
+    render_sample_batch, route_of, BatchEntry, BatchFailure, InputFormat, DEFAULT_CONCURRENCY,
+};
+use carbon_footprint_cli::config::Config;
+use carbon_footprint_cli::credentials::{default_store, stored_api_key, SecretStore};
+use carbon_footprint_cli::currency::{Currency, HttpRates, RateSource, StaticRates};
+use carbon_footprint_cli::estimate_cache::{EstimateCache, DEFAULT_CACHE_TTL_HOURS};
+use carbon_footprint_cli::estimator::{CarbonEstimator, OfflineEstimator, Provider};
+use carbon_footprint_cli::footprint::{
+    combine_cabin_estimates, convert_distance, distance_km, exceeds_budget, exceeds_max_distance,
+    is_short_flight, is_zero_distance, sum_attributes, sum_known, with_distance_unit, CarbonUnit,
+    DEFAULT_OFFSET_PRICE, DEFAULT_RF_MULTIPLIER, DEFAULT_SHORT_FLIGHT_KM,
+};
+use carbon_footprint_cli::geojson::route_geojson;
+use carbon_footprint_cli::history::{
+    append_record, default_history_path, read_history, total_emissions, HistoryRecord,
+    HistoryTotal, Period,
+};
+use carbon_footprint_cli::i18n::{set_locale, t, tf, tfv, Locale};
+use carbon_footprint_cli::offline::{leg_distance_km, CabinFactors, DEFAULT_EMISSION_FACTOR};
+use carbon_footprint_cli::output::{
+    compare_results, explanation, format_from_path, itinerary_file_stem, rank_itineraries,
+    render_batch, render_comparison, render_electricity_estimate, render_estimate,
+    render_itineraries, render_leg_breakdown, render_ranking, render_shipping_estimate,
+    render_total, render_vehicle_estimate, shown_units, sort_results, units_legend, OutputFormat,
+    RenderOptions, SortKey, DEFAULT_PRECISION,
+};
+use carbon_footprint_cli::{
+    attributes_of, build_http_client, is_missing, is_same_airport_leg,
+    make_electricity_estimates_request, make_estimates_request_with_diagnostics,
+    make_shipping_estimates_request, make_vehicle_estimates_request, mirror_legs,
+    normalize_cabin_class, split_by_cabin, split_by_leg_passengers, split_legs, validate_request,
+    ApiClient, CabinPassengers, CliError, ElectricityEstimateRequest, EstimateAttributes,
+    EstimateResult, FlightEstimateRequest, ItineraryGroup, Leg, MixedCabinLeg, RequestDiagnostics,
+    ShippingEstimateRequest, SystemClock, ValidationIssue, VehicleEstimateRequest, CABIN_CLASSES,
+    DEFAULT_BASE_URL, DEFAULT_MAX_RETRIES, DEFAULT_RETRY_DELAY, DEFAULT_TIMEOUT,
+    DEFAULT_USER_AGENT, DISTANCE_UNITS, ELECTRICITY_UNITS, TRANSPORT_METHODS, WEIGHT_UNITS,
+};
+use chrono::{NaiveDate, Utc};
+use clap::{CommandFactory, Parser, Subcommand};
+use clap_complete::Shell;
+use colored::Colorize;
 use indicatif::{ProgressBar, ProgressStyle};
-use prettytable::{format, row, Cell, Row, Table};
-use reqwest::Client;
 use rpassword::read_password;
-use serde_derive::{Deserialize, Serialize};
-use std::error::Error;
-use std::fmt;
-use std::io::{self, Write};
-use std::time::Duration;
-use colored::*;
-
-#[derive(Serialize, Deserialize)]
-struct Leg {
-    departure_airport: String,
-    destination_airport: String,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    cabin_class: Option<String>,
-}
-
-#[derive(Serialize, Deserialize)]
-struct FlightEstimateRequest {
-    #[serde(rename = "type")]
-    estimate_type: String,
-    passengers: u32,
-    legs: Vec<Leg>,
-    #[serde(skip_serializing_if = "Option::is_none")]
-    distance_unit: Option<String>,
-}
+use std::collections::HashSet;
+use std::fs;
+use std::future::Future;
+use std::io::{self, BufRead, IsTerminal, Read, Write};
+use std::path::{Path, PathBuf};
+use std::time::{Duration, SystemTime};
 
-#[derive(Serialize, Deserialize, Default, Debug)]
-struct FlightEstimateResponse {
-    #[serde(default)]
-    data: Option<EstimateData>,
-    #[serde(default)]
-    message: Option<String>,
-}
+#[derive(Parser, Debug)]
+#[command(about = "Estimate the carbon footprint of flights", version)]
+struct Args {
+    /// Carbon Interface API key; prompted for when neither this, --api-key-file nor
+    /// CARBON_INTERFACE_API_KEY is set
+    #[arg(long)]
+    api_key: Option<String>,
 
-#[derive(Serialize, Deserialize, Debug)]
-struct EstimateData {
-    attributes: EstimateAttributes,
-}
+    /// Read the API key from the first line of this file, e.g. a mounted secret
+    #[arg(long, value_name = "PATH")]
+    api_key_file: Option<PathBuf>,
 
-#[derive(Serialize, Deserialize, Debug)]
-struct EstimateAttributes {
-    carbon_g: f32,
-    carbon_lb: f32,
-    carbon_kg: f32,
-    carbon_mt: f32,
-    distance_unit: String,
-    distance_value: f32,
-}
+    /// The key from CARBON_INTERFACE_API_KEY, which ranks below both flags
+    #[arg(skip = std::env::var("CARBON_INTERFACE_API_KEY").ok())]
+    env_api_key: Option<String>,
 
-struct ApiClient {
-    client: Client,
-    base_url: String,
-}
+    /// Departure airport IATA code; pair each --from with a --to to add a leg
+    #[arg(long, value_name = "IATA", value_parser = parse_airport_code)]
+    from: Vec<String>,
 
-impl ApiClient {
-    fn new(client: Client, base_url: &str) -> Self {
-        Self {
-            client,
-            base_url: base_url.into(),
-        }
-    }
+    /// Destination airport IATA code for the matching --from
+    #[arg(long, value_name = "IATA", value_parser = parse_airport_code)]
+    to: Vec<String>,
 
-    async fn post_estimate(
-        &self,
-        request: &FlightEstimateRequest,
-        api_key: &str,
-    ) -> Result<String, CliError> {
-        let json_body = serde_json::to_string(request)?;
+    /// Add a leg as FROM:TO[:CABIN], appended after any --from/--to legs
+    #[arg(long, value_name = "FROM:TO[:CABIN]", value_parser = parse_leg)]
+    leg: Vec<Leg>,
 
-        let pb = ProgressBar::new_spinner();
+    /// Airports to fly through in order, e.g. `LHR JFK LAX` for two legs; instead of
+    /// --from/--to
+    #[arg(
+        value_name = "IATA",
+        value_parser = parse_airport_code,
+        conflicts_with_all = ["from", "to"]
+    )]
+    airports: Vec<String>,
 
-        let style = ProgressStyle::default_spinner()
-            .tick_chars("/|\\- ")
-            .template("{spinner} {wide_msg}");
+    /// Add a leg with passengers split across cabins, e.g. LHR:JFK:economy=2+business=1.
+    /// Replaces --from/--to, --leg, positional airports, --passengers and --cabin.
+    #[arg(
+        long = "mixed-leg",
+        value_name = "FROM:TO:CABIN=N[+CABIN=N...]",
+        value_parser = parse_mixed_leg
+    )]
+    mixed_legs: Vec<MixedCabinLeg>,
 
-        match style {
-            Ok(st) => pb.set_style(st),
-            Err(e) => eprintln!("Error setting progress bar style: {}", e),
-        }
+    /// Number of passengers when legs are given as flags (default: 1)
+    #[arg(long, value_parser = parse_passengers)]
+    passengers: Option<u32>,
 
-        pb.set_message("Estimating...");
-        pb.enable_steady_tick(Duration::from_millis(50));
+    /// Accept well-formed airport codes that aren't in the bundled airport list
+    #[arg(long)]
+    allow_unknown_iata: bool,
 
-        let response = self
-            .client
-            .post(&format!("{}/api/v1/estimates", self.base_url))
-            .header("Authorization", format!("Bearer {}", api_key))
-            .header("Content-Type", "application/json")
-            .body(json_body)
-            .send()
-            .await?;
+    /// Accept legs that depart from and land at the same airport
+    #[arg(long)]
+    allow_same_airport: bool,
 
-        pb.finish_and_clear();
+    /// Send metropolitan area codes such as LON as given, instead of their main airport
+    #[arg(long)]
+    no_metro_resolution: bool,
 
-        if response.status() == 401 {
-            return Err(CliError::InvalidApiKey);
-        }
+    /// Cabin class for legs given via --from/--to: economy, premium, business or first
+    #[arg(long, value_parser = parse_cabin_class)]
+    cabin: Option<String>,
 
-        response.text().await.map_err(CliError::NetworkError)
-    }
-}
+    /// ICAO aircraft type for legs given via --from/--to, e.g. A20N or B789. The API doesn't
+    /// take one, so it only refines --offline estimates
+    #[arg(long, value_name = "TYPE", value_parser = parse_aircraft)]
+    aircraft: Option<String>,
 
-#[derive(Debug)]
-enum CliError {
-    NetworkError(reqwest::Error),
-    UnexpectedResponseFormat(serde_json::Error),
-    ApiError(String),
-    InvalidApiKey,
-}
+    /// Carbon Interface API root, e.g. to target a proxy or mock server (default: the public API)
+    #[arg(long, env = "CARBON_INTERFACE_BASE_URL", value_parser = parse_base_url)]
+    base_url: Option<String>,
 
-impl fmt::Display for CliError {
-    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
-        match self {
-            CliError::NetworkError(err) => write!(f, "Network error: {}", err),
-            CliError::UnexpectedResponseFormat(err) => {
-                write!(f, "Unexpected response format: {}", err)
-            }
-            CliError::ApiError(err) => write!(f, "API error: {}", err),
-            CliError::InvalidApiKey => write!(f, "Invalid API key."),
-        }
-    }
-}
+    /// Send requests through this proxy, e.g. http://proxy.example.com:8080. Without it,
+    /// HTTPS_PROXY, HTTP_PROXY and NO_PROXY from the environment are used.
+    #[arg(long, value_name = "URL", value_parser = parse_proxy)]
+    proxy: Option<String>,
 
-impl Error for CliError {}
+    /// What requests send as their User-Agent (default: carbon-footprint-cli/VERSION)
+    #[arg(long, value_name = "VALUE")]
+    user_agent: Option<String>,
 
-impl From<reqwest::Error> for CliError {
-    fn from(err: reqwest::Error) -> Self {
-        CliError::NetworkError(err)
-    }
-}
+    /// Seconds to wait for the API before giving up
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        default_value_t = DEFAULT_TIMEOUT.as_secs(),
+        value_parser = clap::value_parser!(u64).range(1..)
+    )]
+    timeout: u64,
 
-impl From<serde_json::Error> for CliError {
-    fn from(err: serde_json::Error) -> Self {
-        CliError::UnexpectedResponseFormat(err)
-    }
-}
+    /// Retry rate-limited and failed (5xx) requests up to this many times; 0 disables retries
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_MAX_RETRIES,
+        value_parser = clap::value_parser!(u32).range(..=MAX_RETRIES as i64)
+    )]
+    retries: u32,
 
-async fn make_estimates_request(
-    api_client: &ApiClient,
-    request: &FlightEstimateRequest,
-    api_key: &str,
-) -> Result<FlightEstimateResponse, CliError> {
-    let response_body = api_client.post_estimate(request, api_key).await?;
-
-    let response_json: Result<FlightEstimateResponse, _> = serde_json::from_str(&response_body);
-    match response_json {
-        Ok(mut response) => {
-            if let Some(error_message) = response.message.take() {
-                return Err(CliError::ApiError(error_message));
-            }
+    /// Longest wait in milliseconds before the first retry, doubling for each one after
+    #[arg(
+        long,
+        value_name = "MS",
+        default_value_t = DEFAULT_RETRY_DELAY.as_millis() as u64,
+        value_parser = clap::value_parser!(u64).range(..=MAX_RETRY_DELAY_MS)
+    )]
+    retry_delay_ms: u64,
 
-            if let Some(data) = response.data.take() {
-                Ok(FlightEstimateResponse {
-                    data: Some(data),
-                    ..Default::default()
-                })
-            } else {
-                Err(CliError::ApiError("Missing response data".to_string()))
-            }
-        }
-        Err(err) => Err(CliError::UnexpectedResponseFormat(err)),
-    }
-}
+    /// How to print the estimate (default: from the --output-file extension, else text)
+    #[arg(long, value_enum)]
+    output: Option<OutputFormat>,
 
-fn get_flight_details() -> (u32, Vec<Leg>, Option<String>) {
-    let passengers = get_user_input(
-        "👥 Enter the number of passengers: ",
-        "❌ Invalid input. Please enter a valid number.",
-        |input| input.parse::<u32>().is_ok(),
-    )
-        .parse::<u32>()
-        .unwrap(); // Assuming the user inputs a valid integer
+    /// Print JSON on one line instead of indented, and a batch as one object per line
+    #[arg(long)]
+    compact: bool,
 
-    let number_of_legs = get_user_input(
-        "✈️ Enter the number of legs: ",
-        "❌ Invalid input. Please enter a valid number.",
-        |input| input.parse::<usize>().is_ok(),
-    )
-        .parse::<usize>()
-        .unwrap(); // Assuming the user inputs a valid integer
+    /// Decimal places shown for carbon and distance figures (tonnes get one more)
+    #[arg(
+        long,
+        value_name = "PLACES",
+        default_value_t = DEFAULT_PRECISION,
+        value_parser = clap::value_parser!(u32).range(..=MAX_PRECISION as i64)
+    )]
+    precision: u32,
 
-    let distance_unit = get_user_input(
-        "📏 Enter the distance unit (km or mi): ",
-        "❌ Invalid input. Distance unit can be 'km' or 'mi'.",
-        |input| input.is_empty() || ["km", "mi"].contains(&input),
-    );
+    /// Estimate every request in a JSON array (or YAML or JSON Lines) file instead of a single trip; `-`
+    /// reads stdin
+    #[arg(long, value_name = "FILE")]
+    input: Option<PathBuf>,
 
-    let mut legs: Vec<Leg> = Vec::new();
+    /// How --input is written (default: YAML for .yaml/.yml files, JSON Lines for
+    /// .jsonl/.ndjson, else JSON)
+    #[arg(long, value_enum)]
+    input_format: Option<InputFormat>,
 
-    for i in 0..number_of_legs {
-        println!("🔢 Enter details for leg {}:", i + 1);
+    /// How many batch requests to send at once
+    #[arg(
+        long,
+        value_name = "N",
+        default_value_t = DEFAULT_CONCURRENCY,
+        value_parser = clap::builder::RangedU64ValueParser::<usize>::new().range(1..)
+    )]
+    concurrency: usize,
 
-        let departure_airport = get_user_input(
-            "🛫 Enter the departure airport IATA code: ",
-            "❌ Invalid input. IATA codes should be exactly 3 uppercase letters.",
-            |input| input.chars().all(|c| c.is_ascii_uppercase()) && input.len() == 3,
-        );
+    /// Print each --input batch result as soon as it's estimated, then the totals, instead
+    /// of waiting for the whole batch; results come in the order they complete
+    #[arg(
+        long,
+        requires = "input",
+        conflicts_with_all = [
+            "fail_fast", "sort", "rank", "output_dir", "output_file", "min_carbon_kg",
+            "max_carbon_kg"
+        ]
+    )]
+    stream: bool,
 
-        let destination_airport = get_user_input(
-            "🛬 Enter the destination airport IATA code: ",
-            "❌ Invalid input. IATA codes should be exactly 3 uppercase letters.",
-            |input| input.chars().all(|c| c.is_ascii_uppercase()) && input.len() == 3,
-        );
+    /// Keep --stream results in batch order, holding each back until the earlier ones are in
+    #[arg(long, requires = "stream")]
+    ordered: bool,
 
-        let cabin_class = get_user_input(
-            "💺 Enter the cabin class (economy or premium): ",
-            "❌ Invalid input. Cabin class can be 'economy' or 'premium'.",
-            |input| input.is_empty() || ["economy", "premium"].contains(&input),
-        );
+    /// Stop an --input batch at the first failed request instead of estimating the rest
+    #[arg(long)]
+    fail_fast: bool,
 
-        let leg = Leg {
-            departure_airport,
-            destination_airport,
-            cabin_class: Some(if cabin_class.is_empty() {
-                "economy".to_string()
-            } else {
-                cabin_class
-            }),
-        };
+    /// Give up on an --input batch after this long in total, retries included; requests
+    /// not finished by then are reported as skipped
+    #[arg(long, value_name = "SECONDS", value_parser = clap::value_parser!(u64).range(1..))]
+    deadline: Option<u64>,
 
-        legs.push(leg);
-    }
+    /// Estimate the trip again every this many seconds, redrawing the result, until
+    /// interrupted with Ctrl-C
+    #[arg(
+        long,
+        value_name = "SECONDS",
+        value_parser = clap::value_parser!(u64).range(1..),
+        conflicts_with_all = ["dry_run", "output_file", "budget_kg"]
+    )]
+    watch: Option<u64>,
 
-    (passengers, legs, Some(distance_unit))
-}
+    /// Only show --input batch results with at least this much carbon, in kg
+    #[arg(long, value_name = "KG", value_parser = parse_positive)]
+    min_carbon_kg: Option<f32>,
 
-#[tokio::main]
-async fn main() {
-    print_banner();
+    /// Only show --input batch results with at most this much carbon, in kg
+    #[arg(long, value_name = "KG", value_parser = parse_positive)]
+    max_carbon_kg: Option<f32>,
 
-    print!("🔑 Please enter your API key: ");
-    io::stdout().flush().unwrap();
+    /// Rank an --input batch's itineraries from greenest, with how much more each emits
+    #[arg(long, requires = "input", conflicts_with = "sort")]
+    rank: bool,
 
-    // Read the API key securely, without displaying it in the console
-    let api_key = read_password().expect("Failed to read API key");
+    /// Order --input batch or --per-leg results by carbon, distance or route (default: as given)
+    #[arg(long, value_enum, value_name = "KEY")]
+    sort: Option<SortKey>,
 
-    let (passengers, legs, distance_unit) = get_flight_details();
+    /// With --sort, put the largest first
+    #[arg(long, requires = "sort")]
+    sort_desc: bool,
 
-    let request = FlightEstimateRequest {
-        estimate_type: String::from("flight"),
-        passengers,
-        legs,
-        distance_unit,
-    };
-    let client = Client::new();
-    let api_client = ApiClient::new(client, "https://www.carboninterface.com");
-
-    match make_estimates_request(&api_client, &request, &api_key).await {
-        Ok(response) => {
-            let mut table = Table::new();
-            table.set_format(*format::consts::FORMAT_CLEAN);
-            table.set_titles(row![bc=> "Metric".bold(), "Value".bold(), "Unit".bold()]);
-
-            if let Some(data) = response.data {
-                let estimate = data.attributes;
-                let max_metric_length = 22;
-                table.add_row(Row::new(vec![
-                    Cell::new(&format!("{:<max_width$}", "Carbon emissions (g)", max_width = max_metric_length)),
-                    Cell::new(&format!("{:.2}", estimate.carbon_g)),
-                    Cell::new(&"g".italic().magenta().to_string()),
-                ]));
-                table.add_row(Row::new(vec![
-                    Cell::new(&format!("{:<max_width$}", "Carbon emissions (kg)", max_width = max_metric_length)),
-                    Cell::new(&format!("{:.2}", estimate.carbon_kg)),
-                    Cell::new(&"kg".italic().magenta().to_string()),
-                ]));
-                table.add_row(Row::new(vec![
-                    Cell::new(&format!("{:<max_width$}", "Distance", max_width = max_metric_length)),
-                    Cell::new(&format!("{:.2}", estimate.distance_value)),
-                    Cell::new(&estimate.distance_unit.italic().magenta().to_string()),
-                ]));
-            } else {
-                eprintln!("Error: Missing response data");
-            }
+    /// Fly back home: append the outbound legs reversed
+    #[arg(long)]
+    round_trip: bool,
 
-            println!("\n");
-            println!("{}", "🌍 Estimated carbon emissions for your trip are: 🌍".bold().green());
+    /// Unit for the returned distance (default: the API's, km)
+    #[arg(long, value_parser = DISTANCE_UNITS)]
+    distance_unit: Option<String>,
 
-            table.printstd();
+    /// Offset price in USD per tonne of CO2 (default: 15)
+    #[arg(long, value_name = "USD_PER_TONNE", value_parser = parse_non_negative)]
+    offset_price: Option<f32>,
 
-            println!("\n");
-            println!("{}", "🌳 Please consider offsetting your carbon footprint. 🌳".bold().green());
-            println!("{}", "Learn more at: https://carbonfund.org/how-to-offset-the-carbon-footprint-of-flying/".underline());
-        }
-        Err(err) => {
-            eprintln!("Error: {}", err);
-        }
-    }
-}
+    /// Show offset costs in this currency, e.g. EUR (default: USD)
+    #[arg(long, value_name = "ISO", value_parser = parse_currency)]
+    currency: Option<String>,
 
-fn get_user_input(prompt: &str, error_message: &str, validator: impl Fn(&str) -> bool) -> String {
-    loop {
-        print!("{}", prompt);
-        io::stdout().flush().unwrap();
+    /// Fetch exchange rates from this endpoint instead of the bundled table,
+    /// e.g. https://api.frankfurter.app/latest
+    #[arg(long, value_name = "URL", value_parser = parse_base_url)]
+    fx_url: Option<String>,
 
-        let mut input = String::new();
-        io::stdin().read_line(&mut input).unwrap();
+    /// Download the airport list from this URL (a CSV in the bundled format) instead of
+    /// using the bundled one; it's cached and only fetched again once it's stale
+    #[arg(long, value_name = "URL", value_parser = parse_base_url)]
+    airports_url: Option<String>,
 
-        let input = input.trim();
-        if !input.is_empty() && validator(input) {
-            return input.to_string();
-        } else {
-            eprintln!("{}", error_message);
-        }
-    }
-}
-fn print_banner() {
-    let banner = r#"
+    /// Days a downloaded airport list is used before it's fetched again (default: 30)
+    #[arg(long, value_name = "DAYS")]
+    airports_ttl_days: Option<u32>,
 
--------WELCOME TO THE CARBON FOOTPRINT CLI-------
-                   __|__
-            --------(_)--------
-              O  O       O  O
-"#;
-    println!("{}", banner);
-}
+    /// Fetch the --airports-url list again even if the cached copy is fresh
+    #[arg(long)]
+    refresh_airports: bool,
 
-#[cfg(test)]
-mod tests {
-    use super::*;
-    use wiremock::{
-        matchers::{method, path},
-        Mock, MockServer, ResponseTemplate,
-    };
+    /// Emissions unit to show; repeat or comma-separate for several (default: kg in text,
+    /// every unit in JSON and CSV)
+    #[arg(long = "unit", value_name = "UNIT", value_enum, value_delimiter = ',')]
+    units: Vec<CarbonUnit>,
 
-    fn create_mock_response(
-        carbon_g: f32,
-        carbon_lb: f32,
-        carbon_kg: f32,
-        carbon_mt: f32,
-        distance_unit: &str,
-        distance_value: f32,
-    ) -> FlightEstimateResponse {
-        FlightEstimateResponse {
-            data: Some(EstimateData {
-                attributes: EstimateAttributes {
-                    carbon_g,
-                    carbon_lb,
-                    carbon_kg,
-                    carbon_mt,
-                    distance_unit: distance_unit.to_string(),
-                    distance_value,
-                },
-            }),
-            message: None,
-        }
-    }
+    /// Leave the everyday equivalents out of the text output
+    #[arg(long)]
+    no_equivalencies: bool,
 
-    #[tokio::test]
-    async fn test_make_estimates_for_single_leg_request_success() {
-        // Start a WireMock server
-        let server = MockServer::start().await;
+    /// Show distances in this unit, whichever unit was requested (default: as the API answers)
+    #[arg(long, value_name = "UNIT", value_parser = DISTANCE_UNITS)]
+    display_distance: Option<String>,
 
-        // Set up a mock response for a successful request
-        let mock_response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
-        Mock::given(method("POST"))
-            .and(path("/api/v1/estimates"))
-            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
-            .mount(&server)
-            .await;
+    /// Warn and exit with code 3 if the run's total carbon_kg is over this budget
+    #[arg(long, value_name = "KG", value_parser = parse_positive)]
+    budget_kg: Option<f32>,
 
-        // Create a test request
-        let request = FlightEstimateRequest {
-            estimate_type: "flight".to_string(),
-            passengers: 100,
-            legs: vec![Leg {
-                departure_airport: "LHR".to_string(),
-                destination_airport: "JFK".to_string(),
-                cabin_class: None,
-            }],
-            distance_unit: None,
-        };
+    /// Warn and exit with code 6 if the run's total carbon_kg is over this, though not
+    /// over --budget-kg
+    #[arg(long, value_name = "KG", value_parser = parse_positive)]
+    warn_carbon_kg: Option<f32>,
 
-        // Create the API client with the mock server's URI
-        let api_client = ApiClient::new(Client::new(), &server.uri());
+    /// Fail if a trip's distance comes back over this, in --distance-unit (default km); a
+    /// single flight over 20000 km usually means a mistyped airport code
+    #[arg(long, value_name = "DISTANCE", value_parser = parse_positive)]
+    max_distance: Option<f32>,
 
-        // Make the request to the mock server
-        let response = make_estimates_request(&api_client, &request, "").await;
+    /// Warn if a trip's distance comes back over this, in --distance-unit (default km)
+    #[arg(long, value_name = "DISTANCE", value_parser = parse_positive)]
+    warn_distance: Option<f32>,
 
-        // Check the response
-        assert!(response.is_ok());
-        let response = response.unwrap();
-        assert!(response.data.is_some());
-        let estimate = response.data.unwrap().attributes;
-        assert_eq!(estimate.carbon_g, 99911700.0);
-        assert_eq!(estimate.carbon_lb, 267.6);
-        assert_eq!(estimate.carbon_kg, 99911.7);
-        assert_eq!(estimate.carbon_mt, 99.91);
-        assert_eq!(estimate.distance_unit, "km");
-        assert_eq!(estimate.distance_value, 5660.34);
-    }
+    /// Suggest a train or car for any leg shorter than this many km (default: 500)
+    #[arg(long, value_name = "KM", value_parser = parse_positive)]
+    short_flight_km: Option<f32>,
 
-    #[tokio::test]
-    async fn test_make_estimates_for_single_leg_request_error() {
-        // Start a WireMock server
-        let server = MockServer::start().await;
+    /// Also show what the same distance would emit by train or by car
+    #[arg(long)]
+    compare_alternatives: bool,
 
-        // Set up a mock response for an error request
-        let error_response = FlightEstimateResponse {
-            message: Some("Validation failed: Legs require valid airport codes".to_string()),
-            ..Default::default()
-        };
-        Mock::given(method("POST"))
-            .and(path("/api/v1/estimates"))
-            .respond_with(ResponseTemplate::new(400).set_body_json(&error_response))
-            .mount(&server)
-            .await;
+    /// Estimate each leg on its own to show what it contributes (one request per leg)
+    #[arg(long)]
+    per_leg: bool,
 
-        // Create a test request
-        let request = FlightEstimateRequest {
-            estimate_type: "flight".to_string(),
-            passengers: 100,
-            legs: vec![Leg {
-                departure_airport: "LHR".to_string(),
-                destination_airport: "XYZ".to_string(), // Invalid airport code
-                cabin_class: None,
-            }],
-            distance_unit: None,
-        };
+    /// Write the results to this file instead of stdout
+    #[arg(long, value_name = "PATH")]
+    output_file: Option<PathBuf>,
 
-        // Create the API client with the mock server's URI
-        let api_client = ApiClient::new(Client::new(), &server.uri());
+    /// Write each --input itinerary to its own file in this directory, named after it
+    #[arg(long, value_name = "DIR", requires = "input", conflicts_with_all = ["output_file", "rank"])]
+    output_dir: Option<PathBuf>,
 
-        // Make the request to the mock server
-        let response = make_estimates_request(&api_client, &request, "").await;
+    /// Overwrite --output-file if it already exists
+    #[arg(long)]
+    force: bool,
 
-        // Check the response
-        assert!(response.is_err());
-        let error = response.err().unwrap().to_string();
-        assert_eq!(
-            error,
-            "API error: Validation failed: Legs require valid airport codes"
-        );
-    }
-    #[tokio::test]
-    async fn test_make_estimates_request_multiple_legs_success() {
-        // Start a WireMock server
-        let server = MockServer::start().await;
+    /// Also write the route as a GeoJSON map to this file (overwritten with --force)
+    #[arg(long, value_name = "PATH")]
+    export_geojson: Option<PathBuf>,
 
-        // Set up a mock response for a successful request
-        let mock_response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
-        Mock::given(method("POST"))
-            .and(path("/api/v1/estimates"))
-            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
-            .mount(&server)
-            .await;
+    /// Give up after this many invalid answers to a single prompt (default: unlimited)
+    #[arg(long, value_name = "N")]
+    reprompt_limit: Option<u32>,
 
-        // Create a test request with multiple legs
-        let request = FlightEstimateRequest {
-            estimate_type: "flight".to_string(),
-            passengers: 100,
-            legs: vec![
-                Leg {
-                    departure_airport: "LHR".to_string(),
-                    destination_airport: "JFK".to_string(),
-                    cabin_class: None,
-                },
-                Leg {
-                    departure_airport: "JFK".to_string(),
-                    destination_airport: "LHR".to_string(),
-                    cabin_class: None,
-                },
-            ],
-            distance_unit: None,
-        };
+    /// Log requests and responses to stderr; repeat for more detail (-vv)
+    #[arg(short, long, action = clap::ArgAction::Count)]
+    verbose: u8,
 
-        // Create the API client with the mock server's URI
-        let api_client = ApiClient::new(Client::new(), &server.uri());
+    /// Write logs to this file instead of stderr, with -v detail at least. A file over
+    /// 1 MiB is moved aside to PATH.1 first.
+    #[arg(long, value_name = "PATH")]
+    log_file: Option<PathBuf>,
 
-        // Make the request to the mock server
-        let response = make_estimates_request(&api_client, &request, "").await;
+    /// Print only the carbon_kg figure (or the --output format): no banner, spinner or totals
+    #[arg(short, long)]
+    quiet: bool,
 
-        // Check the response
-        assert!(response.is_ok());
-        let response = response.unwrap();
-        assert!(response.data.is_some());
-        let estimate = response.data.unwrap().attributes;
-        assert_eq!(estimate.carbon_g, 99911700.0);
-        assert_eq!(estimate.carbon_lb, 267.6);
-        assert_eq!(estimate.carbon_kg, 99911.7);
-        assert_eq!(estimate.carbon_mt, 99.91);
-        assert_eq!(estimate.distance_unit, "km");
-        assert_eq!(estimate.distance_value, 5660.34);
-    }
-    #[tokio::test]
-    async fn test_make_estimates_request_multiple_legs_error() {
-        // Start a WireMock server
-        let server = MockServer::start().await;
+    /// Don't print the welcome banner, which is already left out when stdout isn't a terminal
+    #[arg(long)]
+    no_banner: bool,
 
-        // Set up a mock response for an error request
-        let error_response = FlightEstimateResponse {
-            message: Some("Validation failed: Legs require valid airport codes".to_string()),
-            ..Default::default()
-        };
-        Mock::given(method("POST"))
-            .and(path("/api/v1/estimates"))
-            .respond_with(ResponseTemplate::new(400).set_body_json(&error_response))
-            .mount(&server)
-            .await;
+    /// Read defaults from this TOML file instead of ~/.config/carbon-footprint/config.toml
+    #[arg(long, value_name = "PATH")]
+    config: Option<PathBuf>,
 
-        // Create a test request with multiple legs
-        let request = FlightEstimateRequest {
-            estimate_type: "flight".to_string(),
-            passengers: 100,
-            legs: vec![
-                Leg {
-                    departure_airport: "LHR".to_string(),
-                    destination_airport: "XYZ".to_string(), // Invalid airport code
-                    cabin_class: None,
-                },
-                Leg {
-                    departure_airport: "JFK".to_string(),
-                    destination_airport: "LHR".to_string(),
-                    cabin_class: None,
-                },
-            ],
-            distance_unit: None,
-        };
+    /// Use this profile from the config file instead of its default_profile
+    #[arg(long, value_name = "NAME")]
+    profile: Option<String>,
 
-        // Create the API client with the mock server's URI
-        let api_client = ApiClient::new(Client::new(), &server.uri());
+    /// Don't add this estimate to the history file
+    #[arg(long)]
+    no_history: bool,
 
-        // Make the request to the mock server
-        let response = make_estimates_request(&api_client, &request, "").await;
+    /// Keep history here instead of ~/.local/share/carbon-footprint/history.jsonl
+    #[arg(long, value_name = "PATH")]
+    history_file: Option<PathBuf>,
 
-        // Check the response
-        assert!(response.is_err());
-        let error = response.err().unwrap().to_string();
-        assert_eq!(
-            error,
-            "API error: Validation failed: Legs require valid airport codes"
-        );
-    }
+    /// Submit interactively entered trips without asking for confirmation
+    #[arg(short, long)]
+    yes: bool,
 
-    #[tokio::test]
-    async fn test_make_estimates_request_different_cabin_classes() {
-        // Start a WireMock server
-        let server = MockServer::start().await;
+    /// Approximate the estimate locally from great-circle distances instead of calling the
+    /// API; the same as --provider offline
+    #[arg(long, conflicts_with = "provider")]
+    offline: bool,
 
-        // Set up a mock response for a successful request
-        let mock_response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
-        Mock::given(method("POST"))
-            .and(path("/api/v1/estimates"))
-            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
-            .mount(&server)
-            .await;
+    /// Where flight estimates come from
+    #[arg(long, value_enum, default_value_t = Provider::CarbonInterface)]
+    provider: Provider,
 
-        // Create a test request with different cabin classes
-        let request = FlightEstimateRequest {
-            estimate_type: "flight".to_string(),
-            passengers: 100,
-            legs: vec![
-                Leg {
-                    departure_airport: "LHR".to_string(),
-                    destination_airport: "JFK".to_string(),
-                    cabin_class: Some("economy".to_string()),
-                },
-                Leg {
-                    departure_airport: "JFK".to_string(),
-                    destination_airport: "LHR".to_string(),
-                    cabin_class: Some("business".to_string()),
-                },
-            ],
-            distance_unit: None,
-        };
+    /// kg of CO2 per passenger-km for --offline (default: 0.115)
+    #[arg(long, value_name = "KG", value_parser = parse_positive)]
+    emission_factor: Option<f32>,
 
-        // Create the API client with the mock server's URI
-        let api_client = ApiClient::new(Client::new(), &server.uri());
+    /// Also show emissions as CO2-equivalent, multiplied by this radiative forcing
+    /// factor for their extra warming at altitude, e.g. 1.9 (default: 1.0, not shown)
+    #[arg(long, value_name = "FACTOR", value_parser = parse_positive)]
+    rf_multiplier: Option<f32>,
 
-        // Make the request to the mock server
-        let response = make_estimates_request(&api_client, &request, "").await;
+    /// Fail instead of only warning when the API reports a distance of 0, which usually
+    /// means bad airport codes
+    #[arg(long)]
+    strict: bool,
 
-        // Check the response
-        assert!(response.is_ok());
-        let response = response.unwrap();
-        assert!(response.data.is_some());
-        let estimate = response.data.unwrap().attributes;
-        assert_eq!(estimate.carbon_g, 99911700.0);
-        assert_eq!(estimate.carbon_lb, 267.6);
-        assert_eq!(estimate.carbon_kg, 99911.7);
-        assert_eq!(estimate.carbon_mt, 99.91);
-        assert_eq!(estimate.distance_unit, "km");
-        assert_eq!(estimate.distance_value, 5660.34);
-    }
+    /// Print the request JSON that would be sent, without calling the API
+    #[arg(long)]
+    dry_run: bool,
 
-    #[tokio::test]
-    async fn test_make_estimates_request_different_distance_units() {
-        // Start a WireMock server
-        let server = MockServer::start().await;
+    /// Check every request in the --input file and list all the problems found, without
+    /// calling the API
+    #[arg(long, requires = "input", conflicts_with = "dry_run")]
+    dry_run_validate: bool,
 
-        // Set up a mock response for a successful request
-        let mock_response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "mi", 3512.0);
-        Mock::given(method("POST"))
-            .and(path("/api/v1/estimates"))
-            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
-            .mount(&server)
-            .await;
+    /// Print a JSON Schema for --input files and exit
+    #[arg(long)]
+    print_schema: bool,
 
-        // Create a test request with different distance units
-        let request = FlightEstimateRequest {
-            estimate_type: "flight".to_string(),
-            passengers: 100,
-            legs: vec![
-                Leg {
-                    departure_airport: "LHR".to_string(),
-                    destination_airport: "JFK".to_string(),
-                    cabin_class: None,
-                },
-                Leg {
-                    departure_airport: "JFK".to_string(),
-                    destination_airport: "LHR".to_string(),
-                    cabin_class: None,
-                },
-            ],
-            distance_unit: Some("mi".to_string()),
-        };
+    /// Write an example --input file to PATH to start from (YAML for .yaml/.yml, else
+    /// JSON) and exit
+    #[arg(long, value_name = "PATH")]
+    generate_sample: Option<PathBuf>,
 
-        // Create the API client with the mock server's URI
-        let api_client = ApiClient::new(Client::new(), &server.uri());
+    /// Language for prompts and results: en or es (default: from LANG, else English)
+    #[arg(long, value_name = "CODE", env = "LANG", hide_env_values = true)]
+    lang: Option<String>,
 
-        // Make the request to the mock server
-        let response = make_estimates_request(&api_client, &request, "").await;
+    /// After the result, explain what the figure covers and how it's calculated
+    #[arg(long)]
+    explain: bool,
 
-        // Check the response
-        assert!(response.is_ok());
-        let response = response.unwrap();
-        assert!(response.data.is_some());
-        let estimate = response.data.unwrap().attributes;
-        assert_eq!(estimate.carbon_g, 99911700.0);
-        assert_eq!(estimate.carbon_lb, 267.6);
-        assert_eq!(estimate.carbon_kg, 99911.7);
-        assert_eq!(estimate.carbon_mt, 99.91);
-        assert_eq!(estimate.distance_unit, "mi");
-        assert_eq!(estimate.distance_value, 3512.0);
-    }
-    #[tokio::test]
-    async fn test_make_estimates_request_different_passenger_counts() {
-        // Start a WireMock server
-        let server = MockServer::start().await;
+    /// After the result, explain the units shown (g, kg, lb, mt) and the distance
+    #[arg(long)]
+    units_legend: bool,
 
-        // Set up a mock response for a successful request
-        let mock_response = create_mock_response(199823400.0, 535.2, 199823.4, 199.82, "km", 5660.34);
-        Mock::given(method("POST"))
-            .and(path("/api/v1/estimates"))
-            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
-            .mount(&server)
-            .await;
+    /// Print how long the API call took and how many bytes it sent and received
+    #[arg(long)]
+    timing: bool,
 
-        let request = FlightEstimateRequest {
-            estimate_type: "flight".to_string(),
-            passengers: 200,
-            legs: vec![
-                Leg {
-                    departure_airport: "LHR".to_string(),
-                    destination_airport: "JFK".to_string(),
-                    cabin_class: None,
-                },
-                Leg {
-                    departure_airport: "JFK".to_string(),
-                    destination_airport: "LHR".to_string(),
-                    cabin_class: None,
+    /// Keep flight estimates on disk and reuse them for identical requests in later runs
+    #[arg(long)]
+    cache: bool,
+
+    /// Hours a cached estimate is reused before the API is asked again (default: 24)
+    #[arg(long, value_name = "HOURS")]
+    cache_ttl_hours: Option<u32>,
+
+    /// Per-cabin multipliers for --offline; only settable in the config file
+    #[arg(skip)]
+    cabin_factors: CabinFactors,
+
+    #[command(subcommand)]
+    command: Option<Command>,
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+enum Command {
+    /// List previously estimated trips, newest first
+    History,
+    /// Add up the emissions in the history file, optionally over a period
+    Total {
+        /// Only count estimates made on or after this date (YYYY-MM-DD)
+        #[arg(long, value_name = "DATE")]
+        since: Option<NaiveDate>,
+        /// Only count estimates made in this calendar year
+        #[arg(long)]
+        year: Option<i32>,
+    },
+    /// Save the API key in the system keyring so later runs don't ask for it
+    Login,
+    /// Remove the API key saved by `login`
+    Logout,
+    /// Estimate two itineraries and show which is greener
+    Compare {
+        /// Legs as FROM:TO[:CABIN], comma-separated; give exactly two itineraries
+        #[arg(long, value_name = "LEGS", value_parser = parse_itinerary, required = true)]
+        itinerary: Vec<Itinerary>,
+    },
+    /// Estimate a ground trip by car; --distance-unit sets the unit of --distance
+    Vehicle {
+        /// Carbon Interface vehicle model ID
+        #[arg(long, value_name = "ID")]
+        model_id: String,
+        /// How far the vehicle travels
+        #[arg(long, value_parser = parse_positive)]
+        distance: f32,
+    },
+    /// Estimate moving freight; --distance-unit sets the unit of --distance
+    Shipping {
+        /// How heavy the shipment is
+        #[arg(long, value_parser = parse_positive)]
+        weight: f32,
+        /// Unit of --weight
+        #[arg(long, value_parser = WEIGHT_UNITS, default_value = "kg")]
+        weight_unit: String,
+        /// How far the shipment travels
+        #[arg(long, value_parser = parse_positive)]
+        distance: f32,
+        /// How the shipment travels
+        #[arg(long, value_parser = TRANSPORT_METHODS)]
+        transport_method: String,
+    },
+    /// Estimate electricity use
+    Electricity {
+        /// How much electricity was used
+        #[arg(long, value_parser = parse_positive)]
+        value: f32,
+        /// Unit of --value
+        #[arg(long, value_parser = ELECTRICITY_UNITS, default_value = "kwh")]
+        unit: String,
+        /// Two-letter country code, e.g. US
+        #[arg(long, value_parser = parse_region_code)]
+        country: String,
+        /// Two-letter state or province code, for the countries the API breaks down
+        #[arg(long, value_parser = parse_region_code)]
+        state: Option<String>,
+    },
+    /// List what the other commands accept
+    Info {
+        #[command(subcommand)]
+        topic: InfoTopic,
+    },
+    /// Print a tab completion script for a shell, e.g. `completions bash`
+    Completions {
+        #[arg(value_enum)]
+        shell: Shell,
+    },
+}
+
+#[derive(Subcommand, Debug, PartialEq)]
+enum InfoTopic {
+    /// The cabin classes a leg can be flown in
+    Cabins,
+    /// Airports in the bundled list matching a code, city or name
+    Airports {
+        /// What to look for, e.g. LHR, Tokyo or Heathrow
+        #[arg(long, value_name = "QUERY")]
+        search: String,
+    },
+}
+
+/// Writes the completion script for `shell`, generated from the flag definitions so it
+/// never falls behind them.
+fn write_completions(shell: Shell, out: &mut impl Write) {
+    clap_complete::generate(shell, &mut Args::command(), env!("CARGO_BIN_NAME"), out);
+}
+
+/// One line per cabin class the API accepts.
+fn cabin_listing() -> Vec<String> {
+    CABIN_CLASSES
+        .iter()
+        .map(|class| match *class {
+            "economy" => format!("{} (used when a leg doesn't give one)", class),
+            _ => class.to_string(),
+        })
+        .collect()
+}
+
+/// An airport as `info airports` lists it: its code, name, city and country.
+fn format_airport(airport: &Airport) -> String {
+    format!(
+        "{}  {}, {} ({})",
+        airport.iata, airport.name, airport.city, airport.country
+    )
+}
+
+fn print_info(topic: &InfoTopic) -> Result<(), CliError> {
+    let lines = match topic {
+        InfoTopic::Cabins => cabin_listing(),
+        InfoTopic::Airports { search } => {
+            let matches = search_airports(search);
+            if matches.is_empty() {
+                return Err(CliError::InvalidInput(format!(
+                    "no bundled airport matches \"{}\"",
+                    search
+                )));
+            }
+            matches.iter().map(format_airport).collect()
+        }
+    };
+    println!("{}", lines.join("\n"));
+    Ok(())
+}
+
+/// Accepts two-letter country and state codes in any case, lowercased as the API expects.
+fn parse_region_code(code: &str) -> Result<String, String> {
+    if code.len() == 2 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(code.to_ascii_lowercase())
+    } else {
+        Err("expected a two-letter code, e.g. US".to_string())
+    }
+}
+
+/// Accepts the positive amounts the estimate subcommands take: distances, weights and energy.
+fn parse_positive(input: &str) -> Result<f32, String> {
+    match input.parse::<f32>() {
+        Ok(distance) if distance > 0.0 && distance.is_finite() => Ok(distance),
+        _ => Err("the value must be a positive number".to_string()),
+    }
+}
+
+/// Accepts zero or more, for prices where a free offset still makes sense.
+fn parse_non_negative(input: &str) -> Result<f32, String> {
+    match input.parse::<f32>() {
+        Ok(value) if value >= 0.0 && value.is_finite() => Ok(value),
+        _ => Err("the value must be zero or a positive number".to_string()),
+    }
+}
+
+/// `--distance-unit`, or the API's default of km, for the ground estimate subcommands.
+fn ground_distance_unit(args: &Args) -> String {
+    args.distance_unit
+        .clone()
+        .unwrap_or_else(|| DISTANCE_UNITS[0].to_string())
+}
+
+fn vehicle_request(args: &Args) -> Option<VehicleEstimateRequest> {
+    match &args.command {
+        Some(Command::Vehicle { model_id, distance }) => Some(VehicleEstimateRequest {
+            estimate_type: String::from("vehicle"),
+            distance_unit: ground_distance_unit(args),
+            distance_value: *distance,
+            vehicle_model_id: model_id.clone(),
+        }),
+        _ => None,
+    }
+}
+
+fn electricity_request(args: &Args) -> Option<ElectricityEstimateRequest> {
+    match &args.command {
+        Some(Command::Electricity {
+            value,
+            unit,
+            country,
+            state,
+        }) => Some(ElectricityEstimateRequest {
+            estimate_type: String::from("electricity"),
+            electricity_unit: unit.clone(),
+            electricity_value: *value,
+            country: country.clone(),
+            state: state.clone(),
+        }),
+        _ => None,
+    }
+}
+
+fn shipping_request(args: &Args) -> Option<ShippingEstimateRequest> {
+    match &args.command {
+        Some(Command::Shipping {
+            weight,
+            weight_unit,
+            distance,
+            transport_method,
+        }) => Some(ShippingEstimateRequest {
+            estimate_type: String::from("shipping"),
+            weight_value: *weight,
+            weight_unit: weight_unit.clone(),
+            distance_value: *distance,
+            distance_unit: ground_distance_unit(args),
+            transport_method: transport_method.clone(),
+        }),
+        _ => None,
+    }
+}
+
+/// The legs of one trip given to `compare`.
+#[derive(Clone, Debug, PartialEq)]
+struct Itinerary(Vec<Leg>);
+
+/// Parses comma-separated `FROM:TO[:CABIN]` legs, e.g. `LHR:JFK,JFK:LAX:business`.
+fn parse_itinerary(spec: &str) -> Result<Itinerary, String> {
+    spec.split(',')
+        .map(parse_leg)
+        .collect::<Result<_, _>>()
+        .map(Itinerary)
+}
+
+impl Args {
+    /// Fills in anything not given on the command line from the config file. Built-in
+    /// defaults only apply when neither sets a value.
+    fn apply_config(&mut self, config: Config) -> Result<(), CliError> {
+        if self.base_url.is_none() {
+            self.base_url = config
+                .base_url
+                .map(|url| {
+                    parse_base_url(&url).map_err(|err| {
+                        CliError::InvalidArguments(format!("config base_url: {}", err))
+                    })
+                })
+                .transpose()?;
+        }
+        self.distance_unit = self.distance_unit.take().or(config.distance_unit);
+        self.passengers = self.passengers.or(config.passengers);
+        self.offset_price = self.offset_price.or(config.offset_price);
+        self.emission_factor = self.emission_factor.or(config.emission_factor);
+        self.rf_multiplier = self.rf_multiplier.or(config.rf_multiplier);
+        self.cabin_factors = config.cabin_factors.unwrap_or_default();
+        let thresholds = config.thresholds.unwrap_or_default();
+        self.warn_carbon_kg = self.warn_carbon_kg.or(thresholds.warn_carbon_kg);
+        self.budget_kg = self.budget_kg.or(thresholds.error_carbon_kg);
+        self.warn_distance = self.warn_distance.or(thresholds.warn_distance);
+        self.max_distance = self.max_distance.or(thresholds.error_distance);
+        self.short_flight_km = self.short_flight_km.or(thresholds.short_flight_km);
+        self.currency = self.currency.take().or(config.currency);
+        if self.airports_url.is_none() {
+            self.airports_url = config
+                .airports_url
+                .map(|url| {
+                    parse_base_url(&url).map_err(|err| {
+                        CliError::InvalidArguments(format!("config airports_url: {}", err))
+                    })
+                })
+                .transpose()?;
+        }
+        self.airports_ttl_days = self.airports_ttl_days.or(config.airports_ttl_days);
+        self.user_agent = self.user_agent.take().or(config.user_agent);
+        // The output file's extension says more about this run than the config does
+        self.output = self
+            .output
+            .or_else(|| self.output_file.as_deref().and_then(format_from_path))
+            .or(config.output_format);
+        Ok(())
+    }
+
+    fn user_agent(&self) -> &str {
+        self.user_agent.as_deref().unwrap_or(DEFAULT_USER_AGENT)
+    }
+
+    fn emission_factor(&self) -> f32 {
+        self.emission_factor.unwrap_or(DEFAULT_EMISSION_FACTOR)
+    }
+
+    fn airports_ttl(&self) -> Duration {
+        let days = self.airports_ttl_days.unwrap_or(DEFAULT_AIRPORTS_TTL_DAYS);
+        Duration::from_secs(u64::from(days) * 24 * 60 * 60)
+    }
+
+    /// Where this run's flight estimates are kept: only in memory, so repeats within the
+    /// run are reused, unless `--cache` keeps them on disk too.
+    fn estimate_cache(&self) -> EstimateCache {
+        match EstimateCache::default_file() {
+            Some(file) if self.cache => {
+                let hours = self.cache_ttl_hours.unwrap_or(DEFAULT_CACHE_TTL_HOURS);
+                let ttl = Duration::from_secs(u64::from(hours) * 60 * 60);
+                EstimateCache::persistent(file, self.base_url(), ttl, SystemTime::now())
+            }
+            _ => EstimateCache::in_memory(),
+        }
+    }
+
+    fn base_url(&self) -> &str {
+        self.base_url.as_deref().unwrap_or(DEFAULT_BASE_URL)
+    }
+
+    fn output(&self) -> OutputFormat {
+        self.output.unwrap_or(OutputFormat::Text)
+    }
+
+    fn currency(&self) -> &str {
+        self.currency.as_deref().unwrap_or("USD")
+    }
+
+    fn offset_price(&self) -> f32 {
+        self.offset_price.unwrap_or(DEFAULT_OFFSET_PRICE)
+    }
+
+    /// The estimate source, with `--offline` standing for `--provider offline`.
+    fn provider(&self) -> Provider {
+        if self.offline {
+            Provider::Offline
+        } else {
+            self.provider
+        }
+    }
+
+    fn short_flight_km(&self) -> f32 {
+        self.short_flight_km.unwrap_or(DEFAULT_SHORT_FLIGHT_KM)
+    }
+
+    fn rf_multiplier(&self) -> f32 {
+        self.rf_multiplier.unwrap_or(DEFAULT_RF_MULTIPLIER)
+    }
+}
+
+/// Switches lookups to the `--airports-url` list, read from the cache while it's fresh,
+/// before any airport is checked.
+async fn load_airports(args: &Args) -> Result<(), CliError> {
+    let Some(url) = &args.airports_url else {
+        if args.refresh_airports {
+            return Err(CliError::InvalidArguments(
+                "--refresh-airports needs an --airports-url (or airports_url in the config)"
+                    .to_string(),
+            ));
+        }
+        return Ok(());
+    };
+    let dir = AirportCache::default_dir().ok_or_else(|| {
+        CliError::InvalidArguments(
+            "couldn't find a cache directory to keep the airport list in".to_string(),
+        )
+    })?;
+
+    let fetcher = HttpAirportFetcher::new(
+        build_http_client(args.proxy.as_deref(), args.user_agent())?,
+        url,
+    );
+    let csv = interruptible(AirportCache::new(dir, args.airports_ttl()).load(
+        &fetcher,
+        args.refresh_airports,
+        SystemTime::now(),
+    ))
+    .await?;
+    use_airport_data(csv);
+    Ok(())
+}
+
+/// Reads `--config`, or the default config file if there is one, with `--profile` (or
+/// the default profile) applied.
+fn load_config(args: &Args) -> Result<Config, CliError> {
+    let config = match (&args.config, Config::default_path()) {
+        (Some(path), _) => Config::load(path)?,
+        (None, Some(path)) => Config::load_or_default(&path)?,
+        (None, None) => Config::default(),
+    };
+    config.select_profile(args.profile.as_deref())
+}
+
+fn unknown_airport_message(code: &str) -> String {
+    tf("message.unknown_airport", code)
+}
+
+/// Accepts well-formed codes from the bundled list, and unknown ones only when
+/// `allow_unknown_iata` is set. A rejected code comes back with the reason to show.
+fn check_airport_code(code: &str, allow_unknown_iata: bool) -> Result<(), String> {
+    if !is_valid_airport_code(code) {
+        return Err(t("error.airport").to_string());
+    }
+    if is_known_iata(code) || allow_unknown_iata {
+        Ok(())
+    } else {
+        Err(format!("❌ {}.", unknown_airport_message(code)))
+    }
+}
+
+fn is_valid_passenger_count(input: &str) -> bool {
+    input.parse::<u32>().is_ok_and(|passengers| passengers >= 1)
+}
+
+fn parse_passengers(input: &str) -> Result<u32, String> {
+    if is_valid_passenger_count(input) {
+        Ok(input.parse().unwrap())
+    } else {
+        Err("Number of passengers must be at least 1.".to_string())
+    }
+}
+
+fn parse_airport_code(code: &str) -> Result<String, String> {
+    normalize_airport_code(code).ok_or_else(|| "IATA codes should be exactly 3 letters".to_string())
+}
+
+/// The code an interactive airport answer stands for, if it should be read as one. Three
+/// letters are a code unless they aren't a known airport but do match a name, like `rom`.
+fn airport_code_answer(input: &str) -> Option<String> {
+    normalize_airport_code(input)
+        .filter(|code| is_known_iata(code) || search_airports(input).is_empty())
+}
+
+/// Checks that `url` is an absolute http(s) URL and strips trailing slashes so
+/// endpoint paths can be appended directly.
+fn parse_base_url(url: &str) -> Result<String, String> {
+    let parsed = reqwest::Url::parse(url).map_err(|err| format!("invalid URL: {}", err))?;
+    if !["http", "https"].contains(&parsed.scheme()) {
+        return Err("the base URL must use http or https".to_string());
+    }
+    Ok(url.trim_end_matches('/').to_string())
+}
+
+/// Rejects proxy URLs up front rather than failing once a request is sent.
+fn parse_proxy(url: &str) -> Result<String, String> {
+    reqwest::Proxy::all(url)
+        .map(|_| url.to_string())
+        .map_err(|err| format!("invalid proxy URL: {}", err))
+}
+
+/// Accepts a three-letter ISO 4217 code in any case.
+fn parse_currency(code: &str) -> Result<String, String> {
+    if code.len() == 3 && code.chars().all(|c| c.is_ascii_alphabetic()) {
+        Ok(code.to_ascii_uppercase())
+    } else {
+        Err("currencies should be three-letter ISO codes, e.g. EUR".to_string())
+    }
+}
+
+fn parse_cabin_class(cabin_class: &str) -> Result<String, String> {
+    normalize_cabin_class(cabin_class)
+        .ok_or_else(|| format!("cabin class should be one of: {}", CABIN_CLASSES.join(", ")))
+}
+
+/// ICAO type designators are two to four letters and digits, e.g. `A20N`.
+fn parse_aircraft(aircraft: &str) -> Result<String, String> {
+    let aircraft = aircraft.trim();
+    if (2..=4).contains(&aircraft.len()) && aircraft.chars().all(|c| c.is_ascii_alphanumeric()) {
+        Ok(aircraft.to_ascii_uppercase())
+    } else {
+        Err("the aircraft should be an ICAO type designator such as A20N".to_string())
+    }
+}
+
+/// Parses a `FROM:TO[:CABIN]` leg specification.
+fn parse_leg(spec: &str) -> Result<Leg, String> {
+    let parts: Vec<&str> = spec.split(':').collect();
+    let (departure, destination, cabin_class) = match parts.as_slice() {
+        [departure, destination] => (departure, destination, None),
+        [departure, destination, cabin] => (departure, destination, Some(*cabin)),
+        _ => return Err("legs should look like FROM:TO or FROM:TO:CABIN".to_string()),
+    };
+
+    Ok(Leg {
+        departure_airport: parse_airport_code(departure)?,
+        destination_airport: parse_airport_code(destination)?,
+        cabin_class: cabin_class.map(parse_cabin_class).transpose()?,
+        passengers: None,
+        aircraft: None,
+    })
+}
+
+/// Parses a `FROM:TO:CABIN=N[+CABIN=N...]` leg with its passengers split by cabin class.
+fn parse_mixed_leg(spec: &str) -> Result<MixedCabinLeg, String> {
+    let [departure, destination, cabins] = spec.split(':').collect::<Vec<_>>()[..] else {
+        return Err("mixed legs should look like FROM:TO:economy=2+business=1".to_string());
+    };
+
+    let mut parsed: Vec<CabinPassengers> = Vec::new();
+    for cabin in cabins.split('+') {
+        let Some((cabin_class, passengers)) = cabin.split_once('=') else {
+            return Err(format!("{} should look like CABIN=PASSENGERS", cabin));
+        };
+        let cabin_class = parse_cabin_class(cabin_class)?;
+        if parsed.iter().any(|other| other.cabin_class == cabin_class) {
+            return Err(format!("{} is given more than once", cabin_class));
+        }
+        parsed.push(CabinPassengers {
+            cabin_class,
+            passengers: parse_passengers(passengers)?,
+        });
+    }
+
+    Ok(MixedCabinLeg {
+        departure_airport: parse_airport_code(departure)?,
+        destination_airport: parse_airport_code(destination)?,
+        cabins: parsed,
+    })
+}
+
+/// The `--mixed-leg` legs, with their return legs for `--round-trip`. They describe the
+/// whole trip, so they can't be combined with the other ways of giving one.
+fn mixed_cabin_legs(args: &Args) -> Result<Vec<MixedCabinLeg>, CliError> {
+    if args.mixed_legs.is_empty() {
+        return Ok(Vec::new());
+    }
+    if !args.from.is_empty()
+        || !args.leg.is_empty()
+        || !args.airports.is_empty()
+        || args.passengers.is_some()
+        || args.cabin.is_some()
+        || args.command.is_some()
+        || args.input.is_some()
+    {
+        return Err(CliError::InvalidArguments(
+            "--mixed-leg gives the whole trip, so it can't be combined with --from/--to, --leg, positional airports, --passengers, --cabin, --input or a subcommand".to_string(),
+        ));
+    }
+
+    let mut legs = args.mixed_legs.clone();
+    for leg in &mut legs {
+        resolve_metro_airport(args, &mut leg.departure_airport);
+        resolve_metro_airport(args, &mut leg.destination_airport);
+    }
+    let plain_legs: Vec<Leg> = legs
+        .iter()
+        .map(|leg| Leg {
+            departure_airport: leg.departure_airport.clone(),
+            destination_airport: leg.destination_airport.clone(),
+            cabin_class: None,
+            passengers: None,
+            aircraft: None,
+        })
+        .collect();
+    check_known_airports(&plain_legs, args.allow_unknown_iata)?;
+    check_same_airports(&plain_legs, args.allow_same_airport)?;
+    if args.round_trip {
+        let return_legs: Vec<MixedCabinLeg> =
+            legs.iter().rev().map(MixedCabinLeg::reversed).collect();
+        legs.extend(return_legs);
+    }
+    Ok(legs)
+}
+
+/// A single request standing for a mixed-cabin trip in the output and history: the fullest
+/// leg's passenger count, and a cabin class only on legs flown in one class.
+fn mixed_cabin_summary(args: &Args, legs: &[MixedCabinLeg]) -> FlightEstimateRequest {
+    FlightEstimateRequest {
+        estimate_type: String::from("flight"),
+        passengers: legs
+            .iter()
+            .map(MixedCabinLeg::passengers)
+            .max()
+            .unwrap_or(1),
+        legs: legs
+            .iter()
+            .map(|leg| Leg {
+                departure_airport: leg.departure_airport.clone(),
+                destination_airport: leg.destination_airport.clone(),
+                cabin_class: match leg.cabins.as_slice() {
+                    [cabin] => Some(cabin.cabin_class.clone()),
+                    _ => None,
                 },
-            ],
-            distance_unit: None,
+                passengers: None,
+                aircraft: None,
+            })
+            .collect(),
+        distance_unit: args.distance_unit.clone(),
+    }
+}
+
+/// Builds the legs given on the command line: `--from`/`--to` pairs first, then `--leg` specs.
+fn legs_from_args(args: &Args) -> Result<Vec<Leg>, CliError> {
+    if args.from.len() != args.to.len() {
+        return Err(CliError::InvalidArguments(format!(
+            "every --from needs a matching --to (got {} --from and {} --to)",
+            args.from.len(),
+            args.to.len()
+        )));
+    }
+
+    if args.airports.len() == 1 {
+        return Err(CliError::InvalidArguments(format!(
+            "{} needs at least one more airport to fly to",
+            args.airports[0]
+        )));
+    }
+
+    let chained = args.airports.iter().zip(args.airports.iter().skip(1));
+    let mut legs: Vec<Leg> = args
+        .from
+        .iter()
+        .zip(&args.to)
+        .chain(chained)
+        .map(|(departure, destination)| Leg {
+            departure_airport: departure.clone(),
+            destination_airport: destination.clone(),
+            cabin_class: args.cabin.clone(),
+            passengers: None,
+            aircraft: args.aircraft.clone(),
+        })
+        .collect();
+    legs.extend(args.leg.iter().cloned());
+
+    resolve_metro_airports(args, &mut legs);
+    check_known_airports(&legs, args.allow_unknown_iata)?;
+    check_same_airports(&legs, args.allow_same_airport)?;
+    Ok(legs)
+}
+
+/// Swaps a metropolitan area code for its main airport, saying so, unless
+/// `--no-metro-resolution` was given.
+fn resolve_metro_airport(args: &Args, code: &mut String) {
+    if args.no_metro_resolution {
+        return;
+    }
+    let Some(airport) = resolve_metro_code(code) else {
+        return;
+    };
+    announce_metro_airport(code, airport);
+    *code = airport.to_string();
+}
+
+/// Says on stderr which airport stands in for the metro area `code`.
+fn announce_metro_airport(code: &str, airport: &str) {
+    let name = find_airport(airport).map_or_else(String::new, |found| format!(" ({})", found.name));
+    eprintln!(
+        "📍 {} is a metro area code; using {}{}.",
+        code, airport, name
+    );
+}
+
+fn resolve_metro_airports(args: &Args, legs: &mut [Leg]) {
+    for leg in legs {
+        resolve_metro_airport(args, &mut leg.departure_airport);
+        resolve_metro_airport(args, &mut leg.destination_airport);
+    }
+}
+
+/// Rejects airports missing from the bundled list, or just warns about them when
+/// `allow_unknown_iata` is set.
+fn check_known_airports(legs: &[Leg], allow_unknown_iata: bool) -> Result<(), CliError> {
+    for leg in legs {
+        for code in [&leg.departure_airport, &leg.destination_airport] {
+            if is_known_iata(code) {
+                continue;
+            }
+            if !allow_unknown_iata {
+                return Err(CliError::InvalidArguments(unknown_airport_message(code)));
+            }
+            eprintln!("{}", tf("message.unknown_airport_used", code));
+        }
+    }
+    Ok(())
+}
+
+/// Rejects legs that depart from the airport they land at, or just warns about them when
+/// `allow_same_airport` is set.
+fn check_same_airports(legs: &[Leg], allow_same_airport: bool) -> Result<(), CliError> {
+    for leg in legs.iter().filter(|leg| is_same_airport_leg(leg)) {
+        if !allow_same_airport {
+            return Err(CliError::InvalidArguments(format!(
+                "{} is both the departure and the destination of a leg; pass --allow-same-airport if that's intended",
+                leg.departure_airport
+            )));
+        }
+        eprintln!(
+            "⚠️ {} is both the departure and the destination, using it anyway.",
+            leg.departure_airport
+        );
+    }
+    Ok(())
+}
+
+/// Builds one request per `compare` itinerary, sharing the passenger count, distance
+/// unit and `--round-trip` setting.
+fn comparison_requests(
+    args: &Args,
+    itineraries: &[Itinerary],
+) -> Result<Vec<FlightEstimateRequest>, CliError> {
+    if itineraries.len() != 2 {
+        return Err(CliError::InvalidArguments(format!(
+            "compare needs exactly two --itinerary values (got {})",
+            itineraries.len()
+        )));
+    }
+
+    itineraries
+        .iter()
+        .map(|Itinerary(legs)| {
+            let mut legs = legs.clone();
+            resolve_metro_airports(args, &mut legs);
+            check_known_airports(&legs, args.allow_unknown_iata)?;
+            check_same_airports(&legs, args.allow_same_airport)?;
+            if args.round_trip {
+                let return_legs = mirror_legs(&legs);
+                legs.extend(return_legs);
+            }
+            flight_request_from_args(args, legs)
+        })
+        .collect()
+}
+
+/// A request for `legs` with the passenger count and distance unit given as flags.
+fn flight_request_from_args(
+    args: &Args,
+    legs: Vec<Leg>,
+) -> Result<FlightEstimateRequest, CliError> {
+    let mut builder = FlightEstimateRequest::builder().passengers(args.passengers.unwrap_or(1));
+    if let Some(distance_unit) = &args.distance_unit {
+        builder = builder.distance_unit(distance_unit);
+    }
+    legs.into_iter()
+        .fold(builder, |builder, leg| builder.add_leg(leg))
+        .build()
+}
+
+/// Builds the request from flags alone, or returns `None` when no legs were given
+/// and the details have to be collected interactively.
+fn request_from_args(args: &Args) -> Result<Option<FlightEstimateRequest>, CliError> {
+    let legs = legs_from_args(args)?;
+    if legs.is_empty() {
+        return Ok(None);
+    }
+
+    flight_request_from_args(args, legs).map(Some)
+}
+
+/// Reads validated answers to interactive prompts from an input source.
+struct Prompter<R> {
+    input: R,
+    reprompt_limit: Option<u32>,
+    /// Whether airport answers that are metro area codes become their main airport
+    resolve_metro: bool,
+}
+
+impl<R: BufRead> Prompter<R> {
+    fn new(input: R, reprompt_limit: Option<u32>) -> Self {
+        Self {
+            input,
+            resolve_metro: true,
+            reprompt_limit,
+        }
+    }
+
+    /// Prompts until `validator` accepts the answer, or fails with
+    /// `CliError::TooManyInvalidInputs` once the reprompt limit is exceeded.
+    fn get_user_input(
+        &mut self,
+        prompt: &str,
+        error_message: &str,
+        validator: impl Fn(&str) -> bool,
+    ) -> Result<String, CliError> {
+        self.get_checked_input(prompt, error_message, |input| {
+            validator(input)
+                .then_some(())
+                .ok_or_else(|| error_message.to_string())
+        })
+    }
+
+    /// Like `get_user_input`, but `validator` gives the reason an answer was turned down,
+    /// which is shown in place of `error_message`; that's left for empty answers.
+    fn get_checked_input(
+        &mut self,
+        prompt: &str,
+        error_message: &str,
+        validator: impl Fn(&str) -> Result<(), String>,
+    ) -> Result<String, CliError> {
+        let mut invalid_inputs = 0;
+        loop {
+            eprint!("{}", prompt);
+            io::stderr().flush().unwrap();
+
+            let input = self.read_answer()?;
+            let input = input.trim();
+            let reason = if input.is_empty() {
+                error_message.to_string()
+            } else {
+                match validator(input) {
+                    Ok(()) => return Ok(input.to_string()),
+                    Err(reason) => reason,
+                }
+            };
+
+            eprintln!("{}", reason);
+            invalid_inputs += 1;
+            if self
+                .reprompt_limit
+                .is_some_and(|limit| invalid_inputs > limit)
+            {
+                return Err(CliError::TooManyInvalidInputs);
+            }
+        }
+    }
+
+    /// Asks a yes/no question where anything but `y` or `yes` means no.
+    fn confirm(&mut self, prompt: &str) -> Result<bool, CliError> {
+        eprint!("{}", prompt);
+        io::stderr().flush().unwrap();
+
+        let input = self.read_answer()?;
+        Ok(matches!(input.trim().to_lowercase().as_str(), "y" | "yes"))
+    }
+
+    /// Reads one line, failing once the input is closed so prompts can't loop forever.
+    fn read_answer(&mut self) -> Result<String, CliError> {
+        let mut input = String::new();
+        match self.input.read_line(&mut input) {
+            Ok(0) => Err(CliError::InputClosed),
+            Ok(_) => Ok(input),
+            Err(err) => Err(CliError::InvalidInput(format!(
+                "could not read the answer: {}",
+                err
+            ))),
+        }
+    }
+
+    /// Prompts for an airport given either as an IATA code or as a city or airport name,
+    /// offering a numbered menu when a name matches several airports.
+    fn get_airport(&mut self, prompt: &str, allow_unknown_iata: bool) -> Result<String, CliError> {
+        let resolve_metro = self.resolve_metro;
+        let metro_airport = move |input: &str| {
+            normalize_airport_code(input)
+                .filter(|_| resolve_metro)
+                .and_then(|code| resolve_metro_code(&code).map(|airport| (code, airport)))
         };
+        let answer = self.get_checked_input(prompt, t("error.airport"), |input| {
+            if metro_airport(input).is_some() {
+                return Ok(());
+            }
+            match airport_code_answer(input) {
+                Some(code) => check_airport_code(&code, allow_unknown_iata),
+                None if search_airports(input).is_empty() => Err(t("error.airport").to_string()),
+                None => Ok(()),
+            }
+        })?;
+        if let Some((code, airport)) = metro_airport(&answer) {
+            announce_metro_airport(&code, airport);
+            return Ok(airport.to_string());
+        }
+        if let Some(code) = airport_code_answer(&answer) {
+            if !is_known_iata(&code) {
+                eprintln!("{}", tf("message.unknown_airport_used", &code));
+            }
+            return Ok(code);
+        }
 
-        // Create the API client with the mock server's URI
-        let api_client = ApiClient::new(Client::new(), &server.uri());
+        let matches = search_airports(&answer);
+        if let [airport] = matches.as_slice() {
+            eprintln!(
+                "📍 Using {} ({}, {})",
+                airport.iata, airport.name, airport.city
+            );
+            return Ok(airport.iata.clone());
+        }
 
-        // Make the request to the mock server
-        let response = make_estimates_request(&api_client, &request, "").await;
+        eprintln!("🔎 Several airports match \"{}\":", answer);
+        for (i, airport) in matches.iter().enumerate() {
+            eprintln!(
+                "  {}. {} - {} ({})",
+                i + 1,
+                airport.iata,
+                airport.name,
+                airport.city
+            );
+        }
+        let choice =
+            self.get_user_input(t("prompt.pick_airport"), t("error.pick_airport"), |input| {
+                input
+                    .parse::<usize>()
+                    .is_ok_and(|choice| (1..=matches.len()).contains(&choice))
+            })?;
+        let choice = choice.parse::<usize>().unwrap(); // The validator guarantees a listed number
+        Ok(matches[choice - 1].iata.clone())
+    }
+}
+
+/// A choice from the leg editor's menu. Leg numbers are zero-based.
+#[derive(Debug, PartialEq)]
+enum MenuCommand {
+    Add,
+    Remove(usize),
+    Edit(usize),
+    View,
+    Done,
+}
+
+/// Parses `a`, `r N`, `e N`, `v` or `d`, where `N` is a one-based leg number no higher
+/// than `leg_count`.
+fn parse_menu_command(input: &str, leg_count: usize) -> Option<MenuCommand> {
+    let mut words = input.split_whitespace();
+    let command = words.next()?.to_lowercase();
+    let leg_number = words
+        .next()
+        .and_then(|number| number.parse::<usize>().ok())
+        .filter(|number| (1..=leg_count).contains(number))
+        .map(|number| number - 1);
+    if words.next().is_some() {
+        return None;
+    }
+
+    match (command.as_str(), leg_number) {
+        ("a" | "add", None) => Some(MenuCommand::Add),
+        ("r" | "remove", Some(index)) => Some(MenuCommand::Remove(index)),
+        ("e" | "edit", Some(index)) => Some(MenuCommand::Edit(index)),
+        ("v" | "view", None) => Some(MenuCommand::View),
+        ("d" | "done", None) => Some(MenuCommand::Done),
+        _ => None,
+    }
+}
+
+/// A change to the itinerary being built.
+#[derive(Debug, PartialEq)]
+enum LegEdit {
+    Add(Leg),
+    Remove(usize),
+    Replace(usize, Leg),
+}
+
+/// Applies `edit` to `legs`; the menu only hands out indices of existing legs.
+fn apply_leg_edit(legs: &mut Vec<Leg>, edit: LegEdit) {
+    match edit {
+        LegEdit::Add(leg) => legs.push(leg),
+        LegEdit::Remove(index) => {
+            legs.remove(index);
+        }
+        LegEdit::Replace(index, leg) => legs[index] = leg,
+    }
+}
+
+/// Lists the legs with their one-based numbers, as the editor's menu refers to them.
+fn format_legs(legs: &[Leg]) -> String {
+    if legs.is_empty() {
+        return "  (no legs yet)".to_string();
+    }
+    legs.iter()
+        .enumerate()
+        .map(|(i, leg)| {
+            format!(
+                "  {}. {} → {} ({})",
+                i + 1,
+                leg.departure_airport,
+                leg.destination_airport,
+                leg.cabin_class.as_deref().unwrap_or("economy")
+            )
+        })
+        .collect::<Vec<_>>()
+        .join("\n")
+}
+
+/// Asks for one leg's airports and cabin class. A destination that's also the departure
+/// is asked for again unless the user confirms it or `allow_same_airport` is set.
+fn prompt_leg<R: BufRead>(
+    prompter: &mut Prompter<R>,
+    allow_unknown_iata: bool,
+    allow_same_airport: bool,
+) -> Result<Leg, CliError> {
+    let departure_airport = prompter.get_airport(t("prompt.departure"), allow_unknown_iata)?;
+
+    let destination_airport = loop {
+        let destination_airport =
+            prompter.get_airport(t("prompt.destination"), allow_unknown_iata)?;
+        let leg = Leg {
+            departure_airport: departure_airport.clone(),
+            destination_airport: destination_airport.clone(),
+            cabin_class: None,
+            passengers: None,
+            aircraft: None,
+        };
+        if allow_same_airport
+            || !is_same_airport_leg(&leg)
+            || prompter.confirm(&tf("prompt.same_airport", &destination_airport))?
+        {
+            break destination_airport;
+        }
+    };
 
-        // Check the response
-        assert!(response.is_ok());
-        let response = response.unwrap();
-        assert!(response.data.is_some());
-        let estimate = response.data.unwrap().attributes;
-        assert_eq!(estimate.carbon_g, 199823400.0);
-        assert_eq!(estimate.carbon_lb, 535.2);
-        assert_eq!(estimate.carbon_kg, 199823.4);
-        assert_eq!(estimate.carbon_mt, 199.82);
-        assert_eq!(estimate.distance_unit, "km");
-        assert_eq!(estimate.distance_value, 5660.34);
+    let cabin_class = prompter.get_user_input(t("prompt.cabin"), t("error.cabin"), |input| {
+        normalize_cabin_class(input).is_some()
+    })?;
+
+    Ok(Leg {
+        departure_airport,
+        destination_airport,
+        cabin_class: normalize_cabin_class(&cabin_class),
+        passengers: None,
+        aircraft: None,
+    })
+}
+
+/// Builds the itinerary from a menu of add, remove, edit and view commands, so a mistake
+/// can be fixed without starting over. Finishing needs at least one leg.
+fn edit_legs<R: BufRead>(
+    prompter: &mut Prompter<R>,
+    allow_unknown_iata: bool,
+    allow_same_airport: bool,
+) -> Result<Vec<Leg>, CliError> {
+    let mut legs: Vec<Leg> = Vec::new();
+    loop {
+        let leg_count = legs.len();
+        let answer =
+            prompter.get_user_input(t("prompt.legs_menu"), t("error.legs_menu"), |input| {
+                parse_menu_command(input, leg_count).is_some()
+            })?;
+
+        let edit = match parse_menu_command(&answer, leg_count) {
+            Some(MenuCommand::Add) => {
+                eprintln!("{}", tf("prompt.leg_details", leg_count + 1));
+                LegEdit::Add(prompt_leg(
+                    prompter,
+                    allow_unknown_iata,
+                    allow_same_airport,
+                )?)
+            }
+            Some(MenuCommand::Remove(index)) => LegEdit::Remove(index),
+            Some(MenuCommand::Edit(index)) => {
+                eprintln!("{}", tf("prompt.leg_new_details", index + 1));
+                LegEdit::Replace(
+                    index,
+                    prompt_leg(prompter, allow_unknown_iata, allow_same_airport)?,
+                )
+            }
+            Some(MenuCommand::View) => {
+                eprintln!("{}", format_legs(&legs));
+                continue;
+            }
+            Some(MenuCommand::Done) if legs.is_empty() => {
+                eprintln!("{}", t("error.no_legs"));
+                continue;
+            }
+            Some(MenuCommand::Done) => return Ok(legs),
+            None => unreachable!("the validator only accepts menu commands"),
+        };
+        apply_leg_edit(&mut legs, edit);
+        eprintln!("{}", format_legs(&legs));
+    }
+}
+
+/// Prompts for the itinerary, only asking for the distance unit when `distance_unit` is `None`.
+fn get_flight_details<R: BufRead>(
+    prompter: &mut Prompter<R>,
+    distance_unit: Option<String>,
+    allow_unknown_iata: bool,
+    allow_same_airport: bool,
+) -> Result<(u32, Vec<Leg>, Option<String>), CliError> {
+    let passengers = prompter
+        .get_user_input(
+            t("prompt.passengers"),
+            t("error.passengers"),
+            is_valid_passenger_count,
+        )?
+        .parse::<u32>()
+        .unwrap(); // The validator guarantees a valid integer
+
+    let distance_unit = match distance_unit {
+        Some(distance_unit) => distance_unit,
+        None => prompter.get_user_input(
+            t("prompt.distance_unit"),
+            t("error.distance_unit"),
+            |input| DISTANCE_UNITS.contains(&input),
+        )?,
+    };
+
+    let legs = edit_legs(prompter, allow_unknown_iata, allow_same_airport)?;
+
+    Ok((passengers, legs, Some(distance_unit)))
+}
+
+/// Returns the API key from `--api-key`, else `--api-key-file`, else
+/// `CARBON_INTERFACE_API_KEY`, ignoring empty flag and environment values.
+fn resolve_api_key(args: &Args) -> Result<Option<String>, CliError> {
+    if let Some(api_key) = args.api_key.clone().filter(|api_key| !api_key.is_empty()) {
+        return Ok(Some(api_key));
+    }
+    if let Some(path) = &args.api_key_file {
+        return read_api_key_file(path).map(Some);
+    }
+    Ok(args
+        .env_api_key
+        .clone()
+        .filter(|api_key| !api_key.is_empty()))
+}
+
+/// The first line of `path`, without surrounding whitespace. A missing or empty file is an
+/// error rather than a reason to prompt, since the caller clearly meant to use it.
+fn read_api_key_file(path: &Path) -> Result<String, CliError> {
+    let contents = fs::read_to_string(path).map_err(|err| {
+        CliError::InvalidArguments(format!(
+            "could not read the API key file {}: {}",
+            path.display(),
+            err
+        ))
+    })?;
+    match contents.lines().next().map(str::trim) {
+        Some(api_key) if !api_key.is_empty() => Ok(api_key.to_string()),
+        _ => Err(CliError::InvalidArguments(format!(
+            "the API key file {} is empty",
+            path.display()
+        ))),
+    }
+}
+
+/// The shortest key accepted; real keys are longer, but this only has to catch typos.
+const MIN_API_KEY_LENGTH: usize = 8;
+
+/// A loose check that catches empty, truncated or mangled keys before they cost a 401.
+fn is_plausible_api_key(key: &str) -> bool {
+    key.chars().count() >= MIN_API_KEY_LENGTH && !key.chars().any(char::is_whitespace)
+}
+
+/// The key from `--api-key`, `--api-key-file` or `CARBON_INTERFACE_API_KEY`, rejected
+/// early if it's obviously malformed.
+fn given_api_key(args: &Args) -> Result<Option<String>, CliError> {
+    match resolve_api_key(args)? {
+        Some(api_key) if !is_plausible_api_key(&api_key) => Err(CliError::InvalidArguments(
+            "the API key from --api-key, --api-key-file or CARBON_INTERFACE_API_KEY doesn't look like a Carbon Interface key".to_string(),
+        )),
+        api_key => Ok(api_key),
+    }
+}
+
+/// Saves the given (or prompted for) API key in `store` for later runs.
+fn login(args: &Args, store: &dyn SecretStore) -> Result<(), CliError> {
+    let api_key = match given_api_key(args)? {
+        Some(api_key) => api_key,
+        None => prompt_api_key(args.reprompt_limit)?,
+    };
+    store.save(&api_key)?;
+    println!("🔑 API key saved; later runs will use it automatically.");
+    Ok(())
+}
+
+fn logout(store: &dyn SecretStore) -> Result<(), CliError> {
+    if store.delete()? {
+        println!("Removed the saved API key.");
+    } else {
+        println!("No API key was saved.");
+    }
+    Ok(())
+}
+
+/// Asks for the API key until it looks plausible, giving up like other prompts do once
+/// `reprompt_limit` is exceeded.
+fn prompt_api_key(reprompt_limit: Option<u32>) -> Result<String, CliError> {
+    // Read the API key securely, without displaying it in the console
+    read_api_key(read_password, reprompt_limit)
+}
+
+/// The loop behind `prompt_api_key`, reading each answer with `read`. An empty answer,
+/// which is also all a closed input gives, ends the prompt instead of asking again.
+fn read_api_key(
+    mut read: impl FnMut() -> io::Result<String>,
+    reprompt_limit: Option<u32>,
+) -> Result<String, CliError> {
+    let mut invalid_inputs = 0;
+    loop {
+        eprint!("{}", t("prompt.api_key"));
+        io::stderr().flush().unwrap();
+
+        let api_key = read().map_err(|err| {
+            CliError::InvalidInput(format!("could not read the API key: {}", err))
+        })?;
+        // Surrounding whitespace is usually left over from pasting
+        let api_key = api_key.trim();
+        if api_key.is_empty() {
+            return Err(CliError::InputClosed);
+        }
+        if is_plausible_api_key(api_key) {
+            return Ok(api_key.to_string());
+        }
+
+        eprintln!(
+            "❌ That doesn't look like an API key. Keys are at least {} characters with no spaces.",
+            MIN_API_KEY_LENGTH
+        );
+        invalid_inputs += 1;
+        if reprompt_limit.is_some_and(|limit| invalid_inputs > limit) {
+            return Err(CliError::TooManyInvalidInputs);
+        }
+    }
+}
+
+#[tokio::main]
+async fn main() {
+    let args = Args::parse();
+    if let Err(err) = init_logging(args.verbose, args.log_file.as_deref()) {
+        eprintln!("Error: {}", err);
+        std::process::exit(exit_code_for(&err));
+    }
+
+    match run(args).await {
+        Ok(()) => {}
+        Err(err @ CliError::BudgetExceeded { .. }) => {
+            eprintln!("{}", format!("ERROR: {}", err).red().bold());
+            std::process::exit(exit_code_for(&err));
+        }
+        Err(err @ CliError::OverWarningLevel { .. }) => {
+            eprintln!("{}", format!("WARN: {}", err).yellow().bold());
+            std::process::exit(exit_code_for(&err));
+        }
+        Err(err) => {
+            eprintln!("Error: {}", err);
+            std::process::exit(exit_code_for(&err));
+        }
+    }
+}
+
+/// More retries than this would keep a failing run going for minutes.
+const MAX_RETRIES: u32 = 10;
+
+/// The longest first retry delay `--retry-delay-ms` accepts: one minute.
+const MAX_RETRY_DELAY_MS: u64 = 60_000;
+
+/// Past this many places an f32 figure is showing noise, not precision.
+const MAX_PRECISION: u32 = 6;
+
+fn api_client(args: &Args, client: reqwest::Client) -> ApiClient {
+    ApiClient::new(client, args.base_url())
+        .with_auth_scheme(args.provider().auth_scheme().unwrap_or_default())
+        .with_timeout(Duration::from_secs(args.timeout))
+        .with_retries(args.retries, Duration::from_millis(args.retry_delay_ms))
+}
+
+/// Exit codes scripts can branch on; clap also exits with 2 for bad flags.
+const GENERIC_EXIT_CODE: i32 = 1;
+const USAGE_EXIT_CODE: i32 = 2;
+/// Lets scripts tell a run that went over `--budget-kg` apart from one that failed.
+const BUDGET_EXCEEDED_EXIT_CODE: i32 = 3;
+const AUTHENTICATION_EXIT_CODE: i32 = 4;
+const NETWORK_EXIT_CODE: i32 = 5;
+/// The run finished but went over `--warn-carbon-kg`.
+const WARNING_EXIT_CODE: i32 = 6;
+
+/// Why the run failed, as an exit code: 2 for bad arguments or input, 3 for going over
+/// the budget, 4 for a rejected API key, 5 when the API couldn't be reached, 6 for going
+/// over the warning level, and 1 for anything else.
+fn exit_code_for(err: &CliError) -> i32 {
+    match err {
+        CliError::InvalidArguments(_)
+        | CliError::InvalidInput(_)
+        | CliError::ValidationError(_)
+        | CliError::TooManyInvalidInputs
+        | CliError::InputClosed
+        | CliError::InvalidConfig(..)
+        | CliError::OutputFileExists(_) => USAGE_EXIT_CODE,
+        CliError::InRequest(err) => exit_code_for(&err.error),
+        CliError::BudgetExceeded { .. } => BUDGET_EXCEEDED_EXIT_CODE,
+        CliError::OverWarningLevel { .. } => WARNING_EXIT_CODE,
+        CliError::Unauthorized => AUTHENTICATION_EXIT_CODE,
+        CliError::NetworkError(_)
+        | CliError::DnsError(_)
+        | CliError::ConnectionRefused(_)
+        | CliError::Timeout(_) => NETWORK_EXIT_CODE,
+        CliError::UnexpectedResponseFormat(_)
+        | CliError::EmptyResponse
+        | CliError::ApiError(_)
+        | CliError::ServerError(_)
+        | CliError::ImplausibleResponse(_)
+        | CliError::UncheckedLimit { .. }
+        | CliError::OutputFileError(..)
+        | CliError::HistoryError(..)
+        | CliError::KeyringError(_)
+        | CliError::BatchFailed { .. }
+        | CliError::DeadlineExceeded(_) => GENERIC_EXIT_CODE,
+    }
+}
+
+/// The conventional exit code for a process stopped by SIGINT (128 + 2).
+const INTERRUPTED_EXIT_CODE: i32 = 130;
+
+/// Waits for `work`, or exits with code 130 if Ctrl-C comes first. `work` is dropped before
+/// exiting, which cancels its request and clears its spinner. Only requests are wrapped, so
+/// Ctrl-C at a prompt still ends the process the usual way.
+async fn interruptible<T>(work: impl Future<Output = T>) -> T {
+    match race_interrupt(work, tokio::signal::ctrl_c()).await {
+        Some(output) => output,
+        None => std::process::exit(report_cancelled(&mut io::stderr())),
+    }
+}
+
+/// `work`'s output, or `None` if `interrupt` fires first. An interrupt that fails to
+/// install is ignored rather than treated as a cancellation.
+async fn race_interrupt<T>(
+    work: impl Future<Output = T>,
+    interrupt: impl Future<Output = io::Result<()>>,
+) -> Option<T> {
+    tokio::select! {
+        output = work => Some(output),
+        Ok(()) = interrupt => None,
+    }
+}
+
+/// Tells the user the run was cancelled and returns the exit code to finish with.
+fn report_cancelled(err: &mut impl Write) -> i32 {
+    let _ = writeln!(err, "Cancelled.");
+    INTERRUPTED_EXIT_CODE
+}
+
+fn init_logging(verbose: u8, log_file: Option<&Path>) -> Result<(), CliError> {
+    let logger = build_logger(verbose, log_file)?;
+    let max_level = logger.filter();
+    log::set_boxed_logger(Box::new(logger)).expect("logging is only set up once");
+    log::set_max_level(max_level);
+    Ok(())
+}
+
+/// Logs warnings by default, this crate's debug output with `-v`, and everything
+/// including the HTTP stack with `-vv`. `RUST_LOG` still applies without `--verbose`.
+/// A log file gets timestamped lines and at least the `-v` detail, since nobody is
+/// watching it live.
+fn build_logger(verbose: u8, log_file: Option<&Path>) -> Result<env_logger::Logger, CliError> {
+    let mut builder = env_logger::Builder::new();
+    builder
+        .filter_level(log::LevelFilter::Warn)
+        .parse_default_env();
+    let verbose = if log_file.is_some() {
+        verbose.max(1)
+    } else {
+        verbose
+    };
+    match verbose {
+        0 => {}
+        1 => {
+            builder.filter_module("carbon_footprint_cli", log::LevelFilter::Debug);
+        }
+        _ => {
+            builder.filter_level(log::LevelFilter::Trace);
+        }
+    }
+
+    if let Some(path) = log_file {
+        let file = open_log_file(path)
+            .map_err(|err| CliError::OutputFileError(path.to_path_buf(), err))?;
+        builder
+            .target(env_logger::Target::Pipe(Box::new(file)))
+            .format(|buf, record| {
+                writeln!(
+                    buf,
+                    "{} {} {}: {}",
+                    Utc::now().to_rfc3339_opts(chrono::SecondsFormat::Millis, true),
+                    record.level(),
+                    record.target(),
+                    record.args()
+                )
+            });
+    }
+    Ok(builder.build())
+}
+
+/// Log files bigger than this are rotated to `<path>.1` when the next run starts.
+const MAX_LOG_FILE_BYTES: u64 = 1024 * 1024;
+
+/// Opens `path` for appending, first moving it aside if it has grown past
+/// `MAX_LOG_FILE_BYTES`. Only one old file is kept.
+fn open_log_file(path: &Path) -> io::Result<fs::File> {
+    if fs::metadata(path).is_ok_and(|metadata| metadata.len() > MAX_LOG_FILE_BYTES) {
+        let mut rotated = path.as_os_str().to_owned();
+        rotated.push(".1");
+        fs::rename(path, rotated)?;
+    }
+    if let Some(parent) = path.parent() {
+        fs::create_dir_all(parent)?;
+    }
+    fs::OpenOptions::new().create(true).append(true).open(path)
+}
+
+/// Follows https://no-color.org: a non-empty `NO_COLOR` turns colours off.
+fn no_color_requested() -> bool {
+    std::env::var_os("NO_COLOR").is_some_and(|value| !value.is_empty())
+}
+
+async fn run(mut args: Args) -> Result<(), CliError> {
+    // Completions only describe the flags, so they don't depend on the config
+    if let Some(Command::Completions { shell }) = args.command {
+        write_completions(shell, &mut io::stdout());
+        return Ok(());
+    }
+    let config = load_config(&args)?;
+    args.apply_config(config)?;
+    set_locale(
+        args.lang
+            .as_deref()
+            .and_then(Locale::from_code)
+            .unwrap_or(Locale::English),
+    );
+
+    if args.print_schema {
+        let schema = serde_json::to_string_pretty(&batch_schema())
+            .expect("the batch schema always serializes to JSON");
+        return emit_output(&args, &schema);
+    }
+    if let Some(path) = &args.generate_sample {
+        return generate_sample(path, args.force);
+    }
+    match args.command {
+        Some(Command::History) => return print_history(&args),
+        Some(Command::Total { since, year }) => return print_total(&args, since, year),
+        Some(Command::Login) => return login(&args, &*default_store()?),
+        Some(Command::Logout) => return logout(&*default_store()?),
+        _ => {}
+    }
+    load_airports(&args).await?;
+    if let Some(Command::Info { topic }) = &args.command {
+        return print_info(topic);
+    }
+    if let (true, Some(input)) = (args.dry_run_validate, &args.input) {
+        let contents = read_batch_contents(input, io::stdin().lock())?;
+        let format = args
+            .input_format
+            .unwrap_or_else(|| input_format_from_path(input));
+        return validate_batch(&contents, format, &args, &mut io::stdout());
+    }
+    let comparison = match &args.command {
+        Some(Command::Compare { itinerary }) => Some(comparison_requests(&args, itinerary)?),
+        _ => None,
+    };
+    let mixed_legs = mixed_cabin_legs(&args)?;
+    check_geojson_export(&args)?;
+    check_carbon_range(&args)?;
+    check_per_leg(&args, &mixed_legs)?;
+    check_watch(&args, &mixed_legs)?;
+    check_stream(&args)?;
+
+    if args.output_file.is_some() || !io::stdout().is_terminal() || no_color_requested() {
+        // Keep terminal escape codes out of written reports and piped output
+        colored::control::set_override(false);
+    }
+
+    print_intro(&args, io::stdout().is_terminal(), &mut io::stdout());
+
+    let vehicle = vehicle_request(&args);
+    let shipping = shipping_request(&args);
+    let electricity = electricity_request(&args);
+    if args.dry_run {
+        let json = match (&vehicle, &shipping, &electricity) {
+            (Some(request), _, _) => serde_json::to_string_pretty(request),
+            (_, Some(request), _) => serde_json::to_string_pretty(request),
+            (_, _, Some(request)) => serde_json::to_string_pretty(request),
+            _ if !mixed_legs.is_empty() => {
+                let requests = split_by_cabin(&mixed_legs, args.distance_unit.as_deref());
+                return print_dry_run(&args, Some(requests));
+            }
+            _ => return print_dry_run(&args, comparison),
+        };
+        return emit_output(&args, &json.expect("requests always serialize to JSON"));
+    }
+    if args.provider() == Provider::Offline {
+        return run_offline(&args, &offline_estimator(&args)).await;
+    }
+
+    let stored = || {
+        default_store()
+            .ok()
+            .and_then(|store| stored_api_key(&*store))
+    };
+    let api_key = match given_api_key(&args)?.or_else(stored) {
+        Some(api_key) => api_key,
+        None => prompt_api_key(args.reprompt_limit)?,
+    };
+
+    let client = build_http_client(args.proxy.as_deref(), args.user_agent())?;
+    let currency = interruptible(resolve_currency(&args, &client)).await?;
+    let options = render_options(&args, currency);
+    let api_client = api_client(&args, client);
+
+    if let Some(request) = vehicle {
+        let response = interruptible(async {
+            let _spinner = Spinner::start(show_spinner(&args, io::stderr().is_terminal()));
+            make_vehicle_estimates_request(&api_client, &request, &api_key).await
+        })
+        .await?;
+        let attributes = attributes_of(response)?;
+        emit_output(
+            &args,
+            &render_vehicle_estimate(&attributes, args.output(), &options),
+        )?;
+        return check_budget(&args, attributes.carbon_kg);
+    }
+    if let Some(request) = shipping {
+        let response = interruptible(async {
+            let _spinner = Spinner::start(show_spinner(&args, io::stderr().is_terminal()));
+            make_shipping_estimates_request(&api_client, &request, &api_key).await
+        })
+        .await?;
+        let attributes = attributes_of(response)?;
+        emit_output(
+            &args,
+            &render_shipping_estimate(&attributes, args.output(), &options),
+        )?;
+        return check_budget(&args, attributes.carbon_kg);
+    }
+    if let Some(request) = electricity {
+        let response = interruptible(async {
+            let _spinner = Spinner::start(show_spinner(&args, io::stderr().is_terminal()));
+            make_electricity_estimates_request(&api_client, &request, &api_key).await
+        })
+        .await?;
+        let attributes = attributes_of(response)?;
+        emit_output(
+            &args,
+            &render_electricity_estimate(&attributes, args.output(), &options),
+        )?;
+        return check_budget(&args, attributes.carbon_kg);
+    }
+    let cache = args.estimate_cache();
+    if let Some(requests) = comparison {
+        return run_comparison(&api_client, requests, &api_key, &args, &options, &cache).await;
+    }
+    if let Some(input) = &args.input {
+        return run_batch_file(&api_client, input, &api_key, &args, &options, &cache).await;
+    }
+    if !mixed_legs.is_empty() {
+        return run_mixed_cabins(&api_client, &mixed_legs, &api_key, &args, &options).await;
+    }
+
+    let Some(request) = single_request(&args)? else {
+        return Ok(());
+    };
+    if args.per_leg {
+        return run_per_leg(&api_client, &request, &api_key, &args, &options).await;
+    }
+    if let Some(seconds) = args.watch {
+        return run_watch(&api_client, &request, &api_key, &args, &options, seconds).await;
+    }
+
+    let mut attributes = match cache.get(&request) {
+        Some(attributes) => attributes,
+        None => {
+            let (response, diagnostics) = interruptible(async {
+                let _spinner = Spinner::start(show_spinner(&args, io::stderr().is_terminal()));
+                make_estimates_request_with_diagnostics(
+                    &api_client,
+                    &request,
+                    &api_key,
+                    &SystemClock,
+                )
+                .await
+            })
+            .await?;
+            if args.timing {
+                eprintln!("{}", format_diagnostics(&diagnostics));
+            }
+            let attributes = attributes_of(response)?;
+            cache.insert(&request, &attributes);
+            attributes
+        }
+    };
+    check_distance(&args, &attributes)?;
+    warn_about_short_legs(&args, &request, &attributes);
+    record_history(&args, &request, &attributes);
+    convert_for_display(&args, &mut attributes);
+    export_geojson(&args, &request, &attributes)?;
+
+    emit_output(
+        &args,
+        &render_estimate(&request, &attributes, args.output(), &options),
+    )?;
+    print_explanation(&args, &request);
+    print_units_legend(&args, &options);
+    report_cache_hits(&args, &cache);
+
+    check_budget(&args, attributes.carbon_kg)
+}
+
+/// Notes on stderr how many estimates were reused rather than asked for, unless `--quiet`.
+fn report_cache_hits(args: &Args, cache: &EstimateCache) {
+    let hits = cache.hits();
+    if hits > 0 && !args.quiet {
+        eprintln!(
+            "♻️ {} estimate(s) reused from an identical request; no API call was made for them",
+            hits
+        );
+    }
+}
+
+/// Prints `--explain`'s notes after the result, on stderr when stdout carries a
+/// machine-readable format.
+fn print_explanation(args: &Args, request: &FlightEstimateRequest) {
+    if !args.explain {
+        return;
+    }
+    let explained = explanation(request);
+    if args.output() == OutputFormat::Text && !args.quiet {
+        println!("\n{}", explained);
+    } else {
+        eprintln!("{}", explained);
+    }
+}
+
+/// Prints `--units-legend`'s glossary after the result, on stderr when stdout carries a
+/// machine-readable format.
+fn print_units_legend(args: &Args, options: &RenderOptions) {
+    if !args.units_legend {
+        return;
+    }
+    let legend = units_legend(&shown_units(args.output(), options));
+    if args.output() == OutputFormat::Text && !args.quiet {
+        println!("\n{}", legend);
+    } else {
+        eprintln!("{}", legend);
+    }
+}
+
+/// The `--timing` report, on one line so it's easy to pick out of stderr.
+fn format_diagnostics(diagnostics: &RequestDiagnostics) -> String {
+    format!(
+        "⏱ API call took {} ms (sent {} bytes, received {} bytes)",
+        diagnostics.duration.as_millis(),
+        diagnostics.bytes_sent,
+        diagnostics.bytes_received
+    )
+}
+
+/// The single trip to estimate, from the flags or else the interactive prompts. Prompted
+/// trips are summarised and only returned once the user confirms them, unless `--yes`
+/// (or `--dry-run`, which sends nothing) is given.
+fn single_request(args: &Args) -> Result<Option<FlightEstimateRequest>, CliError> {
+    if let Some(request) = request_from_args(args)? {
+        return Ok(Some(with_return_legs(args, request)));
+    }
+
+    let mut prompter = Prompter::new(io::stdin().lock(), args.reprompt_limit);
+    prompter.resolve_metro = !args.no_metro_resolution;
+    let (passengers, legs, distance_unit) = get_flight_details(
+        &mut prompter,
+        args.distance_unit.clone(),
+        args.allow_unknown_iata,
+        args.allow_same_airport,
+    )?;
+    let request = with_return_legs(
+        args,
+        FlightEstimateRequest {
+            estimate_type: String::from("flight"),
+            passengers,
+            legs,
+            distance_unit,
+        },
+    );
+    if args.yes || args.dry_run {
+        return Ok(Some(request));
+    }
+
+    eprintln!("{}", format_itinerary_summary(&request));
+    if prompter.confirm(t("prompt.submit"))? {
+        Ok(Some(request))
+    } else {
+        eprintln!("{}", t("message.not_sent"));
+        Ok(None)
+    }
+}
+
+fn with_return_legs(args: &Args, mut request: FlightEstimateRequest) -> FlightEstimateRequest {
+    if args.round_trip {
+        let return_legs = mirror_legs(&request.legs);
+        request.legs.extend(return_legs);
+    }
+    request
+}
+
+/// Describes a trip for the user to check before it's sent.
+fn format_itinerary_summary(request: &FlightEstimateRequest) -> String {
+    format!(
+        "📋 Your trip:\n{}\nPassengers: {}\nDistance unit: {}",
+        format_legs(&request.legs),
+        request.passengers,
+        request.distance_unit.as_deref().unwrap_or("km")
+    )
+}
+
+/// Estimates a single trip without the API, labelling the result as an approximation.
+fn offline_estimator(args: &Args) -> OfflineEstimator {
+    OfflineEstimator {
+        kg_per_passenger_km: args.emission_factor(),
+        cabin_factors: args.cabin_factors,
+    }
+}
+
+async fn run_offline(args: &Args, estimator: &impl CarbonEstimator) -> Result<(), CliError> {
+    if args.command.is_some() || args.input.is_some() {
+        return Err(CliError::InvalidArguments(
+            "--offline only estimates a single flight".to_string(),
+        ));
+    }
+    let mixed_legs = mixed_cabin_legs(args)?;
+    let (request, mut attributes) = if mixed_legs.is_empty() {
+        let Some(request) = single_request(args)? else {
+            return Ok(());
+        };
+        let attributes = estimator.estimate(&request, "").await?;
+        (request, attributes)
+    } else {
+        let mut estimates = Vec::new();
+        for request in split_by_cabin(&mixed_legs, args.distance_unit.as_deref()) {
+            estimates.push(estimator.estimate(&request, "").await?);
+        }
+        (
+            mixed_cabin_summary(args, &mixed_legs),
+            combine_cabin_estimates(&mixed_legs, estimates),
+        )
+    };
+    warn_about_short_legs(args, &request, &attributes);
+    convert_for_display(args, &mut attributes);
+    export_geojson(args, &request, &attributes)?;
+
+    // Offline runs can't fetch exchange rates, so they always use the bundled table
+    let currency = Currency {
+        code: args.currency().to_string(),
+        usd_rate: StaticRates.usd_rate(args.currency()).await?,
+    };
+    let options = render_options(args, currency);
+    let mut output = render_estimate(&request, &attributes, args.output(), &options);
+    let label = format!(
+        "⚠️ Offline approximation: great-circle distance × {} kg CO2 per passenger-km, scaled by cabin class; not an API estimate.",
+        args.emission_factor()
+    );
+    if args.quiet {
+        // Keep the bare figure clean for scripts
+    } else if args.output() == OutputFormat::Text {
+        output = format!("{}\n{}", label, output);
+    } else {
+        eprintln!("{}", label);
+    }
+    emit_output(args, &output)?;
+    print_units_legend(args, &options);
+    check_budget(args, attributes.carbon_kg)
+}
+
+/// Prints what would be sent to the API: the request object for a single trip, or an
+/// array of them for `compare` and `--input`.
+fn print_dry_run(
+    args: &Args,
+    comparison: Option<Vec<FlightEstimateRequest>>,
+) -> Result<(), CliError> {
+    let requests = match (comparison, &args.input) {
+        (Some(requests), _) => requests,
+        // Legs with passengers of their own go out as separate requests
+        (None, Some(input)) => read_batch_requests(input, args, io::stdin().lock())?
+            .into_iter()
+            .flat_map(|(_, entry)| split_by_leg_passengers(&entry.request))
+            .collect(),
+        (None, None) => {
+            let Some(request) = single_request(args)? else {
+                return Ok(());
+            };
+            if args.per_leg {
+                let json = serde_json::to_string_pretty(&split_legs(&request))
+                    .expect("flight requests always serialize to JSON");
+                return emit_output(args, &json);
+            }
+            let json = serde_json::to_string_pretty(&request)
+                .expect("flight requests always serialize to JSON");
+            return emit_output(args, &json);
+        }
+    };
+    let json =
+        serde_json::to_string_pretty(&requests).expect("flight requests always serialize to JSON");
+    emit_output(args, &json)
+}
+
+async fn run_comparison(
+    api_client: &ApiClient,
+    requests: Vec<FlightEstimateRequest>,
+    api_key: &str,
+    args: &Args,
+    options: &RenderOptions,
+    cache: &EstimateCache,
+) -> Result<(), CliError> {
+    let results = interruptible(async {
+        let _spinner = Spinner::start(show_spinner(args, io::stderr().is_terminal()));
+        estimate_batch_cached(api_client, requests, api_key, 2, cache).await
+    })
+    .await;
+    let mut estimates = results.into_iter().collect::<Result<Vec<_>, _>>()?;
+    for estimate in &estimates {
+        check_distance(args, &estimate.attributes)?;
+    }
+    for estimate in &mut estimates {
+        record_history(args, &estimate.request, &estimate.attributes);
+        convert_for_display(args, &mut estimate.attributes);
+    }
+
+    let mut output = render_comparison(&estimates[0], &estimates[1], args.output(), options);
+    append_summary(args, &mut output, &render_total(&estimates, options));
+    emit_output(args, &output)?;
+    report_cache_hits(args, cache);
+    check_budget(
+        args,
+        total_kg(args, estimates.iter().map(|estimate| &estimate.attributes)),
+    )
+}
+
+/// Estimates a `--mixed-leg` trip, one request per cabin class, and reports the sum as a
+/// single estimate.
+async fn run_mixed_cabins(
+    api_client: &ApiClient,
+    legs: &[MixedCabinLeg],
+    api_key: &str,
+    args: &Args,
+    options: &RenderOptions,
+) -> Result<(), CliError> {
+    let mut attributes = interruptible(async {
+        let _spinner = Spinner::start(show_spinner(args, io::stderr().is_terminal()));
+        estimate_mixed_cabins(
+            api_client,
+            legs,
+            args.distance_unit.as_deref(),
+            api_key,
+            args.concurrency,
+        )
+        .await
+    })
+    .await?;
+    check_distance(args, &attributes)?;
+    let request = mixed_cabin_summary(args, legs);
+    record_history(args, &request, &attributes);
+    convert_for_display(args, &mut attributes);
+    export_geojson(args, &request, &attributes)?;
+
+    emit_output(
+        args,
+        &render_estimate(&request, &attributes, args.output(), options),
+    )?;
+    check_budget(args, attributes.carbon_kg)
+}
+
+/// Applies `--display-distance`. History keeps the distance the API reported.
+fn convert_for_display(args: &Args, attributes: &mut EstimateAttributes) {
+    if let Some(unit) = &args.display_distance {
+        with_distance_unit(attributes, unit);
+    }
+}
+
+/// Adds `summary` to text output, or prints it to stderr to keep other formats
+/// machine-readable. Quiet runs leave it out altogether.
+fn append_summary(args: &Args, output: &mut String, summary: &str) {
+    // Tables and reports carry their own totals
+    if args.quiet
+        || matches!(
+            args.output(),
+            OutputFormat::Table | OutputFormat::Markdown | OutputFormat::Html
+        )
+    {
+        return;
+    }
+    if args.output() == OutputFormat::Text {
+        output.push('\n');
+        output.push_str(summary);
+    } else {
+        eprintln!("{}", summary);
+    }
+}
+
+async fn run_batch_file(
+    api_client: &ApiClient,
+    input: &Path,
+    api_key: &str,
+    args: &Args,
+    options: &RenderOptions,
+    cache: &EstimateCache,
+) -> Result<(), CliError> {
+    let (indices, entries): (Vec<usize>, Vec<BatchEntry>) =
+        read_batch_requests(input, args, io::stdin().lock())?
+            .into_iter()
+            .unzip();
+    let (itineraries, requests): (Vec<Option<String>>, Vec<FlightEstimateRequest>) = entries
+        .into_iter()
+        .map(|entry| (entry.itinerary, entry.request))
+        .unzip();
+    let grouped = itineraries.iter().any(Option::is_some);
+    let routes: Vec<String> = requests.iter().map(route_of).collect();
+    let total = requests.len();
+    let deadline = args.deadline.map(Duration::from_secs);
+    let mut stream = args
+        .stream
+        .then(|| ResultStream::new(io::stdout(), args.output(), options));
+    let results = interruptible(async {
+        // Streamed results are the progress, and a spinner would only get in their way
+        let _spinner =
+            Spinner::start(show_spinner(args, io::stderr().is_terminal()) && stream.is_none());
+        if args.fail_fast {
+            let batch =
+                estimate_batch_fail_fast(api_client, requests, api_key, args.concurrency, cache);
+            let finished = match deadline {
+                Some(deadline) => tokio::time::timeout(deadline, batch)
+                    .await
+                    .map_err(|_| CliError::DeadlineExceeded(deadline))?,
+                None => batch.await,
+            };
+            return match finished {
+                Ok(estimates) => Ok(estimates.into_iter().map(Ok).collect()),
+                Err(mut err) => {
+                    // Name the entry by where it is in the file, not among those estimated
+                    err.index = indices[err.index];
+                    Err(CliError::InRequest(Box::new(err)))
+                }
+            };
+        }
+        Ok(estimate_batch_streaming(
+            api_client,
+            requests,
+            api_key,
+            args.concurrency,
+            cache,
+            deadline,
+            args.ordered,
+            |_, result| {
+                let (Some(stream), Ok(estimate)) = (&mut stream, result) else {
+                    return;
+                };
+                // What --max-distance and --strict reject is reported with the other failures
+                if passes_distance_checks(args, &estimate.attributes) {
+                    let mut estimate = estimate.clone();
+                    convert_for_display(args, &mut estimate.attributes);
+                    stream.write(&estimate);
+                }
+            },
+        )
+        .await)
+    })
+    .await?;
+    let mut labelled = Vec::new();
+    let mut failures = Vec::new();
+    for (((index, itinerary), route), result) in indices
+        .into_iter()
+        .zip(itineraries)
+        .zip(routes)
+        .zip(results)
+    {
+        let result = result.and_then(|estimate| {
+            check_distance(args, &estimate.attributes)?;
+            Ok(estimate)
+        });
+        match result {
+            Ok(mut estimate) => {
+                record_history(args, &estimate.request, &estimate.attributes);
+                convert_for_display(args, &mut estimate.attributes);
+                labelled.push((itinerary, estimate))
+            }
+            Err(err) => failures.push(BatchFailure {
+                index,
+                route,
+                error: err.to_string(),
+            }),
+        }
+    }
+    // Going over the budget counts the filtered-out estimates too, as those flights still happen
+    let estimated_kg = total_kg(
+        args,
+        labelled.iter().map(|(_, estimate)| &estimate.attributes),
+    );
+    let (mut labelled, filtered_out, unknown) =
+        filter_by_carbon(labelled, args.min_carbon_kg, args.max_carbon_kg);
+    if filtered_out > 0 && !args.quiet {
+        eprintln!("{}", tf("message.filtered_out", filtered_out));
+    }
+    if unknown > 0 && !args.quiet {
+        eprintln!("{}", tf("message.unknown_carbon_kept", unknown));
+    }
+    if let Some(key) = args.sort {
+        labelled.sort_by(|(_, a), (_, b)| {
+            let ordering = compare_results(a, b, key);
+            if args.sort_desc {
+                ordering.reverse()
+            } else {
+                ordering
+            }
+        });
+    }
+    let estimates: Vec<EstimateResult> = labelled
+        .iter()
+        .map(|(_, estimate)| estimate.clone())
+        .collect();
+
+    if let Some(dir) = &args.output_dir {
+        write_itinerary_files(args, dir, &group_by_itinerary(labelled), options)?;
+    } else if args.stream {
+        // The results are out already; only the totals are left
+        let mut output = String::new();
+        append_summary(args, &mut output, &render_total(&estimates, options));
+        if !output.is_empty() {
+            emit_output(args, output.trim_start())?;
+        }
+    } else {
+        let output = batch_output(args, labelled, &estimates, grouped, options);
+        emit_output(args, &output)?;
+    }
+    print_units_legend(args, options);
+    if !args.quiet || !failures.is_empty() {
+        eprintln!("{}", batch_summary(total, &failures));
+    }
+    report_cache_hits(args, cache);
+    // Going over the budget counts even when some requests are missing from the total
+    check_budget(args, estimated_kg)?;
+    if failures.is_empty() {
+        Ok(())
+    } else {
+        Err(CliError::BatchFailed {
+            failed: failures.len(),
+            total,
+        })
+    }
+}
+
+/// Writes `sample_batch` to `path` in the format its extension asks for.
+fn generate_sample(path: &Path, force: bool) -> Result<(), CliError> {
+    let sample = render_sample_batch(input_format_from_path(path));
+    write_output_file(path, &sample, force)?;
+    eprintln!(
+        "Wrote a sample batch to {}; edit it, then estimate it with --input {}",
+        path.display(),
+        path.display()
+    );
+    Ok(())
+}
+
+/// A batch's results as one document: ranked, grouped into itineraries, or as a list.
+fn batch_output(
+    args: &Args,
+    labelled: Vec<(Option<String>, EstimateResult)>,
+    estimates: &[EstimateResult],
+    grouped: bool,
+    options: &RenderOptions,
+) -> String {
+    if args.rank {
+        render_ranking(
+            &rank_itineraries(&group_by_itinerary(labelled)),
+            args.output(),
+            options,
+        )
+    } else if grouped {
+        // Subtotals and the grand total are part of the grouped output itself
+        render_itineraries(&group_by_itinerary(labelled), args.output(), options)
+    } else {
+        let mut output = render_batch(estimates, args.output(), options);
+        append_summary(args, &mut output, &render_total(estimates, options));
+        output
+    }
+}
+
+/// Writes `--stream` results one at a time as they arrive: a text block each, a JSON
+/// object per line, or CSV rows under a header written with the first.
+struct ResultStream<W> {
+    out: W,
+    format: OutputFormat,
+    options: RenderOptions,
+    started: bool,
+}
+
+impl<W: Write> ResultStream<W> {
+    fn new(out: W, format: OutputFormat, options: &RenderOptions) -> Self {
+        Self {
+            out,
+            format,
+            options: RenderOptions {
+                compact: true,
+                ..options.clone()
+            },
+            started: false,
+        }
+    }
+
+    fn write(&mut self, estimate: &EstimateResult) {
+        let rendered = render_batch(std::slice::from_ref(estimate), self.format, &self.options);
+        let rendered = match rendered.split_once('\n') {
+            // Only the first result needs the CSV header
+            Some((_, row)) if self.format == OutputFormat::Csv && self.started => row,
+            _ => &rendered,
+        };
+        self.started = true;
+        // One write a result, so each appears whole
+        let _ = self.out.write_all(format!("{}\n", rendered).as_bytes());
+        let _ = self.out.flush();
+    }
+}
+
+/// `--stream` writes formats that can be read a result at a time.
+fn check_stream(args: &Args) -> Result<(), CliError> {
+    if args.stream
+        && !matches!(
+            args.output(),
+            OutputFormat::Text | OutputFormat::Json | OutputFormat::Csv
+        )
+    {
+        return Err(CliError::InvalidArguments(
+            "--stream only writes text, JSON or CSV".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Whether a trip's distance is within `--max-distance`, if that's given, and isn't
+/// zero under `--strict`.
+fn passes_distance_checks(args: &Args, attributes: &EstimateAttributes) -> bool {
+    let within_max_distance = args.max_distance.is_none_or(|max_distance| {
+        !is_missing(attributes.distance_value)
+            && !exceeds_max_distance(attributes, max_distance, &ground_distance_unit(args))
+    });
+    within_max_distance && !(args.strict && is_zero_distance(attributes))
+}
+
+/// Writes each itinerary's estimates to a file of its own in `dir`, named after the
+/// itinerary and numbered when two names would clash.
+fn write_itinerary_files(
+    args: &Args,
+    dir: &Path,
+    groups: &[ItineraryGroup],
+    options: &RenderOptions,
+) -> Result<(), CliError> {
+    let format = args.output();
+    let mut names = HashSet::new();
+    for group in groups {
+        let stem = itinerary_file_stem(&group.name);
+        let mut name = format!("{}.{}", stem, format.extension());
+        for n in 2.. {
+            if names.insert(name.clone()) {
+                break;
+            }
+            name = format!("{}-{}.{}", stem, n, format.extension());
+        }
+        let output = render_batch(&group.estimates, format, options);
+        write_output_file(&dir.join(name), &output, args.force)?;
+    }
+    eprintln!("Wrote {} file(s) to {}", groups.len(), dir.display());
+    Ok(())
+}
+
+/// The run's emissions for `check_budget`, from the estimates' known `carbon_kg`. Leaving
+/// any out is noted on stderr when there's a budget or warning level to check.
+fn total_kg<'a>(args: &Args, estimates: impl IntoIterator<Item = &'a EstimateAttributes>) -> f32 {
+    let figures: Vec<f32> = estimates
+        .into_iter()
+        .map(|attributes| attributes.carbon_kg)
+        .collect();
+    let missing = figures.iter().filter(|kg| is_missing(**kg)).count();
+    let checked = args.budget_kg.is_some() || args.warn_carbon_kg.is_some();
+    if checked && missing > 0 && missing < figures.len() {
+        eprintln!(
+            "{}",
+            tf("warning.unknown_in_total", missing).yellow().bold()
+        );
+    }
+    sum_known(figures)
+}
+
+/// Notes on stderr that a warning level couldn't be checked, which doesn't stop the run.
+fn warn_unchecked_limit(limit: &str, figure: &str) {
+    eprintln!(
+        "{}",
+        tfv("warning.unchecked_limit", &[&limit, &figure])
+            .yellow()
+            .bold()
+    );
+}
+
+/// Fails when `--max-distance` is given and the estimate's distance goes over it, or under
+/// `--strict` when it's zero, before the result is shown or recorded. Going over
+/// `--warn-distance`, or a zero distance without `--strict`, only prints a warning.
+fn check_distance(args: &Args, attributes: &EstimateAttributes) -> Result<(), CliError> {
+    if let Some(warning) = zero_distance_warning(attributes) {
+        if args.strict {
+            return Err(CliError::ImplausibleResponse(warning));
+        }
+        eprintln!("{}", format!("WARN: {}", warning).yellow().bold());
+    }
+    if is_missing(attributes.distance_value) {
+        if args.max_distance.is_some() {
+            return Err(CliError::UncheckedLimit {
+                limit: "--max-distance",
+                figure: "distance_value",
+            });
+        }
+        if args.warn_distance.is_some() {
+            warn_unchecked_limit("--warn-distance", "distance_value");
+        }
+        return Ok(());
+    }
+    let unit = ground_distance_unit(args);
+    let distance = convert_distance(attributes.distance_value, &attributes.distance_unit, &unit);
+    match args.max_distance {
+        Some(max_distance) if exceeds_max_distance(attributes, max_distance, &unit) => {
+            Err(CliError::InvalidInput(format!(
+                "the trip is {:.0} {} long, over --max-distance {} {}; check the airport codes",
+                distance, unit, max_distance, unit
+            )))
+        }
+        _ => {
+            warn_about_distance(args, attributes, distance, &unit);
+            Ok(())
+        }
+    }
+}
+
+/// What's wrong with an estimate that covers no distance, if this one doesn't.
+fn zero_distance_warning(attributes: &EstimateAttributes) -> Option<String> {
+    is_zero_distance(attributes).then(|| {
+        format!(
+            "the distance came back as 0 {}, so the emissions can't be right; check that the \
+             airport codes are real and that no leg starts where it ends",
+            attributes.distance_unit
+        )
+    })
+}
+
+/// Notes on stderr that a trip went over `--warn-distance`, which doesn't stop the run.
+fn warn_about_distance(args: &Args, attributes: &EstimateAttributes, distance: f32, unit: &str) {
+    let Some(warn_distance) = args.warn_distance else {
+        return;
+    };
+    if exceeds_max_distance(attributes, warn_distance, unit) {
+        eprintln!(
+            "{}",
+            format!(
+                "WARN: the trip is {:.0} {} long, over the {} {} warning level",
+                distance, unit, warn_distance, unit
+            )
+            .yellow()
+            .bold()
+        );
+    }
+}
+
+/// The legs of `request` under `threshold_km`, by index, with their length in km: the
+/// estimate's own distance for a one-leg trip, else each leg's great-circle distance.
+fn short_legs(
+    request: &FlightEstimateRequest,
+    attributes: &EstimateAttributes,
+    threshold_km: f32,
+) -> Vec<(usize, f32)> {
+    request
+        .legs
+        .iter()
+        .enumerate()
+        .filter_map(|(index, leg)| {
+            let leg_km = if request.legs.len() == 1 {
+                Some(distance_km(attributes))
+            } else {
+                leg_distance_km(leg)
+            };
+            leg_km
+                .filter(|&leg_km| is_short_flight(leg_km, threshold_km))
+                .map(|leg_km| (index, leg_km))
+        })
+        .collect()
+}
+
+/// Notes on stderr each leg short enough to go by land, which doesn't stop the run.
+fn warn_about_short_legs(
+    args: &Args,
+    request: &FlightEstimateRequest,
+    attributes: &EstimateAttributes,
+) {
+    for (index, leg_km) in short_legs(request, attributes, args.short_flight_km()) {
+        let leg = &request.legs[index];
+        eprintln!(
+            "{}",
+            format!(
+                "WARN: leg {} ({} → {}) is only {:.0} km; a train or car would likely emit less, as short flights burn much of their fuel taking off",
+                index + 1,
+                leg.departure_airport,
+                leg.destination_airport,
+                leg_km
+            )
+            .yellow()
+            .bold()
+        );
+    }
+}
+
+/// Fails with `CliError::BudgetExceeded` once the output is out, if the run's emissions
+/// went over `--budget-kg`, or with `CliError::OverWarningLevel` if they only went over
+/// `--warn-carbon-kg`.
+fn check_budget(args: &Args, total_kg: f32) -> Result<(), CliError> {
+    if is_missing(total_kg) {
+        if args.budget_kg.is_some() {
+            return Err(CliError::UncheckedLimit {
+                limit: "--budget-kg",
+                figure: "carbon_kg",
+            });
+        }
+        if args.warn_carbon_kg.is_some() {
+            warn_unchecked_limit("--warn-carbon-kg", "carbon_kg");
+        }
+        return Ok(());
+    }
+    match (args.budget_kg, args.warn_carbon_kg) {
+        (Some(budget_kg), _) if exceeds_budget(total_kg, budget_kg) => {
+            Err(CliError::BudgetExceeded {
+                total_kg,
+                budget_kg,
+            })
+        }
+        (_, Some(warn_kg)) if exceeds_budget(total_kg, warn_kg) => {
+            Err(CliError::OverWarningLevel { total_kg, warn_kg })
+        }
+        _ => Ok(()),
+    }
+}
+
+/// An `--input` of `-` means the batch is piped in on stdin.
+const STDIN_INPUT: &str = "-";
+
+/// Reads the well-formed entries in the batch at `input`, or from `stdin` when `input` is
+/// `-`, along with their indices. Metro area codes are resolved as for flags. Malformed
+/// entries are reported on stderr, as are entries with a leg from and to the same
+/// airport, which are skipped unless `--allow-same-airport` is given. The batch is read
+/// in `--input-format` if given, or else the one its extension suggests; JSON Lines are
+/// read a line at a time and reported by line.
+fn read_batch_requests(
+    input: &Path,
+    args: &Args,
+    stdin: impl Read,
+) -> Result<Vec<(usize, BatchEntry)>, CliError> {
+    let format = args
+        .input_format
+        .unwrap_or_else(|| input_format_from_path(input));
+    if format != InputFormat::Jsonl {
+        let contents = read_batch_contents(input, stdin)?;
+        let entries = parse_batch_entries_as(&contents, format)?
+            .into_iter()
+            .enumerate()
+            .map(|(index, entry)| (format!("at index {}", index), entry));
+        return Ok(keep_batch_entries(entries, args));
+    }
+
+    let lines =
+        |reader| parse_json_lines(reader).map(|(line, entry)| (format!("on line {}", line), entry));
+    if input == Path::new(STDIN_INPUT) {
+        Ok(keep_batch_entries(
+            lines(Box::new(io::BufReader::new(stdin)) as Box<dyn BufRead>),
+            args,
+        ))
+    } else {
+        let file = fs::File::open(input).map_err(|err| {
+            CliError::InvalidInput(format!("could not read {}: {}", input.display(), err))
+        })?;
+        Ok(keep_batch_entries(
+            lines(Box::new(io::BufReader::new(file))),
+            args,
+        ))
+    }
+}
+
+/// The entries worth estimating, numbered in order, reporting the others by `place`.
+fn keep_batch_entries(
+    entries: impl Iterator<Item = (String, Result<BatchEntry, String>)>,
+    args: &Args,
+) -> Vec<(usize, BatchEntry)> {
+    let mut requests = Vec::new();
+    for (index, (place, entry)) in entries.enumerate() {
+        match entry.map(|mut entry| {
+            resolve_metro_airports(args, &mut entry.request.legs);
+            entry
+        }) {
+            Ok(entry) => match entry
+                .request
+                .legs
+                .iter()
+                .find(|leg| is_same_airport_leg(leg))
+            {
+                Some(leg) if !args.allow_same_airport => eprintln!(
+                    "Skipping entry {}: {} is both the departure and the destination of a leg",
+                    place, leg.departure_airport
+                ),
+                _ => requests.push((index, entry)),
+            },
+            Err(err) => eprintln!("Skipping malformed entry {}: {}", place, err),
+        }
+    }
+    requests
+}
+
+/// The text of `--input`, from stdin for `-`.
+fn read_batch_contents(input: &Path, mut stdin: impl Read) -> Result<String, CliError> {
+    if input == Path::new(STDIN_INPUT) {
+        let mut contents = String::new();
+        stdin
+            .read_to_string(&mut contents)
+            .map_err(|err| CliError::InvalidInput(format!("could not read stdin: {}", err)))?;
+        Ok(contents)
+    } else {
+        fs::read_to_string(input).map_err(|err| {
+            CliError::InvalidInput(format!("could not read {}: {}", input.display(), err))
+        })
+    }
+}
+
+/// `--dry-run-validate`: lists every problem in a batch file by entry index, so all of
+/// them can be fixed before anything is sent.
+fn validate_batch(
+    contents: &str,
+    format: InputFormat,
+    args: &Args,
+    out: &mut impl Write,
+) -> Result<(), CliError> {
+    let entries = parse_batch_entries_as(contents, format)?;
+    let mut problems = 0;
+    let mut invalid_entries = 0;
+    for (index, entry) in entries.iter().enumerate() {
+        let messages: Vec<String> = match entry {
+            Ok(entry) => validate_request(&entry.request)
+                .into_iter()
+                .filter(|issue| match issue {
+                    ValidationIssue::UnknownAirport { .. } => !args.allow_unknown_iata,
+                    ValidationIssue::SameAirport { .. } => !args.allow_same_airport,
+                    _ => true,
+                })
+                .map(|issue| issue.to_string())
+                .collect(),
+            Err(err) => vec![err.clone()],
+        };
+        for message in &messages {
+            let _ = writeln!(out, "entry {}: {}", index, message);
+        }
+        problems += messages.len();
+        invalid_entries += usize::from(!messages.is_empty());
+    }
+    if problems > 0 {
+        return Err(CliError::InvalidInput(format!(
+            "{} problem(s) in {} of {} entries",
+            problems,
+            invalid_entries,
+            entries.len()
+        )));
+    }
+    let _ = writeln!(out, "No problems in {} entries", entries.len());
+    Ok(())
+}
+
+/// `--per-leg` breaks down one flight estimated by the API.
+fn check_per_leg(args: &Args, mixed_legs: &[MixedCabinLeg]) -> Result<(), CliError> {
+    if args.per_leg
+        && (args.command.is_some()
+            || args.input.is_some()
+            || !mixed_legs.is_empty()
+            || args.provider() == Provider::Offline)
+    {
+        return Err(CliError::InvalidArguments(
+            "--per-leg only breaks down a single flight estimated with the API".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// `--watch` re-estimates one flight with the API.
+fn check_watch(args: &Args, mixed_legs: &[MixedCabinLeg]) -> Result<(), CliError> {
+    if args.watch.is_some()
+        && (args.command.is_some()
+            || args.input.is_some()
+            || !mixed_legs.is_empty()
+            || args.provider() == Provider::Offline
+            || args.per_leg
+            || vehicle_request(args).is_some()
+            || shipping_request(args).is_some()
+            || electricity_request(args).is_some())
+    {
+        return Err(CliError::InvalidArguments(
+            "--watch only repeats a single flight estimated with the API".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Paces `--watch`, so tests can drive its loop without waiting.
+trait Ticker {
+    /// Waits until the next estimate is due, or returns `false` to stop watching.
+    async fn tick(&mut self) -> bool;
+}
+
+/// Ticks every interval, the first time straight away.
+struct IntervalTicker(tokio::time::Interval);
+
+impl Ticker for IntervalTicker {
+    async fn tick(&mut self) -> bool {
+        self.0.tick().await;
+        true
+    }
+}
+
+/// Clears the terminal and moves the cursor to its top left corner.
+const CLEAR_SCREEN: &str = "\x1b[2J\x1b[H";
+
+/// Errors worth waiting out in `--watch`, as the next estimate may well succeed. Anything
+/// else, such as a rejected key, would fail every time.
+fn is_transient(err: &CliError) -> bool {
+    matches!(
+        err,
+        CliError::NetworkError(_)
+            | CliError::DnsError(_)
+            | CliError::ConnectionRefused(_)
+            | CliError::Timeout(_)
+            | CliError::ServerError(_)
+            | CliError::ApiError(_)
+    )
+}
+
+/// Runs `estimate` on each tick of `ticker` and writes its output to `out`, first
+/// clearing the screen when `redraw` is set. Transient errors are shown (on `out` when
+/// redrawing, else on `err`) and the loop carries on; any other error ends it.
+async fn watch_estimates<F, Fut>(
+    ticker: &mut impl Ticker,
+    mut estimate: F,
+    redraw: bool,
+    out: &mut impl Write,
+    err: &mut impl Write,
+) -> Result<(), CliError>
+where
+    F: FnMut() -> Fut,
+    Fut: Future<Output = Result<String, CliError>>,
+{
+    while ticker.tick().await {
+        let shown = match estimate().await {
+            Ok(output) => output,
+            Err(error) if is_transient(&error) => {
+                let message = format!("⚠️ {}; trying again on the next update", error);
+                if !redraw {
+                    let _ = writeln!(err, "{}", message);
+                    continue;
+                }
+                message
+            }
+            Err(error) => return Err(error),
+        };
+        if redraw {
+            let _ = write!(out, "{}", CLEAR_SCREEN);
+        }
+        let _ = writeln!(out, "{}", shown);
+        let _ = out.flush();
+    }
+    Ok(())
+}
+
+/// Estimates `request` every `seconds` until interrupted. Results are redrawn in place
+/// on a terminal, and printed one after another when piped or `--quiet`. Every run asks
+/// the API again, so nothing is cached or recorded in the history.
+async fn run_watch(
+    api_client: &ApiClient,
+    request: &FlightEstimateRequest,
+    api_key: &str,
+    args: &Args,
+    options: &RenderOptions,
+    seconds: u64,
+) -> Result<(), CliError> {
+    let redraw = !args.quiet && io::stdout().is_terminal();
+    let estimate = || async move {
+        let mut attributes = api_client.estimate(request, api_key).await?;
+        check_distance(args, &attributes)?;
+        convert_for_display(args, &mut attributes);
+        let mut output = render_estimate(request, &attributes, args.output(), options);
+        if redraw {
+            output.push_str(&format!(
+                "\n\n🔄 Updated at {}; refreshing every {} s. Press Ctrl-C to stop.",
+                chrono::Local::now().format("%H:%M:%S"),
+                seconds
+            ));
+        }
+        Ok(output)
+    };
+    let mut ticker = IntervalTicker(tokio::time::interval(Duration::from_secs(seconds)));
+    interruptible(watch_estimates(
+        &mut ticker,
+        estimate,
+        redraw,
+        &mut io::stdout(),
+        &mut io::stderr(),
+    ))
+    .await
+}
+
+/// Estimates each leg of `request` separately and reports their total along with what
+/// each leg contributed.
+async fn run_per_leg(
+    api_client: &ApiClient,
+    request: &FlightEstimateRequest,
+    api_key: &str,
+    args: &Args,
+    options: &RenderOptions,
+) -> Result<(), CliError> {
+    let mut legs = interruptible(async {
+        let _spinner = Spinner::start(show_spinner(args, io::stderr().is_terminal()));
+        estimate_per_leg(api_client, request, api_key, args.concurrency).await
+    })
+    .await?;
+    let attributes: Vec<EstimateAttributes> =
+        legs.iter().map(|leg| leg.attributes.clone()).collect();
+    let mut total = sum_attributes(&attributes);
+    check_distance(args, &total)?;
+    record_history(args, request, &total);
+    convert_for_display(args, &mut total);
+    for leg in &mut legs {
+        convert_for_display(args, &mut leg.attributes);
+    }
+    if let Some(key) = args.sort {
+        sort_results(&mut legs, key, args.sort_desc);
+    }
+    export_geojson(args, request, &total)?;
+
+    let output = if args.output() == OutputFormat::Text {
+        let mut output = render_estimate(request, &total, args.output(), options);
+        if !args.quiet {
+            output.push('\n');
+            output.push_str(&render_leg_breakdown(&legs, options));
+        }
+        output
+    } else {
+        let mut output = render_batch(&legs, args.output(), options);
+        append_summary(args, &mut output, &render_total(&legs, options));
+        output
+    };
+    emit_output(args, &output)?;
+    check_budget(args, total_kg(args, &attributes))
+}
+
+/// An empty `--min-carbon-kg`/`--max-carbon-kg` range would hide every result.
+fn check_carbon_range(args: &Args) -> Result<(), CliError> {
+    if let (Some(min_kg), Some(max_kg)) = (args.min_carbon_kg, args.max_carbon_kg) {
+        if min_kg > max_kg {
+            return Err(CliError::InvalidArguments(
+                "--min-carbon-kg can't be more than --max-carbon-kg".to_string(),
+            ));
+        }
+    }
+    Ok(())
+}
+
+/// Only a single trip has one route to map.
+fn check_geojson_export(args: &Args) -> Result<(), CliError> {
+    if args.export_geojson.is_some() && (args.command.is_some() || args.input.is_some()) {
+        return Err(CliError::InvalidArguments(
+            "--export-geojson only maps a single flight".to_string(),
+        ));
+    }
+    Ok(())
+}
+
+/// Writes the trip's route to `--export-geojson`, if it was given.
+fn export_geojson(
+    args: &Args,
+    request: &FlightEstimateRequest,
+    attributes: &EstimateAttributes,
+) -> Result<(), CliError> {
+    let Some(path) = &args.export_geojson else {
+        return Ok(());
+    };
+    let geojson = route_geojson(request, attributes)?;
+    let json = serde_json::to_string_pretty(&geojson).expect("GeoJSON always serializes");
+    write_output_file(path, &json, args.force)?;
+    eprintln!("Wrote route map to {}", path.display());
+    Ok(())
+}
+
+fn history_path(args: &Args) -> Option<PathBuf> {
+    args.history_file.clone().or_else(default_history_path)
+}
+
+/// Appends a successful estimate to the history file unless `--no-history` was given.
+/// Failing to record it only warns, since the estimate itself succeeded.
+fn record_history(args: &Args, request: &FlightEstimateRequest, attributes: &EstimateAttributes) {
+    if args.no_history {
+        return;
+    }
+    let Some(path) = history_path(args) else {
+        return;
+    };
+
+    let record = HistoryRecord::new(request, attributes, Utc::now());
+    if let Err(err) = append_record(&path, &record) {
+        eprintln!("⚠️ {}", err);
+    }
+}
+
+fn print_history(args: &Args) -> Result<(), CliError> {
+    let records = match history_path(args) {
+        Some(path) => read_history(&path)?,
+        None => Vec::new(),
+    };
+
+    if records.is_empty() {
+        println!("No estimates recorded yet.");
+    }
+    for record in &records {
+        println!("{}", format_history_record(record));
+    }
+    Ok(())
+}
+
+fn print_total(args: &Args, since: Option<NaiveDate>, year: Option<i32>) -> Result<(), CliError> {
+    let records = match history_path(args) {
+        Some(path) => read_history(&path)?,
+        None => Vec::new(),
+    };
+    let total = total_emissions(&records, &Period::new(since, year));
+    println!("{}", format_total(&total, since, year));
+    Ok(())
+}
+
+fn format_total(total: &HistoryTotal, since: Option<NaiveDate>, year: Option<i32>) -> String {
+    let period = match (since, year) {
+        (Some(since), Some(year)) => format!("In {} since {}", year, since),
+        (Some(since), None) => format!("Since {}", since),
+        (None, Some(year)) => format!("In {}", year),
+        (None, None) => "All time".to_string(),
+    };
+    format!(
+        "{}: {:.2} kg CO2 across {} flight(s)",
+        period, total.carbon_kg, total.flights
+    )
+}
+
+fn format_history_record(record: &HistoryRecord) -> String {
+    let cabin_classes: Vec<&str> = record
+        .cabin_classes
+        .iter()
+        .map(|cabin_class| cabin_class.as_deref().unwrap_or("default"))
+        .collect();
+
+    format!(
+        "{}  {}  {} passenger(s)  {}  {:.2} kg CO2",
+        record.timestamp.format("%Y-%m-%d %H:%M UTC"),
+        record.route.join(" → "),
+        record.passengers,
+        cabin_classes.join(", "),
+        record.carbon_kg
+    )
+}
+
+/// Prints rendered output, or writes it to `--output-file` when one was given.
+fn emit_output(args: &Args, output: &str) -> Result<(), CliError> {
+    match &args.output_file {
+        Some(path) => {
+            write_output_file(path, output, args.force)?;
+            eprintln!("Wrote results to {}", path.display());
+            Ok(())
+        }
+        None => {
+            println!("{}", output);
+            Ok(())
+        }
+    }
+}
+
+/// Writes `contents` to `path`, creating missing parent directories. An existing
+/// file is only replaced when `force` is set.
+fn write_output_file(path: &Path, contents: &str, force: bool) -> Result<(), CliError> {
+    if path.exists() && !force {
+        return Err(CliError::OutputFileExists(path.to_path_buf()));
+    }
+
+    let write = || -> io::Result<()> {
+        if let Some(parent) = path.parent() {
+            fs::create_dir_all(parent)?;
+        }
+        fs::write(path, format!("{}\n", contents))
+    };
+    write().map_err(|err| CliError::OutputFileError(path.to_path_buf(), err))
+}
+
+/// Looks up the exchange rate for `--currency` once, so every estimate in the run
+/// is converted at the same rate.
+async fn resolve_currency(args: &Args, client: &reqwest::Client) -> Result<Currency, CliError> {
+    if args.currency() == "USD" {
+        return Ok(Currency::usd());
+    }
+    let usd_rate = match &args.fx_url {
+        Some(url) => {
+            HttpRates::new(client.clone(), url)
+                .usd_rate(args.currency())
+                .await?
+        }
+        None => StaticRates.usd_rate(args.currency()).await?,
+    };
+    Ok(Currency {
+        code: args.currency().to_string(),
+        usd_rate,
+    })
+}
+
+fn render_options(args: &Args, currency: Currency) -> RenderOptions {
+    RenderOptions {
+        offset_price: args.offset_price(),
+        show_equivalencies: !args.no_equivalencies,
+        compare_alternatives: args.compare_alternatives,
+        quiet: args.quiet,
+        units: args.units.clone(),
+        compact: args.compact,
+        rf_multiplier: args.rf_multiplier(),
+        precision: args.precision,
+        currency,
+    }
+}
+
+/// The spinner only makes sense on an interactive terminal, and is kept away from
+/// machine-readable output.
+fn show_spinner(args: &Args, stderr_is_terminal: bool) -> bool {
+    stderr_is_terminal && !args.quiet && args.output() == OutputFormat::Text
+}
+
+/// A progress spinner on stderr while the API is working. It clears itself when dropped,
+/// so an early return on error can't leave it spinning.
+struct Spinner(Option<ProgressBar>);
+
+impl Spinner {
+    fn start(enabled: bool) -> Self {
+        if !enabled {
+            return Spinner(None);
+        }
+
+        let pb = ProgressBar::new_spinner();
+
+        let style = ProgressStyle::default_spinner()
+            .tick_chars("/|\\- ")
+            .template("{spinner} {wide_msg}");
+
+        match style {
+            Ok(st) => pb.set_style(st),
+            Err(e) => eprintln!("Error setting progress bar style: {}", e),
+        }
+
+        pb.set_message("Calculating carbon footprint...");
+        pb.enable_steady_tick(Duration::from_millis(50));
+        Spinner(Some(pb))
+    }
+}
+
+impl Drop for Spinner {
+    fn drop(&mut self) {
+        if let Some(pb) = &self.0 {
+            pb.finish_and_clear();
+        }
+    }
+}
+
+/// Greets interactive text users; scripts asking for --quiet or a machine-readable
+/// format get nothing.
+fn print_intro(args: &Args, is_tty: bool, out: &mut impl Write) {
+    if should_print_banner(args, is_tty) {
+        write_banner(out);
+    }
+}
+
+/// The banner is only for people at a terminal reading text output, and only until they
+/// ask for `--no-banner`. Piped output and logs are kept free of it.
+fn should_print_banner(args: &Args, is_tty: bool) -> bool {
+    is_tty && !args.no_banner && !args.quiet && args.output() == OutputFormat::Text
+}
+
+fn write_banner(out: &mut impl Write) {
+    let banner = r#"
+
+-------WELCOME TO THE CARBON FOOTPRINT CLI-------
+                   __|__
+            --------(_)--------
+              O  O       O  O
+"#;
+    writeln!(out, "{}", banner).expect("Failed to print the banner");
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use carbon_footprint_cli::config::Thresholds;
+    use carbon_footprint_cli::credentials::MemoryStore;
+    use carbon_footprint_cli::{
+        make_estimates_request, EstimateData, FlightEstimateResponse, MISSING_FIGURE,
+    };
+    use reqwest::Client;
+    use std::sync::atomic::{AtomicBool, AtomicUsize, Ordering};
+    use std::sync::Arc;
+    use wiremock::{
+        matchers::{body_string_contains, method, path},
+        Mock, MockServer, ResponseTemplate,
+    };
+
+    fn create_mock_response(
+        carbon_g: f32,
+        carbon_lb: f32,
+        carbon_kg: f32,
+        carbon_mt: f32,
+        distance_unit: &str,
+        distance_value: f32,
+    ) -> FlightEstimateResponse {
+        FlightEstimateResponse {
+            data: Some(EstimateData {
+                attributes: EstimateAttributes {
+                    carbon_g,
+                    carbon_lb,
+                    carbon_kg,
+                    carbon_mt,
+                    distance_unit: distance_unit.to_string(),
+                    distance_value,
+                },
+            }),
+            message: None,
+        }
+    }
+
+    /// A provider that answers every request with the same figures, counting the requests.
+    struct FixedEstimator {
+        carbon_kg: f32,
+        requests: AtomicUsize,
+    }
+
+    impl CarbonEstimator for FixedEstimator {
+        fn estimate(
+            &self,
+            _request: &FlightEstimateRequest,
+            _api_key: &str,
+        ) -> impl Future<Output = Result<EstimateAttributes, CliError>> {
+            self.requests.fetch_add(1, Ordering::SeqCst);
+            let response = create_mock_response(0.0, 0.0, self.carbon_kg, 0.0, "km", 100.0);
+            std::future::ready(attributes_of(response))
+        }
+    }
+
+    #[test]
+    fn test_provider_defaults_to_the_api() {
+        let provider = |flags: &[&str]| {
+            Args::try_parse_from(
+                std::iter::once("carbon-footprint-cli").chain(flags.iter().copied()),
+            )
+            .map(|args| args.provider())
+        };
+
+        assert_eq!(provider(&[]).unwrap(), Provider::CarbonInterface);
+        assert_eq!(provider(&["--offline"]).unwrap(), Provider::Offline);
+        assert_eq!(
+            provider(&["--provider", "offline"]).unwrap(),
+            Provider::Offline
+        );
+        assert!(provider(&["--offline", "--provider", "carbon-interface"]).is_err());
+    }
+
+    #[tokio::test]
+    async fn test_local_provider_estimates_with_the_selected_estimator() {
+        let dir = tempfile::tempdir().unwrap();
+        let output_file = dir.path().join("estimate.txt");
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--provider",
+            "offline",
+            "--from",
+            "LHR",
+            "--to",
+            "JFK",
+            "--quiet",
+            "--output-file",
+            output_file.to_str().unwrap(),
+        ])
+        .unwrap();
+        let estimator = FixedEstimator {
+            carbon_kg: 42.0,
+            requests: AtomicUsize::new(0),
+        };
+
+        run_offline(&args, &estimator).await.unwrap();
+
+        assert_eq!(estimator.requests.load(Ordering::SeqCst), 1);
+        assert_eq!(fs::read_to_string(output_file).unwrap(), "42.00\n");
+    }
+
+    #[test]
+    fn test_same_airport_flags_need_allow_same_airport() {
+        let flags = ["carbon-footprint-cli", "--from", "LHR", "--to", "lhr"];
+        let args = Args::try_parse_from(flags).unwrap();
+        let allowed =
+            Args::try_parse_from(flags.into_iter().chain(["--allow-same-airport"])).unwrap();
+
+        assert!(matches!(
+            legs_from_args(&args),
+            Err(CliError::InvalidArguments(_))
+        ));
+        assert_eq!(legs_from_args(&allowed).unwrap().len(), 1);
+    }
+
+    fn positional_legs(airports: &[&str]) -> Result<Vec<(String, String)>, String> {
+        let args = Args::try_parse_from(
+            std::iter::once("carbon-footprint-cli").chain(airports.iter().copied()),
+        )
+        .map_err(|err| err.to_string())?;
+        let legs = legs_from_args(&args).map_err(|err| err.to_string())?;
+        Ok(legs
+            .into_iter()
+            .map(|leg| (leg.departure_airport, leg.destination_airport))
+            .collect())
+    }
+
+    #[test]
+    fn test_positional_airports_chain_into_legs() {
+        let leg = |from: &str, to: &str| (from.to_string(), to.to_string());
+
+        assert_eq!(
+            positional_legs(&["LHR", "JFK"]),
+            Ok(vec![leg("LHR", "JFK")])
+        );
+        assert_eq!(
+            positional_legs(&["lhr", "JFK", "LAX"]),
+            Ok(vec![leg("LHR", "JFK"), leg("JFK", "LAX")])
+        );
+        assert_eq!(
+            positional_legs(&["LHR", "JFK", "LAX", "SFO"]),
+            Ok(vec![
+                leg("LHR", "JFK"),
+                leg("JFK", "LAX"),
+                leg("LAX", "SFO")
+            ])
+        );
+        assert_eq!(positional_legs(&[]), Ok(Vec::new()));
+    }
+
+    #[test]
+    fn test_positional_airports_are_validated_like_flags() {
+        assert!(positional_legs(&["LHR"])
+            .unwrap_err()
+            .contains("LHR needs at least one more airport"));
+        assert!(positional_legs(&["LHR", "JF"])
+            .unwrap_err()
+            .contains("IATA codes should be exactly 3 letters"));
+        assert!(positional_legs(&["LHR", "JFK", "--from", "CDG", "--to", "NRT"]).is_err());
+        assert!(positional_legs(&["LHR", "LHR"]).is_err());
+
+        let args =
+            Args::try_parse_from(["carbon-footprint-cli", "LHR", "JFK", "--passengers", "2"])
+                .unwrap();
+        assert_eq!(args.passengers, Some(2));
+    }
+
+    #[test]
+    fn test_legs_from_repeated_from_to_flags() {
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--from",
+            "LHR",
+            "--to",
+            "JFK",
+            "--from",
+            "JFK",
+            "--to",
+            "LAX",
+            "--passengers",
+            "2",
+            "--cabin",
+            "business",
+        ])
+        .unwrap();
+
+        let legs = legs_from_args(&args).unwrap();
+
+        assert_eq!(args.passengers, Some(2));
+        assert_eq!(
+            legs,
+            vec![
+                Leg {
+                    departure_airport: "LHR".to_string(),
+                    destination_airport: "JFK".to_string(),
+                    cabin_class: Some("business".to_string()),
+                    passengers: None,
+                    aircraft: None,
+                },
+                Leg {
+                    departure_airport: "JFK".to_string(),
+                    destination_airport: "LAX".to_string(),
+                    cabin_class: Some("business".to_string()),
+                    passengers: None,
+                    aircraft: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_legs_from_leg_flags_are_appended_in_order() {
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--from",
+            "LHR",
+            "--to",
+            "JFK",
+            "--leg",
+            "JFK:SFO:first",
+            "--leg",
+            "SFO:LHR",
+        ])
+        .unwrap();
+
+        let legs = legs_from_args(&args).unwrap();
+
+        assert_eq!(
+            legs,
+            vec![
+                Leg {
+                    departure_airport: "LHR".to_string(),
+                    destination_airport: "JFK".to_string(),
+                    cabin_class: None,
+                    passengers: None,
+                    aircraft: None,
+                },
+                Leg {
+                    departure_airport: "JFK".to_string(),
+                    destination_airport: "SFO".to_string(),
+                    cabin_class: Some("first".to_string()),
+                    passengers: None,
+                    aircraft: None,
+                },
+                Leg {
+                    departure_airport: "SFO".to_string(),
+                    destination_airport: "LHR".to_string(),
+                    cabin_class: None,
+                    passengers: None,
+                    aircraft: None,
+                },
+            ]
+        );
+    }
+
+    #[test]
+    fn test_legs_from_args_rejects_unpaired_from() {
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--from",
+            "LHR",
+            "--from",
+            "JFK",
+            "--to",
+            "LAX",
+        ])
+        .unwrap();
+
+        assert!(matches!(
+            legs_from_args(&args),
+            Err(CliError::InvalidArguments(_))
+        ));
+    }
+
+    #[test]
+    fn test_parse_leg_rejects_malformed_specs() {
+        assert!(parse_leg("LHR").is_err());
+        assert!(parse_leg("LHR:J1K").is_err());
+        assert!(parse_leg("LHR:JFK:luxury").is_err());
+        assert!(parse_leg("LHR:JFK:economy:extra").is_err());
+    }
+
+    #[test]
+    fn test_aircraft_flag_applies_to_from_to_legs() {
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--from",
+            "LHR",
+            "--to",
+            "JFK",
+            "--aircraft",
+            "b789",
+        ])
+        .unwrap();
+
+        let legs = legs_from_args(&args).unwrap();
+
+        assert_eq!(legs[0].aircraft.as_deref(), Some("B789"));
+        assert!(parse_aircraft("Boeing 787").is_err());
+    }
+    #[test]
+    fn test_parse_mixed_leg() {
+        let leg = parse_mixed_leg("LHR:JFK:economy=2+Business=1").unwrap();
+
+        assert_eq!(leg.departure_airport, "LHR");
+        assert_eq!(leg.passengers(), 3);
+        assert_eq!(
+            leg.cabins,
+            vec![
+                CabinPassengers {
+                    cabin_class: "economy".to_string(),
+                    passengers: 2,
+                },
+                CabinPassengers {
+                    cabin_class: "business".to_string(),
+                    passengers: 1,
+                },
+            ]
+        );
+        assert!(parse_mixed_leg("LHR:JFK").is_err());
+        assert!(parse_mixed_leg("LHR:JFK:economy").is_err());
+        assert!(parse_mixed_leg("LHR:JFK:economy=0").is_err());
+        assert!(parse_mixed_leg("LHR:JFK:economy=1+economy=2").is_err());
+    }
+
+    #[test]
+    fn test_mixed_legs_replace_the_other_leg_flags() {
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--mixed-leg",
+            "LHR:JFK:economy=2+business=1",
+            "--passengers",
+            "3",
+        ])
+        .unwrap();
+
+        assert!(matches!(
+            mixed_cabin_legs(&args),
+            Err(CliError::InvalidArguments(_))
+        ));
+
+        let with_airports = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--mixed-leg",
+            "LHR:JFK:economy=2+business=1",
+            "CDG",
+            "NRT",
+        ])
+        .unwrap();
+        assert!(matches!(
+            mixed_cabin_legs(&with_airports),
+            Err(CliError::InvalidArguments(_))
+        ));
+    }
+
+    #[test]
+    fn test_mixed_round_trip_summary() {
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--mixed-leg",
+            "LHR:JFK:economy=2+business=1",
+            "--mixed-leg",
+            "JFK:LAX:economy=2",
+            "--round-trip",
+        ])
+        .unwrap();
+
+        let legs = mixed_cabin_legs(&args).unwrap();
+        let summary = mixed_cabin_summary(&args, &legs);
+
+        assert_eq!(legs.len(), 4);
+        assert_eq!(legs[2].departure_airport, "LAX");
+        assert_eq!(legs[3].cabins, legs[0].cabins);
+        assert_eq!(summary.passengers, 3);
+        assert_eq!(summary.legs[0].cabin_class, None);
+        assert_eq!(summary.legs[1].cabin_class.as_deref(), Some("economy"));
+    }
+
+    /// Flags when it's dropped, like a spinner being cleared.
+    struct Cleanup(Arc<AtomicBool>);
+
+    impl Drop for Cleanup {
+        fn drop(&mut self) {
+            self.0.store(true, Ordering::SeqCst);
+        }
+    }
+
+    #[tokio::test]
+    async fn test_interrupt_drops_the_work_and_exits_130() {
+        let cleaned_up = Arc::new(AtomicBool::new(false));
+        let cleanup = Cleanup(cleaned_up.clone());
+        let work = async move {
+            let _cleanup = cleanup;
+            // Stands in for a request that never answers
+            std::future::pending::<()>().await;
+        };
+
+        let output = race_interrupt(work, async { Ok(()) }).await;
+        let mut stderr = Vec::new();
+        let code = report_cancelled(&mut stderr);
+
+        assert!(output.is_none());
+        assert!(cleaned_up.load(Ordering::SeqCst));
+        assert_eq!(code, 130);
+        assert_eq!(String::from_utf8(stderr).unwrap(), "Cancelled.\n");
+    }
+
+    #[tokio::test]
+    async fn test_work_finishing_first_is_not_interrupted() {
+        let output = race_interrupt(async { 42 }, std::future::pending()).await;
+        assert_eq!(output, Some(42));
+
+        let failed_handler = async { Err(io::Error::other("no signal handler")) };
+        let output = race_interrupt(async { 42 }, failed_handler).await;
+        assert_eq!(output, Some(42));
+    }
+
+    /// Ticks straight away a fixed number of times, then stops the watch.
+    struct CountdownTicker(usize);
+
+    impl Ticker for CountdownTicker {
+        async fn tick(&mut self) -> bool {
+            let ticking = self.0 > 0;
+            self.0 = self.0.saturating_sub(1);
+            ticking
+        }
+    }
+
+    async fn watch_outcomes(
+        outcomes: Vec<Result<String, CliError>>,
+        redraw: bool,
+    ) -> (Result<(), CliError>, String, String) {
+        let mut ticker = CountdownTicker(outcomes.len());
+        let mut outcomes = outcomes.into_iter();
+        let (mut out, mut err) = (Vec::new(), Vec::new());
+        let result = watch_estimates(
+            &mut ticker,
+            || std::future::ready(outcomes.next().unwrap()),
+            redraw,
+            &mut out,
+            &mut err,
+        )
+        .await;
+        (
+            result,
+            String::from_utf8(out).unwrap(),
+            String::from_utf8(err).unwrap(),
+        )
+    }
+
+    #[tokio::test]
+    async fn test_watch_carries_on_after_a_transient_error() {
+        let (result, out, err) = watch_outcomes(
+            vec![
+                Ok("100.00".to_string()),
+                Err(CliError::ServerError(503)),
+                Ok("101.00".to_string()),
+            ],
+            false,
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert_eq!(out, "100.00\n101.00\n");
+        assert!(err.contains("503"));
+        assert!(err.contains("trying again on the next update"));
+    }
+
+    #[tokio::test]
+    async fn test_watch_redraws_each_result() {
+        let (result, out, err) = watch_outcomes(
+            vec![Ok("first".to_string()), Err(CliError::ServerError(502))],
+            true,
+        )
+        .await;
+
+        assert!(result.is_ok());
+        assert!(out.starts_with(&format!("{}first\n{}⚠️", CLEAR_SCREEN, CLEAR_SCREEN)));
+        assert!(err.is_empty());
+    }
+
+    #[tokio::test]
+    async fn test_watch_stops_at_an_error_that_would_repeat() {
+        let (result, out, _) = watch_outcomes(
+            vec![
+                Ok("100.00".to_string()),
+                Err(CliError::Unauthorized),
+                Ok("never shown".to_string()),
+            ],
+            false,
+        )
+        .await;
+
+        assert!(matches!(result, Err(CliError::Unauthorized)));
+        assert_eq!(out, "100.00\n");
+    }
+
+    #[test]
+    fn test_watch_only_repeats_a_single_flight() {
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--watch",
+            "60",
+            "--offline",
+            "--from",
+            "LHR",
+            "--to",
+            "JFK",
+        ])
+        .unwrap();
+
+        assert!(matches!(
+            check_watch(&args, &[]),
+            Err(CliError::InvalidArguments(_))
+        ));
+        assert!(Args::try_parse_from(["carbon-footprint-cli", "--watch", "0"]).is_err());
+    }
+
+    /// Keeps each `write` apart, to see what a reader of the stream gets at a time.
+    #[derive(Default)]
+    struct RecordingWriter(Vec<String>);
+
+    impl Write for RecordingWriter {
+        fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+            self.0.push(String::from_utf8_lossy(buf).into_owned());
+            Ok(buf.len())
+        }
+
+        fn flush(&mut self) -> io::Result<()> {
+            Ok(())
+        }
+    }
+
+    #[test]
+    fn test_stream_writes_each_result_whole() {
+        let mut csv = ResultStream::new(
+            RecordingWriter::default(),
+            OutputFormat::Csv,
+            &test_options(),
+        );
+        csv.write(&create_estimate(&[("LHR", "JFK")], 300.0));
+        csv.write(&create_estimate(&[("LHR", "CDG")], 50.0));
+        let mut json = ResultStream::new(
+            RecordingWriter::default(),
+            OutputFormat::Json,
+            &test_options(),
+        );
+        json.write(&create_estimate(&[("LHR", "JFK")], 300.0));
+
+        let writes = csv.out.0;
+        assert_eq!(writes.len(), 2);
+        assert_eq!(writes[0].lines().count(), 2);
+        assert!(writes[0].ends_with('\n'));
+        assert_eq!(writes[1].lines().count(), 1);
+        assert!(!writes[1].contains(writes[0].lines().next().unwrap()));
+        assert_eq!(json.out.0.len(), 1);
+        assert_eq!(json.out.0[0].lines().count(), 1);
+    }
+
+    #[test]
+    fn test_stream_rejects_formats_read_whole() {
+        let args = |format| {
+            Args::try_parse_from([
+                "carbon-footprint-cli",
+                "--input",
+                "trips.csv",
+                "--stream",
+                "--output",
+                format,
+            ])
+            .unwrap()
+        };
+
+        assert!(check_stream(&args("csv")).is_ok());
+        assert!(matches!(
+            check_stream(&args("table")),
+            Err(CliError::InvalidArguments(_))
+        ));
+        assert!(Args::try_parse_from(["carbon-footprint-cli", "--stream"]).is_err());
+    }
+
+    async fn attempts_against_unavailable_api(retry_args: &[&str]) -> usize {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(503))
+            .mount(&server)
+            .await;
+        let mut argv = vec!["carbon-footprint-cli", "--base-url"];
+        let uri = server.uri();
+        argv.push(&uri);
+        argv.extend(retry_args);
+        let args = Args::try_parse_from(argv).unwrap();
+
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let result = make_estimates_request(&api_client(&args, Client::new()), &request, "").await;
+
+        assert!(result.is_err());
+        server.received_requests().await.unwrap().len()
+    }
+
+    #[tokio::test]
+    async fn test_zero_retries_makes_a_single_attempt() {
+        assert_eq!(
+            attempts_against_unavailable_api(&["--retries", "0"]).await,
+            1
+        );
+    }
+
+    #[tokio::test]
+    async fn test_retries_flag_sets_the_number_of_attempts() {
+        let attempts =
+            attempts_against_unavailable_api(&["--retries", "2", "--retry-delay-ms", "1"]).await;
+        assert_eq!(attempts, 3);
+    }
+
+    #[test]
+    fn test_retry_flags_are_capped() {
+        assert!(Args::try_parse_from(["carbon-footprint-cli", "--retries", "11"]).is_err());
+        assert!(Args::try_parse_from(["carbon-footprint-cli", "--retries", "-1"]).is_err());
+        assert!(
+            Args::try_parse_from(["carbon-footprint-cli", "--retry-delay-ms", "60001"]).is_err()
+        );
+        assert!(Args::try_parse_from(["carbon-footprint-cli", "--retry-delay-ms", "-5"]).is_err());
+        let args = Args::try_parse_from(["carbon-footprint-cli"]).unwrap();
+        assert_eq!(args.retries, DEFAULT_MAX_RETRIES);
+        assert_eq!(args.retry_delay_ms, 500);
+    }
+
+    #[tokio::test]
+    async fn test_distance_unit_flag_is_sent_to_api() {
+        let server = MockServer::start().await;
+        let mock_response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "mi", 3451.5);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(body_string_contains(r#""distance_unit":"mi""#))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
+            .expect(1)
+            .mount(&server)
+            .await;
+
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--from",
+            "LHR",
+            "--to",
+            "JFK",
+            "--distance-unit",
+            "mi",
+        ])
+        .unwrap();
+        let request = request_from_args(&args).unwrap().unwrap();
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+
+        let response = make_estimates_request(&api_client, &request, "").await;
+
+        let estimate = response.unwrap().data.unwrap().attributes;
+        assert_eq!(estimate.distance_unit, "mi");
+    }
+
+    #[tokio::test]
+    async fn test_zero_distance_warns_or_fails_under_strict() {
+        let server = MockServer::start().await;
+        let mock_response = create_mock_response(0.0, 0.0, 0.0, 0.0, "km", 0.0);
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(ResponseTemplate::new(200).set_body_json(&mock_response))
+            .mount(&server)
+            .await;
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+        let request = create_request(1, &[("LHR", "JFK")]);
+
+        let response = make_estimates_request(&api_client, &request, "").await;
+        let attributes = response.unwrap().data.unwrap().attributes;
+
+        let warning = zero_distance_warning(&attributes).unwrap();
+        assert!(warning.starts_with("the distance came back as 0 km"));
+        let lenient = Args::try_parse_from(["carbon-footprint-cli"]).unwrap();
+        assert!(check_distance(&lenient, &attributes).is_ok());
+        assert!(passes_distance_checks(&lenient, &attributes));
+
+        let strict = Args::try_parse_from(["carbon-footprint-cli", "--strict"]).unwrap();
+        let error = check_distance(&strict, &attributes).unwrap_err();
+        assert!(matches!(&error, CliError::ImplausibleResponse(message) if *message == warning));
+        assert_eq!(exit_code_for(&error), 1);
+        assert!(!passes_distance_checks(&strict, &attributes));
+
+        let mut flown = attributes.clone();
+        flown.distance_value = 5540.0;
+        assert_eq!(zero_distance_warning(&flown), None);
+        assert!(check_distance(&strict, &flown).is_ok());
+
+        let options = render_options(&strict, Currency::usd());
+        let per_leg = run_per_leg(&api_client, &request, "", &strict, &options).await;
+        assert!(matches!(per_leg, Err(CliError::ImplausibleResponse(_))));
+    }
+
+    #[tokio::test]
+    async fn test_dry_run_prints_the_request_without_calling_the_api() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .respond_with(ResponseTemplate::new(200))
+            .expect(0)
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config.toml");
+        fs::write(&config, "").unwrap();
+        let output_file = dir.path().join("request.json");
+
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--api-key",
+            "test",
+            "--base-url",
+            &server.uri(),
+            "--config",
+            config.to_str().unwrap(),
+            "--output-file",
+            output_file.to_str().unwrap(),
+            "--from",
+            "LHR",
+            "--to",
+            "JFK",
+            "--dry-run",
+        ])
+        .unwrap();
+        run(args).await.unwrap();
+
+        let printed: FlightEstimateRequest =
+            serde_json::from_str(&fs::read_to_string(&output_file).unwrap()).unwrap();
+        assert_eq!(printed.legs[0].departure_airport, "LHR");
+        assert_eq!(printed.passengers, 1);
+    }
+
+    #[test]
+    fn test_distance_unit_flag_rejects_unknown_units() {
+        let result = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--from",
+            "LHR",
+            "--to",
+            "JFK",
+            "--distance-unit",
+            "furlongs",
+        ]);
+
+        assert!(result.is_err());
+    }
+
+    fn test_options() -> RenderOptions {
+        RenderOptions {
+            offset_price: DEFAULT_OFFSET_PRICE,
+            currency: Currency::usd(),
+            quiet: false,
+            units: Vec::new(),
+            show_equivalencies: true,
+            compare_alternatives: false,
+            compact: false,
+            rf_multiplier: DEFAULT_RF_MULTIPLIER,
+            precision: DEFAULT_PRECISION,
+        }
+    }
+
+    fn create_request(passengers: u32, route: &[(&str, &str)]) -> FlightEstimateRequest {
+        FlightEstimateRequest {
+            estimate_type: "flight".to_string(),
+            passengers,
+            legs: route
+                .iter()
+                .map(|(departure, destination)| Leg {
+                    departure_airport: departure.to_string(),
+                    destination_airport: destination.to_string(),
+                    cabin_class: None,
+                    passengers: None,
+                    aircraft: None,
+                })
+                .collect(),
+            distance_unit: None,
+        }
+    }
+
+    fn create_estimate(route: &[(&str, &str)], carbon_kg: f32) -> EstimateResult {
+        EstimateResult {
+            request: create_request(1, route),
+            attributes: create_mock_response(
+                carbon_kg * 1000.0,
+                carbon_kg * 2.2,
+                carbon_kg,
+                carbon_kg / 1000.0,
+                "km",
+                5660.0,
+            )
+            .data
+            .unwrap()
+            .attributes,
+        }
+    }
+
+    #[test]
+    fn test_login_saves_and_logout_removes_the_key() {
+        let store = MemoryStore::default();
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--api-key",
+            "Xk3mD9pQ2rT7vW1yZ5bN8c",
+            "login",
+        ])
+        .unwrap();
+
+        login(&args, &store).unwrap();
+        assert_eq!(
+            stored_api_key(&store).as_deref(),
+            Some("Xk3mD9pQ2rT7vW1yZ5bN8c")
+        );
+
+        logout(&store).unwrap();
+        assert_eq!(stored_api_key(&store), None);
+        logout(&store).unwrap();
+    }
+
+    #[test]
+    fn test_login_rejects_malformed_keys() {
+        let store = MemoryStore::default();
+        let args =
+            Args::try_parse_from(["carbon-footprint-cli", "--api-key", "short", "login"]).unwrap();
+
+        assert!(matches!(
+            login(&args, &store),
+            Err(CliError::InvalidArguments(_))
+        ));
+        assert_eq!(stored_api_key(&store), None);
+    }
+
+    #[test]
+    fn test_display_distance_converts_the_reported_distance() {
+        let mut attributes = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "mi", 100.0)
+            .data
+            .unwrap()
+            .attributes;
+        let unchanged = Args::try_parse_from(["carbon-footprint-cli"]).unwrap();
+        convert_for_display(&unchanged, &mut attributes);
+        assert_eq!(attributes.distance_unit, "mi");
+
+        let args =
+            Args::try_parse_from(["carbon-footprint-cli", "--display-distance", "km"]).unwrap();
+        convert_for_display(&args, &mut attributes);
+        assert_eq!(attributes.distance_unit, "km");
+        assert!((attributes.distance_value - 160.934).abs() < 0.001);
+        assert_eq!(attributes.carbon_kg, 99911.7);
+
+        assert!(
+            Args::try_parse_from(["carbon-footprint-cli", "--display-distance", "ft"]).is_err()
+        );
+    }
+
+    #[tokio::test]
+    async fn test_log_file_records_requests_without_the_api_key() {
+        let dir = tempfile::tempdir().unwrap();
+        let log_path = dir.path().join("logs").join("cli.log");
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(create_mock_response(
+                    99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34,
+                )),
+            )
+            .mount(&server)
+            .await;
+        // The only test that installs the process-wide logger
+        init_logging(0, Some(&log_path)).unwrap();
+
+        let api_key = "Xk3mD9pQ2rT7vW1yZ5bN8c";
+        let api_client = ApiClient::new(Client::new(), &server.uri());
+        let request = create_request(1, &[("LHR", "JFK")]);
+        make_estimates_request(&api_client, &request, api_key)
+            .await
+            .unwrap();
+        log::logger().flush();
+
+        let logs = fs::read_to_string(&log_path).unwrap();
+        let request_line = logs
+            .lines()
+            .find(|line| line.contains("POST ") && line.contains("/api/v1/estimates"))
+            .expect("the request should be logged");
+        assert!(request_line.contains(" DEBUG carbon_footprint_cli"));
+        assert!(request_line[..4].chars().all(|c| c.is_ascii_digit()));
+        assert!(!logs.contains(api_key));
+    }
+
+    #[test]
+    fn test_oversized_log_file_is_rotated() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("cli.log");
+        fs::write(&path, vec![b'x'; MAX_LOG_FILE_BYTES as usize + 1]).unwrap();
+
+        open_log_file(&path).unwrap();
+
+        assert_eq!(fs::metadata(&path).unwrap().len(), 0);
+        assert_eq!(
+            fs::metadata(dir.path().join("cli.log.1")).unwrap().len(),
+            MAX_LOG_FILE_BYTES + 1
+        );
+    }
+
+    #[test]
+    fn test_check_budget() {
+        let unlimited = Args::try_parse_from(["carbon-footprint-cli"]).unwrap();
+        assert!(check_budget(&unlimited, 1_000_000.0).is_ok());
+
+        let args = Args::try_parse_from(["carbon-footprint-cli", "--budget-kg", "500"]).unwrap();
+        assert!(check_budget(&args, 500.0).is_ok());
+        let error = check_budget(&args, 512.25).unwrap_err();
+        assert!(matches!(
+            error,
+            CliError::BudgetExceeded {
+                total_kg,
+                budget_kg
+            } if total_kg == 512.25 && budget_kg == 500.0
+        ));
+        assert_eq!(
+            error.to_string(),
+            "Over budget: 512.25 kg CO2 is more than your 500.00 kg budget"
+        );
+    }
+
+    fn args_with_thresholds(flags: &[&str]) -> Args {
+        let config = Config {
+            thresholds: Some(Thresholds {
+                warn_carbon_kg: Some(500.0),
+                error_carbon_kg: Some(1000.0),
+                short_flight_km: Some(400.0),
+                ..Thresholds::default()
+            }),
+            ..Config::default()
+        };
+        let mut args = Args::try_parse_from(
+            std::iter::once("carbon-footprint-cli").chain(flags.iter().copied()),
+        )
+        .unwrap();
+        args.apply_config(config).unwrap();
+        args
+    }
+
+    #[test]
+    fn test_short_legs_use_each_legs_distance() {
+        let connecting = create_estimate(&[("LHR", "MAN"), ("MAN", "JFK")], 900.0);
+        let short = short_legs(
+            &connecting.request,
+            &connecting.attributes,
+            DEFAULT_SHORT_FLIGHT_KM,
+        );
+        assert_eq!(short.len(), 1);
+        assert_eq!(short[0].0, 0);
+        assert!(short[0].1 > 200.0 && short[0].1 < 300.0);
+
+        let mut direct = create_estimate(&[("LHR", "JFK")], 300.0);
+        assert!(
+            short_legs(&direct.request, &direct.attributes, DEFAULT_SHORT_FLIGHT_KM).is_empty()
+        );
+        direct.attributes.distance_value = 500.0;
+        assert!(
+            short_legs(&direct.request, &direct.attributes, DEFAULT_SHORT_FLIGHT_KM).is_empty()
+        );
+        direct.attributes.distance_value = 250.0;
+        direct.attributes.distance_unit = "mi".to_string();
+        assert_eq!(
+            short_legs(&direct.request, &direct.attributes, DEFAULT_SHORT_FLIGHT_KM),
+            vec![(0, 250.0 * 1.609_344)]
+        );
+    }
+
+    #[test]
+    fn test_short_flight_km_comes_from_the_config() {
+        assert_eq!(args_with_thresholds(&[]).short_flight_km(), 400.0);
+        assert_eq!(
+            args_with_thresholds(&["--short-flight-km", "600"]).short_flight_km(),
+            600.0
+        );
+        let args = Args::try_parse_from(["carbon-footprint-cli"]).unwrap();
+        assert_eq!(args.short_flight_km(), DEFAULT_SHORT_FLIGHT_KM);
+    }
+
+    #[test]
+    fn test_config_thresholds_apply_without_flags() {
+        let args = args_with_thresholds(&[]);
+
+        assert!(check_budget(&args, 499.0).is_ok());
+
+        let warning = check_budget(&args, 750.0).unwrap_err();
+        assert!(matches!(warning, CliError::OverWarningLevel { warn_kg, .. } if warn_kg == 500.0));
+        assert_eq!(exit_code_for(&warning), 6);
+        assert_eq!(
+            warning.to_string(),
+            "750.00 kg CO2 is over the 500.00 kg warning level"
+        );
+
+        let error = check_budget(&args, 1200.0).unwrap_err();
+        assert!(matches!(error, CliError::BudgetExceeded { budget_kg, .. } if budget_kg == 1000.0));
+        assert_eq!(exit_code_for(&error), 3);
+    }
+
+    #[test]
+    fn test_threshold_flags_override_the_config() {
+        let args = args_with_thresholds(&["--warn-carbon-kg", "800", "--budget-kg", "2000"]);
+
+        assert!(check_budget(&args, 750.0).is_ok());
+        assert!(matches!(
+            check_budget(&args, 1200.0),
+            Err(CliError::OverWarningLevel { .. })
+        ));
+    }
+
+    #[test]
+    fn test_budget_uses_the_session_total() {
+        let estimates = [
+            create_estimate(&[("LHR", "JFK")], 300.0),
+            create_estimate(&[("JFK", "LAX")], 250.0),
+        ];
+        let args = Args::try_parse_from(["carbon-footprint-cli", "--budget-kg", "500"]).unwrap();
+
+        let attributes = estimates.iter().map(|estimate| &estimate.attributes);
+        assert_eq!(total_kg(&args, attributes.clone()), 550.0);
+        assert!(check_budget(&args, total_kg(&args, attributes)).is_err());
+    }
+
+    #[test]
+    fn test_missing_figures_fail_limits_instead_of_passing_them() {
+        let mut partial = create_estimate(&[("LHR", "JFK")], 300.0).attributes;
+        partial.carbon_kg = MISSING_FIGURE;
+        let known = create_estimate(&[("JFK", "LAX")], 250.0).attributes;
+        let budget = Args::try_parse_from(["carbon-footprint-cli", "--budget-kg", "500"]).unwrap();
+
+        assert_eq!(total_kg(&budget, [&partial, &known]), 250.0);
+        assert!(matches!(
+            check_budget(&budget, total_kg(&budget, [&partial])),
+            Err(CliError::UncheckedLimit {
+                limit: "--budget-kg",
+                ..
+            })
+        ));
+        let warn_only =
+            Args::try_parse_from(["carbon-footprint-cli", "--warn-carbon-kg", "500"]).unwrap();
+        assert!(check_budget(&warn_only, MISSING_FIGURE).is_ok());
+
+        partial.distance_value = MISSING_FIGURE;
+        let max_distance =
+            Args::try_parse_from(["carbon-footprint-cli", "--max-distance", "20000"]).unwrap();
+        assert!(matches!(
+            check_distance(&max_distance, &partial),
+            Err(CliError::UncheckedLimit { .. })
+        ));
+        assert!(!passes_distance_checks(&max_distance, &partial));
+        let unlimited = Args::try_parse_from(["carbon-footprint-cli"]).unwrap();
+        assert!(check_distance(&unlimited, &partial).is_ok());
+    }
+
+    #[test]
+    fn test_total_subcommand_parses_its_period() {
+        let args = Args::try_parse_from(["carbon-footprint-cli", "total", "--since", "2024-01-01"])
+            .unwrap();
+        assert_eq!(
+            args.command,
+            Some(Command::Total {
+                since: NaiveDate::from_ymd_opt(2024, 1, 1),
+                year: None
+            })
+        );
+
+        assert!(
+            Args::try_parse_from(["carbon-footprint-cli", "total", "--since", "01/01/2024"])
+                .is_err()
+        );
+    }
+
+    #[test]
+    fn test_format_total() {
+        let total = HistoryTotal {
+            flights: 3,
+            carbon_kg: 1110.0,
+        };
+
+        assert_eq!(
+            format_total(&total, None, Some(2024)),
+            "In 2024: 1110.00 kg CO2 across 3 flight(s)"
+        );
+        assert_eq!(
+            format_total(&total, NaiveDate::from_ymd_opt(2024, 3, 1), None),
+            "Since 2024-03-01: 1110.00 kg CO2 across 3 flight(s)"
+        );
+        assert!(format_total(&total, None, None).starts_with("All time:"));
+    }
+
+    #[test]
+    fn test_is_plausible_api_key() {
+        assert!(!is_plausible_api_key(""));
+        assert!(!is_plausible_api_key("abc123"));
+        assert!(!is_plausible_api_key("ab12cd34 ef56gh78"));
+        assert!(!is_plausible_api_key("ab12cd34ef56\tgh78"));
+        assert!(is_plausible_api_key("Xk3mD9pQ2rT7vW1yZ5bN8c"));
+        assert!(is_plausible_api_key("abc-123_DEF.456"));
+    }
+
+    #[test]
+    fn test_api_key_prompt_ends_on_closed_input_or_read_error() {
+        let mut answers = vec!["abc123".to_string(), String::new()].into_iter();
+        let closed = read_api_key(|| Ok(answers.next().unwrap()), None);
+        assert!(matches!(closed, Err(CliError::InputClosed)));
+
+        let no_tty = read_api_key(|| Err(io::Error::from(io::ErrorKind::NotFound)), None);
+        assert!(matches!(no_tty, Err(CliError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_resolve_api_key_precedence() {
+        let mut from_env = Args::try_parse_from(["carbon-footprint-cli"]).unwrap();
+        from_env.env_api_key = Some("env-key".to_string());
+        let mut from_flag =
+            Args::try_parse_from(["carbon-footprint-cli", "--api-key", "flag-key"]).unwrap();
+        from_flag.env_api_key = Some("env-key".to_string());
+        let mut empty_env = Args::try_parse_from(["carbon-footprint-cli"]).unwrap();
+        empty_env.env_api_key = Some(String::new());
+
+        assert_eq!(
+            resolve_api_key(&from_env).unwrap(),
+            Some("env-key".to_string())
+        );
+        assert_eq!(
+            resolve_api_key(&from_flag).unwrap(),
+            Some("flag-key".to_string())
+        );
+        assert_eq!(resolve_api_key(&empty_env).unwrap(), None);
+    }
+
+    #[test]
+    fn test_api_key_file_is_trimmed_and_ranks_between_the_flag_and_env() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("api-key");
+        fs::write(&path, "  Xk3mD9pQ2rT7vW1yZ5bN8c \r\nsecond line\n").unwrap();
+        let path = path.to_str().unwrap();
+
+        let mut from_file =
+            Args::try_parse_from(["carbon-footprint-cli", "--api-key-file", path]).unwrap();
+        from_file.env_api_key = Some("env-key".to_string());
+        assert_eq!(
+            resolve_api_key(&from_file).unwrap(),
+            Some("Xk3mD9pQ2rT7vW1yZ5bN8c".to_string())
+        );
+
+        let from_flag = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--api-key",
+            "flag-key",
+            "--api-key-file",
+            path,
+        ])
+        .unwrap();
+        assert_eq!(
+            resolve_api_key(&from_flag).unwrap(),
+            Some("flag-key".to_string())
+        );
+    }
+
+    #[test]
+    fn test_missing_or_empty_api_key_file_is_an_error() {
+        let dir = tempfile::tempdir().unwrap();
+        let empty = dir.path().join("empty");
+        fs::write(&empty, "\n").unwrap();
+
+        for path in [empty, dir.path().join("missing")] {
+            let args = Args::try_parse_from([
+                "carbon-footprint-cli",
+                "--api-key-file",
+                path.to_str().unwrap(),
+            ])
+            .unwrap();
+
+            assert!(matches!(
+                resolve_api_key(&args),
+                Err(CliError::InvalidArguments(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_parse_base_url_strips_trailing_slashes() {
+        assert_eq!(
+            parse_base_url("https://www.carboninterface.com/").unwrap(),
+            "https://www.carboninterface.com"
+        );
+        assert_eq!(
+            parse_base_url("http://localhost:8080/proxy//").unwrap(),
+            "http://localhost:8080/proxy"
+        );
+        assert_eq!(
+            parse_base_url("https://www.carboninterface.com").unwrap(),
+            "https://www.carboninterface.com"
+        );
+    }
+
+    #[test]
+    fn test_parse_base_url_rejects_invalid_urls() {
+        assert!(parse_base_url("not a url").is_err());
+        assert!(parse_base_url("ftp://example.com").is_err());
+    }
+
+    #[test]
+    fn test_parse_currency_uppercases_iso_codes() {
+        assert_eq!(parse_currency("eur"), Ok("EUR".to_string()));
+        assert!(parse_currency("EURO").is_err());
+        assert!(parse_currency("E1R").is_err());
+    }
+    #[test]
+    fn test_passenger_count_must_be_at_least_one() {
+        assert!(!is_valid_passenger_count("0"));
+        assert!(is_valid_passenger_count("1"));
+        assert!(!is_valid_passenger_count("-1"));
+        assert!(!is_valid_passenger_count("two"));
+    }
+
+    #[test]
+    fn test_passengers_flag_rejects_zero() {
+        let result = Args::try_parse_from(["carbon-footprint-cli", "--passengers", "0"]);
+
+        let error = result.unwrap_err().to_string();
+        assert!(error.contains("Number of passengers must be at least 1."));
+    }
+    #[test]
+    fn test_unknown_airport_codes_need_override() {
+        assert_eq!(check_airport_code("LHR", false), Ok(()));
+        assert_eq!(
+            check_airport_code("ZZZ", false),
+            Err(
+                "❌ ZZZ isn't in the bundled airport list; pass --allow-unknown-iata to use it anyway."
+                    .to_string()
+            )
+        );
+        assert_eq!(check_airport_code("ZZZ", true), Ok(()));
+        assert_eq!(
+            check_airport_code("ZZ", true),
+            Err(t("error.airport").to_string())
+        );
+    }
+
+    #[test]
+    fn test_per_leg_is_only_for_single_flights() {
+        let args = |extra: &[&str]| {
+            Args::try_parse_from(["carbon-footprint-cli", "--per-leg"].iter().chain(extra)).unwrap()
+        };
+
+        assert!(check_per_leg(&args(&["--leg", "LHR:JFK"]), &[]).is_ok());
+        assert!(matches!(
+            check_per_leg(&args(&["--input", "trips.json"]), &[]),
+            Err(CliError::InvalidArguments(_))
+        ));
+        assert!(check_per_leg(&args(&["--offline"]), &[]).is_err());
+        assert!(check_per_leg(&args(&["history"]), &[]).is_err());
+    }
+
+    #[test]
+    fn test_metro_codes_resolve_unless_disabled() {
+        let args = Args::try_parse_from(["carbon-footprint-cli", "--leg", "LON:NYC"]).unwrap();
+        let legs = legs_from_args(&args).unwrap();
+        assert_eq!(legs[0].departure_airport, "LHR");
+        assert_eq!(legs[0].destination_airport, "JFK");
+
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--leg",
+            "LON:NYC",
+            "--no-metro-resolution",
+            "--allow-unknown-iata",
+        ])
+        .unwrap();
+        let legs = legs_from_args(&args).unwrap();
+        assert_eq!(legs[0].departure_airport, "LON");
+    }
+
+    #[test]
+    fn test_batch_entries_resolve_metro_codes() {
+        let stdin = r#"[
+            {"passengers": 1, "legs": [{"departure_airport": "LON", "destination_airport": "NYC"}]},
+            {"passengers": 1, "legs": [{"departure_airport": "LON", "destination_airport": "LHR"}]}
+        ]"#;
+        let args = Args::try_parse_from(["carbon-footprint-cli"]).unwrap();
+
+        let requests = read_batch_requests(Path::new("-"), &args, io::Cursor::new(stdin)).unwrap();
+
+        // LON becomes LHR, so the second entry goes nowhere and is skipped
+        assert_eq!(requests.len(), 1);
+        let leg = &requests[0].1.request.legs[0];
+        assert_eq!(
+            (
+                leg.departure_airport.as_str(),
+                leg.destination_airport.as_str()
+            ),
+            ("LHR", "JFK")
+        );
+
+        let unresolved =
+            Args::try_parse_from(["carbon-footprint-cli", "--no-metro-resolution"]).unwrap();
+        let requests =
+            read_batch_requests(Path::new("-"), &unresolved, io::Cursor::new(stdin)).unwrap();
+        assert_eq!(requests[0].1.request.legs[0].departure_airport, "LON");
+    }
+
+    #[test]
+    fn test_legs_from_args_rejects_unknown_airport_without_override() {
+        let strict = Args::try_parse_from(["carbon-footprint-cli", "--leg", "LHR:ZZZ"]).unwrap();
+        let lenient = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--leg",
+            "LHR:ZZZ",
+            "--allow-unknown-iata",
+        ])
+        .unwrap();
+
+        assert!(matches!(
+            legs_from_args(&strict),
+            Err(CliError::InvalidArguments(_))
+        ));
+        assert_eq!(legs_from_args(&lenient).unwrap().len(), 1);
+    }
+
+    #[test]
+    fn test_write_output_file_creates_parent_directories() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("reports").join("estimate.json");
+        let request = create_request(100, &[("LHR", "JFK")]);
+        let response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        let attributes = response.data.unwrap().attributes;
+        let rendered = render_estimate(&request, &attributes, OutputFormat::Json, &test_options());
+
+        write_output_file(&path, &rendered, false).unwrap();
+
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["carbon_kg"].as_f64().unwrap() as f32, 99911.7);
+        assert_eq!(written["distance_unit"], "km");
+    }
+
+    #[test]
+    fn test_write_output_file_requires_force_to_overwrite() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("estimate.txt");
+        fs::write(&path, "old").unwrap();
+
+        let refused = write_output_file(&path, "new", false);
+        assert!(matches!(refused, Err(CliError::OutputFileExists(_))));
+        assert_eq!(fs::read_to_string(&path).unwrap(), "old");
+
+        write_output_file(&path, "new", true).unwrap();
+        assert_eq!(fs::read_to_string(&path).unwrap(), "new\n");
+    }
+    #[test]
+    fn test_get_user_input_gives_up_after_reprompt_limit() {
+        let input = "abc\nxyz\nLHR\n".as_bytes();
+        let mut prompter = Prompter::new(input, Some(1));
+
+        let result = prompter.get_user_input("Code: ", "Invalid.", |input| input == "LHR");
+
+        assert!(matches!(result, Err(CliError::TooManyInvalidInputs)));
+    }
+
+    #[test]
+    fn test_get_user_input_accepts_answer_within_reprompt_limit() {
+        let input = "abc\nxyz\nLHR\n".as_bytes();
+        let mut prompter = Prompter::new(input, Some(2));
+
+        let result = prompter.get_user_input("Code: ", "Invalid.", |input| input == "LHR");
+
+        assert_eq!(result.unwrap(), "LHR");
+    }
+
+    #[test]
+    fn test_get_airport_accepts_codes_and_city_names() {
+        let input = "JFK\nheathrow\n".as_bytes();
+        let mut prompter = Prompter::new(input, Some(0));
+
+        assert_eq!(prompter.get_airport("From: ", false).unwrap(), "JFK");
+        assert_eq!(prompter.get_airport("From: ", false).unwrap(), "LHR");
+        // "3" isn't on the two-item Paris menu, so it is asked again
+        let mut prompter = Prompter::new("paris\n3\n2\n".as_bytes(), Some(1));
+        assert_eq!(prompter.get_airport("From: ", false).unwrap(), "ORY");
+    }
+
+    #[test]
+    fn test_get_airport_uppercases_typed_codes() {
+        let mut prompter = Prompter::new("lhr\nXyz\n".as_bytes(), Some(0));
+
+        assert_eq!(prompter.get_airport("From: ", false).unwrap(), "LHR");
+        assert_eq!(prompter.get_airport("From: ", true).unwrap(), "XYZ");
+    }
+
+    #[test]
+    fn test_get_airport_resolves_metro_codes_unless_disabled() {
+        let mut prompter = Prompter::new("nyc\nLON\n".as_bytes(), Some(0));
+        assert_eq!(prompter.get_airport("From: ", false).unwrap(), "JFK");
+        assert_eq!(prompter.get_airport("From: ", true).unwrap(), "LHR");
+
+        let mut prompter = Prompter::new("nyc\n".as_bytes(), Some(0));
+        prompter.resolve_metro = false;
+        assert_eq!(prompter.get_airport("From: ", true).unwrap(), "NYC");
+    }
+
+    #[test]
+    fn test_airport_flags_are_uppercased() {
+        let args =
+            Args::try_parse_from(["carbon-footprint-cli", "--from", "lhr", "--to", "Jfk"]).unwrap();
+
+        assert_eq!(args.from, ["LHR"]);
+        assert_eq!(args.to, ["JFK"]);
+        assert!(Args::try_parse_from(["carbon-footprint-cli", "--from", "L4R"]).is_err());
+    }
+
+    #[test]
+    fn test_get_airport_rejects_names_without_matches() {
+        let mut prompter = Prompter::new("atlantis\n".as_bytes(), Some(0));
+
+        let result = prompter.get_airport("From: ", false);
+
+        assert!(matches!(result, Err(CliError::TooManyInvalidInputs)));
+    }
+
+    #[test]
+    fn test_format_itinerary_summary() {
+        let mut request = create_request(2, &[("LHR", "JFK"), ("JFK", "LAX")]);
+        request.legs[1].cabin_class = Some("business".to_string());
+
+        assert_eq!(
+            format_itinerary_summary(&request),
+            "📋 Your trip:\n  1. LHR → JFK (economy)\n  2. JFK → LAX (business)\n\
+             Passengers: 2\nDistance unit: km"
+        );
+    }
+
+    #[test]
+    fn test_format_diagnostics() {
+        let diagnostics = RequestDiagnostics {
+            duration: Duration::from_millis(1250),
+            bytes_sent: 118,
+            bytes_received: 402,
+        };
+
+        assert_eq!(
+            format_diagnostics(&diagnostics),
+            "⏱ API call took 1250 ms (sent 118 bytes, received 402 bytes)"
+        );
+    }
+
+    #[test]
+    fn test_confirm_defaults_to_no() {
+        let mut prompter = Prompter::new("y\nYes\n\nn\nsure\n".as_bytes(), None);
+
+        let answers: Vec<bool> = (0..5)
+            .map(|_| prompter.confirm("Submit? ").unwrap())
+            .collect();
+
+        assert_eq!(answers, [true, true, false, false, false]);
+    }
+
+    #[test]
+    fn test_closed_input_stops_prompting() {
+        let mut prompter = Prompter::new("".as_bytes(), None);
+
+        let result = prompter.get_user_input("Airport? ", "Try again", |_| true);
+
+        assert!(matches!(result, Err(CliError::InputClosed)));
+        assert!(matches!(
+            prompter.confirm("Submit? "),
+            Err(CliError::InputClosed)
+        ));
+    }
+
+    #[test]
+    fn test_parse_menu_command() {
+        assert_eq!(parse_menu_command("a", 0), Some(MenuCommand::Add));
+        assert_eq!(parse_menu_command("R 2", 2), Some(MenuCommand::Remove(1)));
+        assert_eq!(parse_menu_command("edit 1", 1), Some(MenuCommand::Edit(0)));
+        assert_eq!(parse_menu_command("v", 0), Some(MenuCommand::View));
+        assert_eq!(parse_menu_command("d", 3), Some(MenuCommand::Done));
+        assert_eq!(parse_menu_command("r 3", 2), None);
+        assert_eq!(parse_menu_command("e 0", 2), None);
+        assert_eq!(parse_menu_command("e", 2), None);
+        assert_eq!(parse_menu_command("a 1", 2), None);
+        assert_eq!(parse_menu_command("x", 2), None);
+    }
+
+    #[test]
+    fn test_apply_leg_edit() {
+        let mut legs = create_request(1, &[("LHR", "JFK"), ("JFK", "LAX")]).legs;
+        let replacement = create_request(1, &[("JFK", "SFO")]).legs.remove(0);
+
+        apply_leg_edit(&mut legs, LegEdit::Replace(1, replacement.clone()));
+        apply_leg_edit(&mut legs, LegEdit::Remove(0));
+        apply_leg_edit(&mut legs, LegEdit::Add(replacement.clone()));
+
+        assert_eq!(legs, vec![replacement.clone(), replacement]);
+    }
+
+    #[test]
+    fn test_prompt_leg_asks_again_for_a_destination_that_is_the_departure() {
+        let input = "LHR\nLHR\nn\nJFK\neconomy\n\
+                     LHR\nLHR\ny\neconomy\n"
+            .as_bytes();
+        let mut prompter = Prompter::new(input, Some(0));
+
+        let declined = prompt_leg(&mut prompter, false, false).unwrap();
+        let confirmed = prompt_leg(&mut prompter, false, false).unwrap();
+
+        assert_eq!(declined.destination_airport, "JFK");
+        assert_eq!(confirmed.destination_airport, "LHR");
+    }
+
+    #[test]
+    fn test_edit_legs_fixes_a_typo_before_submitting() {
+        let input = "d\n\
+                     a\nLHR\nJFK\neconomy\n\
+                     a\nJFK\nLAS\nfirst\n\
+                     e 2\nJFK\nLAX\nbusiness\n\
+                     v\n\
+                     a\nLAX\nSFO\neconomy\n\
+                     r 3\n\
+                     d\n"
+        .as_bytes();
+        let mut prompter = Prompter::new(input, Some(0));
+
+        let legs = edit_legs(&mut prompter, false, false).unwrap();
+
+        let route: Vec<(&str, &str, Option<&str>)> = legs
+            .iter()
+            .map(|leg| {
+                (
+                    leg.departure_airport.as_str(),
+                    leg.destination_airport.as_str(),
+                    leg.cabin_class.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            route,
+            [
+                ("LHR", "JFK", Some("economy")),
+                ("JFK", "LAX", Some("business"))
+            ]
+        );
+    }
+
+    #[test]
+    fn test_spinner_only_on_interactive_text_output() {
+        let text = Args::try_parse_from(["carbon-footprint-cli"]).unwrap();
+        let json = Args::try_parse_from(["carbon-footprint-cli", "--output", "json"]).unwrap();
+        let quiet = Args::try_parse_from(["carbon-footprint-cli", "--quiet"]).unwrap();
+
+        assert!(show_spinner(&text, true));
+        assert!(!show_spinner(&text, false));
+        assert!(!show_spinner(&json, true));
+        assert!(!show_spinner(&quiet, true));
+    }
+
+    #[test]
+    fn test_unit_flag_is_repeatable() {
+        let args = Args::try_parse_from(["carbon-footprint-cli", "--unit", "g,lb", "--unit", "mt"])
+            .unwrap();
+
+        assert_eq!(args.units, [CarbonUnit::G, CarbonUnit::Lb, CarbonUnit::Mt]);
+        assert!(Args::try_parse_from(["carbon-footprint-cli", "--unit", "oz"]).is_err());
+    }
+
+    #[test]
+    fn test_banner_is_skipped_when_quiet() {
+        let text = Args::try_parse_from(["carbon-footprint-cli"]).unwrap();
+        let quiet = Args::try_parse_from(["carbon-footprint-cli", "-q"]).unwrap();
+        let json = Args::try_parse_from(["carbon-footprint-cli", "--output", "json"]).unwrap();
+
+        let mut out = Vec::new();
+        print_intro(&text, true, &mut out);
+        assert!(String::from_utf8(out).unwrap().contains("WELCOME"));
+
+        for args in [quiet, json] {
+            let mut out = Vec::new();
+            print_intro(&args, true, &mut out);
+            assert!(out.is_empty());
+        }
+    }
+
+    #[test]
+    fn test_bash_completions_cover_the_flags() {
+        let args = Args::try_parse_from(["carbon-footprint-cli", "completions", "bash"]).unwrap();
+        let Some(Command::Completions { shell }) = args.command else {
+            panic!("expected the completions subcommand");
+        };
+
+        let mut out = Vec::new();
+        write_completions(shell, &mut out);
+        let script = String::from_utf8(out).unwrap();
+
+        assert!(script.contains("carbon-footprint-cli"));
+        assert!(script.contains("--passengers"));
+        assert!(script.contains("compare"));
+        assert!(Args::try_parse_from(["carbon-footprint-cli", "completions", "tcsh"]).is_err());
+    }
+
+    #[test]
+    fn test_should_print_banner() {
+        let interactive = Args::try_parse_from(["carbon-footprint-cli"]).unwrap();
+        let no_banner = Args::try_parse_from(["carbon-footprint-cli", "--no-banner"]).unwrap();
+
+        assert!(should_print_banner(&interactive, true));
+        assert!(!should_print_banner(&interactive, false));
+        assert!(!should_print_banner(&no_banner, true));
+    }
+
+    #[test]
+    fn test_command_line_overrides_config_which_overrides_defaults() {
+        let config = Config {
+            distance_unit: Some("mi".to_string()),
+            passengers: Some(3),
+            offset_price: Some(10.0),
+            base_url: Some("http://localhost:8080/".to_string()),
+            output_format: None,
+            emission_factor: Some(0.2),
+            rf_multiplier: Some(1.9),
+            cabin_factors: None,
+            user_agent: Some("fleet-reports/2.1".to_string()),
+            ..Config::default()
+        };
+        let mut args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--offset-price",
+            "20",
+            "--distance-unit",
+            "km",
+        ])
+        .unwrap();
+
+        args.apply_config(config).unwrap();
+
+        assert_eq!(args.offset_price(), 20.0);
+        assert_eq!(args.distance_unit.as_deref(), Some("km"));
+        assert_eq!(args.passengers, Some(3));
+        assert_eq!(args.base_url(), "http://localhost:8080");
+        assert_eq!(args.output(), OutputFormat::Text);
+        assert_eq!(args.emission_factor(), 0.2);
+        assert_eq!(args.rf_multiplier(), 1.9);
+        assert_eq!(args.user_agent(), "fleet-reports/2.1");
+    }
+
+    #[test]
+    fn test_offset_price_must_be_a_non_negative_number() {
+        let parse = |price: &str| {
+            Args::try_parse_from(["carbon-footprint-cli", "--offset-price", price])
+                .map(|args| args.offset_price)
+        };
+
+        assert_eq!(parse("0").unwrap(), Some(0.0));
+        assert_eq!(parse("12.5").unwrap(), Some(12.5));
+        assert!(parse("-1").is_err());
+        assert!(parse("NaN").is_err());
+        assert!(parse("inf").is_err());
+        assert!(parse("cheap").is_err());
+    }
+
+    #[test]
+    fn test_airports_settings_come_from_the_config() {
+        let config = Config {
+            airports_url: Some("https://example.com/airports.csv".to_string()),
+            airports_ttl_days: Some(7),
+            ..Config::default()
+        };
+        let mut args = Args::try_parse_from(["carbon-footprint-cli"]).unwrap();
+        args.apply_config(config).unwrap();
+
+        assert_eq!(
+            args.airports_url.as_deref(),
+            Some("https://example.com/airports.csv")
+        );
+        assert_eq!(args.airports_ttl(), Duration::from_secs(7 * 24 * 60 * 60));
+        assert_eq!(args.user_agent(), DEFAULT_USER_AGENT);
+
+        let mut args = Args::try_parse_from(["carbon-footprint-cli"]).unwrap();
+        args.apply_config(Config {
+            airports_url: Some("ftp://example.com/airports.csv".to_string()),
+            ..Config::default()
+        })
+        .unwrap_err();
+    }
+
+    #[test]
+    fn test_flags_override_the_selected_profile() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("config.toml");
+        fs::write(
+            &path,
+            "[profiles.work]\noffset_price = 30.0\ncurrency = \"EUR\"\n",
+        )
+        .unwrap();
+        let config = path.to_str().unwrap();
+        let mut args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--config",
+            config,
+            "--profile",
+            "work",
+            "--offset-price",
+            "20",
+        ])
+        .unwrap();
+
+        let profile = load_config(&args).unwrap();
+        args.apply_config(profile).unwrap();
+
+        assert_eq!(args.offset_price(), 20.0);
+        assert_eq!(args.currency(), "EUR");
+    }
+
+    #[test]
+    fn test_built_in_defaults_without_config() {
+        let mut args = Args::try_parse_from(["carbon-footprint-cli"]).unwrap();
+
+        args.apply_config(Config::default()).unwrap();
+
+        assert_eq!(args.offset_price(), DEFAULT_OFFSET_PRICE);
+        assert_eq!(args.base_url(), DEFAULT_BASE_URL);
+        assert_eq!(args.output(), OutputFormat::Text);
+        assert_eq!(args.passengers, None);
+    }
+
+    #[test]
+    fn test_output_format_follows_the_output_file_extension() {
+        let config = || Config {
+            output_format: Some(OutputFormat::Json),
+            ..Config::default()
+        };
+        let parse = |extra: &[&str]| {
+            let mut args = Args::try_parse_from(
+                ["carbon-footprint-cli", "--output-file", "trips.csv"]
+                    .iter()
+                    .chain(extra),
+            )
+            .unwrap();
+            args.apply_config(config()).unwrap();
+            args.output()
+        };
+
+        assert_eq!(parse(&[]), OutputFormat::Csv);
+        assert_eq!(parse(&["--output", "text"]), OutputFormat::Text);
+
+        let mut args =
+            Args::try_parse_from(["carbon-footprint-cli", "--output-file", "trips.log"]).unwrap();
+        args.apply_config(Config::default()).unwrap();
+        assert_eq!(args.output(), OutputFormat::Text);
+    }
+
+    #[test]
+    fn test_config_base_url_is_validated() {
+        let mut args = Args::try_parse_from(["carbon-footprint-cli"]).unwrap();
+        let config = Config {
+            base_url: Some("ftp://example.com".to_string()),
+            ..Default::default()
+        };
+
+        assert!(matches!(
+            args.apply_config(config),
+            Err(CliError::InvalidArguments(_))
+        ));
+    }
+
+    #[test]
+    fn test_missing_config_path_is_an_error() {
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--config",
+            "/nonexistent/config.toml",
+        ])
+        .unwrap();
+
+        assert!(matches!(
+            load_config(&args),
+            Err(CliError::InvalidConfig(_, _))
+        ));
+    }
+
+    #[test]
+    fn test_history_subcommand() {
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--history-file",
+            "h.jsonl",
+            "history",
+        ])
+        .unwrap();
+
+        assert_eq!(args.command, Some(Command::History));
+        assert_eq!(history_path(&args), Some(PathBuf::from("h.jsonl")));
+    }
+
+    #[test]
+    fn test_info_lists_every_cabin_class() {
+        let args = Args::try_parse_from(["carbon-footprint-cli", "info", "cabins"]).unwrap();
+        assert_eq!(
+            args.command,
+            Some(Command::Info {
+                topic: InfoTopic::Cabins
+            })
+        );
+
+        assert_eq!(
+            cabin_listing(),
+            [
+                "economy (used when a leg doesn't give one)",
+                "premium",
+                "business",
+                "first"
+            ]
+        );
+    }
+
+    #[test]
+    fn test_info_airports_finds_a_match() {
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "info",
+            "airports",
+            "--search",
+            "heathrow",
+        ])
+        .unwrap();
+        let Some(Command::Info {
+            topic: InfoTopic::Airports { search },
+        }) = args.command
+        else {
+            panic!("expected info airports");
+        };
+
+        let matches = search_airports(&search);
+
+        assert_eq!(matches[0].iata, "LHR");
+        assert!(format_airport(&matches[0]).starts_with("LHR  "));
+        assert!(format_airport(&matches[0]).ends_with("(GB)"));
+    }
+
+    #[test]
+    fn test_export_geojson_writes_route_file() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("route.geojson");
+        let request = create_request(1, &[("LHR", "JFK"), ("JFK", "LAX")]);
+        let attributes = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34)
+            .data
+            .unwrap()
+            .attributes;
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--export-geojson",
+            path.to_str().unwrap(),
+        ])
+        .unwrap();
+
+        export_geojson(&args, &request, &attributes).unwrap();
+
+        let written: serde_json::Value =
+            serde_json::from_str(&fs::read_to_string(&path).unwrap()).unwrap();
+        assert_eq!(written["type"], "FeatureCollection");
+        assert!(matches!(
+            export_geojson(&args, &request, &attributes),
+            Err(CliError::OutputFileExists(_))
+        ));
+    }
+
+    #[test]
+    fn test_batch_requests_can_be_read_from_stdin() {
+        let stdin = io::Cursor::new(
+            r#"[
+                {"passengers": 1, "legs": [{"departure_airport": "LHR", "destination_airport": "JFK"}]},
+                {"passengers": "two"},
+                {"passengers": 2, "legs": [{"departure_airport": "CDG", "destination_airport": "NRT"}]}
+            ]"#,
+        );
+
+        let requests = read_batch_requests(
+            Path::new("-"),
+            &Args::try_parse_from(["carbon-footprint-cli"]).unwrap(),
+            stdin,
+        )
+        .unwrap();
+
+        let indexes: Vec<usize> = requests.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indexes, vec![0, 2]);
+        assert_eq!(requests[0].1.request.passengers, 1);
+        assert_eq!(requests[0].1.request.legs[0].departure_airport, "LHR");
+        assert_eq!(requests[1].1.request.passengers, 2);
+        assert_eq!(requests[1].1.request.legs[0].destination_airport, "NRT");
+    }
+
+    #[tokio::test]
+    async fn test_batch_keeps_successes_when_a_request_fails() {
+        let server = MockServer::start().await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(body_string_contains("LHR"))
+            .respond_with(
+                ResponseTemplate::new(200).set_body_json(create_mock_response(
+                    99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34,
+                )),
+            )
+            .mount(&server)
+            .await;
+        Mock::given(method("POST"))
+            .and(path("/api/v1/estimates"))
+            .and(body_string_contains("XYZ"))
+            .respond_with(
+                ResponseTemplate::new(400).set_body_json(FlightEstimateResponse {
+                    message: Some(
+                        "Validation failed: Legs require valid airport codes".to_string(),
+                    ),
+                    ..Default::default()
+                }),
+            )
+            .mount(&server)
+            .await;
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config.toml");
+        fs::write(&config, "").unwrap();
+        let input = dir.path().join("trips.json");
+        fs::write(
+            &input,
+            r#"[
+                {"passengers": 1, "legs": [{"departure_airport": "LHR", "destination_airport": "JFK"}]},
+                {"passengers": 1, "legs": [{"departure_airport": "XYZ", "destination_airport": "JFK"}]}
+            ]"#,
+        )
+        .unwrap();
+        let output_file = dir.path().join("estimates.json");
+        let base_url = server.uri();
+        let batch_args = |extra: &[&str]| {
+            let mut argv = vec![
+                "carbon-footprint-cli",
+                "--api-key",
+                "test-key-123",
+                "--base-url",
+                &base_url,
+                "--config",
+                config.to_str().unwrap(),
+                "--input",
+                input.to_str().unwrap(),
+                "--output-file",
+                output_file.to_str().unwrap(),
+                "--force",
+                "--no-history",
+                "--retries",
+                "0",
+            ];
+            argv.extend_from_slice(extra);
+            Args::try_parse_from(argv).unwrap()
+        };
+
+        let err = run(batch_args(&[])).await.unwrap_err();
+
+        assert!(matches!(
+            err,
+            CliError::BatchFailed {
+                failed: 1,
+                total: 2
+            }
+        ));
+        assert_eq!(exit_code_for(&err), 1);
+        let written = fs::read_to_string(&output_file).unwrap();
+        let written: Vec<serde_json::Value> = serde_json::from_str(&written).unwrap();
+        assert_eq!(written.len(), 1);
+        assert_eq!(
+            written[0]["distance_value"].as_f64().unwrap().round(),
+            5660.0
+        );
+
+        fs::remove_file(&output_file).unwrap();
+        let err = run(batch_args(&["--fail-fast"])).await.unwrap_err();
+
+        assert!(err
+            .to_string()
+            .starts_with("index 1 (XYZ → JFK): API error"));
+        assert!(
+            matches!(&err, CliError::InRequest(failed) if matches!(failed.error, CliError::ApiError(_)))
+        );
+        assert_eq!(exit_code_for(&err), 1);
+        assert!(!output_file.exists());
+    }
+
+    #[tokio::test]
+    async fn test_output_dir_writes_a_file_per_itinerary() {
+        let server = MockServer::start().await;
+        for (departure, carbon_kg) in [("LHR", 400.0), ("CDG", 900.0)] {
+            Mock::given(method("POST"))
+                .and(path("/api/v1/estimates"))
+                .and(body_string_contains(departure))
+                .respond_with(
+                    ResponseTemplate::new(200).set_body_json(create_mock_response(
+                        carbon_kg * 1000.0,
+                        0.0,
+                        carbon_kg,
+                        0.0,
+                        "km",
+                        5000.0,
+                    )),
+                )
+                .mount(&server)
+                .await;
+        }
+        let dir = tempfile::tempdir().unwrap();
+        let config = dir.path().join("config.toml");
+        fs::write(&config, "").unwrap();
+        let input = dir.path().join("trips.json");
+        fs::write(
+            &input,
+            r#"[
+                {"passengers": 1, "legs": [{"departure_airport": "LHR", "destination_airport": "JFK"}]},
+                {"itinerary": "../Paris", "passengers": 1, "legs": [{"departure_airport": "CDG", "destination_airport": "NRT"}]}
+            ]"#,
+        )
+        .unwrap();
+        let output_dir = dir.path().join("estimates");
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--api-key",
+            "test-key-123",
+            "--base-url",
+            &server.uri(),
+            "--config",
+            config.to_str().unwrap(),
+            "--input",
+            input.to_str().unwrap(),
+            "--output",
+            "json",
+            "--output-dir",
+            output_dir.to_str().unwrap(),
+            "--no-history",
+        ])
+        .unwrap();
+
+        run(args).await.unwrap();
+
+        let mut names: Vec<String> = fs::read_dir(&output_dir)
+            .unwrap()
+            .map(|entry| entry.unwrap().file_name().into_string().unwrap())
+            .collect();
+        names.sort();
+        assert_eq!(names, ["LHR-JFK.json", "Paris.json"]);
+        let read = |name: &str| -> Vec<serde_json::Value> {
+            serde_json::from_str(&fs::read_to_string(output_dir.join(name)).unwrap()).unwrap()
+        };
+        assert_eq!(read("LHR-JFK.json")[0]["carbon_kg"], 400.0);
+        assert_eq!(read("Paris.json")[0]["carbon_kg"], 900.0);
+    }
+
+    #[test]
+    fn test_generated_sample_reads_back_as_a_batch() {
+        let dir = tempfile::tempdir().unwrap();
+        for name in ["sample.json", "sample.yaml"] {
+            let path = dir.path().join(name);
+            generate_sample(&path, false).unwrap();
+
+            let entries = read_batch_requests(
+                &path,
+                &Args::try_parse_from(["carbon-footprint-cli"]).unwrap(),
+                io::empty(),
+            )
+            .unwrap();
+
+            assert_eq!(entries.len(), 3);
+            assert_eq!(
+                entries[0].1.itinerary.as_deref(),
+                Some("Tokyo via Helsinki")
+            );
+            assert!(matches!(
+                generate_sample(&path, false),
+                Err(CliError::OutputFileExists(_))
+            ));
+        }
+    }
+
+    #[test]
+    fn test_batch_file_path_ignores_stdin() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trips.json");
+        fs::write(
+            &path,
+            r#"[{"passengers": 3, "legs": [{"departure_airport": "LHR", "destination_airport": "JFK"}]}]"#,
+        )
+        .unwrap();
+
+        let requests = read_batch_requests(
+            &path,
+            &Args::try_parse_from(["carbon-footprint-cli"]).unwrap(),
+            io::Cursor::new("not json"),
+        )
+        .unwrap();
+
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].1.request.passengers, 3);
+    }
+
+    #[test]
+    fn test_batch_entries_to_the_same_airport_are_skipped_unless_allowed() {
+        let stdin = r#"[
+            {"passengers": 1, "legs": [{"departure_airport": "LHR", "destination_airport": "LHR"}]},
+            {"passengers": 2, "legs": [{"departure_airport": "CDG", "destination_airport": "NRT"}]}
+        ]"#;
+
+        let skipped = read_batch_requests(
+            Path::new("-"),
+            &Args::try_parse_from(["carbon-footprint-cli"]).unwrap(),
+            io::Cursor::new(stdin),
+        )
+        .unwrap();
+        let allowed = read_batch_requests(
+            Path::new("-"),
+            &Args::try_parse_from(["carbon-footprint-cli", "--allow-same-airport"]).unwrap(),
+            io::Cursor::new(stdin),
+        )
+        .unwrap();
+
+        let indexes: Vec<usize> = skipped.iter().map(|(index, _)| *index).collect();
+        assert_eq!(indexes, vec![1]);
+        assert_eq!(allowed.len(), 2);
+    }
+
+    #[test]
+    fn test_validate_batch_lists_every_problem_by_entry() {
+        let contents = r#"[
+            {"passengers": 1, "legs": [{"departure_airport": "LHR", "destination_airport": "JFK"}]},
+            {"passengers": 0, "legs": [
+                {"departure_airport": "LHR", "destination_airport": "LHR", "cabin_class": "luxury"}
+            ]},
+            {"passengers": "two", "legs": []}
+        ]"#;
+        let args = |extra: &[&str]| {
+            let mut argv = vec![
+                "carbon-footprint-cli",
+                "--input",
+                "trips.json",
+                "--dry-run-validate",
+            ];
+            argv.extend(extra);
+            Args::try_parse_from(argv).unwrap()
+        };
+        let mut out = Vec::new();
+
+        let result = validate_batch(contents, InputFormat::Json, &args(&[]), &mut out);
+
+        let report = String::from_utf8(out).unwrap();
+        let lines: Vec<&str> = report.lines().collect();
+        assert_eq!(lines.len(), 4);
+        assert_eq!(lines[0], "entry 1: passengers must be at least 1");
+        assert!(lines[1].starts_with("entry 1: leg 1: unknown cabin class 'luxury'"));
+        assert_eq!(
+            lines[2],
+            "entry 1: leg 1: LHR is both the departure and the destination"
+        );
+        assert!(lines[3].starts_with("entry 2: "));
+        assert!(
+            matches!(result, Err(CliError::InvalidInput(message)) if message == "4 problem(s) in 2 of 3 entries")
+        );
+
+        let mut out = Vec::new();
+        let same_airport = r#"[{"passengers": 1, "legs": [{"departure_airport": "LHR", "destination_airport": "LHR"}]}]"#;
+        validate_batch(
+            same_airport,
+            InputFormat::Json,
+            &args(&["--allow-same-airport"]),
+            &mut out,
+        )
+        .unwrap();
+        assert_eq!(
+            String::from_utf8(out).unwrap(),
+            "No problems in 1 entries\n"
+        );
+        assert!(Args::try_parse_from(["carbon-footprint-cli", "--dry-run-validate"]).is_err());
+    }
+
+    #[test]
+    fn test_json_lines_batch_keeps_the_good_lines() {
+        let stdin = concat!(
+            r#"{"passengers": 1, "legs": [{"departure_airport": "LHR", "destination_airport": "JFK"}]}"#,
+            "\nnot json\n",
+            r#"{"passengers": 2, "legs": [{"departure_airport": "CDG", "destination_airport": "NRT"}]}"#,
+            "\n",
+        );
+
+        let requests = read_batch_requests(
+            Path::new("-"),
+            &Args::try_parse_from(["carbon-footprint-cli", "--input-format", "jsonl"]).unwrap(),
+            io::Cursor::new(stdin),
+        )
+        .unwrap();
+
+        let kept: Vec<(usize, u32)> = requests
+            .iter()
+            .map(|(index, entry)| (*index, entry.request.passengers))
+            .collect();
+        assert_eq!(kept, vec![(0, 1), (2, 2)]);
+    }
+
+    #[test]
+    fn test_batch_format_follows_the_extension_unless_overridden() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("trips.yml");
+        fs::write(
+            &path,
+            "- passengers: 2\n  legs: [{departure_airport: LHR, destination_airport: JFK}]\n",
+        )
+        .unwrap();
+
+        let requests = read_batch_requests(
+            &path,
+            &Args::try_parse_from(["carbon-footprint-cli"]).unwrap(),
+            io::empty(),
+        )
+        .unwrap();
+        assert_eq!(requests[0].1.request.passengers, 2);
+
+        assert!(matches!(
+            read_batch_requests(
+                &path,
+                &Args::try_parse_from(["carbon-footprint-cli", "--input-format", "json"]).unwrap(),
+                io::empty()
+            ),
+            Err(CliError::InvalidInput(_))
+        ));
+    }
+
+    #[test]
+    fn test_export_geojson_rejects_batches() {
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--export-geojson",
+            "route.geojson",
+            "--input",
+            "trips.json",
+        ])
+        .unwrap();
+
+        assert!(matches!(
+            check_geojson_export(&args),
+            Err(CliError::InvalidArguments(_))
+        ));
+    }
+
+    #[test]
+    fn test_record_history_respects_no_history() {
+        let dir = tempfile::tempdir().unwrap();
+        let path = dir.path().join("history.jsonl");
+        let path_arg = path.to_str().unwrap();
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let attributes = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34)
+            .data
+            .unwrap()
+            .attributes;
+
+        let disabled = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--history-file",
+            path_arg,
+            "--no-history",
+        ])
+        .unwrap();
+        record_history(&disabled, &request, &attributes);
+        assert!(!path.exists());
+
+        let enabled =
+            Args::try_parse_from(["carbon-footprint-cli", "--history-file", path_arg]).unwrap();
+        record_history(&enabled, &request, &attributes);
+        let records = read_history(&path).unwrap();
+        assert_eq!(records.len(), 1);
+        assert_eq!(records[0].carbon_kg, 99911.7);
+    }
+
+    #[test]
+    fn test_format_history_record() {
+        let record = HistoryRecord {
+            timestamp: "2026-10-14T09:30:00Z".parse().unwrap(),
+            route: vec!["LHR".to_string(), "JFK".to_string()],
+            passengers: 2,
+            cabin_classes: vec![Some("business".to_string())],
+            carbon_kg: 1998.234,
+        };
+
+        assert_eq!(
+            format_history_record(&record),
+            "2026-10-14 09:30 UTC  LHR → JFK  2 passenger(s)  business  1998.23 kg CO2"
+        );
+    }
+
+    #[test]
+    fn test_vehicle_subcommand_builds_request() {
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--distance-unit",
+            "mi",
+            "vehicle",
+            "--model-id",
+            "7268a9b7",
+            "--distance",
+            "100",
+        ])
+        .unwrap();
+
+        let request = vehicle_request(&args).unwrap();
+
+        assert_eq!(request.estimate_type, "vehicle");
+        assert_eq!(request.distance_unit, "mi");
+        assert_eq!(request.distance_value, 100.0);
+        assert_eq!(request.vehicle_model_id, "7268a9b7");
+        assert!(Args::try_parse_from([
+            "carbon-footprint-cli",
+            "vehicle",
+            "--model-id",
+            "7268a9b7",
+            "--distance",
+            "-5",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_shipping_subcommand_validates_transport_method() {
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "shipping",
+            "--weight",
+            "200",
+            "--weight-unit",
+            "g",
+            "--distance",
+            "2000",
+            "--transport-method",
+            "truck",
+        ])
+        .unwrap();
+
+        let request = shipping_request(&args).unwrap();
+
+        assert_eq!(request.weight_unit, "g");
+        assert_eq!(request.distance_unit, "km");
+        assert_eq!(request.transport_method, "truck");
+        assert!(Args::try_parse_from([
+            "carbon-footprint-cli",
+            "shipping",
+            "--weight",
+            "200",
+            "--distance",
+            "2000",
+            "--transport-method",
+            "rocket",
+        ])
+        .is_err());
+    }
+
+    #[test]
+    fn test_electricity_subcommand_builds_request() {
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "electricity",
+            "--value",
+            "42",
+            "--unit",
+            "mwh",
+            "--country",
+            "US",
+            "--state",
+            "fl",
+        ])
+        .unwrap();
+
+        let request = electricity_request(&args).unwrap();
+
+        assert_eq!(request.electricity_unit, "mwh");
+        assert_eq!(request.country, "us");
+        assert_eq!(request.state.as_deref(), Some("fl"));
+    }
+
+    #[test]
+    fn test_electricity_subcommand_rejects_invalid_country_code() {
+        for country in ["USA", "U", "1X"] {
+            let result = Args::try_parse_from([
+                "carbon-footprint-cli",
+                "electricity",
+                "--value",
+                "42",
+                "--country",
+                country,
+            ]);
+
+            assert!(result.is_err(), "{} should be rejected", country);
+        }
+    }
+
+    #[test]
+    fn test_compare_subcommand_builds_both_requests() {
+        let args = Args::try_parse_from([
+            "carbon-footprint-cli",
+            "--passengers",
+            "2",
+            "compare",
+            "--itinerary",
+            "LHR:LAX",
+            "--itinerary",
+            "LHR:JFK,JFK:LAX:business",
+        ])
+        .unwrap();
+        let Some(Command::Compare { itinerary }) = &args.command else {
+            panic!("expected the compare subcommand");
+        };
+
+        let requests = comparison_requests(&args, itinerary).unwrap();
+
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].legs.len(), 1);
+        assert_eq!(requests[1].legs.len(), 2);
+        assert_eq!(requests[1].legs[1].cabin_class.as_deref(), Some("business"));
+        assert!(requests.iter().all(|request| request.passengers == 2));
+    }
+
+    #[test]
+    fn test_compare_needs_exactly_two_itineraries() {
+        let args =
+            Args::try_parse_from(["carbon-footprint-cli", "compare", "--itinerary", "LHR:LAX"])
+                .unwrap();
+        let Some(Command::Compare { itinerary }) = &args.command else {
+            panic!("expected the compare subcommand");
+        };
+
+        assert!(matches!(
+            comparison_requests(&args, itinerary),
+            Err(CliError::InvalidArguments(_))
+        ));
+        assert!(parse_itinerary("LHR:JFK,JFK").is_err());
+    }
+
+    #[tokio::test]
+    async fn test_exit_code_for_each_kind_of_error() {
+        let reqwest_err = || async { Client::new().get("http://").send().await.unwrap_err() };
+        let serde_err = serde_json::from_str::<u32>("x").unwrap_err();
+        let path = PathBuf::from("out.json");
+
+        assert_eq!(exit_code_for(&CliError::InvalidArguments("x".into())), 2);
+        assert_eq!(exit_code_for(&CliError::InvalidInput("x".into())), 2);
+        assert_eq!(exit_code_for(&CliError::ValidationError("x".into())), 2);
+        assert_eq!(exit_code_for(&CliError::TooManyInvalidInputs), 2);
+        assert_eq!(exit_code_for(&CliError::InputClosed), 2);
+        assert_eq!(
+            exit_code_for(&CliError::InvalidConfig(path.clone(), "x".into())),
+            2
+        );
+        assert_eq!(exit_code_for(&CliError::OutputFileExists(path.clone())), 2);
+        assert_eq!(
+            exit_code_for(&CliError::BudgetExceeded {
+                total_kg: 2.0,
+                budget_kg: 1.0
+            }),
+            3
+        );
+        assert_eq!(exit_code_for(&CliError::Unauthorized), 4);
+        assert_eq!(
+            exit_code_for(&CliError::NetworkError(reqwest_err().await)),
+            5
+        );
+        assert_eq!(exit_code_for(&CliError::DnsError(reqwest_err().await)), 5);
+        assert_eq!(
+            exit_code_for(&CliError::ConnectionRefused(reqwest_err().await)),
+            5
+        );
+        assert_eq!(exit_code_for(&CliError::Timeout(None)), 5);
+        assert_eq!(
+            exit_code_for(&CliError::UnexpectedResponseFormat(serde_err)),
+            1
+        );
+        assert_eq!(exit_code_for(&CliError::ApiError("x".into())), 1);
+        assert_eq!(exit_code_for(&CliError::ServerError(500)), 1);
+        assert_eq!(exit_code_for(&CliError::ImplausibleResponse("x".into())), 1);
+        assert_eq!(
+            exit_code_for(&CliError::OutputFileError(
+                path.clone(),
+                io::Error::other("x")
+            )),
+            1
+        );
+        assert_eq!(
+            exit_code_for(&CliError::HistoryError(path, io::Error::other("x"))),
+            1
+        );
+        assert_eq!(exit_code_for(&CliError::KeyringError("x".into())), 1);
+        assert_eq!(
+            exit_code_for(&CliError::BatchFailed {
+                failed: 1,
+                total: 2
+            }),
+            1
+        );
     }
 }
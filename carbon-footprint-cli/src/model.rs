@@ -0,0 +1,890 @@
+//! Request and response types for the Carbon Interface estimates API.
+
+use crate::airports::{is_known_iata, normalize_airport_code};
+use crate::error::CliError;
+use schemars::JsonSchema;
+use serde::de::Deserializer;
+use serde::Deserialize as _;
+use serde_derive::{Deserialize, Serialize};
+use std::fmt;
+
+/// Distance units accepted by the Carbon Interface API.
+pub const DISTANCE_UNITS: [&str; 2] = ["km", "mi"];
+
+/// Cabin classes accepted by the Carbon Interface API.
+pub const CABIN_CLASSES: [&str; 4] = ["economy", "premium", "business", "first"];
+
+/// Ways of moving freight accepted by the Carbon Interface API.
+pub const TRANSPORT_METHODS: [&str; 4] = ["ship", "train", "truck", "plane"];
+
+/// Weight units accepted by the Carbon Interface API.
+pub const WEIGHT_UNITS: [&str; 4] = ["g", "lb", "kg", "mt"];
+
+/// Electricity units accepted by the Carbon Interface API.
+pub const ELECTRICITY_UNITS: [&str; 2] = ["kwh", "mwh"];
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct Leg {
+    pub departure_airport: String,
+    pub destination_airport: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub cabin_class: Option<String>,
+    /// How many fly this leg, when it isn't the request's `passengers`. The API has no
+    /// such field, so these legs are estimated on their own.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub passengers: Option<u32>,
+    /// The aircraft type flying the leg, as an ICAO designator such as `A20N`. The API
+    /// doesn't take one, so it's left out of what's sent and only refines offline estimates.
+    #[serde(default, skip_serializing_if = "Option::is_none")]
+    pub aircraft: Option<String>,
+}
+
+#[derive(Serialize, Deserialize, JsonSchema, Clone, Debug, PartialEq)]
+pub struct FlightEstimateRequest {
+    #[serde(rename = "type", default = "default_estimate_type")]
+    pub estimate_type: String,
+    pub passengers: u32,
+    pub legs: Vec<Leg>,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub distance_unit: Option<String>,
+}
+
+fn default_estimate_type() -> String {
+    String::from("flight")
+}
+
+impl FlightEstimateRequest {
+    pub fn builder() -> FlightEstimateRequestBuilder {
+        FlightEstimateRequestBuilder::default()
+    }
+
+    /// Rejects a request without legs, which the API would only refuse with a vaguer
+    /// message.
+    pub fn check_has_legs(&self) -> Result<(), CliError> {
+        if self.legs.is_empty() {
+            return Err(CliError::InvalidInput(
+                "at least one leg is required".to_string(),
+            ));
+        }
+        Ok(())
+    }
+
+    /// The request as the API takes it, without the aircraft types it has no field for.
+    pub fn without_aircraft(&self) -> Self {
+        let mut request = self.clone();
+        for leg in &mut request.legs {
+            leg.aircraft = None;
+        }
+        request
+    }
+}
+
+/// Builds a `FlightEstimateRequest` a leg at a time. Passengers default to 1 and the
+/// distance unit to the API's own default.
+#[derive(Clone, Debug)]
+pub struct FlightEstimateRequestBuilder {
+    passengers: u32,
+    legs: Vec<Leg>,
+    distance_unit: Option<String>,
+}
+
+impl Default for FlightEstimateRequestBuilder {
+    fn default() -> Self {
+        Self {
+            passengers: 1,
+            legs: Vec::new(),
+            distance_unit: None,
+        }
+    }
+}
+
+impl FlightEstimateRequestBuilder {
+    pub fn passengers(mut self, passengers: u32) -> Self {
+        self.passengers = passengers;
+        self
+    }
+
+    /// Appends a leg; legs are flown in the order they're added.
+    pub fn add_leg(mut self, leg: Leg) -> Self {
+        self.legs.push(leg);
+        self
+    }
+
+    pub fn distance_unit(mut self, distance_unit: &str) -> Self {
+        self.distance_unit = Some(distance_unit.to_string());
+        self
+    }
+
+    /// The finished request, or an error if no legs were added.
+    pub fn build(self) -> Result<FlightEstimateRequest, CliError> {
+        let request = FlightEstimateRequest {
+            estimate_type: default_estimate_type(),
+            passengers: self.passengers,
+            legs: self.legs,
+            distance_unit: self.distance_unit,
+        };
+        request.check_has_legs()?;
+        Ok(request)
+    }
+}
+
+/// A ground trip in a vehicle model from Carbon Interface's vehicle model list.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct VehicleEstimateRequest {
+    #[serde(rename = "type", default = "default_vehicle_estimate_type")]
+    pub estimate_type: String,
+    pub distance_unit: String,
+    pub distance_value: f32,
+    pub vehicle_model_id: String,
+}
+
+fn default_vehicle_estimate_type() -> String {
+    String::from("vehicle")
+}
+
+/// Freight moved a distance by one transport method.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ShippingEstimateRequest {
+    #[serde(rename = "type", default = "default_shipping_estimate_type")]
+    pub estimate_type: String,
+    pub weight_value: f32,
+    pub weight_unit: String,
+    pub distance_value: f32,
+    pub distance_unit: String,
+    pub transport_method: String,
+}
+
+fn default_shipping_estimate_type() -> String {
+    String::from("shipping")
+}
+
+/// Electricity used in a country, and optionally a state or province within it.
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct ElectricityEstimateRequest {
+    #[serde(rename = "type", default = "default_electricity_estimate_type")]
+    pub estimate_type: String,
+    pub electricity_unit: String,
+    pub electricity_value: f32,
+    /// ISO 3166-1 alpha-2 country code, lowercase
+    pub country: String,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub state: Option<String>,
+}
+
+fn default_electricity_estimate_type() -> String {
+    String::from("electricity")
+}
+
+/// The API's response envelope, shared by every estimate type; `A` is the attributes
+/// returned for that type.
+#[derive(Serialize, Deserialize, Debug)]
+#[serde(bound(deserialize = "A: serde::Deserialize<'de>"))]
+pub struct EstimateResponse<A> {
+    #[serde(default)]
+    pub data: Option<EstimateData<A>>,
+    #[serde(default)]
+    pub message: Option<String>,
+}
+
+impl<A> Default for EstimateResponse<A> {
+    fn default() -> Self {
+        Self {
+            data: None,
+            message: None,
+        }
+    }
+}
+
+pub type FlightEstimateResponse = EstimateResponse<EstimateAttributes>;
+
+pub type VehicleEstimateResponse = EstimateResponse<VehicleEstimateAttributes>;
+
+pub type ShippingEstimateResponse = EstimateResponse<ShippingEstimateAttributes>;
+
+pub type ElectricityEstimateResponse = EstimateResponse<ElectricityEstimateAttributes>;
+
+#[derive(Serialize, Deserialize, Clone, Debug)]
+pub struct EstimateData<A = EstimateAttributes> {
+    pub attributes: A,
+}
+
+/// Stands in for a figure the API left out of a response, so the others can still be
+/// shown. Text output shows it as "N/A", and JSON as `null`.
+pub const MISSING_FIGURE: f32 = f32::NAN;
+
+/// Whether `figure` is `MISSING_FIGURE`, which as NaN never compares equal to anything.
+pub fn is_missing(figure: f32) -> bool {
+    figure.is_nan()
+}
+
+fn missing_figure() -> f32 {
+    MISSING_FIGURE
+}
+
+fn figure_or_missing<'de, D: Deserializer<'de>>(deserializer: D) -> Result<f32, D::Error> {
+    Ok(Option::<f32>::deserialize(deserializer)?.unwrap_or(MISSING_FIGURE))
+}
+
+/// The figures of a flight estimate. Any the API leaves out, or sends as `null`, are
+/// `MISSING_FIGURE`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct EstimateAttributes {
+    #[serde(default = "missing_figure", deserialize_with = "figure_or_missing")]
+    pub carbon_g: f32,
+    #[serde(default = "missing_figure", deserialize_with = "figure_or_missing")]
+    pub carbon_lb: f32,
+    #[serde(default = "missing_figure", deserialize_with = "figure_or_missing")]
+    pub carbon_kg: f32,
+    #[serde(default = "missing_figure", deserialize_with = "figure_or_missing")]
+    pub carbon_mt: f32,
+    pub distance_unit: String,
+    #[serde(default = "missing_figure", deserialize_with = "figure_or_missing")]
+    pub distance_value: f32,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct VehicleEstimateAttributes {
+    pub carbon_g: f32,
+    pub carbon_lb: f32,
+    pub carbon_kg: f32,
+    pub carbon_mt: f32,
+    pub distance_unit: String,
+    pub distance_value: f32,
+    pub vehicle_make: String,
+    pub vehicle_model: String,
+    pub vehicle_year: u32,
+    pub vehicle_model_id: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ShippingEstimateAttributes {
+    pub carbon_g: f32,
+    pub carbon_lb: f32,
+    pub carbon_kg: f32,
+    pub carbon_mt: f32,
+    pub distance_unit: String,
+    pub distance_value: f32,
+    pub weight_unit: String,
+    pub weight_value: f32,
+    pub transport_method: String,
+}
+
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct ElectricityEstimateAttributes {
+    pub carbon_g: f32,
+    pub carbon_lb: f32,
+    pub carbon_kg: f32,
+    pub carbon_mt: f32,
+    pub electricity_unit: String,
+    pub electricity_value: f32,
+    pub country: String,
+    #[serde(default)]
+    pub state: Option<String>,
+}
+
+/// Returns the lowercase cabin class if it's one the API accepts, in any letter case.
+pub fn normalize_cabin_class(cabin_class: &str) -> Option<String> {
+    let cabin_class = cabin_class.to_lowercase();
+    CABIN_CLASSES
+        .contains(&cabin_class.as_str())
+        .then_some(cabin_class)
+}
+
+/// Whether `leg` departs from the airport it lands at, which is almost always a typo
+/// and would be estimated as a flight of no distance.
+pub fn is_same_airport_leg(leg: &Leg) -> bool {
+    leg.departure_airport
+        .trim()
+        .eq_ignore_ascii_case(leg.destination_airport.trim())
+}
+
+/// Something in a request the API would refuse or get wrong. Legs are numbered from 1.
+#[derive(Clone, Debug, PartialEq)]
+pub enum ValidationIssue {
+    NoLegs,
+    NoPassengers,
+    UnknownDistanceUnit(String),
+    MalformedAirportCode { leg: usize, code: String },
+    UnknownAirport { leg: usize, code: String },
+    UnknownCabinClass { leg: usize, cabin_class: String },
+    NoLegPassengers { leg: usize },
+    SameAirport { leg: usize, code: String },
+}
+
+impl fmt::Display for ValidationIssue {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::NoLegs => write!(f, "at least one leg is required"),
+            Self::NoPassengers => write!(f, "passengers must be at least 1"),
+            Self::UnknownDistanceUnit(unit) => write!(
+                f,
+                "unknown distance unit '{}', expected one of {}",
+                unit,
+                DISTANCE_UNITS.join(", ")
+            ),
+            Self::MalformedAirportCode { leg, code } => write!(
+                f,
+                "leg {}: '{}' isn't a three-letter airport code",
+                leg, code
+            ),
+            Self::UnknownAirport { leg, code } => {
+                write!(f, "leg {}: {} isn't a known airport", leg, code)
+            }
+            Self::UnknownCabinClass { leg, cabin_class } => write!(
+                f,
+                "leg {}: unknown cabin class '{}', expected one of {}",
+                leg,
+                cabin_class,
+                CABIN_CLASSES.join(", ")
+            ),
+            Self::NoLegPassengers { leg } => {
+                write!(f, "leg {}: passengers must be at least 1", leg)
+            }
+            Self::SameAirport { leg, code } => write!(
+                f,
+                "leg {}: {} is both the departure and the destination",
+                leg, code
+            ),
+        }
+    }
+}
+
+/// Every problem with `request` that can be found without asking the API, in the order
+/// of its fields and legs.
+pub fn validate_request(request: &FlightEstimateRequest) -> Vec<ValidationIssue> {
+    let mut issues = Vec::new();
+    if request.legs.is_empty() {
+        issues.push(ValidationIssue::NoLegs);
+    }
+    if request.passengers == 0 {
+        issues.push(ValidationIssue::NoPassengers);
+    }
+    if let Some(unit) = &request.distance_unit {
+        if !DISTANCE_UNITS.contains(&unit.as_str()) {
+            issues.push(ValidationIssue::UnknownDistanceUnit(unit.clone()));
+        }
+    }
+    for (index, leg_request) in request.legs.iter().enumerate() {
+        let leg = index + 1;
+        for code in [
+            &leg_request.departure_airport,
+            &leg_request.destination_airport,
+        ] {
+            match normalize_airport_code(code) {
+                None => issues.push(ValidationIssue::MalformedAirportCode {
+                    leg,
+                    code: code.clone(),
+                }),
+                Some(code) if !is_known_iata(&code) => {
+                    issues.push(ValidationIssue::UnknownAirport { leg, code })
+                }
+                Some(_) => {}
+            }
+        }
+        if let Some(cabin_class) = &leg_request.cabin_class {
+            if normalize_cabin_class(cabin_class).is_none() {
+                issues.push(ValidationIssue::UnknownCabinClass {
+                    leg,
+                    cabin_class: cabin_class.clone(),
+                });
+            }
+        }
+        if leg_request.passengers == Some(0) {
+            issues.push(ValidationIssue::NoLegPassengers { leg });
+        }
+        if is_same_airport_leg(leg_request) {
+            issues.push(ValidationIssue::SameAirport {
+                leg,
+                code: leg_request.departure_airport.trim().to_ascii_uppercase(),
+            });
+        }
+    }
+    issues
+}
+
+/// Returns the journey back: each leg reversed, in reverse order, keeping its cabin class.
+pub fn mirror_legs(legs: &[Leg]) -> Vec<Leg> {
+    legs.iter()
+        .rev()
+        .map(|leg| Leg {
+            departure_airport: leg.destination_airport.clone(),
+            destination_airport: leg.departure_airport.clone(),
+            cabin_class: leg.cabin_class.clone(),
+            passengers: leg.passengers,
+            aircraft: leg.aircraft.clone(),
+        })
+        .collect()
+}
+
+/// One single-leg request for each leg of `request`, so the API reports every leg's
+/// emissions on its own.
+pub fn split_legs(request: &FlightEstimateRequest) -> Vec<FlightEstimateRequest> {
+    request
+        .legs
+        .iter()
+        .map(|leg| FlightEstimateRequest {
+            legs: vec![leg.clone()],
+            ..request.clone()
+        })
+        .collect()
+}
+
+/// The requests to send for `request`: its legs flown by the request's `passengers` kept
+/// together, and a single-leg request for each leg with its own passenger count. Adding
+/// their estimates up gives the trip's. A request without per-leg counts is returned as
+/// it is.
+pub fn split_by_leg_passengers(request: &FlightEstimateRequest) -> Vec<FlightEstimateRequest> {
+    let (shared, own): (Vec<&Leg>, Vec<&Leg>) = request.legs.iter().partition(|leg| {
+        leg.passengers
+            .is_none_or(|count| count == request.passengers)
+    });
+    if own.is_empty() {
+        return vec![request.clone()];
+    }
+
+    let without_count = |leg: &Leg| Leg {
+        passengers: None,
+        ..leg.clone()
+    };
+    let mut requests = Vec::new();
+    if !shared.is_empty() {
+        requests.push(FlightEstimateRequest {
+            legs: shared.into_iter().map(without_count).collect(),
+            ..request.clone()
+        });
+    }
+    requests.extend(own.into_iter().map(|leg| FlightEstimateRequest {
+        passengers: leg.passengers.unwrap_or(request.passengers),
+        legs: vec![without_count(leg)],
+        ..request.clone()
+    }));
+    requests
+}
+
+/// How many of a leg's passengers sit in one cabin class.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct CabinPassengers {
+    pub cabin_class: String,
+    pub passengers: u32,
+}
+
+/// A leg whose passengers are spread across cabin classes. The API only takes one cabin
+/// class per leg for everyone, so these are estimated with one request per class; see
+/// `split_by_cabin`.
+#[derive(Serialize, Deserialize, Clone, Debug, PartialEq)]
+pub struct MixedCabinLeg {
+    pub departure_airport: String,
+    pub destination_airport: String,
+    pub cabins: Vec<CabinPassengers>,
+}
+
+impl MixedCabinLeg {
+    /// Everyone on the leg, whatever their cabin.
+    pub fn passengers(&self) -> u32 {
+        self.cabins.iter().map(|cabin| cabin.passengers).sum()
+    }
+
+    /// The same leg flown the other way, with the same passengers.
+    pub fn reversed(&self) -> Self {
+        Self {
+            departure_airport: self.destination_airport.clone(),
+            destination_airport: self.departure_airport.clone(),
+            cabins: self.cabins.clone(),
+        }
+    }
+}
+
+/// One single-class request for each cabin class on each leg, in leg order. Their
+/// emissions add up to the whole trip's.
+pub fn split_by_cabin(
+    legs: &[MixedCabinLeg],
+    distance_unit: Option<&str>,
+) -> Vec<FlightEstimateRequest> {
+    legs.iter()
+        .flat_map(|leg| {
+            leg.cabins.iter().map(move |cabin| FlightEstimateRequest {
+                estimate_type: default_estimate_type(),
+                passengers: cabin.passengers,
+                legs: vec![Leg {
+                    departure_airport: leg.departure_airport.clone(),
+                    destination_airport: leg.destination_airport.clone(),
+                    cabin_class: Some(cabin.cabin_class.clone()),
+                    passengers: None,
+                    aircraft: None,
+                }],
+                distance_unit: distance_unit.map(String::from),
+            })
+        })
+        .collect()
+}
+
+/// A submitted request together with the figures the API returned for it.
+#[derive(Clone, Debug)]
+pub struct EstimateResult {
+    pub request: FlightEstimateRequest,
+    pub attributes: EstimateAttributes,
+}
+
+/// The estimates in a batch that belong to one itinerary, reported with a subtotal.
+#[derive(Clone, Debug)]
+pub struct ItineraryGroup {
+    pub name: String,
+    pub estimates: Vec<EstimateResult>,
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::create_request;
+
+    #[test]
+    fn test_normalize_cabin_class_accepts_every_api_class() {
+        assert_eq!(
+            normalize_cabin_class("economy"),
+            Some("economy".to_string())
+        );
+        assert_eq!(
+            normalize_cabin_class("Premium"),
+            Some("premium".to_string())
+        );
+        assert_eq!(
+            normalize_cabin_class("BUSINESS"),
+            Some("business".to_string())
+        );
+        assert_eq!(normalize_cabin_class("first"), Some("first".to_string()));
+    }
+
+    fn split_leg(departure: &str, destination: &str, cabins: &[(&str, u32)]) -> MixedCabinLeg {
+        MixedCabinLeg {
+            departure_airport: departure.to_string(),
+            destination_airport: destination.to_string(),
+            cabins: cabins
+                .iter()
+                .map(|(cabin_class, passengers)| CabinPassengers {
+                    cabin_class: cabin_class.to_string(),
+                    passengers: *passengers,
+                })
+                .collect(),
+        }
+    }
+
+    fn leg(departure: &str, destination: &str) -> Leg {
+        Leg {
+            departure_airport: departure.to_string(),
+            destination_airport: destination.to_string(),
+            cabin_class: None,
+            passengers: None,
+            aircraft: None,
+        }
+    }
+
+    #[test]
+    fn test_builder_fills_in_the_request() {
+        let request = FlightEstimateRequest::builder()
+            .passengers(3)
+            .add_leg(leg("LHR", "JFK"))
+            .add_leg(leg("JFK", "LAX"))
+            .distance_unit("mi")
+            .build()
+            .unwrap();
+
+        assert_eq!(request.estimate_type, "flight");
+        assert_eq!(request.passengers, 3);
+        assert_eq!(request.legs, vec![leg("LHR", "JFK"), leg("JFK", "LAX")]);
+        assert_eq!(request.distance_unit.as_deref(), Some("mi"));
+    }
+
+    #[test]
+    fn test_builder_defaults_to_one_passenger() {
+        let request = FlightEstimateRequest::builder()
+            .add_leg(leg("LHR", "JFK"))
+            .build()
+            .unwrap();
+
+        assert_eq!(request.passengers, 1);
+        assert_eq!(request.distance_unit, None);
+    }
+
+    #[test]
+    fn test_builder_without_legs_is_an_error() {
+        let result = FlightEstimateRequest::builder().passengers(2).build();
+
+        assert!(matches!(result, Err(CliError::InvalidInput(_))));
+    }
+
+    #[test]
+    fn test_split_by_cabin_sends_one_request_per_class() {
+        let legs = vec![
+            split_leg("LHR", "JFK", &[("economy", 3), ("business", 1)]),
+            split_leg("JFK", "LAX", &[("economy", 4)]),
+        ];
+
+        let requests = split_by_cabin(&legs, Some("mi"));
+
+        let summary: Vec<(u32, &str, Option<&str>)> = requests
+            .iter()
+            .map(|request| {
+                (
+                    request.passengers,
+                    request.legs[0].departure_airport.as_str(),
+                    request.legs[0].cabin_class.as_deref(),
+                )
+            })
+            .collect();
+        assert_eq!(
+            summary,
+            vec![
+                (3, "LHR", Some("economy")),
+                (1, "LHR", Some("business")),
+                (4, "JFK", Some("economy")),
+            ]
+        );
+        assert!(requests.iter().all(
+            |request| request.legs.len() == 1 && request.distance_unit.as_deref() == Some("mi")
+        ));
+        assert_eq!(legs[0].passengers(), 4);
+    }
+
+    #[test]
+    fn test_split_legs_keeps_the_shared_settings() {
+        let request = FlightEstimateRequest {
+            estimate_type: default_estimate_type(),
+            passengers: 2,
+            legs: vec![
+                Leg {
+                    departure_airport: "LHR".to_string(),
+                    destination_airport: "JFK".to_string(),
+                    cabin_class: Some("business".to_string()),
+                    passengers: None,
+                    aircraft: None,
+                },
+                Leg {
+                    departure_airport: "JFK".to_string(),
+                    destination_airport: "LAX".to_string(),
+                    cabin_class: None,
+                    passengers: None,
+                    aircraft: None,
+                },
+            ],
+            distance_unit: Some("mi".to_string()),
+        };
+
+        let requests = split_legs(&request);
+
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].legs, request.legs[..1]);
+        assert_eq!(requests[1].legs, request.legs[1..]);
+        assert!(requests
+            .iter()
+            .all(|split| split.passengers == 2 && split.distance_unit.as_deref() == Some("mi")));
+    }
+
+    #[test]
+    fn test_split_by_leg_passengers_sends_changed_legs_on_their_own() {
+        let mut request = create_request(2, &[("LHR", "JFK"), ("JFK", "LAX"), ("LAX", "SFO")]);
+        request.legs[1].passengers = Some(3);
+        request.legs[2].passengers = Some(2);
+
+        let requests = split_by_leg_passengers(&request);
+
+        assert_eq!(requests.len(), 2);
+        assert_eq!(requests[0].passengers, 2);
+        let shared: Vec<&str> = requests[0]
+            .legs
+            .iter()
+            .map(|leg| leg.departure_airport.as_str())
+            .collect();
+        assert_eq!(shared, vec!["LHR", "LAX"]);
+        assert_eq!(requests[1].passengers, 3);
+        assert_eq!(requests[1].legs[0].departure_airport, "JFK");
+        assert!(requests
+            .iter()
+            .flat_map(|split| &split.legs)
+            .all(|leg| leg.passengers.is_none()));
+    }
+
+    #[test]
+    fn test_split_by_leg_passengers_leaves_plain_requests_alone() {
+        let request = create_request(2, &[("LHR", "JFK"), ("JFK", "LAX")]);
+
+        assert_eq!(split_by_leg_passengers(&request), vec![request]);
+    }
+
+    #[test]
+    fn test_leg_passengers_are_read_but_never_sent() {
+        let leg: Leg = serde_json::from_str(
+            r#"{"departure_airport": "LHR", "destination_airport": "JFK", "passengers": 3}"#,
+        )
+        .unwrap();
+        assert_eq!(leg.passengers, Some(3));
+
+        let request = create_request(1, &[("LHR", "JFK")]);
+        assert_eq!(
+            serde_json::to_value(&request.legs[0]).unwrap(),
+            serde_json::json!({"departure_airport": "LHR", "destination_airport": "JFK"})
+        );
+    }
+
+    #[test]
+    fn test_leg_aircraft_is_only_serialized_when_set() {
+        let mut leg = create_request(1, &[("LHR", "JFK")]).legs.remove(0);
+        assert_eq!(
+            serde_json::to_value(&leg).unwrap(),
+            serde_json::json!({"departure_airport": "LHR", "destination_airport": "JFK"})
+        );
+
+        leg.aircraft = Some("A20N".to_string());
+        assert_eq!(
+            serde_json::to_value(&leg).unwrap(),
+            serde_json::json!({
+                "departure_airport": "LHR",
+                "destination_airport": "JFK",
+                "aircraft": "A20N"
+            })
+        );
+    }
+
+    #[test]
+    fn test_aircraft_is_not_sent_to_the_api() {
+        let mut request = create_request(1, &[("LHR", "JFK"), ("JFK", "LAX")]);
+        request.legs[0].aircraft = Some("B789".to_string());
+
+        let sent = request.without_aircraft();
+
+        assert_eq!(sent.legs[0].aircraft, None);
+        assert_eq!(sent, create_request(1, &[("LHR", "JFK"), ("JFK", "LAX")]));
+    }
+
+    #[test]
+    fn test_reversed_mixed_cabin_leg_keeps_its_passengers() {
+        let leg = split_leg("LHR", "JFK", &[("economy", 3), ("business", 1)]);
+
+        let back = leg.reversed();
+
+        assert_eq!(back.departure_airport, "JFK");
+        assert_eq!(back.destination_airport, "LHR");
+        assert_eq!(back.cabins, leg.cabins);
+    }
+
+    #[test]
+    fn test_is_same_airport_leg() {
+        assert!(is_same_airport_leg(&leg("LHR", "LHR")));
+        assert!(is_same_airport_leg(&leg("lhr", " LHR")));
+        assert!(!is_same_airport_leg(&leg("LHR", "JFK")));
+        assert!(!is_same_airport_leg(&leg("LHR", "LGW")));
+    }
+
+    #[test]
+    fn test_validate_request_reports_every_problem() {
+        let mut request = create_request(0, &[("LHR", "JFK"), ("JFK", "XQZ"), ("L1", "l1")]);
+        request.distance_unit = Some("furlongs".to_string());
+        request.legs[0].cabin_class = Some("luxury".to_string());
+        request.legs[1].passengers = Some(0);
+
+        let issues = validate_request(&request);
+
+        assert_eq!(
+            issues,
+            vec![
+                ValidationIssue::NoPassengers,
+                ValidationIssue::UnknownDistanceUnit("furlongs".to_string()),
+                ValidationIssue::UnknownCabinClass {
+                    leg: 1,
+                    cabin_class: "luxury".to_string()
+                },
+                ValidationIssue::UnknownAirport {
+                    leg: 2,
+                    code: "XQZ".to_string()
+                },
+                ValidationIssue::NoLegPassengers { leg: 2 },
+                ValidationIssue::MalformedAirportCode {
+                    leg: 3,
+                    code: "L1".to_string()
+                },
+                ValidationIssue::MalformedAirportCode {
+                    leg: 3,
+                    code: "l1".to_string()
+                },
+                ValidationIssue::SameAirport {
+                    leg: 3,
+                    code: "L1".to_string()
+                },
+            ]
+        );
+        assert_eq!(
+            validate_request(&create_request(1, &[])),
+            vec![ValidationIssue::NoLegs]
+        );
+        assert!(validate_request(&create_request(2, &[("LHR", "JFK")])).is_empty());
+    }
+
+    #[test]
+    fn test_normalize_cabin_class_rejects_unknown_class() {
+        assert_eq!(normalize_cabin_class("luxury"), None);
+        assert_eq!(normalize_cabin_class(""), None);
+    }
+
+    #[test]
+    fn test_mirror_legs_single_leg() {
+        let legs = vec![Leg {
+            departure_airport: "LHR".to_string(),
+            destination_airport: "JFK".to_string(),
+            cabin_class: Some("business".to_string()),
+            passengers: None,
+            aircraft: None,
+        }];
+
+        assert_eq!(
+            mirror_legs(&legs),
+            vec![Leg {
+                departure_airport: "JFK".to_string(),
+                destination_airport: "LHR".to_string(),
+                cabin_class: Some("business".to_string()),
+                passengers: None,
+                aircraft: None,
+            }]
+        );
+    }
+
+    #[test]
+    fn test_mirror_legs_multi_leg() {
+        let legs = create_request(1, &[("LHR", "JFK"), ("JFK", "LAX")]).legs;
+
+        let route: Vec<(String, String)> = mirror_legs(&legs)
+            .into_iter()
+            .map(|leg| (leg.departure_airport, leg.destination_airport))
+            .collect();
+
+        assert_eq!(
+            route,
+            vec![
+                ("LAX".to_string(), "JFK".to_string()),
+                ("JFK".to_string(), "LHR".to_string()),
+            ]
+        );
+    }
+
+    #[test]
+    fn test_shipping_request_serializes_with_type() {
+        let request = ShippingEstimateRequest {
+            estimate_type: "shipping".to_string(),
+            weight_value: 200.0,
+            weight_unit: "g".to_string(),
+            distance_value: 2000.0,
+            distance_unit: "km".to_string(),
+            transport_method: "truck".to_string(),
+        };
+
+        assert_eq!(
+            serde_json::to_value(&request).unwrap(),
+            serde_json::json!({
+                "type": "shipping",
+                "weight_value": 200.0,
+                "weight_unit": "g",
+                "distance_value": 2000.0,
+                "distance_unit": "km",
+                "transport_method": "truck"
+            })
+        );
+    }
+}
@@ -0,0 +1,323 @@
+//! Rough estimates worked out locally when the API can't be reached.
+
+use crate::airports::airports;
+use crate::error::CliError;
+use crate::footprint::KM_PER_MILE;
+use crate::model::{EstimateAttributes, FlightEstimateRequest, Leg};
+use serde_derive::Deserialize;
+use std::collections::HashMap;
+use std::sync::OnceLock;
+
+/// Approximate kg of CO2 per passenger per km flown, averaged over short and long haul.
+pub const DEFAULT_EMISSION_FACTOR: f32 = 0.115;
+
+/// How much more each cabin class emits per passenger than the emission factor, reflecting
+/// the extra space its seats take up. Set in the config file's `[cabin_factors]` table;
+/// classes left out keep their defaults.
+#[derive(Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(default, deny_unknown_fields)]
+pub struct CabinFactors {
+    pub economy: f32,
+    pub premium: f32,
+    pub business: f32,
+    pub first: f32,
+}
+
+impl Default for CabinFactors {
+    fn default() -> Self {
+        Self {
+            economy: 1.0,
+            premium: 1.6,
+            business: 2.9,
+            first: 4.0,
+        }
+    }
+}
+
+/// The multiplier for a cabin class. Classes the API doesn't know are treated as economy,
+/// as the API does for legs without one.
+pub fn cabin_multiplier(class: &str, cfg: &CabinFactors) -> f32 {
+    match class {
+        "premium" => cfg.premium,
+        "business" => cfg.business,
+        "first" => cfg.first,
+        _ => cfg.economy,
+    }
+}
+
+/// How much more or less than a typical airliner each aircraft type burns per seat, by
+/// ICAO type designator. New-generation twins burn about 15% less; regional jets and
+/// older four-engine widebodies burn more. Types not listed count as typical.
+const AIRCRAFT_FACTORS: &[(&[&str], f32)] = &[
+    (
+        &[
+            "A20N", "A21N", "B38M", "B39M", "BCS1", "BCS3", "A339", "A359", "A35K", "B788", "B789",
+            "B78X",
+        ],
+        0.85,
+    ),
+    (&["AT72", "AT76", "DH8D"], 0.9),
+    (&["A343", "A346", "A388", "B744", "B748"], 1.2),
+    (
+        &["CRJ2", "CRJ7", "CRJ9", "E170", "E175", "E190", "E195"],
+        1.25,
+    ),
+];
+
+/// The multiplier for an aircraft type, or 1.0 for one that isn't listed.
+pub fn aircraft_multiplier(aircraft: &str) -> f32 {
+    AIRCRAFT_FACTORS
+        .iter()
+        .find(|(types, _)| {
+            types
+                .iter()
+                .any(|known| known.eq_ignore_ascii_case(aircraft))
+        })
+        .map_or(1.0, |(_, factor)| *factor)
+}
+
+/// Mean radius of the Earth in km.
+const EARTH_RADIUS_KM: f64 = 6371.0;
+
+/// Great-circle distance in km between two points given in decimal degrees.
+pub fn haversine_km(lat1: f64, lon1: f64, lat2: f64, lon2: f64) -> f32 {
+    let (lat1, lat2) = (lat1.to_radians(), lat2.to_radians());
+    let delta_lat = lat2 - lat1;
+    let delta_lon = (lon2 - lon1).to_radians();
+
+    let a =
+        (delta_lat / 2.0).sin().powi(2) + lat1.cos() * lat2.cos() * (delta_lon / 2.0).sin().powi(2);
+    (2.0 * EARTH_RADIUS_KM * a.sqrt().asin()) as f32
+}
+
+/// The great-circle length of `leg` in km, or `None` if either airport isn't in the list.
+pub fn leg_distance_km(leg: &Leg) -> Option<f32> {
+    let departure = locate(&leg.departure_airport).ok()?;
+    let destination = locate(&leg.destination_airport).ok()?;
+    Some(haversine_km(
+        departure.0,
+        departure.1,
+        destination.0,
+        destination.1,
+    ))
+}
+
+/// Approximates what the API would return for `request` from the great-circle length of
+/// each leg, `kg_per_passenger_km` and the leg's cabin and aircraft multipliers. Every
+/// airport has to be in the bundled list.
+pub fn offline_estimate(
+    request: &FlightEstimateRequest,
+    kg_per_passenger_km: f32,
+    cabin_factors: &CabinFactors,
+) -> Result<EstimateAttributes, CliError> {
+    let mut distance_km = 0.0;
+    let mut passenger_km = 0.0;
+    for leg in &request.legs {
+        let departure = locate(&leg.departure_airport)?;
+        let destination = locate(&leg.destination_airport)?;
+        let leg_km = haversine_km(departure.0, departure.1, destination.0, destination.1);
+        let cabin_class = leg.cabin_class.as_deref().unwrap_or("economy");
+        distance_km += leg_km;
+        let passengers = leg.passengers.unwrap_or(request.passengers);
+        let aircraft = leg.aircraft.as_deref().map_or(1.0, aircraft_multiplier);
+        passenger_km +=
+            leg_km * cabin_multiplier(cabin_class, cabin_factors) * aircraft * passengers as f32;
+    }
+
+    let carbon_kg = passenger_km * kg_per_passenger_km;
+    let (distance_unit, distance_value) = match request.distance_unit.as_deref() {
+        Some("mi") => ("mi", distance_km / KM_PER_MILE),
+        _ => ("km", distance_km),
+    };
+    Ok(EstimateAttributes {
+        carbon_g: carbon_kg * 1000.0,
+        carbon_lb: carbon_kg * 2.204_623,
+        carbon_kg,
+        carbon_mt: carbon_kg / 1000.0,
+        distance_unit: distance_unit.to_string(),
+        distance_value,
+    })
+}
+
+/// Latitude and longitude by IATA code, read from the airport list the first time an
+/// offline estimate needs it and reused for the rest of the run. A downloaded list has to
+/// be in use before then to be indexed.
+static COORDINATES: OnceLock<HashMap<String, (f32, f32)>> = OnceLock::new();
+
+fn coordinate_index() -> &'static HashMap<String, (f32, f32)> {
+    COORDINATES.get_or_init(|| {
+        airports()
+            .map(|airport| {
+                (
+                    airport.iata,
+                    (airport.latitude as f32, airport.longitude as f32),
+                )
+            })
+            .collect()
+    })
+}
+
+fn locate(code: &str) -> Result<(f64, f64), CliError> {
+    coordinate_index()
+        .get(code)
+        .map(|&(latitude, longitude)| (f64::from(latitude), f64::from(longitude)))
+        .ok_or_else(|| {
+            CliError::InvalidInput(format!(
+                "{} isn't in the bundled airport list, so it can't be estimated offline",
+                code
+            ))
+        })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::test_support::create_request;
+
+    fn assert_close(actual: f32, expected: f32, tolerance: f32) {
+        assert!(
+            (actual - expected).abs() <= tolerance,
+            "{} is not within {} of {}",
+            actual,
+            tolerance,
+            expected
+        );
+    }
+
+    #[test]
+    fn test_haversine_km_known_city_pairs() {
+        // London Heathrow to New York JFK
+        assert_close(
+            haversine_km(51.4700, -0.4543, 40.6413, -73.7781),
+            5540.0,
+            5.0,
+        );
+        // New York JFK to Los Angeles
+        assert_close(
+            haversine_km(40.6413, -73.7781, 33.9416, -118.4085),
+            3974.0,
+            5.0,
+        );
+        // Sydney to Los Angeles
+        assert_close(
+            haversine_km(-33.9399, 151.1753, 33.9416, -118.4085),
+            12061.0,
+            10.0,
+        );
+    }
+
+    #[test]
+    fn test_haversine_km_degenerate_cases() {
+        assert_eq!(haversine_km(51.47, -0.4543, 51.47, -0.4543), 0.0);
+        assert_close(haversine_km(0.0, 0.0, 0.0, 180.0), 20015.1, 1.0);
+    }
+
+    #[test]
+    fn test_offline_estimate_sums_legs_and_passengers() {
+        let request = create_request(2, &[("LHR", "JFK"), ("JFK", "LAX")]);
+
+        let estimate = offline_estimate(&request, 0.1, &CabinFactors::default()).unwrap();
+
+        assert_close(estimate.distance_value, 9514.0, 10.0);
+        assert_eq!(estimate.distance_unit, "km");
+        assert_close(estimate.carbon_kg, 1902.8, 2.0);
+        assert_close(estimate.carbon_mt, 1.9028, 0.002);
+    }
+
+    #[test]
+    fn test_offline_estimate_uses_each_legs_passengers() {
+        let mut request = create_request(2, &[("LHR", "JFK"), ("JFK", "LAX")]);
+        request.legs[1].passengers = Some(3);
+        let jfk_lax = offline_estimate(
+            &create_request(1, &[("JFK", "LAX")]),
+            0.1,
+            &CabinFactors::default(),
+        )
+        .unwrap();
+
+        let estimate = offline_estimate(&request, 0.1, &CabinFactors::default()).unwrap();
+
+        assert_close(estimate.distance_value, 9514.0, 10.0);
+        assert_close(estimate.carbon_kg, 1902.8 + jfk_lax.carbon_kg, 2.0);
+    }
+
+    #[test]
+    fn test_offline_estimate_in_miles() {
+        let mut request = create_request(1, &[("LHR", "JFK")]);
+        request.distance_unit = Some("mi".to_string());
+
+        let estimate = offline_estimate(&request, 0.1, &CabinFactors::default()).unwrap();
+
+        assert_eq!(estimate.distance_unit, "mi");
+        assert_close(estimate.distance_value, 3442.0, 5.0);
+    }
+
+    #[test]
+    fn test_cabin_multiplier_for_each_class() {
+        let factors = CabinFactors::default();
+
+        assert_eq!(cabin_multiplier("economy", &factors), 1.0);
+        assert_eq!(cabin_multiplier("premium", &factors), 1.6);
+        assert_eq!(cabin_multiplier("business", &factors), 2.9);
+        assert_eq!(cabin_multiplier("first", &factors), 4.0);
+    }
+
+    #[test]
+    fn test_cabin_multiplier_falls_back_to_economy() {
+        let factors = CabinFactors {
+            economy: 0.9,
+            ..CabinFactors::default()
+        };
+
+        assert_eq!(cabin_multiplier("luxury", &factors), 0.9);
+        assert_eq!(cabin_multiplier("", &factors), 0.9);
+    }
+
+    #[test]
+    fn test_offline_estimate_scales_each_leg_by_cabin() {
+        let mut request = create_request(1, &[("LHR", "JFK"), ("JFK", "LHR")]);
+        request.legs[1].cabin_class = Some("business".to_string());
+
+        let estimate = offline_estimate(&request, 0.1, &CabinFactors::default()).unwrap();
+
+        // 5540 km in economy plus 5540 km at 2.9x
+        assert_close(estimate.carbon_kg, 554.0 * 3.9, 2.0);
+        assert_close(estimate.distance_value, 11080.0, 10.0);
+    }
+
+    #[test]
+    fn test_offline_estimate_scales_each_leg_by_aircraft() {
+        let mut request = create_request(1, &[("LHR", "JFK"), ("JFK", "LHR")]);
+        request.legs[0].aircraft = Some("b789".to_string());
+        request.legs[1].aircraft = Some("ZZZZ".to_string());
+
+        let estimate = offline_estimate(&request, 0.1, &CabinFactors::default()).unwrap();
+
+        // 5540 km on a 787-9 at 0.85x plus 5540 km on an unlisted type at 1x
+        assert_close(estimate.carbon_kg, 554.0 * 1.85, 2.0);
+        assert_eq!(aircraft_multiplier("A388"), 1.2);
+    }
+
+    #[test]
+    fn test_airport_coordinates_are_indexed_once() {
+        let (latitude, longitude) = locate("LHR").unwrap();
+
+        assert_close(latitude as f32, 51.47, 0.01);
+        assert_close(longitude as f32, -0.4543, 0.01);
+        assert!(std::ptr::eq(coordinate_index(), coordinate_index()));
+        assert!(matches!(
+            locate("ZZZ"),
+            Err(CliError::InvalidInput(message)) if message.starts_with("ZZZ isn't in")
+        ));
+    }
+
+    #[test]
+    fn test_offline_estimate_needs_known_airports() {
+        let request = create_request(1, &[("LHR", "ZZZ")]);
+
+        assert!(matches!(
+            offline_estimate(&request, 0.1, &CabinFactors::default()),
+            Err(CliError::InvalidInput(_))
+        ));
+    }
+}
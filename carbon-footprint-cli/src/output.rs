@@ -0,0 +1,2629 @@
+//! Rendering estimates as text, JSON, CSV, tables, Markdown, HTML or Prometheus metrics,
+//! in the order they're asked for.
+
+use crate::currency::Currency;
+use crate::footprint::{
+    alternative_emissions, apply_rf, compare_estimates, distance_km, equivalencies, intensity,
+    offset_cost, per_passenger, reduction_vs_flying, select_units, sum_attributes, sum_known,
+    CarbonUnit, Comparison, Greener, SessionTotals,
+};
+use crate::i18n::{t, tf};
+use crate::model::{
+    is_missing, ElectricityEstimateAttributes, EstimateAttributes, EstimateResult,
+    FlightEstimateRequest, ItineraryGroup, Leg, ShippingEstimateAttributes,
+    VehicleEstimateAttributes,
+};
+use clap::ValueEnum;
+use colored::*;
+use prettytable::{format, row, Cell, Row, Table};
+use serde::Serialize;
+use serde_derive::Deserialize;
+use std::cmp::Ordering;
+use std::path::Path;
+
+#[derive(ValueEnum, Deserialize, Clone, Copy, Debug, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum OutputFormat {
+    /// Human-readable table
+    Text,
+    /// The raw estimate attributes as a JSON object
+    Json,
+    /// A CSV header and one row per request
+    Csv,
+    /// A bordered table with one row per request and a totals row
+    Table,
+    /// A Markdown section per request, with a totals table for several
+    Markdown,
+    /// A self-contained HTML snippet with a summary banner and a table of requests
+    Html,
+    /// Prometheus text exposition format, with carbon and distance gauges per request
+    Prometheus,
+}
+
+impl OutputFormat {
+    /// The extension of files written in this format, one `format_from_path` recognises.
+    pub fn extension(self) -> &'static str {
+        match self {
+            OutputFormat::Text | OutputFormat::Table => "txt",
+            OutputFormat::Json => "json",
+            OutputFormat::Csv => "csv",
+            OutputFormat::Markdown => "md",
+            OutputFormat::Html => "html",
+            OutputFormat::Prometheus => "prom",
+        }
+    }
+}
+
+/// A file name, without extension, for an itinerary called `name`. Anything but ASCII
+/// letters, digits, `-` and `_` becomes `-`, so a route such as `LHR>JFK` gives `LHR-JFK`
+/// and no label can name a path outside the output directory.
+pub fn itinerary_file_stem(name: &str) -> String {
+    let stem: String = name
+        .chars()
+        .map(|c| {
+            if c.is_ascii_alphanumeric() || c == '-' || c == '_' {
+                c
+            } else {
+                '-'
+            }
+        })
+        .collect();
+    let stem = stem.trim_matches('-');
+    if stem.is_empty() {
+        "itinerary".to_string()
+    } else {
+        stem.to_string()
+    }
+}
+
+/// The format an output file's extension asks for, e.g. CSV for `trips.csv`. Unrecognised
+/// extensions give `None`.
+pub fn format_from_path(path: &Path) -> Option<OutputFormat> {
+    let extension = path.extension()?.to_str()?.to_ascii_lowercase();
+    match extension.as_str() {
+        "json" => Some(OutputFormat::Json),
+        "csv" => Some(OutputFormat::Csv),
+        "txt" => Some(OutputFormat::Text),
+        "md" | "markdown" => Some(OutputFormat::Markdown),
+        "html" | "htm" => Some(OutputFormat::Html),
+        "prom" => Some(OutputFormat::Prometheus),
+        _ => None,
+    }
+}
+
+/// Display settings shared by every output format.
+#[derive(Clone)]
+pub struct RenderOptions {
+    /// USD per tonne of CO2 used to price offsets
+    pub offset_price: f32,
+    /// The currency offset costs are shown in
+    pub currency: Currency,
+    /// Whether the text output translates emissions into everyday equivalents
+    pub show_equivalencies: bool,
+    /// Whether the text output compares flying with taking the train or driving
+    pub compare_alternatives: bool,
+    /// Whether the text output is reduced to the bare `carbon_kg` figure, for scripts
+    pub quiet: bool,
+    /// Which emissions figures to show. Left empty, text shows kg while JSON and CSV keep
+    /// their full set of columns.
+    pub units: Vec<CarbonUnit>,
+    /// Whether JSON goes on one line, with a batch as one object per line (JSON Lines)
+    pub compact: bool,
+    /// Radiative forcing multiplier for the CO2-equivalent figure shown next to the
+    /// emissions. At 1.0 no such figure is shown.
+    pub rf_multiplier: f32,
+    /// Decimal places shown for kg and distances; tonnes get one more
+    pub precision: u32,
+}
+
+/// Decimal places shown unless `--precision` says otherwise.
+pub const DEFAULT_PRECISION: u32 = 2;
+
+impl RenderOptions {
+    fn places(&self) -> usize {
+        self.precision as usize
+    }
+
+    fn tonne_places(&self) -> usize {
+        self.places() + 1
+    }
+}
+
+/// `value` rounded to `places` decimal places, halves away from zero. Only what's shown
+/// is rounded; totals are worked out from the figures as they came.
+pub fn round_to(value: f32, places: u32) -> f32 {
+    let scale = 10f64.powi(places as i32);
+    ((f64::from(value) * scale).round() / scale) as f32
+}
+
+/// `attributes` rounded for JSON and CSV, each carbon figure to about what `precision`
+/// places of kg would show.
+fn rounded(attributes: &EstimateAttributes, precision: u32) -> EstimateAttributes {
+    EstimateAttributes {
+        carbon_g: round_to(attributes.carbon_g, precision.saturating_sub(3)),
+        carbon_lb: round_to(attributes.carbon_lb, precision),
+        carbon_kg: round_to(attributes.carbon_kg, precision),
+        carbon_mt: round_to(attributes.carbon_mt, precision + 3),
+        distance_unit: attributes.distance_unit.clone(),
+        distance_value: round_to(attributes.distance_value, precision),
+    }
+}
+
+/// `value` as JSON: on one line when `options.compact`, else indented for reading.
+fn to_json(value: &impl Serialize, options: &RenderOptions) -> String {
+    if options.compact {
+        serde_json::to_string(value)
+    } else {
+        serde_json::to_string_pretty(value)
+    }
+    .expect("estimate attributes always serialize to JSON")
+}
+
+/// The emissions units shown in text output.
+fn text_units(options: &RenderOptions) -> &[CarbonUnit] {
+    if options.units.is_empty() {
+        &[CarbonUnit::Kg]
+    } else {
+        &options.units
+    }
+}
+
+/// The emissions columns in CSV output.
+fn csv_units(options: &RenderOptions) -> &[CarbonUnit] {
+    if options.units.is_empty() {
+        &DEFAULT_CSV_UNITS
+    } else {
+        &options.units
+    }
+}
+
+/// An estimate's attributes as JSON, keeping only the selected emissions figures.
+fn attributes_json(attributes: &EstimateAttributes, options: &RenderOptions) -> serde_json::Value {
+    let mut json = serde_json::to_value(rounded(attributes, options.precision))
+        .expect("estimate attributes always serialize to JSON");
+    if !options.units.is_empty() {
+        let selected = select_units(attributes, &options.units);
+        if let serde_json::Value::Object(fields) = &mut json {
+            fields.retain(|key, _| match key.strip_prefix("carbon_") {
+                Some(suffix) => selected.iter().any(|(unit, _)| unit.suffix() == suffix),
+                None => true,
+            });
+        }
+    }
+    if options.rf_multiplier != 1.0 {
+        let co2e = apply_rf(attributes, options.rf_multiplier);
+        if let serde_json::Value::Object(fields) = &mut json {
+            fields.insert("rf_multiplier".to_string(), options.rf_multiplier.into());
+            let co2e = rounded(&co2e, options.precision);
+            fields.insert("co2e_kg".to_string(), co2e.carbon_kg.into());
+            fields.insert("co2e_mt".to_string(), co2e.carbon_mt.into());
+        }
+    }
+    json
+}
+
+/// Renders an estimate for display in the requested output format.
+pub fn render_estimate(
+    request: &FlightEstimateRequest,
+    attributes: &EstimateAttributes,
+    format: OutputFormat,
+    options: &RenderOptions,
+) -> String {
+    match format {
+        OutputFormat::Text => render_text(request, attributes, options),
+        OutputFormat::Table => render_table(
+            &[EstimateResult {
+                request: request.clone(),
+                attributes: attributes.clone(),
+            }],
+            options,
+        ),
+        OutputFormat::Markdown => markdown_section(request, attributes, options),
+        OutputFormat::Html => to_html(
+            &[EstimateResult {
+                request: request.clone(),
+                attributes: attributes.clone(),
+            }],
+            options,
+        ),
+        OutputFormat::Prometheus => to_prometheus(&[EstimateResult {
+            request: request.clone(),
+            attributes: attributes.clone(),
+        }]),
+        OutputFormat::Json => to_json(&attributes_json(attributes, options), options),
+        OutputFormat::Csv => format!(
+            "{}\n{}",
+            csv_header(csv_units(options)),
+            csv_row(
+                request,
+                &rounded(attributes, options.precision),
+                csv_units(options)
+            )
+        ),
+    }
+}
+
+/// Renders several estimates at once: one text block each, a JSON array, or CSV rows under a
+/// single header.
+pub fn render_batch(
+    estimates: &[EstimateResult],
+    format: OutputFormat,
+    options: &RenderOptions,
+) -> String {
+    match format {
+        OutputFormat::Text => estimates
+            .iter()
+            .map(|estimate| render_text(&estimate.request, &estimate.attributes, options))
+            .collect::<Vec<_>>()
+            .join("\n"),
+        OutputFormat::Table => render_table(estimates, options),
+        OutputFormat::Markdown => render_markdown(estimates, options),
+        OutputFormat::Html => to_html(estimates, options),
+        OutputFormat::Prometheus => to_prometheus(estimates),
+        OutputFormat::Json => {
+            let attributes: Vec<serde_json::Value> = estimates
+                .iter()
+                .map(|estimate| attributes_json(&estimate.attributes, options))
+                .collect();
+            if options.compact {
+                // One object per line, so each can be read as it arrives
+                attributes
+                    .iter()
+                    .map(|attributes| to_json(attributes, options))
+                    .collect::<Vec<_>>()
+                    .join("\n")
+            } else {
+                to_json(&attributes, options)
+            }
+        }
+        OutputFormat::Csv => std::iter::once(csv_header(csv_units(options)))
+            .chain(estimates.iter().map(|estimate| {
+                csv_row(
+                    &estimate.request,
+                    &rounded(&estimate.attributes, options.precision),
+                    csv_units(options),
+                )
+            }))
+            .collect::<Vec<_>>()
+            .join("\n"),
+    }
+}
+
+/// Renders a batch grouped into itineraries: each itinerary's estimates followed by its
+/// subtotal, then a grand total of the subtotals. Quiet text output leaves the totals out.
+pub fn render_itineraries(
+    groups: &[ItineraryGroup],
+    format: OutputFormat,
+    options: &RenderOptions,
+) -> String {
+    let sections = Section::of_itineraries(groups);
+    let subtotals: Vec<EstimateAttributes> = sections.iter().map(Section::subtotal).collect();
+    let total = sum_attributes(&subtotals);
+    let estimates: Vec<EstimateResult> = groups
+        .iter()
+        .flat_map(|group| group.estimates.iter().cloned())
+        .collect();
+
+    match format {
+        OutputFormat::Text if options.quiet => render_batch(&estimates, format, options),
+        OutputFormat::Text => {
+            let mut blocks: Vec<String> = groups
+                .iter()
+                .zip(&subtotals)
+                .map(|(group, subtotal)| {
+                    format!(
+                        "{}\n{}",
+                        render_batch(&group.estimates, format, options),
+                        render_summary_text(
+                            &format!("Subtotal for {}", group.name),
+                            subtotal.carbon_kg,
+                            subtotal.carbon_mt,
+                            options
+                        )
+                    )
+                })
+                .collect();
+            blocks.push(format!(
+                "{}\n{}",
+                "=".repeat(40),
+                render_total(&estimates, options)
+            ));
+            blocks.join("\n\n")
+        }
+        OutputFormat::Table => table_report(&sections, options),
+        OutputFormat::Html => html_report(&sections, options),
+        OutputFormat::Prometheus => to_prometheus(&estimates),
+        OutputFormat::Markdown => {
+            let mut blocks: Vec<String> = Vec::new();
+            for (group, subtotal) in groups.iter().zip(&subtotals) {
+                blocks.extend(group.estimates.iter().map(|estimate| {
+                    markdown_section(&estimate.request, &estimate.attributes, options)
+                }));
+                blocks.push(format!(
+                    "**Subtotal for {}: {} kg CO2**",
+                    group.name,
+                    format_figure(subtotal.carbon_kg, options.places())
+                ));
+            }
+            let mut totals = vec![
+                "## Total".to_string(),
+                String::new(),
+                "| Itinerary | Trips | carbon_kg |".to_string(),
+                "| --- | ---: | ---: |".to_string(),
+            ];
+            for (group, subtotal) in groups.iter().zip(&subtotals) {
+                totals.push(format!(
+                    "| {} | {} | {} |",
+                    group.name.replace('|', "\\|"),
+                    group.estimates.len(),
+                    format_figure(subtotal.carbon_kg, options.places())
+                ));
+            }
+            totals.push(format!(
+                "| **Total** | | **{}** |",
+                format_figure(total.carbon_kg, options.places())
+            ));
+            blocks.push(totals.join("\n"));
+            blocks.join("\n\n")
+        }
+        OutputFormat::Json => {
+            let itineraries: Vec<serde_json::Value> = groups
+                .iter()
+                .zip(&subtotals)
+                .map(|(group, subtotal)| {
+                    serde_json::json!({
+                        "itinerary": group.name,
+                        "estimates": group
+                            .estimates
+                            .iter()
+                            .map(|estimate| attributes_json(&estimate.attributes, options))
+                            .collect::<Vec<_>>(),
+                        "subtotal": attributes_json(subtotal, options),
+                    })
+                })
+                .collect();
+            to_json(
+                &serde_json::json!({
+                    "itineraries": itineraries,
+                    "total": attributes_json(&total, options),
+                }),
+                options,
+            )
+        }
+        OutputFormat::Csv => {
+            let units = csv_units(options);
+            let mut lines = vec![csv_header(units)];
+            for (section, subtotal) in sections.iter().zip(&subtotals) {
+                lines.extend(section.estimates.iter().map(|estimate| {
+                    csv_row(
+                        &estimate.request,
+                        &rounded(&estimate.attributes, options.precision),
+                        units,
+                    )
+                }));
+                lines.push(csv_total_row(
+                    "subtotal",
+                    section.itinerary.unwrap_or_default(),
+                    section.passengers(),
+                    &rounded(subtotal, options.precision),
+                    units,
+                ));
+            }
+            let passengers = sections.iter().map(Section::passengers).sum();
+            let total = rounded(&total, options.precision);
+            lines.push(csv_total_row("total", "", passengers, &total, units));
+            lines.join("\n")
+        }
+    }
+}
+
+/// Renders a vehicle estimate; there's no per-passenger or multi-leg breakdown for these.
+pub fn render_vehicle_estimate(
+    attributes: &VehicleEstimateAttributes,
+    format: OutputFormat,
+    options: &RenderOptions,
+) -> String {
+    match format {
+        OutputFormat::Text if options.quiet => {
+            format!("{:.*}", options.places(), attributes.carbon_kg)
+        }
+        OutputFormat::Text
+        | OutputFormat::Table
+        | OutputFormat::Markdown
+        | OutputFormat::Html
+        | OutputFormat::Prometheus => render_vehicle_text(attributes, options),
+        OutputFormat::Json => to_json(attributes, options),
+        OutputFormat::Csv => format!(
+            "{}\n{},{},{},{},{},{},{},{}",
+            VEHICLE_CSV_HEADER,
+            csv_field(&attributes.vehicle_model_id),
+            csv_field(&attributes.vehicle_make),
+            csv_field(&attributes.vehicle_model),
+            attributes.vehicle_year,
+            attributes.carbon_kg,
+            attributes.carbon_mt,
+            attributes.distance_value,
+            csv_field(&attributes.distance_unit)
+        ),
+    }
+}
+
+pub const VEHICLE_CSV_HEADER: &str = "vehicle_model_id,vehicle_make,vehicle_model,vehicle_year,carbon_kg,carbon_mt,distance_value,distance_unit";
+
+fn render_vehicle_text(attributes: &VehicleEstimateAttributes, options: &RenderOptions) -> String {
+    let heading = format!(
+        "🚗 Estimated carbon emissions for {:.*} {} in a {} {} {}: 🚗",
+        options.places(),
+        attributes.distance_value,
+        attributes.distance_unit,
+        attributes.vehicle_year,
+        attributes.vehicle_make,
+        attributes.vehicle_model
+    );
+    render_summary_text(
+        &heading,
+        attributes.carbon_kg,
+        attributes.carbon_mt,
+        options,
+    )
+}
+
+/// Renders a shipping estimate in the requested output format.
+pub fn render_shipping_estimate(
+    attributes: &ShippingEstimateAttributes,
+    format: OutputFormat,
+    options: &RenderOptions,
+) -> String {
+    match format {
+        OutputFormat::Text if options.quiet => {
+            format!("{:.*}", options.places(), attributes.carbon_kg)
+        }
+        OutputFormat::Text
+        | OutputFormat::Table
+        | OutputFormat::Markdown
+        | OutputFormat::Html
+        | OutputFormat::Prometheus => {
+            let heading = format!(
+                "📦 Estimated carbon emissions for shipping {} {} {} {} by {}: 📦",
+                attributes.weight_value,
+                attributes.weight_unit,
+                attributes.distance_value,
+                attributes.distance_unit,
+                attributes.transport_method
+            );
+            render_summary_text(
+                &heading,
+                attributes.carbon_kg,
+                attributes.carbon_mt,
+                options,
+            )
+        }
+        OutputFormat::Json => to_json(attributes, options),
+        OutputFormat::Csv => format!(
+            "{}\n{},{},{},{},{},{},{}",
+            SHIPPING_CSV_HEADER,
+            csv_field(&attributes.transport_method),
+            attributes.weight_value,
+            csv_field(&attributes.weight_unit),
+            attributes.carbon_kg,
+            attributes.carbon_mt,
+            attributes.distance_value,
+            csv_field(&attributes.distance_unit)
+        ),
+    }
+}
+
+/// Renders an electricity estimate in the requested output format.
+pub fn render_electricity_estimate(
+    attributes: &ElectricityEstimateAttributes,
+    format: OutputFormat,
+    options: &RenderOptions,
+) -> String {
+    match format {
+        OutputFormat::Text if options.quiet => {
+            format!("{:.*}", options.places(), attributes.carbon_kg)
+        }
+        OutputFormat::Text
+        | OutputFormat::Table
+        | OutputFormat::Markdown
+        | OutputFormat::Html
+        | OutputFormat::Prometheus => {
+            let place = match &attributes.state {
+                Some(state) => format!("{}-{}", attributes.country, state),
+                None => attributes.country.clone(),
+            };
+            let heading = format!(
+                "⚡ Estimated carbon emissions for {} {} of electricity in {}: ⚡",
+                attributes.electricity_value,
+                attributes.electricity_unit,
+                place.to_uppercase()
+            );
+            render_summary_text(
+                &heading,
+                attributes.carbon_kg,
+                attributes.carbon_mt,
+                options,
+            )
+        }
+        OutputFormat::Json => to_json(attributes, options),
+        OutputFormat::Csv => format!(
+            "{}\n{},{},{},{},{},{}",
+            ELECTRICITY_CSV_HEADER,
+            csv_field(&attributes.country),
+            csv_field(attributes.state.as_deref().unwrap_or("")),
+            attributes.electricity_value,
+            csv_field(&attributes.electricity_unit),
+            attributes.carbon_kg,
+            attributes.carbon_mt
+        ),
+    }
+}
+
+pub const ELECTRICITY_CSV_HEADER: &str =
+    "country,state,electricity_value,electricity_unit,carbon_kg,carbon_mt";
+
+pub const SHIPPING_CSV_HEADER: &str =
+    "transport_method,weight_value,weight_unit,carbon_kg,carbon_mt,distance_value,distance_unit";
+
+/// The short text layout for estimates without flight details: a heading, the emissions
+/// and the offset cost.
+fn render_summary_text(
+    heading: &str,
+    carbon_kg: f32,
+    carbon_mt: f32,
+    options: &RenderOptions,
+) -> String {
+    let mut output = format!("{}\n", heading.bold().green());
+    output.push_str(&format!(
+        "{} {} kg ({:.*} t)\n",
+        "Carbon emissions:".bold(),
+        colorize_emissions(&format!("{:.*}", options.places(), carbon_kg), carbon_kg),
+        options.tonne_places(),
+        carbon_mt
+    ));
+    output.push_str(&format!(
+        "Estimated offset cost: {} (at {} per tonne)",
+        options
+            .currency
+            .format(offset_cost(carbon_mt, options.offset_price)),
+        options.currency.format(options.offset_price)
+    ));
+    output
+}
+
+pub const CSV_HEADER: &str =
+    "departure,destination,passengers,carbon_kg,carbon_mt,distance_value,distance_unit";
+
+/// The emissions columns in `CSV_HEADER`, used unless `--unit` picks others.
+const DEFAULT_CSV_UNITS: [CarbonUnit; 2] = [CarbonUnit::Kg, CarbonUnit::Mt];
+
+/// The CSV header with a `carbon_<unit>` column for each of `units`.
+pub fn csv_header(units: &[CarbonUnit]) -> String {
+    let mut columns = vec!["departure", "destination", "passengers"];
+    let carbon_columns: Vec<String> = units
+        .iter()
+        .map(|unit| format!("carbon_{}", unit.suffix()))
+        .collect();
+    columns.extend(carbon_columns.iter().map(String::as_str));
+    columns.extend(["distance_value", "distance_unit"]);
+    columns.join(",")
+}
+
+/// Formats one CSV row matching `CSV_HEADER`. Multi-leg itineraries list every
+/// stop after the origin in the destination column, joined with `>`.
+pub fn to_csv_row(request: &FlightEstimateRequest, attributes: &EstimateAttributes) -> String {
+    csv_row(request, attributes, &DEFAULT_CSV_UNITS)
+}
+
+/// Formats one CSV row matching `csv_header(units)`.
+fn csv_row(
+    request: &FlightEstimateRequest,
+    attributes: &EstimateAttributes,
+    units: &[CarbonUnit],
+) -> String {
+    let stops = route_stops(&request.legs);
+    let (departure, destinations) = stops.split_first().unwrap_or((&"", &[]));
+
+    let mut fields = vec![
+        departure.to_string(),
+        destinations.join(">"),
+        request.passengers.to_string(),
+    ];
+    fields.extend(
+        select_units(attributes, units)
+            .into_iter()
+            .map(|(_, value)| csv_figure(value)),
+    );
+    fields.push(csv_figure(attributes.distance_value));
+    fields.push(attributes.distance_unit.clone());
+    fields.join(",")
+}
+
+/// `value` unrounded for CSV, or "N/A" for a `MISSING_FIGURE`.
+fn csv_figure(value: f32) -> String {
+    if is_missing(value) {
+        MISSING_TEXT.to_string()
+    } else {
+        value.to_string()
+    }
+}
+
+/// `value` as a CSV field, quoted when it holds a comma, quote or newline.
+fn csv_field(value: &str) -> String {
+    if value.contains([',', '"', '\n']) {
+        format!("\"{}\"", value.replace('"', "\"\""))
+    } else {
+        value.to_string()
+    }
+}
+
+/// A subtotal or total row for `csv_header(units)`, labelled in the departure column. A
+/// total over mixed distance units gives its distance in km.
+fn csv_total_row(
+    label: &str,
+    name: &str,
+    passengers: u32,
+    attributes: &EstimateAttributes,
+    units: &[CarbonUnit],
+) -> String {
+    // Itinerary names come from the batch file, so may need quoting
+    let mut fields = vec![label.to_string(), csv_field(name), passengers.to_string()];
+    fields.extend(
+        select_units(attributes, units)
+            .into_iter()
+            .map(|(_, value)| csv_figure(value)),
+    );
+    fields.push(csv_figure(attributes.distance_value));
+    fields.push(attributes.distance_unit.clone());
+    fields.join(",")
+}
+
+/// Compares one passenger's share of a flight with covering the same distance by train or
+/// car.
+fn render_alternatives(flight_kg: f32, distance_km: f32, places: usize) -> String {
+    let alternatives = alternative_emissions(distance_km);
+    let versus_flying = |alternative_kg: f32| match reduction_vs_flying(flight_kg, alternative_kg) {
+        Some(percent) if percent >= 0.0 => format!(" ({:.0}% less than flying)", percent),
+        Some(percent) => format!(" ({:.0}% more than flying)", -percent),
+        None => String::new(),
+    };
+
+    let mut output = format!("\nPer passenger over the same {:.0} km:\n", distance_km);
+    output.push_str(&format!("  ✈️ Flying: {:.*} kg\n", places, flight_kg));
+    output.push_str(&format!(
+        "  🚆 Train: {:.*} kg{}\n",
+        places,
+        alternatives.train_kg,
+        versus_flying(alternatives.train_kg)
+    ));
+    output.push_str(&format!(
+        "  🚗 Car: {:.*} kg{}\n",
+        places,
+        alternatives.car_kg,
+        versus_flying(alternatives.car_kg)
+    ));
+    output
+}
+
+/// What an estimate for `request` stands for and how it's worked out, in a few lines for
+/// `--explain`.
+pub fn explanation(request: &FlightEstimateRequest) -> String {
+    let mut cabins: Vec<&str> = Vec::new();
+    for leg in &request.legs {
+        let cabin = leg.cabin_class.as_deref().unwrap_or("economy");
+        if !cabins.contains(&cabin) {
+            cabins.push(cabin);
+        }
+    }
+    let passengers = match request.passengers {
+        1 => "the 1 passenger".to_string(),
+        passengers => format!("all {} passengers combined", passengers),
+    };
+
+    let mut output = String::from("About this estimate:\n");
+    output.push_str(&format!(
+        "  • It covers {}, over every leg of the trip.\n",
+        passengers
+    ));
+    output.push_str(
+        "  • It counts the CO2 from burning the fuel in flight (tank-to-wake), not from \
+         producing and delivering it; a well-to-wake figure would be higher.\n",
+    );
+    output.push_str(&format!(
+        "  • Cabin class: {}. Premium, business and first seats take more space, so each \
+         of those passengers is given a larger share of the flight's emissions.\n",
+        cabins.join(", ")
+    ));
+    output.push_str(
+        "  • The distance is the great-circle distance between the airports, plus an uplift \
+         for the detours and holding patterns real flights add.\n",
+    );
+    output
+}
+
+/// The emissions units `format` shows: the `--unit` selection if there is one, else
+/// every unit in JSON and kg with tonnes everywhere else.
+pub fn shown_units(format: OutputFormat, options: &RenderOptions) -> Vec<CarbonUnit> {
+    if !options.units.is_empty() {
+        return options.units.clone();
+    }
+    match format {
+        OutputFormat::Json => vec![
+            CarbonUnit::G,
+            CarbonUnit::Kg,
+            CarbonUnit::Lb,
+            CarbonUnit::Mt,
+        ],
+        _ => DEFAULT_CSV_UNITS.to_vec(),
+    }
+}
+
+/// A short glossary of `units`, in the order given and without repeats, and of the
+/// distance, for `--units-legend`.
+pub fn units_legend(units: &[CarbonUnit]) -> String {
+    let mut seen: Vec<CarbonUnit> = Vec::new();
+    let mut output = String::from("Units:\n");
+    for &unit in units {
+        if seen.contains(&unit) {
+            continue;
+        }
+        seen.push(unit);
+        output.push_str(match unit {
+            CarbonUnit::G => "  • g: grams of CO2; 1,000 g make 1 kg.\n",
+            CarbonUnit::Kg => "  • kg: kilograms of CO2.\n",
+            CarbonUnit::Lb => "  • lb: pounds of CO2; 1 lb is about 0.454 kg.\n",
+            CarbonUnit::Mt => "  • mt (t): metric tonnes of CO2; 1 t is 1,000 kg.\n",
+        });
+    }
+    output.push_str(
+        "  • Distances are great-circle: the shortest path over the Earth between the \
+         airports.\n",
+    );
+    output
+}
+
+/// Lays estimates out in a bordered table, one row per request, with a totals row at the
+/// bottom. Columns are padded to their widest cell and numbers are right-aligned.
+pub fn render_table(estimates: &[EstimateResult], options: &RenderOptions) -> String {
+    table_report(
+        &[Section {
+            itinerary: None,
+            estimates,
+        }],
+        options,
+    )
+}
+
+/// Part of a report: some estimates, followed by a subtotal when they make up a named
+/// itinerary.
+struct Section<'a> {
+    itinerary: Option<&'a str>,
+    estimates: &'a [EstimateResult],
+}
+
+impl<'a> Section<'a> {
+    fn of_itineraries(groups: &'a [ItineraryGroup]) -> Vec<Self> {
+        groups
+            .iter()
+            .map(|group| Section {
+                itinerary: Some(&group.name),
+                estimates: &group.estimates,
+            })
+            .collect()
+    }
+
+    fn passengers(&self) -> u32 {
+        self.estimates.iter().map(|e| e.request.passengers).sum()
+    }
+
+    fn subtotal(&self) -> EstimateAttributes {
+        let attributes: Vec<EstimateAttributes> = self
+            .estimates
+            .iter()
+            .map(|estimate| estimate.attributes.clone())
+            .collect();
+        sum_attributes(&attributes)
+    }
+}
+
+fn table_report(sections: &[Section], options: &RenderOptions) -> String {
+    let header = ["Route", "Passengers", "Cabin", "carbon_kg"];
+    let mut rows: Vec<[String; 4]> = Vec::new();
+    for section in sections {
+        rows.extend(section.estimates.iter().map(|estimate| {
+            [
+                route_stops(&estimate.request.legs).join(">"),
+                estimate.request.passengers.to_string(),
+                cabin_classes(&estimate.request.legs),
+                format_figure(estimate.attributes.carbon_kg, options.places()),
+            ]
+        }));
+        if let Some(itinerary) = section.itinerary {
+            rows.push([
+                format!("Subtotal: {}", itinerary),
+                section.passengers().to_string(),
+                String::new(),
+                format_figure(section.subtotal().carbon_kg, options.places()),
+            ]);
+        }
+    }
+    let total_passengers: u32 = sections.iter().map(Section::passengers).sum();
+    let total_kg = sum_known(sections.iter().map(|section| section.subtotal().carbon_kg));
+    let footer = [
+        "TOTAL".to_string(),
+        total_passengers.to_string(),
+        String::new(),
+        format_figure(total_kg, options.places()),
+    ];
+
+    let mut widths = header.map(|title| title.chars().count());
+    for row in rows.iter().chain(std::iter::once(&footer)) {
+        for (width, cell) in widths.iter_mut().zip(row) {
+            *width = (*width).max(cell.chars().count());
+        }
+    }
+    // Passengers and carbon_kg are numeric
+    let right_aligned = [false, true, false, true];
+
+    let border = format!(
+        "+{}+",
+        widths
+            .iter()
+            .map(|width| "-".repeat(width + 2))
+            .collect::<Vec<_>>()
+            .join("+")
+    );
+    let format_row = |cells: &[String; 4]| {
+        let cells: Vec<String> = cells
+            .iter()
+            .zip(widths)
+            .zip(right_aligned)
+            .map(|((cell, width), right)| {
+                let padding = " ".repeat(width - cell.chars().count());
+                if right {
+                    format!(" {}{} ", padding, cell)
+                } else {
+                    format!(" {}{} ", cell, padding)
+                }
+            })
+            .collect();
+        format!("|{}|", cells.join("|"))
+    };
+
+    let mut lines = vec![
+        border.clone(),
+        format_row(&header.map(String::from)),
+        border.clone(),
+    ];
+    lines.extend(rows.drain(..).map(|row| format_row(&row)));
+    lines.push(border.clone());
+    lines.push(format_row(&footer));
+    lines.push(border);
+    lines.join("\n")
+}
+
+/// Renders estimates as Markdown, one section each, followed by a table totalling them.
+pub fn render_markdown(estimates: &[EstimateResult], options: &RenderOptions) -> String {
+    let mut sections: Vec<String> = estimates
+        .iter()
+        .map(|estimate| markdown_section(&estimate.request, &estimate.attributes, options))
+        .collect();
+
+    let mut totals = vec![
+        "## Total".to_string(),
+        String::new(),
+        "| Trip | Route | carbon_kg |".to_string(),
+        "| --- | --- | ---: |".to_string(),
+    ];
+    for (i, estimate) in estimates.iter().enumerate() {
+        totals.push(format!(
+            "| {} | {} | {} |",
+            i + 1,
+            route_stops(&estimate.request.legs).join(" → "),
+            format_figure(estimate.attributes.carbon_kg, options.places())
+        ));
+    }
+    let total_kg = sum_known(estimates.iter().map(|e| e.attributes.carbon_kg));
+    totals.push(format!(
+        "| **Total** | | **{}** |",
+        format_figure(total_kg, options.places())
+    ));
+    sections.push(totals.join("\n"));
+    sections.join("\n\n")
+}
+
+/// One estimate as a Markdown section: the route as a heading, its legs as a list and the
+/// figures in a table.
+fn markdown_section(
+    request: &FlightEstimateRequest,
+    attributes: &EstimateAttributes,
+    options: &RenderOptions,
+) -> String {
+    let mut lines = vec![
+        format!("## {}", route_stops(&request.legs).join(" → ")),
+        String::new(),
+    ];
+    for leg in &request.legs {
+        lines.push(format!(
+            "- {} → {} ({})",
+            leg.departure_airport,
+            leg.destination_airport,
+            leg.cabin_class.as_deref().unwrap_or("economy")
+        ));
+    }
+    lines.push(String::new());
+    lines.push("| Figure | Value |".to_string());
+    lines.push("| --- | ---: |".to_string());
+    lines.push(format!("| Passengers | {} |", request.passengers));
+    // The same figures as a CSV row
+    for (unit, value) in select_units(attributes, csv_units(options)) {
+        lines.push(format!(
+            "| carbon_{} | {} |",
+            unit.suffix(),
+            format_figure(value, options.places())
+        ));
+    }
+    lines.push(format!(
+        "| Distance | {} |",
+        format_distance(attributes, options.places())
+    ));
+    lines.join("\n")
+}
+
+/// Inline styles for `to_html`, so the snippet needs no stylesheet of its own.
+const HTML_STYLE: &str = "\
+.carbon-report { font-family: sans-serif; max-width: 48em; }
+.carbon-report .summary { background: #e8f5e9; border-left: 4px solid #2e7d32; padding: 0.75em 1em; }
+.carbon-report table { border-collapse: collapse; width: 100%; }
+.carbon-report th, .carbon-report td { border: 1px solid #ccc; padding: 0.4em 0.6em; text-align: left; }
+.carbon-report th { background: #f5f5f5; }
+.carbon-report td.number { text-align: right; }
+.carbon-report ul { margin: 0; padding-left: 1.2em; }";
+
+/// Renders estimates as a self-contained HTML snippet: a banner with the total and offset
+/// cost, then a table with each request's legs and emissions.
+pub fn to_html(estimates: &[EstimateResult], options: &RenderOptions) -> String {
+    html_report(
+        &[Section {
+            itinerary: None,
+            estimates,
+        }],
+        options,
+    )
+}
+
+fn html_report(sections: &[Section], options: &RenderOptions) -> String {
+    let subtotals: Vec<EstimateAttributes> = sections.iter().map(Section::subtotal).collect();
+    let total = sum_attributes(&subtotals);
+    let estimate_count: usize = sections.iter().map(|section| section.estimates.len()).sum();
+
+    let mut html = vec![
+        "<div class=\"carbon-report\">".to_string(),
+        format!("<style>\n{}\n</style>", HTML_STYLE),
+        format!(
+            "<p class=\"summary\"><strong>{} kg CO2</strong> across {} estimates. Estimated offset cost: {}</p>",
+            format_figure(total.carbon_kg, options.places()),
+            estimate_count,
+            escape_html(&format_offset_cost(total.carbon_mt, options))
+        ),
+        "<table>".to_string(),
+        "<thead><tr><th>Legs</th><th>Passengers</th><th>Distance</th><th>carbon_kg</th></tr></thead>"
+            .to_string(),
+        "<tbody>".to_string(),
+    ];
+    for section in sections {
+        html.extend(
+            section
+                .estimates
+                .iter()
+                .map(|estimate| html_row(estimate, options.places())),
+        );
+        if let Some(itinerary) = section.itinerary {
+            let subtotal = section.subtotal();
+            html.push(format!(
+                "<tr class=\"subtotal\"><th>Subtotal: {}</th><td class=\"number\">{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td></tr>",
+                escape_html(itinerary),
+                section.passengers(),
+                escape_html(&format_distance(&subtotal, options.places())),
+                format_figure(subtotal.carbon_kg, options.places())
+            ));
+        }
+    }
+    html.push("</tbody>".to_string());
+    html.push("</table>".to_string());
+    html.push("</div>".to_string());
+    html.join("\n")
+}
+
+fn html_row(estimate: &EstimateResult, places: usize) -> String {
+    let legs: String = estimate
+        .request
+        .legs
+        .iter()
+        .map(|leg| {
+            format!(
+                "<li>{} → {} ({})</li>",
+                escape_html(&leg.departure_airport),
+                escape_html(&leg.destination_airport),
+                escape_html(leg.cabin_class.as_deref().unwrap_or("economy"))
+            )
+        })
+        .collect();
+    format!(
+        "<tr><td><ul>{}</ul></td><td class=\"number\">{}</td><td class=\"number\">{}</td><td class=\"number\">{}</td></tr>",
+        legs,
+        estimate.request.passengers,
+        escape_html(&format_distance(&estimate.attributes, places)),
+        format_figure(estimate.attributes.carbon_kg, places)
+    )
+}
+
+/// Renders estimates as Prometheus gauges, e.g.
+/// `flight_carbon_kg{route="LHR-JFK",cabin="economy",passengers="1"} 99911.7`, so a
+/// dashboard can scrape them. Each request is labelled with its route and cabin classes, and
+/// a figure the API left out has no sample.
+pub fn to_prometheus(estimates: &[EstimateResult]) -> String {
+    let labels = |estimate: &EstimateResult| {
+        format!(
+            "route=\"{}\",cabin=\"{}\",passengers=\"{}\"",
+            escape_label_value(&route_stops(&estimate.request.legs).join("-")),
+            escape_label_value(&cabin_classes(&estimate.request.legs)),
+            estimate.request.passengers
+        )
+    };
+    let mut lines = vec![
+        "# HELP flight_carbon_kg Estimated carbon emissions of the trip, in kg.".to_string(),
+        "# TYPE flight_carbon_kg gauge".to_string(),
+    ];
+    lines.extend(
+        estimates
+            .iter()
+            .filter(|estimate| !is_missing(estimate.attributes.carbon_kg))
+            .map(|estimate| {
+                format!(
+                    "flight_carbon_kg{{{}}} {}",
+                    labels(estimate),
+                    estimate.attributes.carbon_kg
+                )
+            }),
+    );
+    lines.push("# HELP flight_distance_value Distance of the trip, in distance_unit.".to_string());
+    lines.push("# TYPE flight_distance_value gauge".to_string());
+    lines.extend(
+        estimates
+            .iter()
+            .filter(|estimate| !is_missing(estimate.attributes.distance_value))
+            .map(|estimate| {
+                format!(
+                    "flight_distance_value{{{},distance_unit=\"{}\"}} {}",
+                    labels(estimate),
+                    escape_label_value(&estimate.attributes.distance_unit),
+                    estimate.attributes.distance_value
+                )
+            }),
+    );
+    lines.join("\n")
+}
+
+/// Escapes a Prometheus label value: backslashes, double quotes and newlines.
+fn escape_label_value(value: &str) -> String {
+    value
+        .replace('\\', "\\\\")
+        .replace('"', "\\\"")
+        .replace('\n', "\\n")
+}
+
+/// How a `MISSING_FIGURE` is shown.
+const MISSING_TEXT: &str = "N/A";
+
+/// `value` to `precision` decimal places, or "N/A" for a `MISSING_FIGURE`.
+fn format_figure(value: f32, precision: usize) -> String {
+    if is_missing(value) {
+        MISSING_TEXT.to_string()
+    } else {
+        format!("{:.*}", precision, value)
+    }
+}
+
+/// The cost of offsetting `carbon_mt` in the display currency, or "N/A" when it's missing.
+fn format_offset_cost(carbon_mt: f32, options: &RenderOptions) -> String {
+    if is_missing(carbon_mt) {
+        MISSING_TEXT.to_string()
+    } else {
+        options
+            .currency
+            .format(offset_cost(carbon_mt, options.offset_price))
+    }
+}
+
+/// A distance with its unit.
+fn format_distance(attributes: &EstimateAttributes, places: usize) -> String {
+    format!(
+        "{} {}",
+        format_figure(attributes.distance_value, places),
+        attributes.distance_unit
+    )
+}
+
+/// Escapes text for use in HTML content and attribute values.
+fn escape_html(text: &str) -> String {
+    let mut escaped = String::with_capacity(text.len());
+    for c in text.chars() {
+        match c {
+            '&' => escaped.push_str("&amp;"),
+            '<' => escaped.push_str("&lt;"),
+            '>' => escaped.push_str("&gt;"),
+            '"' => escaped.push_str("&quot;"),
+            '\'' => escaped.push_str("&#39;"),
+            _ => escaped.push(c),
+        }
+    }
+    escaped
+}
+
+/// The distinct cabin classes flown, in order; legs without one fly economy.
+fn cabin_classes(legs: &[Leg]) -> String {
+    let mut classes: Vec<&str> = Vec::new();
+    for leg in legs {
+        let class = leg.cabin_class.as_deref().unwrap_or("economy");
+        if !classes.contains(&class) {
+            classes.push(class);
+        }
+    }
+    classes.join("/")
+}
+
+/// What `--sort` orders several estimates by.
+#[derive(ValueEnum, Clone, Copy, Debug, PartialEq)]
+pub enum SortKey {
+    /// Emissions in kg
+    Carbon,
+    /// Distance flown, whichever unit each estimate is in
+    Distance,
+    /// The airports visited, alphabetically
+    Route,
+}
+
+/// How `a` and `b` compare by `key`, smallest first.
+pub fn compare_results(a: &EstimateResult, b: &EstimateResult, key: SortKey) -> Ordering {
+    match key {
+        SortKey::Carbon => a.attributes.carbon_kg.total_cmp(&b.attributes.carbon_kg),
+        SortKey::Distance => distance_km(&a.attributes).total_cmp(&distance_km(&b.attributes)),
+        SortKey::Route => route_stops(&a.request.legs).cmp(&route_stops(&b.request.legs)),
+    }
+}
+
+/// Sorts `estimates` by `key`, largest first when `descending`. Ties keep their order.
+pub fn sort_results(estimates: &mut [EstimateResult], key: SortKey, descending: bool) {
+    estimates.sort_by(|a, b| {
+        let ordering = compare_results(a, b, key);
+        if descending {
+            ordering.reverse()
+        } else {
+            ordering
+        }
+    });
+}
+
+/// Lists each airport visited in order, without repeating connections.
+pub fn route_stops(legs: &[Leg]) -> Vec<&str> {
+    let mut stops: Vec<&str> = Vec::new();
+    for leg in legs {
+        if stops.last() != Some(&leg.departure_airport.as_str()) {
+            stops.push(&leg.departure_airport);
+        }
+        stops.push(&leg.destination_airport);
+    }
+    stops
+}
+
+/// Each leg's share of a trip estimated with `--per-leg`, one line per leg.
+/// Quiet output leaves it out, since it's only ever extra detail.
+pub fn render_leg_breakdown(legs: &[EstimateResult], options: &RenderOptions) -> String {
+    if options.quiet {
+        return String::new();
+    }
+    let total_kg = sum_known(legs.iter().map(|leg| leg.attributes.carbon_kg));
+    let mut lines = vec![format!("{}", "Per-leg breakdown:".bold())];
+    for (i, leg) in legs.iter().enumerate() {
+        let share = if total_kg > 0.0 && !is_missing(leg.attributes.carbon_kg) {
+            format!(" ({:.0}%)", leg.attributes.carbon_kg / total_kg * 100.0)
+        } else {
+            String::new()
+        };
+        lines.push(format!(
+            "  {}. {}: {} kg{} over {}",
+            i + 1,
+            route_stops(&leg.request.legs).join(" → "),
+            colorize_emissions(
+                &format_figure(leg.attributes.carbon_kg, options.places()),
+                leg.attributes.carbon_kg
+            ),
+            share,
+            format_distance(&leg.attributes, options.places())
+        ));
+    }
+    lines.push(format!(
+        "  Total: {} kg",
+        format_figure(total_kg, options.places())
+    ));
+    lines.join("\n")
+}
+
+/// The grand total of every estimate in a run, with the cost of offsetting it.
+pub fn render_total(estimates: &[EstimateResult], options: &RenderOptions) -> String {
+    let attributes: Vec<EstimateAttributes> = estimates
+        .iter()
+        .map(|estimate| estimate.attributes.clone())
+        .collect();
+    let total = sum_attributes(&attributes);
+
+    format!(
+        "TOTAL: {} kg CO2 ({} t) over {} across {} estimates\nEstimated offset cost: {}",
+        format_figure(total.carbon_kg, options.places()),
+        format_figure(total.carbon_mt, options.tonne_places()),
+        format_distance(&total, options.places()),
+        estimates.len(),
+        format_offset_cost(total.carbon_mt, options)
+    )
+}
+
+/// The closing summary of an interactive session: only its totals, none of the flights.
+pub fn render_session_totals(totals: &SessionTotals, options: &RenderOptions) -> String {
+    format!(
+        "SESSION: {} flight(s), {:.*} kg CO2 ({:.*} t) over {:.*} {}\nEstimated offset cost: {}",
+        totals.flights,
+        options.places(),
+        totals.carbon_kg,
+        options.tonne_places(),
+        totals.carbon_mt,
+        options.places(),
+        totals.distance_value,
+        totals.distance_unit.as_deref().unwrap_or("km"),
+        options
+            .currency
+            .format(offset_cost(totals.carbon_mt, options.offset_price))
+    )
+}
+
+/// One itinerary's place in a `--rank` listing.
+#[derive(Clone, Debug, PartialEq)]
+pub struct RankedItinerary {
+    pub name: String,
+    pub carbon_kg: f32,
+    /// How much more it emits than the greenest itinerary, or `None` when that emits
+    /// nothing to compare with
+    pub percent_above_greenest: Option<f32>,
+}
+
+/// Orders itineraries from the least to the most emissions, ties keeping their order.
+pub fn rank_itineraries(groups: &[ItineraryGroup]) -> Vec<RankedItinerary> {
+    let mut totals: Vec<(&str, EstimateResult)> = groups
+        .iter()
+        .map(|group| {
+            let legs = group
+                .estimates
+                .iter()
+                .flat_map(|estimate| estimate.request.legs.iter().cloned())
+                .collect();
+            let attributes: Vec<EstimateAttributes> = group
+                .estimates
+                .iter()
+                .map(|estimate| estimate.attributes.clone())
+                .collect();
+            let total = EstimateResult {
+                request: FlightEstimateRequest {
+                    legs,
+                    ..group.estimates[0].request.clone()
+                },
+                attributes: sum_attributes(&attributes),
+            };
+            (group.name.as_str(), total)
+        })
+        .collect();
+    totals.sort_by(|(_, a), (_, b)| compare_results(a, b, SortKey::Carbon));
+
+    let greenest_kg = totals
+        .first()
+        .map_or(0.0, |(_, total)| total.attributes.carbon_kg);
+    totals
+        .into_iter()
+        .map(|(name, total)| {
+            let carbon_kg = total.attributes.carbon_kg;
+            RankedItinerary {
+                name: name.to_string(),
+                carbon_kg,
+                percent_above_greenest: (greenest_kg > 0.0)
+                    .then(|| (carbon_kg - greenest_kg) / greenest_kg * 100.0),
+            }
+        })
+        .collect()
+}
+
+/// Renders a `--rank` listing, greenest first. JSON gives each itinerary's rank and
+/// figures; every other format gets the text listing.
+pub fn render_ranking(
+    ranked: &[RankedItinerary],
+    format: OutputFormat,
+    options: &RenderOptions,
+) -> String {
+    if format == OutputFormat::Json {
+        let entries: Vec<serde_json::Value> = ranked
+            .iter()
+            .enumerate()
+            .map(|(i, itinerary)| {
+                serde_json::json!({
+                    "rank": i + 1,
+                    "itinerary": itinerary.name,
+                    "carbon_kg": itinerary.carbon_kg,
+                    "percent_above_greenest": itinerary.percent_above_greenest,
+                })
+            })
+            .collect();
+        return to_json(&entries, options);
+    }
+
+    let mut lines = vec!["🏆 Itineraries from greenest:".bold().to_string()];
+    for (i, itinerary) in ranked.iter().enumerate() {
+        let line = format!(
+            "  {}. {} - {} kg CO2",
+            i + 1,
+            itinerary.name,
+            format_figure(itinerary.carbon_kg, options.places())
+        );
+        if i == 0 {
+            lines.push(format!("{} (greenest)", line).bold().green().to_string());
+        } else {
+            let above = itinerary
+                .percent_above_greenest
+                .map_or(String::new(), |percent| format!(" (+{:.1}%)", percent));
+            lines.push(format!("{}{}", line, above));
+        }
+    }
+    lines.join("\n")
+}
+
+/// Renders two estimates side by side with the difference between them.
+pub fn render_comparison(
+    first: &EstimateResult,
+    second: &EstimateResult,
+    format: OutputFormat,
+    options: &RenderOptions,
+) -> String {
+    let comparison = compare_estimates(&first.attributes, &second.attributes);
+    match format {
+        OutputFormat::Text if options.quiet => {
+            render_batch(&[first.clone(), second.clone()], format, options)
+        }
+        OutputFormat::Text => render_comparison_text(first, second, &comparison, options),
+        OutputFormat::Json => to_json(
+            &serde_json::json!({
+                "first": attributes_json(&first.attributes, options),
+                "second": attributes_json(&second.attributes, options),
+                "comparison": comparison,
+            }),
+            options,
+        ),
+        OutputFormat::Csv
+        | OutputFormat::Table
+        | OutputFormat::Markdown
+        | OutputFormat::Html
+        | OutputFormat::Prometheus => {
+            render_batch(&[first.clone(), second.clone()], format, options)
+        }
+    }
+}
+
+fn render_comparison_text(
+    first: &EstimateResult,
+    second: &EstimateResult,
+    comparison: &Comparison,
+    options: &RenderOptions,
+) -> String {
+    let places = options.places();
+    let marker = |greener: Greener| {
+        if comparison.greener == greener {
+            " 🌱".to_string()
+        } else {
+            String::new()
+        }
+    };
+
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_CLEAN);
+    table.set_titles(row![
+        bc=> "Metric".bold(),
+        format!("Itinerary A{}", marker(Greener::First)).bold(),
+        format!("Itinerary B{}", marker(Greener::Second)).bold()
+    ]);
+    table.add_row(row![
+        "Route".bold(),
+        route_stops(&first.request.legs).join(" → "),
+        route_stops(&second.request.legs).join(" → ")
+    ]);
+    table.add_row(row![
+        "Carbon emissions (kg)".bold(),
+        colorize_emissions(
+            &format_figure(first.attributes.carbon_kg, places),
+            first.attributes.carbon_kg
+        ),
+        colorize_emissions(
+            &format_figure(second.attributes.carbon_kg, places),
+            second.attributes.carbon_kg
+        )
+    ]);
+    table.add_row(row![
+        "Distance".bold(),
+        format_distance(&first.attributes, places),
+        format_distance(&second.attributes, places)
+    ]);
+
+    let percent = comparison
+        .percent_difference
+        .map(|percent| format!(" ({:+.1}%)", percent))
+        .unwrap_or_default();
+    let verdict = match comparison.greener {
+        _ if is_missing(comparison.difference_kg) => {
+            "The itineraries can't be compared, as the API left out a carbon_kg.".to_string()
+        }
+        Greener::First => format!(
+            "Itinerary A is greener; B emits {:.*} kg CO2 more{}.",
+            places, comparison.difference_kg, percent
+        ),
+        Greener::Second => format!(
+            "Itinerary B is greener, emitting {:.*} kg CO2 less than A{}.",
+            places, -comparison.difference_kg, percent
+        ),
+        Greener::Equal => "Both itineraries have the same footprint.".to_string(),
+    };
+
+    format!("\n{}\n{}", table, verdict.bold().green())
+}
+
+/// Footprints up to this many kg of CO2 are shown in green, roughly a short-haul return trip.
+pub const LOW_EMISSIONS_KG: f32 = 250.0;
+/// Footprints above this many kg of CO2 are shown in red, roughly a long-haul return trip.
+pub const HIGH_EMISSIONS_KG: f32 = 1000.0;
+
+/// Where the text output points readers for ways to offset a flight.
+const OFFSET_INFO_URL: &str =
+    "https://carbonfund.org/how-to-offset-the-carbon-footprint-of-flying/";
+
+/// Traffic-light colour for a footprint of `carbon_kg`.
+pub fn emissions_color(carbon_kg: f32) -> Color {
+    if carbon_kg <= LOW_EMISSIONS_KG {
+        Color::Green
+    } else if carbon_kg <= HIGH_EMISSIONS_KG {
+        Color::Yellow
+    } else {
+        Color::Red
+    }
+}
+
+/// Styles `text` in the traffic-light colour for `carbon_kg`. Whether escape codes are
+/// emitted follows `colored`'s global setting.
+pub fn colorize_emissions(text: &str, carbon_kg: f32) -> ColoredString {
+    text.color(emissions_color(carbon_kg)).bold()
+}
+
+pub fn render_text(
+    request: &FlightEstimateRequest,
+    estimate: &EstimateAttributes,
+    options: &RenderOptions,
+) -> String {
+    if options.quiet {
+        return format_figure(estimate.carbon_kg, options.places());
+    }
+
+    let mut table = Table::new();
+    table.set_format(*format::consts::FORMAT_CLEAN);
+    table.set_titles(
+        row![bc=> t("result.metric").bold(), t("result.value").bold(), t("result.unit").bold()],
+    );
+
+    let max_metric_length = 22;
+    for (unit, value) in select_units(estimate, text_units(options)) {
+        table.add_row(Row::new(vec![
+            Cell::new(
+                &format!(
+                    "{:<max_width$}",
+                    tf("result.carbon", unit.suffix()),
+                    max_width = max_metric_length
+                )
+                .bold()
+                .to_string(),
+            ),
+            Cell::new(
+                &colorize_emissions(&format_figure(value, options.places()), estimate.carbon_kg)
+                    .to_string(),
+            ),
+            Cell::new(&unit.suffix().italic().magenta().to_string()),
+        ]));
+    }
+    table.add_row(Row::new(vec![
+        Cell::new(
+            &format!(
+                "{:<max_width$}",
+                t("result.distance"),
+                max_width = max_metric_length
+            )
+            .bold()
+            .to_string(),
+        ),
+        Cell::new(&format_figure(estimate.distance_value, options.places())),
+        Cell::new(&estimate.distance_unit.italic().magenta().to_string()),
+    ]));
+
+    let mut output = String::from("\n\n");
+    output.push_str(&format!("{}\n", t("result.heading").bold().green()));
+    output.push_str(&table.to_string());
+
+    let (passenger_kg, passenger_mt) = per_passenger(estimate, request.passengers);
+    output.push_str(&format!(
+        "{} {} kg ({} t) {}\n",
+        t("result.per_passenger").bold(),
+        format_figure(passenger_kg, options.places()),
+        format_figure(passenger_mt, options.tonne_places()),
+        tf("result.across_passengers", request.passengers)
+    ));
+    if options.rf_multiplier != 1.0 {
+        let co2e = apply_rf(estimate, options.rf_multiplier);
+        output.push_str(&format!(
+            "{} {} kg ({} t)\n",
+            tf("result.co2e", options.rf_multiplier).bold(),
+            format_figure(co2e.carbon_kg, options.places()),
+            format_figure(co2e.carbon_mt, options.tonne_places())
+        ));
+    }
+    if let Some(kg_per_unit) = intensity(estimate).filter(|kg| !is_missing(*kg)) {
+        output.push_str(&format!(
+            "{} {:.3} kg CO2/{}\n",
+            t("result.intensity").bold(),
+            kg_per_unit,
+            estimate.distance_unit
+        ));
+    }
+
+    // Nothing can be compared with emissions the API didn't report
+    let carbon_known = !is_missing(estimate.carbon_kg);
+    if options.show_equivalencies && carbon_known {
+        let equivalent = equivalencies(estimate.carbon_kg);
+        output.push_str(&format!("\n{}\n", t("result.equivalent")));
+        output.push_str(&format!(
+            "{}\n",
+            tf("result.car_km", format!("{:.0}", equivalent.car_km))
+        ));
+        output.push_str(&format!(
+            "{}\n",
+            tf("result.tree_years", format!("{:.1}", equivalent.tree_years))
+        ));
+        output.push_str(&format!(
+            "{}\n",
+            tf(
+                "result.smartphone_charges",
+                format!("{:.0}", equivalent.smartphone_charges)
+            )
+        ));
+    }
+
+    if options.compare_alternatives && carbon_known {
+        output.push_str(&render_alternatives(
+            passenger_kg,
+            distance_km(estimate),
+            options.places(),
+        ));
+    }
+
+    let offset = format_offset_cost(estimate.carbon_mt, options);
+    output.push_str("\n\n");
+    output.push_str(&format!("{}\n", t("result.offset_heading").bold().green()));
+    output.push_str(&format!(
+        "{} {} {}\n",
+        t("result.offset_cost"),
+        offset,
+        tf(
+            "result.per_tonne",
+            options.currency.format(options.offset_price)
+        )
+    ));
+    output.push_str(&format!(
+        "{}",
+        tf("result.learn_more", OFFSET_INFO_URL).underline()
+    ));
+    output
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::footprint::DEFAULT_OFFSET_PRICE;
+    use crate::model::MISSING_FIGURE;
+    use crate::test_support::{create_mock_response, create_request};
+
+    #[test]
+    fn test_format_from_path() {
+        assert_eq!(
+            format_from_path(Path::new("trips.json")),
+            Some(OutputFormat::Json)
+        );
+        assert_eq!(
+            format_from_path(Path::new("out/trips.CSV")),
+            Some(OutputFormat::Csv)
+        );
+        assert_eq!(
+            format_from_path(Path::new("trips.txt")),
+            Some(OutputFormat::Text)
+        );
+        assert_eq!(
+            format_from_path(Path::new("journal.md")),
+            Some(OutputFormat::Markdown)
+        );
+        assert_eq!(
+            format_from_path(Path::new("report.html")),
+            Some(OutputFormat::Html)
+        );
+        assert_eq!(
+            format_from_path(Path::new("metrics.prom")),
+            Some(OutputFormat::Prometheus)
+        );
+        assert_eq!(format_from_path(Path::new("trips.xlsx")), None);
+        assert_eq!(format_from_path(Path::new("trips")), None);
+    }
+
+    fn test_options() -> RenderOptions {
+        RenderOptions {
+            offset_price: DEFAULT_OFFSET_PRICE,
+            currency: Currency::usd(),
+            show_equivalencies: true,
+            quiet: false,
+            compare_alternatives: false,
+            units: Vec::new(),
+            compact: false,
+            rf_multiplier: 1.0,
+            precision: DEFAULT_PRECISION,
+        }
+    }
+
+    #[test]
+    fn test_render_estimate_text() {
+        let request = create_request(100, &[("LHR", "JFK")]);
+        let response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        let attributes = response.data.unwrap().attributes;
+
+        let output = render_estimate(&request, &attributes, OutputFormat::Text, &test_options());
+
+        assert!(output.contains("Carbon emissions (kg)"));
+        assert!(output.contains("99911.70"));
+        assert!(output.contains("5660.34"));
+        assert!(output.contains("Please consider offsetting your carbon footprint."));
+        assert!(output.contains("Estimated offset cost: $1498.65"));
+        assert!(output.contains("Driving 587716 km in an average petrol car"));
+        assert!(output.contains("999.12 kg (0.999 t) across 100 passenger(s)"));
+        assert!(output.contains("Emission intensity: 17.651 kg CO2/km"));
+    }
+
+    #[test]
+    fn test_round_to() {
+        assert_eq!(round_to(812.5, 0), 813.0);
+        assert_eq!(round_to(-812.5, 0), -813.0);
+        assert_eq!(round_to(1234.567, 2), 1234.57);
+        assert_eq!(round_to(1.234567, 4), 1.2346);
+        assert!(round_to(f32::NAN, 2).is_nan());
+    }
+
+    #[test]
+    fn test_precision_sets_the_decimals_shown() {
+        let request = create_request(100, &[("LHR", "JFK")]);
+        let response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        let attributes = response.data.unwrap().attributes;
+        let whole = RenderOptions {
+            precision: 0,
+            ..test_options()
+        };
+        let fine = RenderOptions {
+            precision: 4,
+            ..test_options()
+        };
+
+        let text = render_estimate(&request, &attributes, OutputFormat::Text, &whole);
+        assert!(text.contains("999 kg (1.0 t) across 100 passenger(s)"));
+        assert!(text.contains("5660 "));
+        let text = render_estimate(&request, &attributes, OutputFormat::Text, &fine);
+        assert!(text.contains("999.1170 kg (0.99910 t) across 100 passenger(s)"));
+
+        let json = render_estimate(&request, &attributes, OutputFormat::Json, &whole);
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed["carbon_kg"].as_f64().unwrap(), 99912.0);
+        assert_eq!(parsed["carbon_mt"].as_f64().unwrap() as f32, 99.91);
+        assert_eq!(parsed["distance_value"].as_f64().unwrap(), 5660.0);
+    }
+
+    #[test]
+    fn test_precision_leaves_totals_unrounded() {
+        let estimates = vec![
+            create_estimate(&[("LHR", "JFK")], 0.4),
+            create_estimate(&[("CDG", "NRT")], 0.4),
+        ];
+        let options = RenderOptions {
+            precision: 0,
+            ..test_options()
+        };
+
+        let output = render_total(&estimates, &options);
+
+        assert!(output.starts_with("TOTAL: 1 kg CO2"), "{}", output);
+    }
+
+    #[test]
+    fn test_render_estimate_json() {
+        let request = create_request(100, &[("LHR", "JFK")]);
+        let response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        let attributes = response.data.unwrap().attributes;
+
+        let output = render_estimate(&request, &attributes, OutputFormat::Json, &test_options());
+
+        let parsed: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(parsed["carbon_kg"].as_f64().unwrap() as f32, 99911.7);
+        assert_eq!(parsed["carbon_mt"].as_f64().unwrap() as f32, 99.91);
+        assert_eq!(parsed["distance_unit"], "km");
+        assert_eq!(parsed["distance_value"].as_f64().unwrap() as f32, 5660.34);
+        assert!(!output.contains("offsetting"));
+    }
+
+    #[test]
+    fn test_render_estimate_csv_has_header_and_row() {
+        let request = create_request(100, &[("LHR", "JFK")]);
+        let response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        let attributes = response.data.unwrap().attributes;
+
+        let output = render_estimate(&request, &attributes, OutputFormat::Csv, &test_options());
+
+        assert_eq!(
+            output,
+            "departure,destination,passengers,carbon_kg,carbon_mt,distance_value,distance_unit\n\
+             LHR,JFK,100,99911.7,99.91,5660.34,km"
+        );
+    }
+
+    #[test]
+    fn test_to_csv_row_multi_leg() {
+        let request = create_request(2, &[("LHR", "JFK"), ("JFK", "LAX")]);
+        let response = create_mock_response(1234567.0, 2721.8, 1234.567, 1.234567, "mi", 6200.5);
+        let attributes = response.data.unwrap().attributes;
+
+        assert_eq!(
+            to_csv_row(&request, &attributes),
+            "LHR,JFK>LAX,2,1234.567,1.234567,6200.5,mi"
+        );
+    }
+
+    #[test]
+    fn test_to_csv_row_keeps_disconnected_legs() {
+        let request = create_request(1, &[("LHR", "JFK"), ("EWR", "LHR")]);
+        let response = create_mock_response(1000000.0, 2204.6, 1000.0, 1.0, "km", 11000.0);
+        let attributes = response.data.unwrap().attributes;
+
+        assert_eq!(
+            to_csv_row(&request, &attributes),
+            "LHR,JFK>EWR>LHR,1,1000,1,11000,km"
+        );
+    }
+
+    #[test]
+    fn test_render_text_compares_alternatives() {
+        let request = create_request(2, &[("LHR", "JFK")]);
+        let response = create_mock_response(400000.0, 881.8, 400.0, 0.4, "km", 1000.0);
+        let attributes = response.data.unwrap().attributes;
+        let options = RenderOptions {
+            compare_alternatives: true,
+            ..test_options()
+        };
+
+        let output = render_estimate(&request, &attributes, OutputFormat::Text, &options);
+
+        assert!(output.contains("Per passenger over the same 1000 km:"));
+        assert!(output.contains("Flying: 200.00 kg"));
+        assert!(output.contains("Train: 35.00 kg (82% less than flying)"));
+        assert!(output.contains("Car: 170.00 kg (15% less than flying)"));
+
+        let without = render_estimate(&request, &attributes, OutputFormat::Text, &test_options());
+        assert!(!without.contains("Train:"));
+    }
+
+    #[test]
+    fn test_alternatives_can_emit_more_than_flying() {
+        let output = render_alternatives(10.0, 100.0, 2);
+
+        assert!(output.contains("Train: 3.50 kg (65% less than flying)"));
+        assert!(output.contains("Car: 17.00 kg (70% more than flying)"));
+    }
+
+    #[test]
+    fn test_explanation_reflects_the_request() {
+        let mut request = create_request(3, &[("LHR", "JFK"), ("JFK", "LAX"), ("LAX", "SFO")]);
+        request.legs[0].cabin_class = Some("business".to_string());
+        request.legs[1].cabin_class = Some("first".to_string());
+
+        let text = explanation(&request);
+
+        assert!(text.contains("all 3 passengers combined"));
+        assert!(text.contains("Cabin class: business, first, economy."));
+        assert!(text.contains("tank-to-wake"));
+        assert!(text.contains("great-circle"));
+    }
+
+    #[test]
+    fn test_units_legend_only_covers_the_units_shown() {
+        let legend = units_legend(&[CarbonUnit::Lb, CarbonUnit::Kg, CarbonUnit::Lb]);
+
+        assert!(legend.contains("lb: pounds"));
+        assert!(legend.contains("kg: kilograms"));
+        assert!(!legend.contains("grams of CO2; 1,000 g"));
+        assert!(!legend.contains("tonnes"));
+        assert_eq!(legend.matches("lb:").count(), 1);
+        assert!(legend.contains("great-circle"));
+    }
+
+    #[test]
+    fn test_shown_units_follow_the_format_and_selection() {
+        let options = test_options();
+        assert_eq!(
+            shown_units(OutputFormat::Text, &options),
+            vec![CarbonUnit::Kg, CarbonUnit::Mt]
+        );
+        assert_eq!(shown_units(OutputFormat::Json, &options).len(), 4);
+
+        let options = RenderOptions {
+            units: vec![CarbonUnit::G],
+            ..test_options()
+        };
+        assert_eq!(
+            shown_units(OutputFormat::Json, &options),
+            vec![CarbonUnit::G]
+        );
+    }
+
+    #[test]
+    fn test_explanation_for_one_passenger() {
+        let request = create_request(1, &[("LHR", "JFK")]);
+
+        let text = explanation(&request);
+
+        assert!(text.contains("the 1 passenger,"));
+        assert!(text.contains("Cabin class: economy."));
+    }
+
+    #[test]
+    fn test_render_text_without_equivalencies() {
+        let request = create_request(100, &[("LHR", "JFK")]);
+        let response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        let attributes = response.data.unwrap().attributes;
+        let options = RenderOptions {
+            show_equivalencies: false,
+            ..test_options()
+        };
+
+        let output = render_estimate(&request, &attributes, OutputFormat::Text, &options);
+
+        assert!(output.contains("99911.70"));
+        assert!(!output.contains("petrol car"));
+    }
+
+    #[test]
+    fn test_rf_multiplier_adds_a_labelled_co2_equivalent() {
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let attributes = create_mock_response(1000000.0, 2204.62, 1000.0, 1.0, "km", 5560.0)
+            .data
+            .unwrap()
+            .attributes;
+        let options = RenderOptions {
+            rf_multiplier: 1.9,
+            ..test_options()
+        };
+
+        let text = render_estimate(&request, &attributes, OutputFormat::Text, &options);
+        let json: serde_json::Value = serde_json::from_str(&render_estimate(
+            &request,
+            &attributes,
+            OutputFormat::Json,
+            &options,
+        ))
+        .unwrap();
+        let plain = render_estimate(&request, &attributes, OutputFormat::Text, &test_options());
+
+        assert!(text.contains("1000.00"));
+        assert!(text.contains("CO2-equivalent with radiative forcing (x1.9): 1900.00 kg (1.900 t)"));
+        assert_eq!(json["carbon_kg"], 1000.0);
+        assert_eq!(json["co2e_kg"], 1900.0);
+        assert!(!plain.contains("radiative forcing"));
+    }
+
+    #[test]
+    fn test_missing_figures_are_shown_as_not_available() {
+        let request = create_request(1, &[("LHR", "JFK")]);
+        let mut attributes = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34)
+            .data
+            .unwrap()
+            .attributes;
+        attributes.carbon_lb = MISSING_FIGURE;
+        attributes.carbon_mt = MISSING_FIGURE;
+        let options = RenderOptions {
+            units: vec![CarbonUnit::Kg, CarbonUnit::Lb],
+            ..test_options()
+        };
+
+        let output = render_estimate(&request, &attributes, OutputFormat::Text, &options);
+        assert!(output.contains("99911.70"));
+        assert!(output.contains("N/A"));
+        assert!(output.contains("Estimated offset cost: N/A"));
+
+        let json = render_estimate(&request, &attributes, OutputFormat::Json, &options);
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert!(parsed["carbon_lb"].is_null());
+    }
+
+    #[test]
+    fn test_missing_figures_are_not_available_in_every_format() {
+        let mut unknown = create_estimate(&[("LHR", "JFK")], 300.0);
+        unknown.attributes.carbon_lb = MISSING_FIGURE;
+        unknown.attributes.carbon_kg = MISSING_FIGURE;
+        let estimates = [unknown, create_estimate(&[("JFK", "LAX")], 250.0)];
+        let options = RenderOptions {
+            units: vec![CarbonUnit::Kg, CarbonUnit::Lb],
+            ..test_options()
+        };
+
+        let csv = render_batch(&estimates, OutputFormat::Csv, &options);
+        assert_eq!(csv.lines().nth(1), Some("LHR,JFK,1,N/A,N/A,5660,km"));
+        let table = render_batch(&estimates, OutputFormat::Table, &options);
+        assert!(table.contains("| LHR>JFK |          1 | economy |       N/A |"));
+        assert!(table.contains("250.00"));
+        for format in [
+            OutputFormat::Csv,
+            OutputFormat::Table,
+            OutputFormat::Markdown,
+            OutputFormat::Html,
+            OutputFormat::Prometheus,
+        ] {
+            assert!(!render_batch(&estimates, format, &options).contains("NaN"));
+        }
+        assert!(render_total(&estimates, &options).starts_with("TOTAL: 250.00 kg CO2"));
+    }
+
+    #[test]
+    fn test_emissions_color_thresholds() {
+        assert_eq!(emissions_color(0.0), Color::Green);
+        assert_eq!(emissions_color(LOW_EMISSIONS_KG), Color::Green);
+        assert_eq!(emissions_color(LOW_EMISSIONS_KG + 1.0), Color::Yellow);
+        assert_eq!(emissions_color(HIGH_EMISSIONS_KG), Color::Yellow);
+        assert_eq!(emissions_color(HIGH_EMISSIONS_KG + 1.0), Color::Red);
+    }
+
+    #[test]
+    fn test_colorize_emissions_styles_with_the_emissions_color() {
+        // Checked on the style rather than the escape codes, which follow a global setting
+        let low = colorize_emissions("120.00", 120.0);
+        let high = colorize_emissions("99911.70", 99911.7);
+
+        assert_eq!(low.fgcolor(), Some(emissions_color(120.0)));
+        assert_eq!(high.fgcolor(), Some(Color::Red));
+        assert!(high.style().contains(colored::Styles::Bold));
+        assert_eq!(&*high, "99911.70");
+    }
+
+    fn create_estimate(route: &[(&str, &str)], carbon_kg: f32) -> EstimateResult {
+        EstimateResult {
+            request: create_request(1, route),
+            attributes: create_mock_response(
+                carbon_kg * 1000.0,
+                carbon_kg * 2.2,
+                carbon_kg,
+                carbon_kg / 1000.0,
+                "km",
+                5660.0,
+            )
+            .data
+            .unwrap()
+            .attributes,
+        }
+    }
+
+    #[test]
+    fn test_render_comparison_text_marks_the_greener_option() {
+        let direct = create_estimate(&[("LHR", "LAX")], 800.0);
+        let connecting = create_estimate(&[("LHR", "JFK"), ("JFK", "LAX")], 1000.0);
+
+        let output = render_comparison(&direct, &connecting, OutputFormat::Text, &test_options());
+
+        assert!(output.contains("Itinerary A 🌱"));
+        assert!(output.contains("LHR → JFK → LAX"));
+        assert!(output.contains("Itinerary A is greener; B emits 200.00 kg CO2 more (+25.0%)."));
+    }
+
+    #[test]
+    fn test_compact_batch_json_is_one_object_per_line() {
+        let estimates = vec![
+            create_estimate(&[("LHR", "JFK")], 400.0),
+            create_estimate(&[("CDG", "NRT")], 900.0),
+            create_estimate(&[("SYD", "LAX")], 100.0),
+        ];
+        let options = RenderOptions {
+            compact: true,
+            ..test_options()
+        };
+
+        let output = render_batch(&estimates, OutputFormat::Json, &options);
+
+        let lines: Vec<&str> = output.lines().collect();
+        assert_eq!(lines.len(), 3);
+        for (line, estimate) in lines.iter().zip(&estimates) {
+            let parsed: serde_json::Value = serde_json::from_str(line).unwrap();
+            assert_eq!(parsed["carbon_kg"], estimate.attributes.carbon_kg);
+        }
+        assert!(render_batch(&estimates, OutputFormat::Json, &test_options()).contains("\n  "));
+    }
+
+    #[test]
+    fn test_compact_single_estimate_is_one_line() {
+        let estimate = create_estimate(&[("LHR", "JFK")], 400.0);
+        let options = RenderOptions {
+            compact: true,
+            ..test_options()
+        };
+
+        let output = render_estimate(
+            &estimate.request,
+            &estimate.attributes,
+            OutputFormat::Json,
+            &options,
+        );
+
+        assert!(!output.contains('\n'));
+        assert!(serde_json::from_str::<serde_json::Value>(&output).is_ok());
+    }
+
+    #[test]
+    fn test_render_comparison_json() {
+        let direct = create_estimate(&[("LHR", "LAX")], 800.0);
+        let connecting = create_estimate(&[("LHR", "JFK"), ("JFK", "LAX")], 1000.0);
+
+        let output = render_comparison(&direct, &connecting, OutputFormat::Json, &test_options());
+
+        let parsed: serde_json::Value = serde_json::from_str(&output).unwrap();
+        assert_eq!(parsed["comparison"]["greener"], "first");
+        assert_eq!(parsed["second"]["carbon_kg"], 1000.0);
+    }
+
+    #[test]
+    fn test_render_total() {
+        let estimates = [
+            create_estimate(&[("LHR", "LAX")], 800.0),
+            create_estimate(&[("LHR", "JFK")], 1000.0),
+        ];
+
+        assert_eq!(
+            render_total(&estimates, &test_options()),
+            "TOTAL: 1800.00 kg CO2 (1.800 t) over 11320.00 km across 2 estimates\n\
+             Estimated offset cost: $27.00"
+        );
+    }
+
+    #[test]
+    fn test_render_total_with_mixed_units() {
+        let mut miles = create_estimate(&[("LHR", "JFK")], 1000.0);
+        miles.attributes.distance_unit = "mi".to_string();
+        let estimates = [create_estimate(&[("LHR", "LAX")], 800.0), miles];
+
+        // 5660 km to LAX and 5660 mi to JFK
+        assert!(render_total(&estimates, &test_options())
+            .contains("over 14768.89 km across 2 estimates"));
+    }
+
+    #[test]
+    fn test_session_totals_leave_out_the_flights() {
+        let mut totals = SessionTotals::default();
+        for estimate in [
+            create_estimate(&[("LHR", "JFK")], 300.0),
+            create_estimate(&[("JFK", "LAX")], 250.0),
+        ] {
+            totals.add(&estimate.attributes);
+        }
+
+        let output = render_session_totals(&totals, &test_options());
+
+        assert!(
+            output.starts_with("SESSION: 2 flight(s), 550.00 kg CO2"),
+            "{}",
+            output
+        );
+        assert!(output.contains("Estimated offset cost: $"));
+        assert!(!output.contains("LHR"));
+    }
+
+    #[test]
+    fn test_render_text_converts_offset_cost() {
+        let request = create_request(100, &[("LHR", "JFK")]);
+        let response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        let attributes = response.data.unwrap().attributes;
+        let options = RenderOptions {
+            currency: Currency {
+                code: "GBP".to_string(),
+                usd_rate: 0.79,
+            },
+            ..test_options()
+        };
+
+        let output = render_estimate(&request, &attributes, OutputFormat::Text, &options);
+
+        assert!(output.contains("Estimated offset cost: £1183.93 (at £11.85 per tonne)"));
+    }
+
+    #[test]
+    fn test_quiet_text_is_just_the_carbon_kg() {
+        let estimates = vec![
+            create_estimate(&[("LHR", "JFK")], 400.0),
+            create_estimate(&[("CDG", "NRT")], 812.5),
+        ];
+        let options = RenderOptions {
+            quiet: true,
+            ..test_options()
+        };
+
+        assert_eq!(
+            render_batch(&estimates, OutputFormat::Text, &options),
+            "400.00\n812.50"
+        );
+        assert_eq!(
+            render_comparison(&estimates[0], &estimates[1], OutputFormat::Text, &options),
+            "400.00\n812.50"
+        );
+    }
+
+    fn create_vehicle_attributes() -> VehicleEstimateAttributes {
+        VehicleEstimateAttributes {
+            carbon_g: 37029.0,
+            carbon_lb: 81.64,
+            carbon_kg: 37.03,
+            carbon_mt: 0.04,
+            distance_unit: "mi".to_string(),
+            distance_value: 100.0,
+            vehicle_make: "Toyota".to_string(),
+            vehicle_model: "Corolla".to_string(),
+            vehicle_year: 1993,
+            vehicle_model_id: "7268a9b7".to_string(),
+        }
+    }
+
+    #[test]
+    fn test_render_vehicle_estimate() {
+        let attributes = create_vehicle_attributes();
+
+        let text = render_vehicle_estimate(&attributes, OutputFormat::Text, &test_options());
+        let csv = render_vehicle_estimate(&attributes, OutputFormat::Csv, &test_options());
+
+        assert!(text.contains("100.00 mi in a 1993 Toyota Corolla"));
+        assert!(text.contains("37.03") && text.contains("kg (0.040 t)"));
+        assert!(text.contains("Estimated offset cost: $0.60"));
+        assert_eq!(
+            csv.lines().nth(1),
+            Some("7268a9b7,Toyota,Corolla,1993,37.03,0.04,100,mi")
+        );
+    }
+
+    #[test]
+    fn test_vehicle_csv_quotes_names_from_the_api() {
+        let attributes = VehicleEstimateAttributes {
+            vehicle_model: "Corolla 1.6 \"GT\", Hatchback".to_string(),
+            ..create_vehicle_attributes()
+        };
+
+        let csv = render_vehicle_estimate(&attributes, OutputFormat::Csv, &test_options());
+
+        assert_eq!(
+            csv.lines().nth(1),
+            Some("7268a9b7,Toyota,\"Corolla 1.6 \"\"GT\"\", Hatchback\",1993,37.03,0.04,100,mi")
+        );
+    }
+
+    #[test]
+    fn test_selected_units_apply_to_every_format() {
+        let request = create_request(100, &[("LHR", "JFK")]);
+        let response = create_mock_response(99911700.0, 267.6, 99911.7, 99.91, "km", 5660.34);
+        let attributes = response.data.unwrap().attributes;
+        let options = RenderOptions {
+            units: vec![CarbonUnit::Lb, CarbonUnit::Mt],
+            ..test_options()
+        };
+
+        let text = render_estimate(&request, &attributes, OutputFormat::Text, &options);
+        let json = render_estimate(&request, &attributes, OutputFormat::Json, &options);
+        let csv = render_estimate(&request, &attributes, OutputFormat::Csv, &options);
+
+        assert!(text.contains("Carbon emissions (lb)") && text.contains("Carbon emissions (mt)"));
+        assert!(!text.contains("Carbon emissions (kg)"));
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+        assert_eq!(parsed["carbon_lb"].as_f64().unwrap() as f32, 267.6);
+        assert!(parsed.get("carbon_kg").is_none() && parsed.get("carbon_g").is_none());
+        assert_eq!(parsed["distance_unit"], "km");
+        assert_eq!(
+            csv,
+            "departure,destination,passengers,carbon_lb,carbon_mt,distance_value,distance_unit\n\
+             LHR,JFK,100,267.6,99.91,5660.34,km"
+        );
+    }
+
+    #[test]
+    fn test_csv_header_for_default_units_matches_constant() {
+        assert_eq!(csv_header(&DEFAULT_CSV_UNITS), CSV_HEADER);
+    }
+
+    #[test]
+    fn test_render_table_snapshot() {
+        let mut business = create_estimate(&[("CDG", "NRT"), ("NRT", "SYD")], 12345.678);
+        business.request.passengers = 12;
+        business.request.legs[1].cabin_class = Some("business".to_string());
+        let estimates = vec![create_estimate(&[("LHR", "JFK")], 400.0), business];
+
+        assert_eq!(
+            render_table(&estimates, &test_options()),
+            "\
++-------------+------------+------------------+-----------+
+| Route       | Passengers | Cabin            | carbon_kg |
++-------------+------------+------------------+-----------+
+| LHR>JFK     |          1 | economy          |    400.00 |
+| CDG>NRT>SYD |         12 | economy/business |  12345.68 |
++-------------+------------+------------------+-----------+
+| TOTAL       |         13 |                  |  12745.68 |
++-------------+------------+------------------+-----------+"
+        );
+    }
+
+    #[test]
+    fn test_markdown_section_snapshot() {
+        let mut estimate = create_estimate(&[("LHR", "JFK"), ("JFK", "LAX")], 400.0);
+        estimate.request.legs[1].cabin_class = Some("business".to_string());
+
+        assert_eq!(
+            render_estimate(
+                &estimate.request,
+                &estimate.attributes,
+                OutputFormat::Markdown,
+                &test_options()
+            ),
+            "\
+## LHR → JFK → LAX
+
+- LHR → JFK (economy)
+- JFK → LAX (business)
+
+| Figure | Value |
+| --- | ---: |
+| Passengers | 1 |
+| carbon_kg | 400.00 |
+| carbon_mt | 0.40 |
+| Distance | 5660.00 km |"
+        );
+    }
+
+    #[test]
+    fn test_markdown_batch_ends_with_totals() {
+        let estimates = vec![
+            create_estimate(&[("LHR", "JFK")], 400.0),
+            create_estimate(&[("CDG", "NRT")], 600.5),
+        ];
+        let mut options = test_options();
+        options.units = vec![CarbonUnit::Kg];
+
+        let markdown = render_batch(&estimates, OutputFormat::Markdown, &options);
+
+        assert_eq!(markdown.matches("\n## ").count() + 1, 3);
+        assert!(markdown.contains("## CDG → NRT\n"));
+        assert!(markdown.ends_with(
+            "\
+## Total
+
+| Trip | Route | carbon_kg |
+| --- | --- | ---: |
+| 1 | LHR → JFK | 400.00 |
+| 2 | CDG → NRT | 600.50 |
+| **Total** | | **1000.50** |"
+        ));
+        assert!(!markdown.contains("carbon_g"));
+    }
+
+    fn two_itineraries() -> Vec<ItineraryGroup> {
+        vec![
+            ItineraryGroup {
+                name: "Tokyo".to_string(),
+                estimates: vec![
+                    create_estimate(&[("LHR", "HND")], 900.0),
+                    create_estimate(&[("HND", "LHR")], 850.5),
+                ],
+            },
+            ItineraryGroup {
+                name: "Paris, weekend".to_string(),
+                estimates: vec![create_estimate(&[("LHR", "CDG")], 50.25)],
+            },
+        ]
+    }
+
+    #[test]
+    fn test_itinerary_subtotals_and_grand_total_in_json() {
+        let json = render_itineraries(&two_itineraries(), OutputFormat::Json, &test_options());
+        let parsed: serde_json::Value = serde_json::from_str(&json).unwrap();
+
+        let itineraries = parsed["itineraries"].as_array().unwrap();
+        assert_eq!(itineraries[0]["itinerary"], "Tokyo");
+        assert_eq!(itineraries[0]["estimates"].as_array().unwrap().len(), 2);
+        assert_eq!(itineraries[0]["subtotal"]["carbon_kg"], 1750.5);
+        assert_eq!(itineraries[1]["subtotal"]["carbon_kg"], 50.25);
+        assert_eq!(parsed["total"]["carbon_kg"], 1800.75);
+    }
+
+    #[test]
+    fn test_rank_itineraries_from_greenest() {
+        let group = |name: &str, carbon_kg: &[f32]| ItineraryGroup {
+            name: name.to_string(),
+            estimates: carbon_kg
+                .iter()
+                .map(|kg| create_estimate(&[("LHR", "HND")], *kg))
+                .collect(),
+        };
+        let groups = vec![
+            group("Via Helsinki", &[300.0, 600.0]),
+            group("Direct", &[800.0]),
+            group("Via Dubai", &[500.0, 700.0]),
+        ];
+
+        let ranked = rank_itineraries(&groups);
+
+        let names: Vec<&str> = ranked.iter().map(|option| option.name.as_str()).collect();
+        assert_eq!(names, ["Direct", "Via Helsinki", "Via Dubai"]);
+        assert_eq!(ranked[0].percent_above_greenest, Some(0.0));
+        assert_eq!(ranked[1].carbon_kg, 900.0);
+        assert_eq!(ranked[1].percent_above_greenest, Some(12.5));
+        assert_eq!(ranked[2].percent_above_greenest, Some(50.0));
+
+        let text = render_ranking(&ranked, OutputFormat::Text, &test_options());
+        assert!(text.contains("1. Direct - 800.00 kg CO2 (greenest)"));
+        assert!(text.contains("3. Via Dubai - 1200.00 kg CO2 (+50.0%)"));
+    }
+
+    #[test]
+    fn test_itinerary_file_stem_is_safe() {
+        assert_eq!(itinerary_file_stem("LHR>JFK"), "LHR-JFK");
+        assert_eq!(itinerary_file_stem("Paris, weekend"), "Paris--weekend");
+        assert_eq!(itinerary_file_stem("../../etc/passwd"), "etc-passwd");
+        assert_eq!(itinerary_file_stem("C:\\trips\0"), "C--trips");
+        assert_eq!(itinerary_file_stem("..."), "itinerary");
+        for format in OutputFormat::value_variants() {
+            assert_eq!(
+                format_from_path(Path::new(&format!("trip.{}", format.extension()))),
+                Some(match format {
+                    OutputFormat::Table => OutputFormat::Text,
+                    other => *other,
+                })
+            );
+        }
+    }
+
+    #[test]
+    fn test_itinerary_subtotals_in_csv_and_table() {
+        let groups = two_itineraries();
+
+        let csv = render_itineraries(&groups, OutputFormat::Csv, &test_options());
+        let lines: Vec<&str> = csv.lines().collect();
+        assert_eq!(lines.len(), 7);
+        assert!(lines[3].starts_with("subtotal,Tokyo,2,1750.5,"));
+        assert!(lines[5].starts_with("subtotal,\"Paris, weekend\",1,50.25,"));
+        assert!(lines[6].starts_with("total,,3,1800.75,"));
+
+        let table = render_itineraries(&groups, OutputFormat::Table, &test_options());
+        assert!(table.contains("| Subtotal: Tokyo "));
+        assert!(table.contains("1750.50 |"));
+        assert!(table.contains("| TOTAL "));
+        assert!(table.contains("1800.75 |"));
+    }
+
+    #[test]
+    fn test_itinerary_subtotals_in_text() {
+        let text = render_itineraries(&two_itineraries(), OutputFormat::Text, &test_options());
+
+        let tokyo = text.find("Subtotal for Tokyo").unwrap();
+        let paris = text.find("Subtotal for Paris, weekend").unwrap();
+        let total = text.find("TOTAL: 1800.75 kg CO2").unwrap();
+        assert!(tokyo < paris && paris < total);
+        assert!(text.contains("1750.50"));
+    }
+
+    #[test]
+    fn test_leg_breakdown_shows_each_share() {
+        let legs = vec![
+            create_estimate(&[("LHR", "JFK")], 300.0),
+            create_estimate(&[("JFK", "LAX")], 100.0),
+        ];
+
+        let breakdown = render_leg_breakdown(&legs, &test_options());
+
+        assert!(breakdown.contains("1. LHR → JFK: 300.00 kg (75%) over 5660.00 km"));
+        assert!(breakdown.contains("2. JFK → LAX: 100.00 kg (25%)"));
+        assert!(breakdown.ends_with("Total: 400.00 kg"));
+    }
+
+    /// Checks that every tag in `html` is closed in the right order.
+    fn assert_balanced_tags(html: &str) {
+        let mut open: Vec<&str> = Vec::new();
+        let mut rest = html;
+        while let Some(start) = rest.find('<') {
+            let end = rest[start..].find('>').expect("unterminated tag") + start;
+            let tag = &rest[start + 1..end];
+            rest = &rest[end + 1..];
+            if let Some(name) = tag.strip_prefix('/') {
+                assert_eq!(open.pop(), Some(name), "unexpected </{}>", name);
+            } else {
+                open.push(tag.split_whitespace().next().unwrap());
+            }
+        }
+        assert!(open.is_empty(), "unclosed tags: {:?}", open);
+    }
+
+    #[test]
+    fn test_html_report_contains_the_figures() {
+        let mut business = create_estimate(&[("CDG", "NRT")], 600.5);
+        business.request.legs[0].cabin_class = Some("business".to_string());
+        let estimates = vec![create_estimate(&[("LHR", "JFK")], 400.0), business];
+
+        let html = render_batch(&estimates, OutputFormat::Html, &test_options());
+
+        assert_balanced_tags(&html);
+        assert!(html.starts_with("<div class=\"carbon-report\">"));
+        assert!(html.contains("<strong>1000.50 kg CO2</strong> across 2 estimates"));
+        assert!(html.contains("<li>CDG → NRT (business)</li>"));
+        assert!(html.contains("<td class=\"number\">600.50</td>"));
+        assert!(
+            !html.contains("http"),
+            "the snippet shouldn't load anything"
+        );
+    }
+
+    #[test]
+    fn test_html_escapes_user_strings() {
+        let mut estimate = create_estimate(&[("LHR", "JFK")], 400.0);
+        estimate.request.legs[0].cabin_class = Some("<script>\"x\" & 'y'</script>".to_string());
+
+        let html = to_html(&[estimate], &test_options());
+
+        assert_balanced_tags(&html);
+        assert!(html.contains("&lt;script&gt;&quot;x&quot; &amp; &#39;y&#39;&lt;/script&gt;"));
+        assert!(!html.contains("<script>"));
+    }
+
+    fn sorted_routes(estimates: &[EstimateResult], key: SortKey, descending: bool) -> Vec<String> {
+        let mut estimates = estimates.to_vec();
+        sort_results(&mut estimates, key, descending);
+        estimates
+            .iter()
+            .map(|estimate| route_stops(&estimate.request.legs).join("-"))
+            .collect()
+    }
+
+    #[test]
+    fn test_sort_by_carbon_keeps_ties_in_order() {
+        let estimates = vec![
+            create_estimate(&[("LHR", "JFK")], 400.0),
+            create_estimate(&[("CDG", "NRT")], 900.0),
+            create_estimate(&[("SYD", "LAX")], 400.0),
+        ];
+
+        assert_eq!(
+            sorted_routes(&estimates, SortKey::Carbon, false),
+            vec!["LHR-JFK", "SYD-LAX", "CDG-NRT"]
+        );
+        assert_eq!(
+            sorted_routes(&estimates, SortKey::Carbon, true),
+            vec!["CDG-NRT", "LHR-JFK", "SYD-LAX"]
+        );
+    }
+
+    #[test]
+    fn test_sort_by_distance_compares_across_units() {
+        let mut miles = create_estimate(&[("CDG", "NRT")], 900.0);
+        // 4,000 mi is about 6,437 km, further than the others' 5,660 km
+        miles.attributes.distance_unit = "mi".to_string();
+        miles.attributes.distance_value = 4000.0;
+        let estimates = vec![
+            miles,
+            create_estimate(&[("LHR", "JFK")], 400.0),
+            create_estimate(&[("SYD", "LAX")], 100.0),
+        ];
+
+        assert_eq!(
+            sorted_routes(&estimates, SortKey::Distance, false),
+            vec!["LHR-JFK", "SYD-LAX", "CDG-NRT"]
+        );
+        assert_eq!(
+            sorted_routes(&estimates, SortKey::Distance, true),
+            vec!["CDG-NRT", "LHR-JFK", "SYD-LAX"]
+        );
+    }
+
+    #[test]
+    fn test_sort_by_route() {
+        let estimates = vec![
+            create_estimate(&[("LHR", "JFK")], 400.0),
+            create_estimate(&[("CDG", "NRT")], 900.0),
+            create_estimate(&[("LHR", "CDG")], 100.0),
+            create_estimate(&[("LHR", "JFK")], 700.0),
+        ];
+
+        let mut ascending = estimates.clone();
+        sort_results(&mut ascending, SortKey::Route, false);
+        let carbon: Vec<f32> = ascending
+            .iter()
+            .map(|estimate| estimate.attributes.carbon_kg)
+            .collect();
+        assert_eq!(carbon, vec![900.0, 100.0, 400.0, 700.0]);
+        assert_eq!(
+            sorted_routes(&estimates, SortKey::Route, true),
+            vec!["LHR-JFK", "LHR-JFK", "LHR-CDG", "CDG-NRT"]
+        );
+        let mut descending = estimates;
+        sort_results(&mut descending, SortKey::Route, true);
+        assert_eq!(descending[0].attributes.carbon_kg, 400.0);
+    }
+
+    #[test]
+    fn test_prometheus_output_is_well_formed() {
+        let mut business = create_estimate(&[("CDG", "NRT")], 600.5);
+        business.request.legs[0].cabin_class = Some("business".to_string());
+        let estimates = vec![create_estimate(&[("LHR", "JFK")], 400.0), business];
+
+        let metrics = render_batch(&estimates, OutputFormat::Prometheus, &test_options());
+
+        assert_eq!(
+            metrics,
+            "# HELP flight_carbon_kg Estimated carbon emissions of the trip, in kg.\n\
+             # TYPE flight_carbon_kg gauge\n\
+             flight_carbon_kg{route=\"LHR-JFK\",cabin=\"economy\",passengers=\"1\"} 400\n\
+             flight_carbon_kg{route=\"CDG-NRT\",cabin=\"business\",passengers=\"1\"} 600.5\n\
+             # HELP flight_distance_value Distance of the trip, in distance_unit.\n\
+             # TYPE flight_distance_value gauge\n\
+             flight_distance_value{route=\"LHR-JFK\",cabin=\"economy\",passengers=\"1\",distance_unit=\"km\"} 5660\n\
+             flight_distance_value{route=\"CDG-NRT\",cabin=\"business\",passengers=\"1\",distance_unit=\"km\"} 5660"
+        );
+    }
+
+    #[test]
+    fn test_prometheus_escapes_label_values() {
+        let mut estimate = create_estimate(&[("LHR", "JFK")], 400.0);
+        estimate.request.legs[0].cabin_class = Some("a\\b \"c\"\nd".to_string());
+
+        let metrics = to_prometheus(&[estimate]);
+
+        assert!(metrics.contains(r#"cabin="a\\b \"c\"\nd""#));
+        assert!(metrics.lines().all(|line| line.starts_with('#')
+            || line.starts_with("flight_carbon_kg{")
+            || line.starts_with("flight_distance_value{")));
+    }
+
+    #[test]
+    fn test_render_table_lines_are_aligned() {
+        let estimates = vec![
+            create_estimate(&[("LHR", "JFK")], 1.0),
+            create_estimate(&[("LHR", "JFK"), ("JFK", "LAX"), ("LAX", "SYD")], 99999.0),
+        ];
+
+        let table = render_estimate(
+            &estimates[1].request,
+            &estimates[1].attributes,
+            OutputFormat::Table,
+            &test_options(),
+        );
+        let batch = render_batch(&estimates, OutputFormat::Table, &test_options());
+
+        for output in [table, batch] {
+            let widths: Vec<usize> = output.lines().map(|line| line.chars().count()).collect();
+            assert!(widths.iter().all(|width| *width == widths[0]), "{}", output);
+        }
+    }
+}
@@ -0,0 +1,46 @@
+//! Fixtures shared by the unit tests.
+
+use crate::model::{
+    EstimateAttributes, EstimateData, FlightEstimateRequest, FlightEstimateResponse, Leg,
+};
+
+pub(crate) fn create_mock_response(
+    carbon_g: f32,
+    carbon_lb: f32,
+    carbon_kg: f32,
+    carbon_mt: f32,
+    distance_unit: &str,
+    distance_value: f32,
+) -> FlightEstimateResponse {
+    FlightEstimateResponse {
+        data: Some(EstimateData {
+            attributes: EstimateAttributes {
+                carbon_g,
+                carbon_lb,
+                carbon_kg,
+                carbon_mt,
+                distance_unit: distance_unit.to_string(),
+                distance_value,
+            },
+        }),
+        message: None,
+    }
+}
+
+pub(crate) fn create_request(passengers: u32, route: &[(&str, &str)]) -> FlightEstimateRequest {
+    FlightEstimateRequest {
+        estimate_type: "flight".to_string(),
+        passengers,
+        legs: route
+            .iter()
+            .map(|(departure, destination)| Leg {
+                departure_airport: departure.to_string(),
+                destination_airport: destination.to_string(),
+                cabin_class: None,
+                passengers: None,
+                aircraft: None,
+            })
+            .collect(),
+        distance_unit: None,
+    }
+}